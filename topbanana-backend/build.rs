@@ -0,0 +1,58 @@
+
+//! Records build information (git commit and build time) for the
+//! `/version` endpoint, and the versions of the migrations in
+//! `migrations/` for the `/health/ready` endpoint.
+
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+fn main() {
+  println!("cargo:rerun-if-changed=../.git/HEAD");
+  println!("cargo:rerun-if-changed=../.git/refs");
+  println!("cargo:rerun-if-changed=migrations");
+
+  let git_commit = Command::new("git")
+    .args(["rev-parse", "HEAD"])
+    .output()
+    .ok()
+    .filter(|output| output.status.success())
+    .and_then(|output| String::from_utf8(output.stdout).ok())
+    .map(|commit| commit.trim().to_owned())
+    .unwrap_or_else(|| String::from("unknown"));
+  let build_timestamp = SystemTime::now()
+    .duration_since(UNIX_EPOCH)
+    .map(|duration| duration.as_secs())
+    .unwrap_or(0);
+
+  let out_dir = env::var("OUT_DIR").expect("OUT_DIR is set by cargo");
+  let contents = format!(
+    "pub const GIT_COMMIT: &str = {:?};\npub const BUILD_TIMESTAMP: i64 = {};\n",
+    git_commit,
+    build_timestamp,
+  );
+  fs::write(Path::new(&out_dir).join("build_info.rs"), contents).expect("Could not write build_info.rs");
+
+  let contents = format!("pub const MIGRATION_VERSIONS: &[&str] = &{:?};\n", migration_versions());
+  fs::write(Path::new(&out_dir).join("migrations.rs"), contents).expect("Could not write migrations.rs");
+}
+
+/// The version of each migration, as Diesel records it in
+/// `__diesel_schema_migrations`: the digits of the directory name
+/// before the first underscore.
+fn migration_versions() -> Vec<String> {
+  let mut versions = fs::read_dir("migrations")
+    .expect("Could not read migrations directory")
+    .filter_map(|entry| entry.ok())
+    .filter(|entry| entry.path().is_dir())
+    .filter_map(|entry| {
+      let name = entry.file_name().into_string().ok()?;
+      let version = name.split('_').next()?.replace('-', "");
+      Some(version)
+    })
+    .collect::<Vec<_>>();
+  versions.sort();
+  versions
+}
@@ -0,0 +1,3 @@
+
+ALTER TABLE games
+      DROP COLUMN IF EXISTS min_submit_interval_ms;
@@ -0,0 +1,3 @@
+
+ALTER TABLE games
+      ADD COLUMN min_submit_interval_ms INTEGER DEFAULT NULL;
@@ -0,0 +1,3 @@
+
+ALTER TABLE developers
+      DROP COLUMN IF EXISTS password_hash;
@@ -0,0 +1,3 @@
+
+ALTER TABLE developers
+      ADD COLUMN password_hash VARCHAR(255) DEFAULT NULL;
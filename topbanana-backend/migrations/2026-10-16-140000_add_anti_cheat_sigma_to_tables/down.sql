@@ -0,0 +1,3 @@
+
+ALTER TABLE highscore_tables
+      DROP COLUMN IF EXISTS anti_cheat_sigma;
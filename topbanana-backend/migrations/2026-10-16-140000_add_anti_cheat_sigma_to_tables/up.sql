@@ -0,0 +1,3 @@
+
+ALTER TABLE highscore_tables
+      ADD COLUMN anti_cheat_sigma DOUBLE PRECISION DEFAULT NULL;
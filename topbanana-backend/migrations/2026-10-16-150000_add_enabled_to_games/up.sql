@@ -0,0 +1,3 @@
+
+ALTER TABLE games
+      ADD COLUMN enabled BOOLEAN NOT NULL DEFAULT TRUE;
@@ -0,0 +1,3 @@
+
+ALTER TABLE highscore_tables
+      ADD COLUMN score_precision INTEGER DEFAULT NULL;
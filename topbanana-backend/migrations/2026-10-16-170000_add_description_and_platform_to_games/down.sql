@@ -0,0 +1,4 @@
+
+ALTER TABLE games
+      DROP COLUMN IF EXISTS description,
+      DROP COLUMN IF EXISTS platform;
@@ -0,0 +1,4 @@
+
+ALTER TABLE games
+      ADD COLUMN description VARCHAR(1000) DEFAULT NULL,
+      ADD COLUMN platform VARCHAR(100) DEFAULT NULL;
@@ -0,0 +1,3 @@
+
+ALTER TABLE games
+      ADD COLUMN daily_submission_quota INTEGER DEFAULT NULL;
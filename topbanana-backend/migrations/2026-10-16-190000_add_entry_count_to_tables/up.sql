@@ -0,0 +1,9 @@
+
+ALTER TABLE highscore_tables
+      ADD COLUMN entry_count BIGINT NOT NULL DEFAULT 0;
+
+UPDATE highscore_tables
+      SET entry_count = (
+        SELECT COUNT(*) FROM highscore_table_entries
+        WHERE highscore_table_entries.highscore_table_id = highscore_tables.id
+      );
@@ -0,0 +1,4 @@
+
+DROP INDEX IF EXISTS game_secret_keys_by_game;
+
+DROP TABLE IF EXISTS game_secret_keys;
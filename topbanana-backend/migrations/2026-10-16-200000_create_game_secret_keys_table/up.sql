@@ -0,0 +1,11 @@
+
+CREATE TABLE game_secret_keys (
+       id SERIAL PRIMARY KEY,
+       game_id INTEGER NOT NULL REFERENCES games (id),
+       key_id UUID NOT NULL UNIQUE,
+       secret_key VARCHAR(100) NOT NULL,
+       active BOOLEAN NOT NULL DEFAULT true,
+       creation_timestamp TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX game_secret_keys_by_game ON game_secret_keys (game_id);
@@ -0,0 +1,4 @@
+
+DROP INDEX IF EXISTS audit_log_by_timestamp;
+
+DROP TABLE IF EXISTS audit_log;
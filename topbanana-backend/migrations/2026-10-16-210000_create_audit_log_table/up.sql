@@ -0,0 +1,11 @@
+
+CREATE TABLE audit_log (
+       id SERIAL PRIMARY KEY,
+       actor_uuid UUID NOT NULL,
+       action VARCHAR(100) NOT NULL,
+       target_uuid UUID,
+       details TEXT,
+       timestamp TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX audit_log_by_timestamp ON audit_log (timestamp DESC);
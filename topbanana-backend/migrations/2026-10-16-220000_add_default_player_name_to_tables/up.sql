@@ -0,0 +1,3 @@
+
+ALTER TABLE highscore_tables
+      ADD COLUMN default_player_name VARCHAR(100) DEFAULT NULL;
@@ -0,0 +1,3 @@
+
+ALTER TABLE highscore_tables
+      ADD COLUMN store_only_improvements BOOLEAN NOT NULL DEFAULT FALSE;
@@ -0,0 +1,3 @@
+
+ALTER TABLE developers
+      ADD COLUMN is_super_admin BOOLEAN NOT NULL DEFAULT FALSE;
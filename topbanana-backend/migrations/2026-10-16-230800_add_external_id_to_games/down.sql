@@ -0,0 +1,5 @@
+
+DROP INDEX IF EXISTS games_external_id_by_developer;
+
+ALTER TABLE games
+      DROP COLUMN IF EXISTS external_id;
@@ -0,0 +1,5 @@
+
+ALTER TABLE games
+      ADD COLUMN external_id VARCHAR(100);
+
+CREATE UNIQUE INDEX games_external_id_by_developer ON games (developer_id, external_id);
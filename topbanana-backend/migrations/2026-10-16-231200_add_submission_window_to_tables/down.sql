@@ -0,0 +1,4 @@
+
+ALTER TABLE highscore_tables
+      DROP COLUMN IF EXISTS submissions_open_at,
+      DROP COLUMN IF EXISTS submissions_close_at;
@@ -0,0 +1,4 @@
+
+ALTER TABLE highscore_tables
+      ADD COLUMN submissions_open_at TIMESTAMP WITH TIME ZONE DEFAULT NULL,
+      ADD COLUMN submissions_close_at TIMESTAMP WITH TIME ZONE DEFAULT NULL;
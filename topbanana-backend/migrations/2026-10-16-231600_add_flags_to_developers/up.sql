@@ -0,0 +1,3 @@
+
+ALTER TABLE developers
+      ADD COLUMN flags INTEGER NOT NULL DEFAULT 0;
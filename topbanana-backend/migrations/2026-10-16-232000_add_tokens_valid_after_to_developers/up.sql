@@ -0,0 +1,3 @@
+
+ALTER TABLE developers
+      ADD COLUMN tokens_valid_after TIMESTAMP WITH TIME ZONE DEFAULT NULL;
@@ -0,0 +1,3 @@
+
+ALTER TABLE highscore_tables
+      ADD COLUMN score_is_integer BOOLEAN NOT NULL DEFAULT FALSE;
@@ -0,0 +1,3 @@
+
+ALTER TABLE games
+      ADD COLUMN default_algorithm VARCHAR(10);
@@ -0,0 +1,3 @@
+
+ALTER TABLE developers
+      ADD COLUMN default_security_level INT;
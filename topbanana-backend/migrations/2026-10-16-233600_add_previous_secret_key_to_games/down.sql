@@ -0,0 +1,4 @@
+
+ALTER TABLE games
+      DROP COLUMN IF EXISTS previous_game_secret_key,
+      DROP COLUMN IF EXISTS previous_secret_key_expires_at;
@@ -0,0 +1,4 @@
+
+ALTER TABLE games
+      ADD COLUMN previous_game_secret_key VARCHAR(100),
+      ADD COLUMN previous_secret_key_expires_at TIMESTAMPTZ;
@@ -0,0 +1,3 @@
+
+ALTER TABLE games
+      ADD COLUMN max_tables INT;
@@ -0,0 +1,3 @@
+
+ALTER TABLE highscore_tables
+      ADD COLUMN public_reads BOOLEAN NOT NULL DEFAULT FALSE;
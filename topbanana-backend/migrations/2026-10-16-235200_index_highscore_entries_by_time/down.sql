@@ -0,0 +1,2 @@
+
+DROP INDEX IF EXISTS highscore_index_by_time;
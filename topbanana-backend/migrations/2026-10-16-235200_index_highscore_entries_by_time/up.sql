@@ -0,0 +1,2 @@
+
+CREATE INDEX highscore_index_by_time ON highscore_table_entries (highscore_table_id, creation_timestamp);
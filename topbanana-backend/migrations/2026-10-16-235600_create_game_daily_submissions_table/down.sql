@@ -0,0 +1,2 @@
+
+DROP TABLE IF EXISTS game_daily_submissions;
@@ -0,0 +1,7 @@
+
+CREATE TABLE game_daily_submissions (
+       game_id INTEGER NOT NULL REFERENCES games (id),
+       day DATE NOT NULL,
+       submission_count INTEGER NOT NULL DEFAULT 0,
+       PRIMARY KEY (game_id, day)
+);
@@ -0,0 +1,10 @@
+
+CREATE TABLE failed_webhooks (
+       id SERIAL PRIMARY KEY,
+       delivery_uuid UUID NOT NULL UNIQUE,
+       url TEXT NOT NULL,
+       payload TEXT NOT NULL,
+       attempts INTEGER NOT NULL,
+       last_error TEXT NOT NULL,
+       failed_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
@@ -14,6 +14,20 @@ pub struct CliArgs {
   /// the server.
   #[arg(long)]
   pub cleanup_historical_requests: bool,
+  /// If supplied, print the configuration the server would use, with
+  /// secrets redacted, instead of starting the server.
+  #[arg(long)]
+  pub print_config: bool,
+  /// With --generate-initial-user, also allow the admin user to log
+  /// in with this password at `/api/login`. Prefer setting it through
+  /// the environment, so that it stays out of the process list and
+  /// shell history.
+  #[arg(long, env = "TOPBANANA_ADMIN_PASSWORD", hide_env_values = true)]
+  pub admin_password: Option<String>,
+  /// With --cleanup-historical-requests, print the result as a single
+  /// line of JSON rather than as prose.
+  #[arg(long)]
+  pub json: bool,
   /// Force the command, even if dangerous.
   #[arg(long)]
   pub force: bool,
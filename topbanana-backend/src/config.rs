@@ -0,0 +1,283 @@
+
+//! Server configuration, read from the environment once at startup
+//! and kept in Rocket's managed state.
+
+use crate::server::api::{TimestampFormat, TIMESTAMP_FORMAT_ENV_VAR};
+use crate::server::cors::CorsConfig;
+use crate::server::requests::{GameRequestBody, BATCH_ENV_VAR};
+use crate::server::transport::TransportConfig;
+use crate::server::webhooks::NEW_HIGHSCORE_WEBHOOK_URL_ENV_VAR;
+
+use chrono::TimeDelta;
+use log::warn;
+use reqwest::Url;
+
+use std::env;
+use std::fmt::Display;
+use std::str::FromStr;
+
+/// Environment variable setting how long, in minutes, a JWT token
+/// remains valid.
+pub const JWT_EXPIRATION_MINUTES_ENV_VAR: &str = "JWT_EXPIRATION_MINUTES";
+pub const DEFAULT_JWT_EXPIRATION_MINUTES: i64 = 60;
+
+/// Environment variable setting the clock skew tolerance, in seconds,
+/// applied to time-based claims when verifying a JWT token.
+pub const JWT_LEEWAY_SECONDS_ENV_VAR: &str = "JWT_LEEWAY_SECONDS";
+pub const DEFAULT_JWT_LEEWAY_SECONDS: u64 = 30;
+
+/// Environment variable setting how many hours of request UUIDs are
+/// kept by `--cleanup-historical-requests` beyond the longest a
+/// request could still be replayed. See
+/// [`AppConfig::historical_request_retention`].
+pub const HISTORICAL_REQUEST_RETENTION_BUFFER_HOURS_ENV_VAR: &str = "HISTORICAL_REQUEST_RETENTION_BUFFER_HOURS";
+pub const DEFAULT_HISTORICAL_REQUEST_RETENTION_BUFFER_HOURS: i64 = 24;
+
+/// Environment variable which, if set to `true` or `1`, describes the
+/// verification of each game request in a response header. This is
+/// meant for client development and should be off in production.
+pub const VERIFY_DEBUG_ENV_VAR: &str = "VERIFY_DEBUG";
+
+/// Environment variable setting how many hours a game's previous
+/// secret key is still accepted after the key is rotated.
+pub const SECRET_ROTATION_WINDOW_HOURS_ENV_VAR: &str = "SECRET_ROTATION_WINDOW_HOURS";
+pub const DEFAULT_SECRET_ROTATION_WINDOW_HOURS: i64 = 72;
+
+/// Environment variable setting how many highscore tables a game may
+/// have, unless an admin overrides it for the game.
+pub const MAX_TABLES_PER_GAME_ENV_VAR: &str = "MAX_TABLES_PER_GAME";
+pub const DEFAULT_MAX_TABLES_PER_GAME: i64 = 50;
+
+/// Environment variable setting how many games a non-admin developer
+/// may have.
+pub const MAX_GAMES_PER_DEVELOPER_ENV_VAR: &str = "MAX_GAMES_PER_DEVELOPER";
+pub const DEFAULT_MAX_GAMES_PER_DEVELOPER: i64 = 20;
+
+/// Environment variable setting the largest `limit` accepted by
+/// paginated endpoints. Larger limits are reduced to this.
+pub const MAX_PAGE_SIZE_ENV_VAR: &str = "MAX_PAGE_SIZE";
+pub const DEFAULT_MAX_PAGE_SIZE: u32 = 1000;
+
+/// Server-wide settings. Handlers should take this from Rocket's
+/// managed state rather than reading the environment themselves.
+#[derive(Debug, Clone)]
+pub struct AppConfig {
+  /// How long a JWT token remains valid after it is issued.
+  pub jwt_expiration: TimeDelta,
+  /// Clock skew tolerated when checking a JWT token's time-based
+  /// claims, in seconds.
+  pub jwt_leeway_seconds: u64,
+  /// How long request UUIDs are kept for replay protection before
+  /// they may be cleaned up. A request UUID is recorded when the
+  /// request arrives, and the request's timestamp may be up to
+  /// [`GameRequestBody::TIME_SKEW`] ahead of the clock then and behind
+  /// it on any replay. Past twice the time skew, a replay is rejected
+  /// for its timestamp alone, so this is that plus a buffer.
+  pub historical_request_retention: TimeDelta,
+  /// Whether request UUIDs are recorded in batches by a background
+  /// task rather than as each request is verified.
+  pub batch_historical_requests: bool,
+  /// Whether game responses describe how their request was verified.
+  pub verify_debug: bool,
+  /// How long a game's previous secret key is still accepted after
+  /// the key is rotated.
+  pub secret_rotation_window: TimeDelta,
+  /// How many highscore tables a game may have, unless overridden for
+  /// the game. Admins are not limited.
+  pub max_tables_per_game: i64,
+  /// How many games a developer may have. Admins are not limited.
+  pub max_games_per_developer: i64,
+  /// The largest `limit` accepted by paginated endpoints.
+  pub max_page_size: u32,
+  /// The format in which score timestamps are returned.
+  pub timestamp_format: TimestampFormat,
+  /// The HTTP or HTTPS URL to which new highscores are POSTed, if
+  /// any.
+  pub new_highscore_webhook_url: Option<String>,
+  pub cors: CorsConfig,
+  pub transport: TransportConfig,
+}
+
+impl AppConfig {
+  /// Reads the configuration from the process environment.
+  pub fn from_env() -> Self {
+    AppConfig::from_vars(|name| env::var(name).ok())
+  }
+
+  /// Reads the configuration through `vars`, which looks up a
+  /// variable by name. Invalid values are logged and replaced by
+  /// their defaults.
+  pub fn from_vars(vars: impl Fn(&str) -> Option<String>) -> Self {
+    let retention_buffer = parse_duration_var(&vars, HISTORICAL_REQUEST_RETENTION_BUFFER_HOURS_ENV_VAR, DEFAULT_HISTORICAL_REQUEST_RETENTION_BUFFER_HOURS, TimeDelta::try_hours);
+    AppConfig {
+      jwt_expiration: parse_duration_var(&vars, JWT_EXPIRATION_MINUTES_ENV_VAR, DEFAULT_JWT_EXPIRATION_MINUTES, TimeDelta::try_minutes),
+      jwt_leeway_seconds: parse_var(&vars, JWT_LEEWAY_SECONDS_ENV_VAR, DEFAULT_JWT_LEEWAY_SECONDS),
+      historical_request_retention: (GameRequestBody::<()>::TIME_SKEW * 2).checked_add(&retention_buffer).unwrap_or(TimeDelta::MAX),
+      batch_historical_requests: vars(BATCH_ENV_VAR).is_some_and(|value| value == "true" || value == "1"),
+      verify_debug: vars(VERIFY_DEBUG_ENV_VAR).is_some_and(|value| value == "true" || value == "1"),
+      secret_rotation_window: parse_duration_var(&vars, SECRET_ROTATION_WINDOW_HOURS_ENV_VAR, DEFAULT_SECRET_ROTATION_WINDOW_HOURS, TimeDelta::try_hours),
+      max_tables_per_game: parse_var(&vars, MAX_TABLES_PER_GAME_ENV_VAR, DEFAULT_MAX_TABLES_PER_GAME),
+      max_games_per_developer: parse_var(&vars, MAX_GAMES_PER_DEVELOPER_ENV_VAR, DEFAULT_MAX_GAMES_PER_DEVELOPER),
+      max_page_size: parse_var(&vars, MAX_PAGE_SIZE_ENV_VAR, DEFAULT_MAX_PAGE_SIZE),
+      timestamp_format: parse_var(&vars, TIMESTAMP_FORMAT_ENV_VAR, TimestampFormat::default()),
+      new_highscore_webhook_url: parse_url_var(&vars, NEW_HIGHSCORE_WEBHOOK_URL_ENV_VAR),
+      cors: CorsConfig::from_vars(&vars),
+      transport: TransportConfig::from_vars(&vars),
+    }
+  }
+}
+
+impl Default for AppConfig {
+  fn default() -> Self {
+    AppConfig::from_vars(|_| None)
+  }
+}
+
+/// Parses the variable `name`, falling back to `default` if it is
+/// absent or invalid.
+fn parse_var<T>(vars: &impl Fn(&str) -> Option<String>, name: &str, default: T) -> T
+where T: FromStr + Display {
+  let Some(value) = vars(name) else {
+    return default;
+  };
+  value.trim().parse().unwrap_or_else(|_| {
+    warn!("Invalid {} value {:?}, using default of {}", name, value, default);
+    default
+  })
+}
+
+/// Parses the variable `name` as a positive number of the units
+/// counted by `unit`, such as [`TimeDelta::try_hours`]. A value which
+/// is not positive, or which is too large for a [`TimeDelta`], is
+/// invalid, and `default` is used instead.
+fn parse_duration_var(
+  vars: &impl Fn(&str) -> Option<String>,
+  name: &str,
+  default: i64,
+  unit: fn(i64) -> Option<TimeDelta>,
+) -> TimeDelta {
+  let default_duration = unit(default).expect("default duration should be in range");
+  let value = parse_var(vars, name, default);
+  if value <= 0 {
+    warn!("Invalid {} value {}, must be positive, using default of {}", name, value, default);
+    return default_duration;
+  }
+  unit(value).unwrap_or_else(|| {
+    warn!("Invalid {} value {}, too large, using default of {}", name, value, default);
+    default_duration
+  })
+}
+
+/// Parses the variable `name` as an HTTP or HTTPS URL. A blank value
+/// is treated as absent, and an invalid one is logged and ignored.
+fn parse_url_var(vars: &impl Fn(&str) -> Option<String>, name: &str) -> Option<String> {
+  let value = vars(name)?;
+  let value = value.trim();
+  if value.is_empty() {
+    return None;
+  }
+  match Url::parse(value) {
+    Ok(url) if url.scheme() == "http" || url.scheme() == "https" => Some(value.to_owned()),
+    _ => {
+      warn!("Invalid {} value {:?}, must be an http or https URL, ignoring it", name, value);
+      None
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  use std::collections::HashMap;
+
+  fn config_from(vars: &[(&str, &str)]) -> AppConfig {
+    let vars = vars.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect::<HashMap<_, _>>();
+    AppConfig::from_vars(|name| vars.get(name).cloned())
+  }
+
+  #[test]
+  fn test_defaults() {
+    let config = AppConfig::default();
+    assert_eq!(config.jwt_expiration, TimeDelta::minutes(DEFAULT_JWT_EXPIRATION_MINUTES));
+    assert_eq!(config.jwt_leeway_seconds, DEFAULT_JWT_LEEWAY_SECONDS);
+    assert!(!config.batch_historical_requests);
+    assert!(!config.verify_debug);
+    assert_eq!(config.max_page_size, DEFAULT_MAX_PAGE_SIZE);
+    assert_eq!(config.timestamp_format, TimestampFormat::Space);
+    assert_eq!(config.new_highscore_webhook_url, None);
+  }
+
+  #[test]
+  fn test_reads_vars() {
+    let config = config_from(&[
+      (JWT_LEEWAY_SECONDS_ENV_VAR, "5"),
+      (BATCH_ENV_VAR, "true"),
+      (VERIFY_DEBUG_ENV_VAR, "1"),
+      (MAX_PAGE_SIZE_ENV_VAR, " 200 "),
+      (TIMESTAMP_FORMAT_ENV_VAR, "ISO8601"),
+      (NEW_HIGHSCORE_WEBHOOK_URL_ENV_VAR, " https://example.com/hook "),
+    ]);
+    assert_eq!(config.jwt_leeway_seconds, 5);
+    assert!(config.batch_historical_requests);
+    assert!(config.verify_debug);
+    assert_eq!(config.max_page_size, 200);
+    assert_eq!(config.timestamp_format, TimestampFormat::Iso8601);
+    assert_eq!(config.new_highscore_webhook_url.as_deref(), Some("https://example.com/hook"));
+  }
+
+  #[test]
+  fn test_invalid_values_fall_back_to_defaults() {
+    let config = config_from(&[
+      (JWT_LEEWAY_SECONDS_ENV_VAR, "-5"),
+      (BATCH_ENV_VAR, "yes"),
+      (MAX_PAGE_SIZE_ENV_VAR, "lots"),
+      (TIMESTAMP_FORMAT_ENV_VAR, "rfc2822"),
+      (NEW_HIGHSCORE_WEBHOOK_URL_ENV_VAR, "ftp://example.com/hook"),
+    ]);
+    assert_eq!(config.jwt_leeway_seconds, DEFAULT_JWT_LEEWAY_SECONDS);
+    assert!(!config.batch_historical_requests);
+    assert_eq!(config.max_page_size, DEFAULT_MAX_PAGE_SIZE);
+    assert_eq!(config.timestamp_format, TimestampFormat::Space);
+    assert_eq!(config.new_highscore_webhook_url, None);
+    assert_eq!(config_from(&[(NEW_HIGHSCORE_WEBHOOK_URL_ENV_VAR, "not a url")]).new_highscore_webhook_url, None);
+    assert_eq!(config_from(&[(NEW_HIGHSCORE_WEBHOOK_URL_ENV_VAR, "  ")]).new_highscore_webhook_url, None);
+  }
+
+  #[test]
+  fn test_historical_request_retention_covers_replay_window() {
+    let config = config_from(&[(HISTORICAL_REQUEST_RETENTION_BUFFER_HOURS_ENV_VAR, "6")]);
+    assert_eq!(config.historical_request_retention, GameRequestBody::<()>::TIME_SKEW * 2 + TimeDelta::hours(6));
+    // A negative buffer is invalid, so the default is used.
+    let config = config_from(&[(HISTORICAL_REQUEST_RETENTION_BUFFER_HOURS_ENV_VAR, "-6")]);
+    assert_eq!(
+      config.historical_request_retention,
+      GameRequestBody::<()>::TIME_SKEW * 2 + TimeDelta::hours(DEFAULT_HISTORICAL_REQUEST_RETENTION_BUFFER_HOURS),
+    );
+  }
+
+  #[test]
+  fn test_durations_must_be_positive_and_in_range() {
+    let config = config_from(&[
+      (JWT_EXPIRATION_MINUTES_ENV_VAR, "90"),
+      (SECRET_ROTATION_WINDOW_HOURS_ENV_VAR, "12"),
+    ]);
+    assert_eq!(config.jwt_expiration, TimeDelta::minutes(90));
+    assert_eq!(config.secret_rotation_window, TimeDelta::hours(12));
+    for value in ["0", "-5", &i64::MAX.to_string()] {
+      let config = config_from(&[
+        (JWT_EXPIRATION_MINUTES_ENV_VAR, value),
+        (SECRET_ROTATION_WINDOW_HOURS_ENV_VAR, value),
+        (HISTORICAL_REQUEST_RETENTION_BUFFER_HOURS_ENV_VAR, value),
+      ]);
+      assert_eq!(config.jwt_expiration, TimeDelta::minutes(DEFAULT_JWT_EXPIRATION_MINUTES), "{}", value);
+      assert_eq!(config.secret_rotation_window, TimeDelta::hours(DEFAULT_SECRET_ROTATION_WINDOW_HOURS), "{}", value);
+      assert_eq!(
+        config.historical_request_retention,
+        GameRequestBody::<()>::TIME_SKEW * 2 + TimeDelta::hours(DEFAULT_HISTORICAL_REQUEST_RETENTION_BUFFER_HOURS),
+        "{}",
+        value,
+      );
+    }
+  }
+}
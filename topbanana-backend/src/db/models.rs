@@ -2,7 +2,7 @@
 use diesel::prelude::*;
 use uuid::Uuid;
 
-#[derive(Queryable, Selectable, Clone)]
+#[derive(Queryable, Selectable, Identifiable, Clone)]
 #[diesel(table_name = super::schema::developers)]
 #[diesel(check_for_backend(diesel::pg::Pg))]
 pub struct Developer {
@@ -13,6 +13,16 @@ pub struct Developer {
   pub url: Option<String>,
   pub is_admin: bool,
   pub api_key: Option<String>,
+  pub password_hash: Option<String>,
+  pub is_super_admin: bool,
+  /// Persisted [`UserFlags`](crate::server::auth::UserFlags) bits,
+  /// granted in addition to those implied by `is_admin` and
+  /// `is_super_admin`.
+  pub flags: i32,
+  /// Tokens for this developer issued before this time are rejected.
+  pub tokens_valid_after: Option<chrono::NaiveDateTime>,
+  /// Security level for new games which do not specify one.
+  pub default_security_level: Option<i32>,
 }
 
 #[derive(Insertable, Clone)]
@@ -25,9 +35,12 @@ pub struct NewDeveloper {
   pub url: Option<String>,
   pub is_admin: bool,
   pub api_key: Option<String>,
+  pub password_hash: Option<String>,
+  pub is_super_admin: bool,
+  pub default_security_level: Option<i32>,
 }
 
-#[derive(Queryable, Selectable, Associations, Clone)]
+#[derive(Queryable, Selectable, Identifiable, Associations, Clone)]
 #[diesel(belongs_to(Developer))]
 #[diesel(table_name = super::schema::games)]
 #[diesel(check_for_backend(diesel::pg::Pg))]
@@ -38,6 +51,20 @@ pub struct Game {
   pub game_secret_key: String,
   pub name: String,
   pub security_level: i32,
+  pub min_submit_interval_ms: Option<i32>,
+  pub enabled: bool,
+  pub description: Option<String>,
+  pub platform: Option<String>,
+  pub daily_submission_quota: Option<i32>,
+  pub external_id: Option<String>,
+  pub default_algorithm: Option<String>,
+  /// The secret key replaced by the last rotation, which is still
+  /// accepted until `previous_secret_key_expires_at`.
+  pub previous_game_secret_key: Option<String>,
+  pub previous_secret_key_expires_at: Option<chrono::NaiveDateTime>,
+  /// Overrides the server's maximum number of highscore tables for
+  /// this game.
+  pub max_tables: Option<i32>,
 }
 
 #[derive(Insertable, Clone)]
@@ -49,6 +76,49 @@ pub struct NewGame {
   pub game_secret_key: String,
   pub name: String,
   pub security_level: i32,
+  pub min_submit_interval_ms: Option<i32>,
+  pub enabled: bool,
+  pub description: Option<String>,
+  pub platform: Option<String>,
+  pub daily_submission_quota: Option<i32>,
+  pub external_id: Option<String>,
+  pub default_algorithm: Option<String>,
+}
+
+/// An additional secret key for a game, identified in requests by
+/// its `key_id`. A game may have several active keys at once, so that
+/// keys can be rotated without breaking existing clients.
+#[derive(Queryable, Selectable, Associations, Clone)]
+#[diesel(belongs_to(Game))]
+#[diesel(table_name = super::schema::game_secret_keys)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct GameSecretKey {
+  pub id: i32,
+  pub game_id: i32,
+  pub key_id: Uuid,
+  pub secret_key: String,
+  pub active: bool,
+  pub creation_timestamp: chrono::NaiveDateTime,
+}
+
+#[derive(Insertable, Clone)]
+#[diesel(table_name = super::schema::game_secret_keys)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct NewGameSecretKey {
+  pub game_id: i32,
+  pub key_id: Uuid,
+  pub secret_key: String,
+}
+
+/// One game's count of score submissions on one UTC day, for daily
+/// submission quotas.
+#[derive(Insertable, Clone)]
+#[diesel(table_name = super::schema::game_daily_submissions)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct NewGameDailySubmissions {
+  pub game_id: i32,
+  pub day: chrono::NaiveDate,
+  pub submission_count: i32,
 }
 
 #[derive(Queryable, Selectable, Associations, Clone)]
@@ -62,6 +132,21 @@ pub struct HighscoreTable {
   pub table_uuid: Uuid,
   pub maximum_scores_retained: Option<i32>,
   pub unique_entries: bool,
+  pub anti_cheat_sigma: Option<f64>,
+  pub score_precision: Option<i32>,
+  /// Number of entries on the table, maintained alongside every
+  /// insert and delete.
+  pub entry_count: i64,
+  pub default_player_name: Option<String>,
+  pub store_only_improvements: bool,
+  pub submissions_open_at: Option<chrono::NaiveDateTime>,
+  pub submissions_close_at: Option<chrono::NaiveDateTime>,
+  pub score_is_integer: bool,
+  /// When the table was soft-deleted, if it has been.
+  pub deleted_at: Option<chrono::NaiveDateTime>,
+  /// Whether games may read the table's scores without signing the
+  /// request.
+  pub public_reads: bool,
 }
 
 #[derive(Insertable, Clone)]
@@ -73,9 +158,17 @@ pub struct NewHighscoreTable {
   pub table_uuid: Uuid,
   pub maximum_scores_retained: Option<i32>,
   pub unique_entries: bool,
+  pub anti_cheat_sigma: Option<f64>,
+  pub score_precision: Option<i32>,
+  pub default_player_name: Option<String>,
+  pub store_only_improvements: bool,
+  pub submissions_open_at: Option<chrono::NaiveDateTime>,
+  pub submissions_close_at: Option<chrono::NaiveDateTime>,
+  pub score_is_integer: bool,
+  pub public_reads: bool,
 }
 
-#[derive(Queryable, Selectable, Associations, Clone)]
+#[derive(Queryable, QueryableByName, Selectable, Associations, Clone)]
 #[diesel(belongs_to(HighscoreTable))]
 #[diesel(table_name = super::schema::highscore_table_entries)]
 #[diesel(check_for_backend(diesel::pg::Pg))]
@@ -96,6 +189,8 @@ pub struct NewHighscoreTableEntry {
   pub player_name: String,
   pub player_score: f64,
   pub player_score_metadata: Option<String>,
+  /// If `None`, the database's current time is used.
+  pub creation_timestamp: Option<chrono::NaiveDateTime>,
 }
 
 #[derive(Queryable, Selectable, Clone)]
@@ -113,3 +208,49 @@ pub struct HistoricalRequest {
 pub struct NewHistoricalRequest {
   pub request_uuid: Uuid,
 }
+
+#[derive(Queryable, Selectable, Clone)]
+#[diesel(table_name = super::schema::audit_log)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct AuditLogEntry {
+  pub id: i32,
+  pub actor_uuid: Uuid,
+  pub action: String,
+  pub target_uuid: Option<Uuid>,
+  pub details: Option<String>,
+  pub timestamp: chrono::NaiveDateTime,
+}
+
+#[derive(Insertable, Clone)]
+#[diesel(table_name = super::schema::audit_log)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct NewAuditLogEntry {
+  pub actor_uuid: Uuid,
+  pub action: String,
+  pub target_uuid: Option<Uuid>,
+  pub details: Option<String>,
+}
+
+#[derive(Queryable, Selectable, Clone)]
+#[diesel(table_name = super::schema::failed_webhooks)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct FailedWebhook {
+  pub id: i32,
+  pub delivery_uuid: Uuid,
+  pub url: String,
+  pub payload: String,
+  pub attempts: i32,
+  pub last_error: String,
+  pub failed_at: chrono::NaiveDateTime,
+}
+
+#[derive(Insertable, Clone, Debug, PartialEq, Eq)]
+#[diesel(table_name = super::schema::failed_webhooks)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct NewFailedWebhook {
+  pub delivery_uuid: Uuid,
+  pub url: String,
+  pub payload: String,
+  pub attempts: i32,
+  pub last_error: String,
+}
@@ -1,5 +1,17 @@
 // @generated automatically by Diesel CLI.
 
+diesel::table! {
+    audit_log (id) {
+        id -> Int4,
+        actor_uuid -> Uuid,
+        #[max_length = 100]
+        action -> Varchar,
+        target_uuid -> Nullable<Uuid>,
+        details -> Nullable<Text>,
+        timestamp -> Timestamptz,
+    }
+}
+
 diesel::table! {
     developers (id) {
         id -> Int4,
@@ -13,6 +25,44 @@ diesel::table! {
         is_admin -> Bool,
         #[max_length = 100]
         api_key -> Nullable<Varchar>,
+        #[max_length = 255]
+        password_hash -> Nullable<Varchar>,
+        is_super_admin -> Bool,
+        flags -> Int4,
+        tokens_valid_after -> Nullable<Timestamptz>,
+        default_security_level -> Nullable<Int4>,
+    }
+}
+
+diesel::table! {
+    failed_webhooks (id) {
+        id -> Int4,
+        delivery_uuid -> Uuid,
+        url -> Text,
+        payload -> Text,
+        attempts -> Int4,
+        last_error -> Text,
+        failed_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    game_daily_submissions (game_id, day) {
+        game_id -> Int4,
+        day -> Date,
+        submission_count -> Int4,
+    }
+}
+
+diesel::table! {
+    game_secret_keys (id) {
+        id -> Int4,
+        game_id -> Int4,
+        key_id -> Uuid,
+        #[max_length = 100]
+        secret_key -> Varchar,
+        active -> Bool,
+        creation_timestamp -> Timestamptz,
     }
 }
 
@@ -26,6 +76,21 @@ diesel::table! {
         #[max_length = 100]
         name -> Varchar,
         security_level -> Int4,
+        min_submit_interval_ms -> Nullable<Int4>,
+        enabled -> Bool,
+        #[max_length = 1000]
+        description -> Nullable<Varchar>,
+        #[max_length = 100]
+        platform -> Nullable<Varchar>,
+        daily_submission_quota -> Nullable<Int4>,
+        #[max_length = 100]
+        external_id -> Nullable<Varchar>,
+        #[max_length = 10]
+        default_algorithm -> Nullable<Varchar>,
+        #[max_length = 100]
+        previous_game_secret_key -> Nullable<Varchar>,
+        previous_secret_key_expires_at -> Nullable<Timestamptz>,
+        max_tables -> Nullable<Int4>,
     }
 }
 
@@ -50,6 +115,17 @@ diesel::table! {
         table_uuid -> Uuid,
         maximum_scores_retained -> Nullable<Int4>,
         unique_entries -> Bool,
+        anti_cheat_sigma -> Nullable<Float8>,
+        score_precision -> Nullable<Int4>,
+        entry_count -> Int8,
+        #[max_length = 100]
+        default_player_name -> Nullable<Varchar>,
+        store_only_improvements -> Bool,
+        submissions_open_at -> Nullable<Timestamptz>,
+        submissions_close_at -> Nullable<Timestamptz>,
+        score_is_integer -> Bool,
+        deleted_at -> Nullable<Timestamptz>,
+        public_reads -> Bool,
     }
 }
 
@@ -61,12 +137,18 @@ diesel::table! {
     }
 }
 
+diesel::joinable!(game_daily_submissions -> games (game_id));
+diesel::joinable!(game_secret_keys -> games (game_id));
 diesel::joinable!(games -> developers (developer_id));
 diesel::joinable!(highscore_table_entries -> highscore_tables (highscore_table_id));
 diesel::joinable!(highscore_tables -> games (game_id));
 
 diesel::allow_tables_to_appear_in_same_query!(
+    audit_log,
     developers,
+    failed_webhooks,
+    game_daily_submissions,
+    game_secret_keys,
     games,
     highscore_table_entries,
     highscore_tables,
@@ -1,5 +1,6 @@
 
 pub mod args;
+pub mod config;
 pub mod db;
 pub mod server;
 pub mod setup;
@@ -1,22 +1,33 @@
 
 use topbanana::server::run_server;
-use topbanana::setup::{generate_initial_user, cleanup_historical_requests, setup_logger};
+use topbanana::setup::{generate_initial_user, cleanup_historical_requests, print_config, setup_logger};
+use topbanana::setup::{InitialUserOutcome, ADMIN_ALREADY_EXISTS_EXIT_CODE};
 use topbanana::args::CliArgs;
 
 use clap::Parser;
 
+use std::process::ExitCode;
+
 #[rocket::main]
-async fn main() -> Result<(), anyhow::Error> {
+async fn main() -> Result<ExitCode, anyhow::Error> {
   let cli_args = CliArgs::parse();
 
   if cli_args.generate_initial_user {
-    generate_initial_user(cli_args.force).await?;
+    let outcome = generate_initial_user(cli_args.force, cli_args.admin_password.as_deref()).await?;
+    if let InitialUserOutcome::AlreadyExists { .. } = outcome {
+      return Ok(ExitCode::from(ADMIN_ALREADY_EXISTS_EXIT_CODE));
+    }
   } else if cli_args.cleanup_historical_requests {
-    cleanup_historical_requests().await?;
+    let deleted_rows_count = cleanup_historical_requests(!cli_args.json).await?;
+    if cli_args.json {
+      println!("{}", serde_json::json!({ "deleted_rows": deleted_rows_count }));
+    }
+  } else if cli_args.print_config {
+    print_config();
   } else {
     setup_logger()?;
     run_server().await?;
   }
 
-  Ok(())
+  Ok(ExitCode::SUCCESS)
 }
@@ -1,19 +1,39 @@
 
-use crate::db::schema;
+use crate::db::{schema, models};
 use crate::db::models::NewDeveloper;
-use crate::util::generate_key;
-use super::data_access::DeveloperResponse;
+use crate::util::{InvalidUuid, UuidParam, generate_key};
+use super::data_access::{DeveloperResponse, GameResponse, HighscoreTableResponse};
 use super::db::Db;
-use super::auth::AdminUser;
+use super::api::{AuthResponse, QueryTimestamp};
+use super::audit::{record_audit_event, AuditAction, AuditLogEntryResponse, AuditLogParams, AuditLogResponse};
+use super::auth::{create_impersonation_token, AdminUser, SuperAdminUser};
 use super::error::{ApiSuccessResponse, ApiSuccessResponseBody, ApiError};
+use super::data_access::find_highscore_table_with_owner;
+use super::highscore_tables::{adjust_entry_count, recount_entries, remove_duplicate_player_rows, remove_extra_highscore_rows};
+use super::requests::{GameRequestPayload, RequestAlgorithm, VerificationMetrics, VerificationMetricsSnapshot};
+use super::openapi::OpenApiUuid;
+use super::pagination::{Paginated, Pagination};
+use super::validation::{deserialize_bounded_vec, FieldErrors, Validate};
+use super::webhooks::{FailedWebhookResponse, FailedWebhooksResponse, WebhookDelivery, WebhookSender};
 
-use rocket::post;
+use rocket::{State, get, post};
+use rocket::http::Status;
+use rocket::tokio::sync::mpsc::error::TrySendError;
 use rocket::serde::json::Json;
 use rocket_db_pools::Connection;
 use serde::{Serialize, Deserialize};
 use uuid::Uuid;
-use diesel_async::RunQueryDsl;
+use diesel::prelude::*;
+use diesel_async::{RunQueryDsl, AsyncConnection, AsyncPgConnection};
+use scoped_futures::ScopedFutureExt;
 use utoipa::ToSchema;
+use log::{error, info};
+use chrono::{DateTime, NaiveDateTime, Utc};
+
+use std::collections::{BTreeSet, HashMap};
+
+/// Maximum number of scores which may be imported in one request.
+pub const MAX_IMPORTED_SCORES: usize = 1000;
 
 #[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct NewDeveloperParams {
@@ -24,6 +44,190 @@ pub struct NewDeveloperParams {
   /// A URL for the developer's website, optional.
   #[serde(default)]
   pub url: Option<String>,
+  /// Security level for the developer's new games which do not
+  /// specify one, optional.
+  #[serde(default)]
+  pub default_security_level: Option<i32>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct SetDefaultSecurityLevelParams {
+  /// Security level for the developer's new games which do not
+  /// specify one, or `null` to use level 0.
+  pub default_security_level: Option<i32>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct SetGameEnabledParams {
+  /// Whether the game should accept new score submissions.
+  pub enabled: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct SetGameMaxTablesParams {
+  /// Maximum number of highscore tables the game may have, or `null`
+  /// to use the server's default.
+  pub max_tables: Option<i32>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct SetGameQuotaParams {
+  /// Maximum number of scores the game may submit per UTC day, or
+  /// `null` to remove the limit.
+  pub daily_submission_quota: Option<i32>,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct RetrimResponse {
+  /// The outcome for each table with a `maximum_scores_retained`.
+  pub tables: Vec<RetrimTableResult>,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct DeveloperTreeResponse {
+  #[serde(flatten)]
+  pub developer: DeveloperResponse,
+  /// The developer's games, oldest first.
+  pub games: Vec<GameTreeResponse>,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct GameTreeResponse {
+  #[serde(flatten)]
+  pub game: GameResponse,
+  /// The game's highscore tables, oldest first.
+  pub tables: Vec<HighscoreTableResponse>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ImportScoresParams {
+  /// The scores to add to the table, at most 1000. Larger arrays are
+  /// rejected with HTTP 422 before any scores are inserted.
+  #[serde(deserialize_with = "deserialize_bounded_vec::<_, _, MAX_IMPORTED_SCORES>")]
+  #[schema(max_items = 1000)]
+  pub entries: Vec<ImportedScore>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ImportedScore {
+  pub player_name: String,
+  pub player_score: f64,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub player_score_metadata: Option<String>,
+  /// When the score was originally submitted, in RFC 3339 format.
+  #[schema(value_type = String, example = "2025-02-01T05:33:10Z")]
+  pub creation_timestamp: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ImportScoresResponse {
+  /// The number of scores inserted.
+  pub rows_inserted: usize,
+  /// The number of scores, imported or existing, removed afterward by
+  /// the table's `unique_entries` or `maximum_scores_retained`
+  /// settings.
+  pub rows_removed: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct VerifySignatureParams {
+  /// A signed game request body, exactly as a game would send it.
+  #[schema(example = "eyJnYW1lX3V1aWQiOiIuLi4ifQ==.c2lnbmF0dXJl")]
+  pub payload: String,
+  /// The secret key to check the signature against. This is never
+  /// compared with any real game's key.
+  pub secret_key: String,
+  /// The algorithm to check the signature with.
+  #[schema(value_type = String, example = "sha256")]
+  pub algo: RequestAlgorithm,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct VerifySignatureResponse {
+  /// Whether the signature matches the payload and secret key.
+  pub matches: bool,
+  /// Why the signature does not match, if it does not.
+  pub reason: Option<String>,
+  /// The length of a signature from the chosen algorithm, in bytes.
+  pub expected_signature_bytes: usize,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct RankChangesResponse {
+  /// The earlier point in time compared.
+  #[schema(value_type = String, example = "2025-02-01T00:00:00Z")]
+  pub from: DateTime<Utc>,
+  /// The later point in time compared.
+  #[schema(value_type = String, example = "2025-02-08T00:00:00Z")]
+  pub to: DateTime<Utc>,
+  /// Every player ranked at either point, in order of their rank at
+  /// `to`. Players who dropped off the table come last.
+  pub players: Vec<PlayerRankChange>,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct PlayerRankChange {
+  pub player_name: String,
+  /// The player's rank at `from`, or `null` if they had no score yet.
+  pub from_rank: Option<i64>,
+  /// The player's rank at `to`, or `null` if their scores have since
+  /// been removed.
+  pub to_rank: Option<i64>,
+  /// How many places the player rose, negative if they fell. Absent
+  /// for new and dropped players.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub change: Option<i64>,
+  pub status: RankChangeStatus,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum RankChangeStatus {
+  /// Ranked at `to` but not at `from`.
+  New,
+  /// Ranked at `from` but not at `to`.
+  Dropped,
+  /// Ranked at both points, in different places.
+  Moved,
+  /// Ranked at both points, in the same place.
+  Unchanged,
+}
+
+/// A player's rank on a table as of some point in time.
+#[derive(Debug, Clone, QueryableByName)]
+struct PlayerRank {
+  #[diesel(sql_type = diesel::sql_types::Varchar)]
+  player_name: String,
+  #[diesel(sql_type = diesel::sql_types::Int8)]
+  rank: i64,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct RevokeTokensResponse {
+  /// Tokens for the developer issued before this time are no longer
+  /// accepted.
+  #[schema(value_type = String, example = "2025-02-01T05:33:10Z")]
+  pub tokens_valid_after: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct RecountResponse {
+  /// The number of tables whose stored entry count was wrong and has
+  /// been corrected.
+  pub tables_corrected: usize,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct RetrimTableResult {
+  #[schema(value_type = OpenApiUuid)]
+  pub table_uuid: Uuid,
+  /// The number of scores removed from the table. Absent if trimming
+  /// this table failed.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub rows_removed: Option<usize>,
+  /// Why trimming this table failed, if it did.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub error: Option<String>,
 }
 
 /// Creates a new developer user.
@@ -35,18 +239,20 @@ pub struct NewDeveloperParams {
   path="/api/developer",
   tag="developer",
   responses(
-    (status = 200, description = "Developer created successfully", body = ApiSuccessResponseBody<DeveloperResponse>),
+    (status = 201, description = "Developer created successfully", body = ApiSuccessResponseBody<DeveloperResponse>),
     (status = 409, description = "Developer with provided arguments already exists"),
+    (status = 422, description = "One or more fields are invalid"),
   )
 )]
 #[post("/developer", data = "<params>")]
 pub async fn create_developer(
-  _admin_user: AdminUser,
+  admin_user: AdminUser,
   params: Json<NewDeveloperParams>,
   mut db: Connection<Db>,
 ) -> Result<ApiSuccessResponse<DeveloperResponse>, ApiError> {
   let Json(params) = params;
-  let developer_uuid = Uuid::new_v4();
+  params.validate()?;
+  let developer_uuid = Uuid::now_v7();
   let api_key = generate_key();
   let new_developer = NewDeveloper {
     developer_uuid,
@@ -55,11 +261,793 @@ pub async fn create_developer(
     url: params.url,
     is_admin: false,
     api_key: Some(api_key),
+    password_hash: None,
+    is_super_admin: false,
+    default_security_level: params.default_security_level,
   };
   diesel::insert_into(schema::developers::table)
     .values(&new_developer)
     .execute(&mut db)
     .await
     .map_err(ApiError::from_on_create)?;
-  Ok(ApiSuccessResponse::new(new_developer.into()))
+  record_audit_event(*admin_user.user_uuid(), AuditAction::CreateDeveloper, Some(developer_uuid), None, &mut db).await?;
+  Ok(ApiSuccessResponse::created(new_developer.into()))
+}
+
+/// Gets a developer together with all of their games and each game's
+/// highscore tables.
+///
+/// This endpoint is only available to administrators. No API keys or
+/// secret keys are included. Soft-deleted tables are included, with
+/// their `deleted_at` set.
+#[utoipa::path(
+  get,
+  path="/api/developer/{uuid}/tree",
+  tag="developer",
+  params(
+    ("uuid" = OpenApiUuid, Path, description = "Developer UUID"),
+  ),
+  responses(
+    (status = 200, description = "Developer, games, and tables", body = ApiSuccessResponseBody<DeveloperTreeResponse>),
+    (status = 404, description = "Developer not found"),
+  )
+)]
+#[get("/developer/<uuid>/tree")]
+pub async fn get_developer_tree(
+  _admin_user: AdminUser,
+  uuid: Result<UuidParam, InvalidUuid>,
+  mut db: Connection<Db>,
+) -> Result<ApiSuccessResponse<DeveloperTreeResponse>, ApiError> {
+  let uuid = uuid?;
+  let developer = schema::developers::table
+    .filter(schema::developers::developer_uuid.eq(&*uuid))
+    .select(models::Developer::as_select())
+    .first::<models::Developer>(&mut db)
+    .await?;
+  let games = models::Game::belonging_to(&developer)
+    .order_by(schema::games::id.asc())
+    .select(models::Game::as_select())
+    .load::<models::Game>(&mut db)
+    .await?;
+  let game_ids = games.iter().map(|game| game.id).collect::<Vec<_>>();
+  let highscore_tables = schema::highscore_tables::table
+    .filter(schema::highscore_tables::game_id.eq_any(&game_ids))
+    .order_by(schema::highscore_tables::id.asc())
+    .select(models::HighscoreTable::as_select())
+    .load::<models::HighscoreTable>(&mut db)
+    .await?;
+  let developer_uuid = developer.developer_uuid;
+  let games = highscore_tables
+    .grouped_by(&games)
+    .into_iter()
+    .zip(games)
+    .map(|(tables, game)| {
+      let game_uuid = game.game_uuid;
+      GameTreeResponse {
+        game: GameResponse::from_game(game, developer_uuid),
+        tables: tables.into_iter().map(|table| HighscoreTableResponse::from_table(table, game_uuid)).collect(),
+      }
+    })
+    .collect();
+  let developer = DeveloperResponse::from(developer).without_api_key();
+  Ok(ApiSuccessResponse::new(DeveloperTreeResponse { developer, games }))
+}
+
+/// Enables or disables score submissions for a game.
+///
+/// This endpoint is only available to administrators. A disabled game
+/// rejects new scores with a 403 but can still be read from, so it
+/// can be sunset without deleting its tables.
+#[utoipa::path(
+  post,
+  path="/api/admin/game/{uuid}/enabled",
+  tag="game",
+  params(
+    ("uuid" = OpenApiUuid, Path, description = "Game UUID"),
+  ),
+  responses(
+    (status = 200, description = "Game updated successfully", body = ApiSuccessResponseBody<GameResponse>),
+    (status = 404, description = "Game not found"),
+  )
+)]
+#[post("/admin/game/<uuid>/enabled", data = "<params>")]
+pub async fn set_game_enabled(
+  admin_user: AdminUser,
+  uuid: Result<UuidParam, InvalidUuid>,
+  params: Json<SetGameEnabledParams>,
+  mut db: Connection<Db>,
+) -> Result<ApiSuccessResponse<GameResponse>, ApiError> {
+  let uuid = uuid?;
+  let game = diesel::update(schema::games::table)
+    .filter(schema::games::game_uuid.eq(&*uuid))
+    .set(schema::games::enabled.eq(params.enabled))
+    .returning(models::Game::as_returning())
+    .get_result::<models::Game>(&mut db)
+    .await?;
+  let details = format!("enabled = {}", params.enabled);
+  record_audit_event(*admin_user.user_uuid(), AuditAction::SetGameEnabled, Some(game.game_uuid), Some(details), &mut db).await?;
+  Ok(ApiSuccessResponse::new(game_response(game, &mut db).await?))
+}
+
+/// Sets the maximum number of scores a game may submit per UTC day.
+///
+/// This endpoint is only available to administrators. Once a game
+/// has submitted its quota for the day, further submissions are
+/// rejected with a 429 until UTC midnight.
+#[utoipa::path(
+  post,
+  path="/api/admin/game/{uuid}/quota",
+  tag="game",
+  params(
+    ("uuid" = OpenApiUuid, Path, description = "Game UUID"),
+  ),
+  responses(
+    (status = 200, description = "Game updated successfully", body = ApiSuccessResponseBody<GameResponse>),
+    (status = 404, description = "Game not found"),
+    (status = 422, description = "One or more fields are invalid"),
+  )
+)]
+#[post("/admin/game/<uuid>/quota", data = "<params>")]
+pub async fn set_game_quota(
+  admin_user: AdminUser,
+  uuid: Result<UuidParam, InvalidUuid>,
+  params: Json<SetGameQuotaParams>,
+  mut db: Connection<Db>,
+) -> Result<ApiSuccessResponse<GameResponse>, ApiError> {
+  let uuid = uuid?;
+  params.validate()?;
+  let game = diesel::update(schema::games::table)
+    .filter(schema::games::game_uuid.eq(&*uuid))
+    .set(schema::games::daily_submission_quota.eq(params.daily_submission_quota))
+    .returning(models::Game::as_returning())
+    .get_result::<models::Game>(&mut db)
+    .await?;
+  let details = format!("daily_submission_quota = {:?}", params.daily_submission_quota);
+  record_audit_event(*admin_user.user_uuid(), AuditAction::SetGameQuota, Some(game.game_uuid), Some(details), &mut db).await?;
+  Ok(ApiSuccessResponse::new(game_response(game, &mut db).await?))
+}
+
+/// Sets the maximum number of highscore tables a game may have,
+/// overriding the server's default.
+///
+/// This endpoint is only available to administrators. Existing tables
+/// are kept even if the game has more than the new maximum, but no
+/// more may be created by non-admins.
+#[utoipa::path(
+  post,
+  path="/api/admin/game/{uuid}/max-tables",
+  tag="game",
+  params(
+    ("uuid" = OpenApiUuid, Path, description = "Game UUID"),
+  ),
+  responses(
+    (status = 200, description = "Game updated successfully", body = ApiSuccessResponseBody<GameResponse>),
+    (status = 404, description = "Game not found"),
+    (status = 422, description = "One or more fields are invalid"),
+  )
+)]
+#[post("/admin/game/<uuid>/max-tables", data = "<params>")]
+pub async fn set_game_max_tables(
+  admin_user: AdminUser,
+  uuid: Result<UuidParam, InvalidUuid>,
+  params: Json<SetGameMaxTablesParams>,
+  mut db: Connection<Db>,
+) -> Result<ApiSuccessResponse<GameResponse>, ApiError> {
+  let uuid = uuid?;
+  params.validate()?;
+  let game = diesel::update(schema::games::table)
+    .filter(schema::games::game_uuid.eq(&*uuid))
+    .set(schema::games::max_tables.eq(params.max_tables))
+    .returning(models::Game::as_returning())
+    .get_result::<models::Game>(&mut db)
+    .await?;
+  let details = format!("max_tables = {:?}", params.max_tables);
+  record_audit_event(*admin_user.user_uuid(), AuditAction::SetGameMaxTables, Some(game.game_uuid), Some(details), &mut db).await?;
+  Ok(ApiSuccessResponse::new(game_response(game, &mut db).await?))
+}
+
+/// Enforces `maximum_scores_retained` on every highscore table.
+///
+/// This endpoint is only available to administrators. Each table is
+/// trimmed in its own transaction, so a failure on one table is
+/// reported in the response and does not prevent the others from
+/// being trimmed.
+#[utoipa::path(
+  post,
+  path="/api/admin/retrim",
+  tag="highscore-table",
+  responses(
+    (status = 200, description = "Summary of scores removed from each table", body = ApiSuccessResponseBody<RetrimResponse>),
+  )
+)]
+#[post("/admin/retrim")]
+pub async fn retrim_highscore_tables(
+  admin_user: AdminUser,
+  mut db: Connection<Db>,
+) -> Result<ApiSuccessResponse<RetrimResponse>, ApiError> {
+  let highscore_tables = schema::highscore_tables::table
+    .filter(schema::highscore_tables::maximum_scores_retained.is_not_null())
+    .select((
+      schema::highscore_tables::id,
+      schema::highscore_tables::table_uuid,
+      schema::highscore_tables::maximum_scores_retained,
+    ))
+    .load::<(i32, Uuid, Option<i32>)>(&mut db)
+    .await?;
+  let mut tables = Vec::with_capacity(highscore_tables.len());
+  for (table_id, table_uuid, maximum_scores_retained) in highscore_tables {
+    let result = db.transaction::<usize, diesel::result::Error, _>(|db| async move {
+      remove_extra_highscore_rows(table_id, maximum_scores_retained, db).await
+    }.scope_boxed()).await;
+    let result = match result {
+      Ok(rows_removed) => RetrimTableResult { table_uuid, rows_removed: Some(rows_removed), error: None },
+      Err(err) => {
+        error!("Failed to retrim highscore table {}: {}", table_uuid, err);
+        RetrimTableResult { table_uuid, rows_removed: None, error: Some(err.to_string()) }
+      }
+    };
+    tables.push(result);
+  }
+  let rows_removed = tables.iter().filter_map(|table| table.rows_removed).sum::<usize>();
+  let details = format!("{} scores removed from {} tables", rows_removed, tables.len());
+  record_audit_event(*admin_user.user_uuid(), AuditAction::Retrim, None, Some(details), &mut db).await?;
+  Ok(ApiSuccessResponse::new(RetrimResponse { tables }))
+}
+
+/// Adds scores to a highscore table with their original submission
+/// times, such as when replaying a journal of past submissions.
+///
+/// This endpoint is only available to super administrators. Unlike
+/// the game API, the request need not be signed, and each score keeps the
+/// given `creation_timestamp` rather than the server's clock. The
+/// table's `unique_entries` and `maximum_scores_retained` settings
+/// are applied afterward, as for any other submission.
+#[utoipa::path(
+  post,
+  path="/api/admin/highscore-table/{uuid}/scores",
+  tag="highscore-table",
+  params(
+    ("uuid" = OpenApiUuid, Path, description = "Highscore table UUID"),
+  ),
+  responses(
+    (status = 200, description = "Scores imported successfully", body = ApiSuccessResponseBody<ImportScoresResponse>),
+    (status = 403, description = "The user is not a super administrator"),
+    (status = 404, description = "Highscore table not found"),
+    (status = 422, description = "One or more fields are invalid"),
+  )
+)]
+#[post("/admin/highscore-table/<uuid>/scores", data = "<params>")]
+pub async fn import_highscore_table_scores(
+  admin_user: SuperAdminUser,
+  uuid: Result<UuidParam, InvalidUuid>,
+  params: Json<ImportScoresParams>,
+  mut db: Connection<Db>,
+) -> Result<ApiSuccessResponse<ImportScoresResponse>, ApiError> {
+  let uuid = uuid?;
+  let Json(params) = params;
+  params.validate()?;
+  let (highscore_table, _developer_uuid) = find_highscore_table_with_owner(&uuid, &mut db)
+    .await?
+    .ok_or_else(ApiError::not_found)?;
+  let highscore_table_id = highscore_table.id;
+  let player_names = params.entries.iter()
+    .map(|entry| entry.player_name.clone())
+    .collect::<BTreeSet<_>>();
+  let new_entries = params.entries.into_iter()
+    .map(|entry| models::NewHighscoreTableEntry {
+      highscore_table_id,
+      player_name: entry.player_name,
+      player_score: entry.player_score,
+      player_score_metadata: entry.player_score_metadata,
+      creation_timestamp: Some(entry.creation_timestamp.naive_utc()),
+    })
+    .collect::<Vec<_>>();
+
+  let response = db.transaction::<ImportScoresResponse, diesel::result::Error, _>(|db| async move {
+    let rows_inserted = diesel::insert_into(schema::highscore_table_entries::table)
+      .values(&new_entries)
+      .execute(db)
+      .await?;
+    adjust_entry_count(highscore_table_id, rows_inserted as i64, db).await?;
+    let mut rows_removed = 0;
+    if highscore_table.unique_entries {
+      for player_name in &player_names {
+        rows_removed += remove_duplicate_player_rows(highscore_table_id, player_name, db).await?;
+      }
+    }
+    rows_removed += remove_extra_highscore_rows(highscore_table_id, highscore_table.maximum_scores_retained, db).await?;
+    Ok(ImportScoresResponse { rows_inserted, rows_removed })
+  }.scope_boxed()).await?;
+  info!("Imported {} scores into highscore table {}", response.rows_inserted, *uuid);
+  let details = format!("{} scores inserted, {} removed", response.rows_inserted, response.rows_removed);
+  record_audit_event(*admin_user.user_uuid(), AuditAction::ImportScores, Some(*uuid), Some(details), &mut db).await?;
+  Ok(ApiSuccessResponse::new(response))
+}
+
+/// Recomputes the stored entry count of every highscore table.
+///
+/// This endpoint is only available to administrators. Entry counts
+/// are kept up to date as scores are added and removed, so this is
+/// only needed if entries were modified outside of the server.
+#[utoipa::path(
+  post,
+  path="/api/admin/recount",
+  tag="highscore-table",
+  responses(
+    (status = 200, description = "Number of tables corrected", body = ApiSuccessResponseBody<RecountResponse>),
+  )
+)]
+#[post("/admin/recount")]
+pub async fn recount_highscore_tables(
+  admin_user: AdminUser,
+  mut db: Connection<Db>,
+) -> Result<ApiSuccessResponse<RecountResponse>, ApiError> {
+  let tables_corrected = recount_entries(&mut db).await?;
+  if tables_corrected > 0 {
+    info!("Corrected entry counts on {} highscore tables", tables_corrected);
+  }
+  let details = format!("{} tables corrected", tables_corrected);
+  record_audit_event(*admin_user.user_uuid(), AuditAction::Recount, None, Some(details), &mut db).await?;
+  Ok(ApiSuccessResponse::new(RecountResponse { tables_corrected }))
+}
+
+/// Issues a token for acting as another developer.
+///
+/// This endpoint is only available to super administrators. The
+/// returned token is valid for fifteen minutes, carries only the target
+/// developer's permissions (never admin permissions), and is marked
+/// with an `impersonatedBy` claim naming the administrator.
+#[utoipa::path(
+  post,
+  path="/api/developer/{uuid}/impersonate",
+  tag="authorization",
+  params(
+    ("uuid" = OpenApiUuid, Path, description = "Developer UUID"),
+  ),
+  responses(
+    (status = 200, description = "A JWT token for the target developer", body = ApiSuccessResponseBody<AuthResponse>),
+    (status = 403, description = "The user is not a super administrator"),
+    (status = 404, description = "Developer not found"),
+  )
+)]
+#[post("/developer/<uuid>/impersonate")]
+pub async fn impersonate_developer(
+  admin_user: SuperAdminUser,
+  uuid: Result<UuidParam, InvalidUuid>,
+  mut db: Connection<Db>,
+) -> Result<ApiSuccessResponse<AuthResponse>, ApiError> {
+  let uuid = uuid?;
+  let developer_uuid = schema::developers::table
+    .filter(schema::developers::developer_uuid.eq(&*uuid))
+    .select(schema::developers::developer_uuid)
+    .first::<Uuid>(&mut db)
+    .await?;
+  let token = create_impersonation_token(&developer_uuid, admin_user.user_uuid())
+    .map_err(|err| ApiError::internal_server_error(err.to_string()))?;
+  info!("Admin {} began impersonating developer {}", admin_user.user_uuid(), developer_uuid);
+  record_audit_event(*admin_user.user_uuid(), AuditAction::ImpersonateDeveloper, Some(developer_uuid), None, &mut db).await?;
+  Ok(ApiSuccessResponse::new(AuthResponse { token }))
+}
+
+/// Sets the security level given to a developer's new games which do
+/// not specify one.
+///
+/// This endpoint is only available to administrators. Existing games
+/// are unaffected, and a game may still be created with any explicit
+/// security level.
+#[utoipa::path(
+  post,
+  path="/api/admin/developer/{uuid}/default-security-level",
+  tag="developer",
+  params(
+    ("uuid" = OpenApiUuid, Path, description = "Developer UUID"),
+  ),
+  responses(
+    (status = 200, description = "Developer updated successfully", body = ApiSuccessResponseBody<DeveloperResponse>),
+    (status = 404, description = "Developer not found"),
+  )
+)]
+#[post("/admin/developer/<uuid>/default-security-level", data = "<params>")]
+pub async fn set_default_security_level(
+  admin_user: AdminUser,
+  uuid: Result<UuidParam, InvalidUuid>,
+  params: Json<SetDefaultSecurityLevelParams>,
+  mut db: Connection<Db>,
+) -> Result<ApiSuccessResponse<DeveloperResponse>, ApiError> {
+  let uuid = uuid?;
+  let developer = diesel::update(schema::developers::table)
+    .filter(schema::developers::developer_uuid.eq(&*uuid))
+    .set(schema::developers::default_security_level.eq(params.default_security_level))
+    .returning(models::Developer::as_returning())
+    .get_result::<models::Developer>(&mut db)
+    .await?;
+  let details = format!("default_security_level = {:?}", params.default_security_level);
+  record_audit_event(*admin_user.user_uuid(), AuditAction::SetDefaultSecurityLevel, Some(developer.developer_uuid), Some(details), &mut db).await?;
+  Ok(ApiSuccessResponse::new(DeveloperResponse::from(developer).without_api_key()))
+}
+
+/// Revokes every token previously issued for a developer.
+///
+/// This endpoint is only available to administrators. Tokens issued
+/// for the developer before now, including impersonation tokens, are
+/// rejected from then on. The developer may still obtain a new token
+/// with their API key or password.
+#[utoipa::path(
+  post,
+  path="/api/developer/{uuid}/revoke-tokens",
+  tag="authorization",
+  params(
+    ("uuid" = OpenApiUuid, Path, description = "Developer UUID"),
+  ),
+  responses(
+    (status = 200, description = "The developer's tokens were revoked", body = ApiSuccessResponseBody<RevokeTokensResponse>),
+    (status = 404, description = "Developer not found"),
+  )
+)]
+#[post("/developer/<uuid>/revoke-tokens")]
+pub async fn revoke_developer_tokens(
+  admin_user: AdminUser,
+  uuid: Result<UuidParam, InvalidUuid>,
+  mut db: Connection<Db>,
+) -> Result<ApiSuccessResponse<RevokeTokensResponse>, ApiError> {
+  let uuid = uuid?;
+  let now = Utc::now();
+  let developer_uuid = diesel::update(schema::developers::table)
+    .filter(schema::developers::developer_uuid.eq(&*uuid))
+    .set(schema::developers::tokens_valid_after.eq(now.naive_utc()))
+    .returning(schema::developers::developer_uuid)
+    .get_result::<Uuid>(&mut db)
+    .await?;
+  info!("Admin {} revoked all tokens for developer {}", admin_user.user_uuid(), developer_uuid);
+  record_audit_event(*admin_user.user_uuid(), AuditAction::RevokeTokens, Some(developer_uuid), None, &mut db).await?;
+  Ok(ApiSuccessResponse::new(RevokeTokensResponse { tokens_valid_after: now }))
+}
+
+/// Reports how many game requests have passed or failed
+/// verification, by failure reason, since the server started.
+///
+/// This endpoint is only available to administrators. A spike in a
+/// particular failure reason may indicate a misconfigured or
+/// malicious client.
+#[utoipa::path(
+  get,
+  path="/api/admin/metrics/verification",
+  tag="server",
+  responses(
+    (status = 200, description = "Verification outcome counts", body = ApiSuccessResponseBody<VerificationMetricsSnapshot>),
+  )
+)]
+#[get("/admin/metrics/verification")]
+pub async fn get_verification_metrics(
+  _admin_user: AdminUser,
+  metrics: &State<VerificationMetrics>,
+) -> ApiSuccessResponse<VerificationMetricsSnapshot> {
+  ApiSuccessResponse::new(metrics.snapshot())
+}
+
+/// Checks a signed game request body against an explicitly supplied
+/// secret key and algorithm.
+///
+/// This endpoint is only available to administrators, and is meant
+/// for client authors debugging their signing code against the
+/// server's own hashing. No game is looked up, and the request's
+/// fields other than its signature are not checked.
+#[utoipa::path(
+  post,
+  path="/api/admin/verify-signature",
+  tag="server",
+  request_body = VerifySignatureParams,
+  responses(
+    (status = 200, description = "Whether the signature matches", body = ApiSuccessResponseBody<VerifySignatureResponse>),
+    (status = 400, description = "The payload is not of the form `payload.signature`"),
+  )
+)]
+#[post("/admin/verify-signature", data = "<params>")]
+pub async fn verify_signature(
+  _admin_user: AdminUser,
+  params: Json<VerifySignatureParams>,
+) -> Result<ApiSuccessResponse<VerifySignatureResponse>, ApiError> {
+  let Json(params) = params;
+  Ok(ApiSuccessResponse::new(params.check()?))
+}
+
+impl VerifySignatureParams {
+  /// Checks the payload's signature against the supplied secret key.
+  /// Only a payload which is not of the form `payload.signature` is
+  /// an error; a signature which does not match is reported in the
+  /// response.
+  fn check(&self) -> Result<VerifySignatureResponse, ApiError> {
+    let payload = self.payload.parse::<GameRequestPayload>()?;
+    let hasher = self.algo.into_hasher();
+    let reason = payload.verify(&self.secret_key, &*hasher).err().map(|err| err.to_string());
+    Ok(VerifySignatureResponse {
+      matches: reason.is_none(),
+      reason,
+      expected_signature_bytes: hasher.output_len(),
+    })
+  }
+}
+
+/// Lists recorded administrative actions, newest first.
+///
+/// This endpoint is only available to administrators. Entries may be
+/// filtered by the administrator who performed them, by action, and
+/// by time, and are paginated with `limit` (at most 1000, default
+/// 100) and `offset`.
+#[utoipa::path(
+  get,
+  path="/api/admin/audit",
+  tag="server",
+  params(
+    ("actor" = Option<OpenApiUuid>, Query, description = "Only include actions by this administrator"),
+    ("action" = Option<String>, Query, description = "Only include this action, such as `set_game_quota`"),
+    ("since" = Option<String>, Query, description = "Only include actions at or after this time"),
+    ("until" = Option<String>, Query, description = "Only include actions at or before this time"),
+    ("limit" = Option<u32>, Query, description = "Maximum number of entries to return"),
+    ("offset" = Option<u32>, Query, description = "Number of entries to skip"),
+  ),
+  responses(
+    (status = 200, description = "Matching audit log entries", body = ApiSuccessResponseBody<AuditLogResponse>),
+    (status = 422, description = "One or more query parameters are invalid"),
+  )
+)]
+#[get("/admin/audit?<params..>")]
+pub async fn get_audit_log(
+  _admin_user: AdminUser,
+  params: AuditLogParams,
+  mut db: Connection<Db>,
+) -> Result<Paginated<ApiSuccessResponse<AuditLogResponse>>, ApiError> {
+  params.validate()?;
+  let limit = params.limit();
+  let total_count = params.filtered_query()
+    .count()
+    .get_result::<i64>(&mut db)
+    .await?;
+  let entries = params.filtered_query()
+    .order_by((schema::audit_log::timestamp.desc(), schema::audit_log::id.desc()))
+    .limit(i64::from(limit))
+    .offset(i64::from(params.offset.unwrap_or(0)))
+    .select(models::AuditLogEntry::as_select())
+    .load::<models::AuditLogEntry>(&mut db)
+    .await?;
+  let entries = entries.into_iter().map(AuditLogEntryResponse::from).collect();
+  Ok(Paginated::new(ApiSuccessResponse::new(AuditLogResponse { entries }), Some(limit), params.offset, total_count))
+}
+
+/// Lists webhook deliveries which failed, newest first.
+///
+/// This endpoint is only available to administrators. A delivery is
+/// listed here once it has been retried as many times as it will be,
+/// or if the server had too many deliveries waiting to send it at
+/// all. The results are paginated with `limit` and `offset`.
+#[utoipa::path(
+  get,
+  path="/api/admin/webhooks/failed",
+  tag="server",
+  params(
+    ("limit" = Option<u32>, Query, description = "Maximum number of deliveries to return"),
+    ("offset" = Option<u32>, Query, description = "Number of deliveries to skip"),
+  ),
+  responses(
+    (status = 200, description = "Failed webhook deliveries", body = ApiSuccessResponseBody<FailedWebhooksResponse>),
+  )
+)]
+#[get("/admin/webhooks/failed")]
+pub async fn get_failed_webhooks(
+  _admin_user: AdminUser,
+  pagination: Pagination,
+  mut db: Connection<Db>,
+) -> Result<Paginated<ApiSuccessResponse<FailedWebhooksResponse>>, ApiError> {
+  let total_count = schema::failed_webhooks::table
+    .count()
+    .get_result::<i64>(&mut db)
+    .await?;
+  let webhooks = schema::failed_webhooks::table
+    .order_by((schema::failed_webhooks::failed_at.desc(), schema::failed_webhooks::id.desc()))
+    .limit(i64::from(pagination.limit))
+    .offset(i64::from(pagination.offset))
+    .select(models::FailedWebhook::as_select())
+    .load::<models::FailedWebhook>(&mut db)
+    .await?;
+  let webhooks = webhooks.into_iter().map(FailedWebhookResponse::from).collect();
+  Ok(Paginated::from_pagination(ApiSuccessResponse::new(FailedWebhooksResponse { webhooks }), pagination, total_count))
+}
+
+/// Sends a failed webhook delivery again.
+///
+/// This endpoint is only available to administrators. The delivery
+/// is removed from the failed list and queued with its original URL,
+/// body, and delivery UUID. It is retried as a new delivery would be,
+/// and listed again if it still fails.
+#[utoipa::path(
+  post,
+  path="/api/admin/webhooks/failed/{uuid}/replay",
+  tag="server",
+  params(
+    ("uuid" = OpenApiUuid, Path, description = "Delivery UUID"),
+  ),
+  responses(
+    (status = 200, description = "The delivery was queued", body = ApiSuccessResponseBody<FailedWebhookResponse>),
+    (status = 404, description = "No failed delivery with that UUID"),
+    (status = 503, description = "Too many deliveries are already waiting; try again later"),
+  )
+)]
+#[post("/admin/webhooks/failed/<uuid>/replay")]
+pub async fn replay_failed_webhook(
+  admin_user: AdminUser,
+  uuid: Result<UuidParam, InvalidUuid>,
+  webhooks: &State<WebhookSender>,
+  mut db: Connection<Db>,
+) -> Result<ApiSuccessResponse<FailedWebhookResponse>, ApiError> {
+  let uuid = uuid?;
+  let admin_uuid = *admin_user.user_uuid();
+  let failed = db.transaction::<_, ApiError, _>(|db| async move {
+    let failed = diesel::delete(schema::failed_webhooks::table)
+      .filter(schema::failed_webhooks::delivery_uuid.eq(&*uuid))
+      .returning(models::FailedWebhook::as_returning())
+      .get_result::<models::FailedWebhook>(db)
+      .await
+      .optional()?
+      .ok_or_else(ApiError::not_found)?;
+    record_audit_event(admin_uuid, AuditAction::ReplayWebhook, Some(failed.delivery_uuid), Some(failed.url.clone()), db).await?;
+    // Queued last, so that the row is only removed if the delivery
+    // will be attempted.
+    webhooks.enqueue(WebhookDelivery::from(failed.clone())).map_err(|err| match err {
+      TrySendError::Full(_) => ApiError::from_status(Status::ServiceUnavailable).with_message("Too many webhooks are waiting to be sent; try again later"),
+      TrySendError::Closed(_) => ApiError::internal_server_error("Webhook sender has stopped"),
+    })?;
+    Ok(failed)
+  }.scope_boxed()).await?;
+  info!("Admin {} replayed webhook {}", admin_uuid, failed.delivery_uuid);
+  Ok(ApiSuccessResponse::new(FailedWebhookResponse::from(failed)))
+}
+
+/// Compares the rankings of a highscore table's players at two
+/// points in time.
+///
+/// This endpoint is only available to administrators. A player's
+/// rank at a given time is that of their best score among the scores
+/// submitted before that time. Scores since removed from the table
+/// (by `unique_entries`, `maximum_scores_retained`, or deletion) are
+/// not counted at either time.
+#[utoipa::path(
+  get,
+  path="/api/admin/highscore-table/{uuid}/rank-changes",
+  tag="highscore-table",
+  params(
+    ("uuid" = OpenApiUuid, Path, description = "Highscore table UUID"),
+    ("from" = String, Query, description = "The earlier point in time"),
+    ("to" = String, Query, description = "The later point in time"),
+  ),
+  responses(
+    (status = 200, description = "Each player's change in rank", body = ApiSuccessResponseBody<RankChangesResponse>),
+    (status = 404, description = "Highscore table not found"),
+    (status = 422, description = "`from` is not before `to`"),
+  )
+)]
+#[get("/admin/highscore-table/<uuid>/rank-changes?<from>&<to>")]
+pub async fn get_highscore_table_rank_changes(
+  _admin_user: AdminUser,
+  uuid: Result<UuidParam, InvalidUuid>,
+  from: QueryTimestamp,
+  to: QueryTimestamp,
+  mut db: Connection<Db>,
+) -> Result<ApiSuccessResponse<RankChangesResponse>, ApiError> {
+  let uuid = uuid?;
+  let (QueryTimestamp(from), QueryTimestamp(to)) = (from, to);
+  if from >= to {
+    let mut errors = FieldErrors::new();
+    errors.add("from", "from must be before to");
+    errors.into_result()?;
+  }
+  let (highscore_table, _developer_uuid) = find_highscore_table_with_owner(&uuid, &mut db)
+    .await?
+    .ok_or_else(ApiError::not_found)?;
+  let from_ranks = player_ranks_at(highscore_table.id, from, &mut db).await?;
+  let to_ranks = player_ranks_at(highscore_table.id, to, &mut db).await?;
+
+  let mut from_ranks = from_ranks.into_iter()
+    .map(|player| (player.player_name, player.rank))
+    .collect::<HashMap<_, _>>();
+  let mut players = to_ranks.into_iter()
+    .map(|player| {
+      let from_rank = from_ranks.remove(&player.player_name);
+      let (change, status) = match from_rank {
+        None => (None, RankChangeStatus::New),
+        Some(from_rank) if from_rank == player.rank => (Some(0), RankChangeStatus::Unchanged),
+        Some(from_rank) => (Some(from_rank - player.rank), RankChangeStatus::Moved),
+      };
+      PlayerRankChange { player_name: player.player_name, from_rank, to_rank: Some(player.rank), change, status }
+    })
+    .collect::<Vec<_>>();
+  let mut dropped = from_ranks.into_iter()
+    .map(|(player_name, from_rank)| {
+      PlayerRankChange { player_name, from_rank: Some(from_rank), to_rank: None, change: None, status: RankChangeStatus::Dropped }
+    })
+    .collect::<Vec<_>>();
+  dropped.sort_by_key(|player| player.from_rank);
+  players.extend(dropped);
+  Ok(ApiSuccessResponse::new(RankChangesResponse { from: from.and_utc(), to: to.and_utc(), players }))
+}
+
+/// Each player's rank on the table by their best score among those
+/// submitted strictly before `time`, highest rank first.
+async fn player_ranks_at(
+  highscore_table_id: i32,
+  time: NaiveDateTime,
+  db: &mut AsyncPgConnection,
+) -> diesel::QueryResult<Vec<PlayerRank>> {
+  // Diesel's query builder has no support for window functions.
+  diesel::sql_query(
+    "SELECT player_name, RANK() OVER (ORDER BY best_score DESC) AS rank
+      FROM (
+        SELECT player_name, MAX(player_score) AS best_score
+        FROM highscore_table_entries
+        WHERE highscore_table_id = $1 AND creation_timestamp < $2
+        GROUP BY player_name
+      ) AS best_scores
+      ORDER BY rank ASC, player_name ASC",
+  )
+    .bind::<diesel::sql_types::Int4, _>(highscore_table_id)
+    .bind::<diesel::sql_types::Timestamptz, _>(time)
+    .load(db)
+    .await
+}
+
+async fn game_response(game: models::Game, db: &mut AsyncPgConnection) -> Result<GameResponse, ApiError> {
+  let developer_uuid = schema::developers::table
+    .filter(schema::developers::id.eq(game.developer_id))
+    .select(schema::developers::developer_uuid)
+    .first::<Uuid>(db)
+    .await?;
+  Ok(GameResponse::from_game(game, developer_uuid))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::server::requests::{RequestSigningHasher, Sha256Hasher};
+
+  use base64::engine::general_purpose::URL_SAFE;
+  use base64::Engine;
+  use rocket::http::Status;
+
+  const SECRET_KEY: &str = "test-secret-key";
+
+  fn params(secret_key: &str) -> VerifySignatureParams {
+    let payload_base64 = URL_SAFE.encode(r#"{"player_score":12.5}"#);
+    let signature = Sha256Hasher.apply_hash(&format!("{}.{}", payload_base64, SECRET_KEY));
+    VerifySignatureParams {
+      payload: format!("{}.{}", payload_base64, URL_SAFE.encode(signature)),
+      secret_key: secret_key.to_owned(),
+      algo: RequestAlgorithm::Sha256,
+    }
+  }
+
+  #[test]
+  fn test_verify_signature_with_matching_secret() {
+    let response = params(SECRET_KEY).check().unwrap();
+    assert!(response.matches);
+    assert_eq!(response.reason, None);
+    assert_eq!(response.expected_signature_bytes, 32);
+  }
+
+  #[test]
+  fn test_verify_signature_with_other_secret() {
+    let response = params("other-secret-key").check().unwrap();
+    assert!(!response.matches);
+    assert_eq!(response.reason.as_deref(), Some("Invalid request signature"));
+
+    let sha1 = VerifySignatureParams { algo: RequestAlgorithm::Sha1, ..params(SECRET_KEY) };
+    let response = sha1.check().unwrap();
+    assert!(!response.matches);
+    assert_eq!(response.reason.as_deref(), Some("Request signature should be 20 bytes, got 32"));
+    assert_eq!(response.expected_signature_bytes, 20);
+  }
+
+  #[test]
+  fn test_verify_signature_without_dot_is_bad_request() {
+    let undotted = VerifySignatureParams { payload: String::from("e30AAAA"), ..params(SECRET_KEY) };
+    assert_eq!(undotted.check().unwrap_err().status(), Status::BadRequest);
+  }
 }
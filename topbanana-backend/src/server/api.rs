@@ -5,81 +5,605 @@
 //! [`admin`](crate::server::admin).
 
 use super::error::{ApiError, ApiSuccessResponse, ApiSuccessResponseBody};
-use super::auth::{create_jwt_for_api_key, DeveloperUser, AuthError, XApiKey};
-use super::data_access::{DeveloperOwnedExt, DeveloperResponse, NewGameDao, GameResponse, NewHighscoreTableDao, HighscoreTableResponse};
+use super::auth::{check_api_key, create_jwt_for_api_key, create_jwt_for_password, jwt_cookie, DeveloperUser, AuthError, XApiKey};
+use super::data_access::{find_highscore_table_with_owner, DeveloperOwnedExt, DeveloperResponse, NewGameDao, GameResponse, NewHighscoreTableDao, HighscoreTableResponse};
 use super::openapi::OpenApiUuid;
+use super::ndjson::{AcceptsNdjson, NdjsonStream, to_ndjson_line};
+use super::pagination::{Paginated, Pagination};
+use super::highscore_tables::{adjust_entry_count, count_extra_highscore_rows};
+use super::validation::{FieldErrors, Validate, MAX_DESCRIPTION_LENGTH};
+use super::audit::{record_audit_event, AuditAction};
 use super::{admin, db};
+use crate::config::AppConfig;
 use crate::db::{schema, models};
-use crate::util::{ParamFromStr, generate_key};
+use crate::util::{InvalidUuid, UuidParam, DEFAULT_KEY_LENGTH, generate_key, generate_key_of_length};
 
-use rocket::{Route, routes, post, get};
+use rocket::{Either, FromForm, FromFormField, Route, State, routes, post, put, get, delete};
+use rocket::form::{self, ValueField};
+use rocket::futures::stream::{Stream, StreamExt};
+use rocket::response::stream::stream;
+use rocket::http::CookieJar;
 use rocket::serde::json::Json;
 use rocket_db_pools::Connection;
 use uuid::Uuid;
 use diesel::prelude::*;
-use diesel_async::{RunQueryDsl, AsyncPgConnection};
+use diesel::pg::Pg;
+use diesel::query_builder::{BoxedSqlQuery, SqlQuery};
+use diesel::result::{DatabaseErrorKind, Error as DieselError};
+use diesel_async::{RunQueryDsl, AsyncConnection, AsyncPgConnection};
+use scoped_futures::ScopedFutureExt;
 use utoipa::ToSchema;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use log::error;
+use chrono::{DateTime, NaiveDateTime, Utc};
+
+use std::cell::Cell;
+use std::fmt::{self, Display};
+use std::str::FromStr;
 
 pub const MAX_HIGHSCORES_RETAINED_FOR_NON_ADMIN: i32 = 100;
 
+/// Default number of scores per table returned by the top scores
+/// endpoint.
+pub const DEFAULT_TOP_SCORES_PER_TABLE: u32 = 3;
+
+/// Maximum number of scores per table which may be requested from the
+/// top scores endpoint.
+pub const MAX_TOP_SCORES_PER_TABLE: u32 = 25;
+
+/// Maximum number of tables included in a top scores response.
+pub const MAX_TABLES_PER_TOP_SCORES_REQUEST: i64 = 100;
+
+/// Default number of submissions returned by the recent submissions
+/// endpoint.
+pub const DEFAULT_RECENT_SUBMISSIONS: u32 = 20;
+
+/// Maximum number of submissions which may be requested from the
+/// recent submissions endpoint.
+pub const MAX_RECENT_SUBMISSIONS: u32 = 100;
+
 #[derive(Debug, Clone, Serialize, ToSchema)]
 pub struct AuthResponse {
   /// A fresh JWT token associated to the user.
   pub token: String,
 }
 
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+pub struct LoginParams {
+  /// The developer's email address.
+  pub email: String,
+  /// The developer's password.
+  pub password: String,
+}
+
 #[derive(Debug, Clone, Serialize, ToSchema)]
 pub struct ScoresResponse {
   /// All highscores in the table, sorted in descending order by score
   /// value. Tied scores are sorted by creation timestamp, with
-  /// earlier scores ranking higher.
-  pub scores: Vec<ScoresResponseEntry>,
+  /// earlier scores ranking higher. Each score carries its rank on
+  /// the whole table, regardless of the order, time window, or page
+  /// requested.
+  pub scores: Vec<ScoresEntryResponse>,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ScoresEntryResponse {
+  #[serde(flatten)]
+  pub entry: ScoresResponseEntry,
+  /// The entry's current position on the table, starting at 1. Tied
+  /// scores share a rank. Where an endpoint accepts a `ranking` query
+  /// parameter, it controls how ties affect lower ranks; otherwise,
+  /// standard competition ranking is used.
+  pub rank: i64,
+  /// When polling for new scores with `since`, an opaque position
+  /// which may be passed as the next request's `since` to resume
+  /// exactly after this score. Absent otherwise.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub cursor: Option<String>,
+}
+
+/// Query parameters accepted by the developer API's scores endpoint.
+#[derive(Debug, Clone, Default, FromForm)]
+pub struct ScoresParams {
+  pub raw: Option<bool>,
+  pub order_by: Option<ScoresOrderBy>,
+  pub direction: Option<SortDirection>,
+  pub from_time: Option<QueryTimestamp>,
+  pub to_time: Option<QueryTimestamp>,
+  pub include_metadata: Option<bool>,
+  pub include_deleted: Option<bool>,
+}
+
+/// A point in time given as a query parameter, either in RFC 3339
+/// format or as `YYYY-MM-DD HH:MM:SS` in UTC, the format in which
+/// timestamps are returned.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct QueryTimestamp(pub NaiveDateTime);
+
+/// The position of a score in submission order: its creation time,
+/// then its ID to break ties between scores submitted in the same
+/// instant. Returned timestamps are truncated to the second, so a
+/// client resuming a poll passes a cursor rather than a timestamp.
+///
+/// As a query parameter, a cursor is given in its opaque encoded form
+/// or as a plain timestamp, which is positioned after every score
+/// submitted at that time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ScoresCursor {
+  pub creation_timestamp: NaiveDateTime,
+  pub id: i32,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ApiKeyCheckResponse {
+  /// The developer to whom the API key belongs.
+  #[schema(value_type = OpenApiUuid)]
+  pub developer_uuid: Uuid,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct TopScoresResponse {
+  /// The requesting developer's tables, oldest first.
+  pub tables: Vec<TableTopScores>,
+  /// Whether the developer has more tables than could be included in
+  /// this response.
+  pub truncated: bool,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct TableTopScores {
+  #[schema(value_type = OpenApiUuid)]
+  pub game_uuid: Uuid,
+  #[schema(value_type = OpenApiUuid)]
+  pub table_uuid: Uuid,
+  pub name: String,
+  /// The table's highest scores, highest first.
+  pub scores: Vec<ScoresEntryResponse>,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct RecentSubmissionsResponse {
+  /// The most recent submissions to any of the developer's tables,
+  /// newest first.
+  pub submissions: Vec<RecentSubmission>,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct RecentSubmission {
+  #[schema(value_type = OpenApiUuid)]
+  pub game_uuid: Uuid,
+  #[schema(value_type = OpenApiUuid)]
+  pub table_uuid: Uuid,
+  #[serde(flatten)]
+  pub entry: ScoresResponseEntry,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct RotateGameSecretResponse {
+  /// The game's new secret key. This is only supplied upon rotation
+  /// and cannot be recovered after the fact.
+  pub game_secret_key: String,
+  /// Until when requests signed with the old secret key are still
+  /// accepted.
+  #[schema(value_type = String, example = "2025-02-04T05:33:10Z")]
+  pub previous_secret_valid_until: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct GameSecretKeyResponse {
+  /// Identifies this key in a game request's `key_id` field.
+  #[schema(value_type = OpenApiUuid)]
+  pub key_id: Uuid,
+  /// The secret key is only supplied upon creation and cannot be
+  /// recovered after the fact.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub secret_key: Option<String>,
+  /// Whether requests signed with this key are accepted.
+  pub active: bool,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct TrimPreviewResponse {
+  /// The number of scores which would be removed if the table
+  /// retained only the proposed number of scores.
+  pub rows_removed: i64,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct DeleteHighscoreTableResponse {
+  /// Whether the table was removed permanently rather than
+  /// soft-deleted.
+  pub hard: bool,
+  /// The number of scores deleted. Soft deletion keeps the table's
+  /// scores, so this is zero unless `hard` is true.
+  pub rows_removed: usize,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct PlayerRankHistoryResponse {
+  pub player_name: String,
+  /// One point per score the player submitted, oldest first. Empty if
+  /// the player has no scores on the table.
+  pub history: Vec<PlayerRankHistoryPoint>,
+}
+
+/// A player's standing on a table just after one of their
+/// submissions.
+#[derive(Debug, Clone, Serialize, ToSchema, QueryableByName)]
+pub struct PlayerRankHistoryPoint {
+  /// When the score was submitted, in UTC. The format is chosen by the
+  /// server's `TIMESTAMP_FORMAT` setting.
+  #[schema(value_type = String, example = "2025-02-01 05:33:10")]
+  #[serde(serialize_with = "serialize_datetime")]
+  #[diesel(sql_type = diesel::sql_types::Timestamptz)]
+  pub creation_timestamp: NaiveDateTime,
+  /// The score submitted at this time.
+  #[diesel(sql_type = diesel::sql_types::Float8)]
+  pub player_score: f64,
+  /// The player's best score as of this submission.
+  #[diesel(sql_type = diesel::sql_types::Float8)]
+  pub best_score: f64,
+  /// The rank of `best_score` among every player's best score as of
+  /// this submission.
+  #[diesel(sql_type = diesel::sql_types::Int8)]
+  pub rank: i64,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct DeletePlayerResponse {
+  /// The number of scores deleted.
+  pub rows_removed: usize,
+}
+
+/// Options controlling which scores [`get_scores_for_table`]
+/// returns.
+#[derive(Debug, Clone, Default)]
+pub struct ScoresQuery {
+  /// Maximum number of scores to return.
+  pub limit: Option<u32>,
+  /// Number of top scores to skip before returning results.
+  pub offset: Option<u32>,
+  /// Number of decimal places to round scores to. If `None`, scores
+  /// are returned at full precision.
+  pub score_precision: Option<i32>,
+  /// If true, scores are returned as integers.
+  pub score_is_integer: bool,
+  /// The key by which scores are ordered.
+  pub order_by: ScoresOrderBy,
+  /// The direction in which scores are ordered. If `None`, this is
+  /// the natural direction for `order_by`.
+  pub direction: Option<SortDirection>,
+  /// If set, only scores submitted at or after this time are
+  /// returned.
+  pub from_time: Option<NaiveDateTime>,
+  /// If set, only scores submitted strictly before this time are
+  /// returned.
+  pub to_time: Option<NaiveDateTime>,
+  /// If true, `player_score_metadata` is returned as `null` for every
+  /// score, for clients which have no use for it.
+  pub omit_metadata: bool,
+  /// If set, only scores submitted under this player name are
+  /// returned. Ranks are still among all of the table's scores.
+  pub player_name: Option<String>,
+  /// If set, only scores strictly after this position are returned,
+  /// and each returned score carries its own cursor. Unlike
+  /// `from_time`, a client polling for new scores can pass the cursor
+  /// of the newest score it has seen without seeing it again.
+  pub since: Option<ScoresCursor>,
+}
+
+/// Key by which a list of scores is ordered.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, FromFormField)]
+pub enum ScoresOrderBy {
+  /// By score value, highest first by default. Tied scores are
+  /// ordered by submission time, earliest first.
+  #[default]
+  Score,
+  /// Purely by submission time, newest first by default.
+  Time,
+}
+
+/// How tied scores affect the ranks of the scores below them.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, FromFormField)]
+pub enum Ranking {
+  /// Competition ranking ("1224"). Tied scores share a rank, and the
+  /// next distinct score's rank skips past all of them.
+  #[default]
+  Standard,
+  /// Dense ranking ("1223"). Tied scores share a rank, and the next
+  /// distinct score's rank is one more.
+  Dense,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, FromFormField)]
+pub enum SortDirection {
+  Asc,
+  Desc,
 }
 
 #[derive(Debug, Clone, Serialize, ToSchema)]
 pub struct ScoresResponseEntry {
   /// The name of the player who submitted the score.
   pub player_name: String,
-  /// The player's score, as a float.
-  pub player_score: f64,
+  /// The player's score, as a float, or as an integer if the table
+  /// has `score_is_integer` set.
+  #[schema(value_type = f64)]
+  pub player_score: ScoreValue,
   /// Optional metadata supplied with the player's submission. The
   /// meaning of this field is game-specific.
   pub player_score_metadata: Option<String>,
-  /// When the score was submitted.
+  /// When the score was submitted, in UTC. The format is chosen by the
+  /// server's `TIMESTAMP_FORMAT` setting.
   #[schema(value_type = String, example = "2025-02-01 05:33:10")]
   #[serde(serialize_with = "serialize_datetime")]
   pub creation_timestamp: chrono::NaiveDateTime,
 }
 
+/// A score as returned to clients. Scores are always stored as
+/// floats, but tables with `score_is_integer` return them as JSON
+/// integers.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(untagged)]
+pub enum ScoreValue {
+  Float(f64),
+  Integer(i64),
+}
+
+impl ScoresResponseEntry {
+  /// Rounds the displayed score to the given number of decimal
+  /// places, or leaves it unchanged if `precision` is `None`.
+  pub fn rounded(mut self, precision: Option<i32>) -> Self {
+    if let (Some(precision), ScoreValue::Float(score)) = (precision, self.player_score) {
+      let scale = 10f64.powi(precision);
+      self.player_score = ScoreValue::Float((score * scale).round() / scale);
+    }
+    self
+  }
+
+  /// Displays the score as an integer if `score_is_integer` is true.
+  pub fn integral(mut self, score_is_integer: bool) -> Self {
+    if let (true, ScoreValue::Float(score)) = (score_is_integer, self.player_score) {
+      self.player_score = ScoreValue::Integer(score.round() as i64);
+    }
+    self
+  }
+
+  pub fn without_metadata(mut self) -> Self {
+    self.player_score_metadata = None;
+    self
+  }
+}
+
+impl ScoresQuery {
+  /// Converts a stored entry to its response form, as requested by
+  /// these options.
+  fn response_entry(&self, ranked_entry: RankedTableEntry) -> ScoresEntryResponse {
+    let cursor = self.since.map(|_| ScoresCursor::of_entry(&ranked_entry.entry).encode());
+    let entry = ScoresResponseEntry::from(ranked_entry.entry)
+      .rounded(self.score_precision)
+      .integral(self.score_is_integer);
+    let entry = if self.omit_metadata { entry.without_metadata() } else { entry };
+    ScoresEntryResponse { entry, rank: ranked_entry.rank, cursor }
+  }
+
+  /// The `ORDER BY` clause for these options, in terms of the columns
+  /// of `highscore_table_entries`.
+  fn order_by_sql(&self) -> &'static str {
+    match (self.order_by, self.direction) {
+      (ScoresOrderBy::Score, None | Some(SortDirection::Desc)) => "player_score DESC, creation_timestamp ASC",
+      (ScoresOrderBy::Score, Some(SortDirection::Asc)) => "player_score ASC, creation_timestamp ASC",
+      (ScoresOrderBy::Time, None | Some(SortDirection::Desc)) => "creation_timestamp DESC, id DESC",
+      (ScoresOrderBy::Time, Some(SortDirection::Asc)) => "creation_timestamp ASC, id ASC",
+    }
+  }
+}
+
+impl QueryTimestamp {
+  fn parse(value: &str) -> Option<NaiveDateTime> {
+    if let Ok(datetime) = DateTime::parse_from_rfc3339(value) {
+      return Some(datetime.naive_utc());
+    }
+    NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S").ok()
+  }
+}
+
+#[rocket::async_trait]
+impl<'v> form::FromFormField<'v> for QueryTimestamp {
+  fn from_value(field: ValueField<'v>) -> form::Result<'v, Self> {
+    match QueryTimestamp::parse(field.value) {
+      Some(timestamp) => Ok(QueryTimestamp(timestamp)),
+      None => Err(form::Error::validation("expected an RFC 3339 timestamp or YYYY-MM-DD HH:MM:SS").into()),
+    }
+  }
+}
+
+impl ScoresCursor {
+  /// The position of the given stored entry.
+  pub fn of_entry(entry: &models::HighscoreTableEntry) -> Self {
+    ScoresCursor { creation_timestamp: entry.creation_timestamp, id: entry.id }
+  }
+
+  /// The position after every score submitted at or before
+  /// `timestamp`.
+  pub fn after_time(timestamp: NaiveDateTime) -> Self {
+    ScoresCursor { creation_timestamp: timestamp, id: i32::MAX }
+  }
+
+  /// Encodes the cursor for clients. Timestamps are stored to the
+  /// microsecond, so that is the precision kept here.
+  pub fn encode(&self) -> String {
+    URL_SAFE_NO_PAD.encode(format!("{}:{}", self.creation_timestamp.and_utc().timestamp_micros(), self.id))
+  }
+
+  pub fn decode(value: &str) -> Option<Self> {
+    let bytes = URL_SAFE_NO_PAD.decode(value).ok()?;
+    let (micros, id) = std::str::from_utf8(&bytes).ok()?.split_once(':')?;
+    let creation_timestamp = DateTime::from_timestamp_micros(micros.parse().ok()?)?.naive_utc();
+    Some(ScoresCursor { creation_timestamp, id: id.parse().ok()? })
+  }
+}
+
+#[rocket::async_trait]
+impl<'v> form::FromFormField<'v> for ScoresCursor {
+  fn from_value(field: ValueField<'v>) -> form::Result<'v, Self> {
+    // Note: Timestamps contain characters outside the base64url
+    // alphabet, so neither form can be mistaken for the other.
+    if let Some(cursor) = ScoresCursor::decode(field.value) {
+      return Ok(cursor);
+    }
+    match QueryTimestamp::parse(field.value) {
+      Some(timestamp) => Ok(ScoresCursor::after_time(timestamp)),
+      None => Err(form::Error::validation("expected a score cursor, an RFC 3339 timestamp or YYYY-MM-DD HH:MM:SS").into()),
+    }
+  }
+}
+
 impl From<models::HighscoreTableEntry> for ScoresResponseEntry {
   fn from(entry: models::HighscoreTableEntry) -> Self {
     Self {
       player_name: entry.player_name,
-      player_score: entry.player_score,
+      player_score: ScoreValue::Float(entry.player_score),
       player_score_metadata: entry.player_score_metadata,
       creation_timestamp: entry.creation_timestamp,
     }
   }
 }
 
+/// Format in which score timestamps are returned, chosen for the
+/// whole server by [`AppConfig::timestamp_format`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TimestampFormat {
+  /// `2025-02-01 05:33:10`, in UTC.
+  #[default]
+  Space,
+  /// `2025-02-01T05:33:10Z`.
+  Iso8601,
+}
+
+/// Environment variable selecting the [`TimestampFormat`], either
+/// `space` (the default) or `iso8601`.
+pub const TIMESTAMP_FORMAT_ENV_VAR: &str = "TIMESTAMP_FORMAT";
+
+thread_local! {
+  /// The format used by [`serialize_datetime`] on this thread. See
+  /// [`TimestampFormat::scope`].
+  static CURRENT_TIMESTAMP_FORMAT: Cell<TimestampFormat> = const { Cell::new(TimestampFormat::Space) };
+}
+
+/// Error parsing a [`TimestampFormat`] name.
+#[derive(Debug, Clone)]
+pub struct InvalidTimestampFormat;
+
+impl TimestampFormat {
+  pub fn name(self) -> &'static str {
+    match self {
+      TimestampFormat::Space => "space",
+      TimestampFormat::Iso8601 => "iso8601",
+    }
+  }
+
+  /// The server's configured format, in which responses to `req`
+  /// serialize score timestamps.
+  pub fn configured(req: &rocket::Request<'_>) -> Self {
+    req.rocket().state::<AppConfig>().map_or_else(TimestampFormat::default, |config| config.timestamp_format)
+  }
+
+  /// Runs `f` with score timestamps serialized in this format on the
+  /// current thread. Serde's `serialize_with` cannot reach Rocket's
+  /// managed state, so responders which serialize timestamps call this
+  /// with the configured format around the serialization.
+  pub fn scope<R>(self, f: impl FnOnce() -> R) -> R {
+    struct Restore(TimestampFormat);
+
+    impl Drop for Restore {
+      fn drop(&mut self) {
+        CURRENT_TIMESTAMP_FORMAT.with(|current| current.set(self.0));
+      }
+    }
+
+    let _restore = Restore(CURRENT_TIMESTAMP_FORMAT.with(|current| current.replace(self)));
+    f()
+  }
+
+  /// The format set by the innermost enclosing
+  /// [`TimestampFormat::scope`], or the default outside of any.
+  pub fn current() -> Self {
+    CURRENT_TIMESTAMP_FORMAT.with(Cell::get)
+  }
+
+  pub fn format(self, datetime: &NaiveDateTime) -> String {
+    match self {
+      TimestampFormat::Space => datetime.format("%Y-%m-%d %H:%M:%S").to_string(),
+      TimestampFormat::Iso8601 => datetime.format("%Y-%m-%dT%H:%M:%SZ").to_string(),
+    }
+  }
+}
+
+impl FromStr for TimestampFormat {
+  type Err = InvalidTimestampFormat;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s.to_ascii_lowercase().as_str() {
+      "space" => Ok(TimestampFormat::Space),
+      "iso8601" => Ok(TimestampFormat::Iso8601),
+      _ => Err(InvalidTimestampFormat),
+    }
+  }
+}
+
+impl Display for TimestampFormat {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str(self.name())
+  }
+}
+
 fn serialize_datetime<S>(datetime: &chrono::NaiveDateTime, serializer: S) -> Result<S::Ok, S::Error>
 where S: serde::Serializer {
-  let formatted = datetime.format("%Y-%m-%d %H:%M:%S").to_string();
-  serializer.serialize_str(&formatted)
+  serializer.serialize_str(&TimestampFormat::current().format(datetime))
 }
 
 pub fn api_routes() -> Vec<Route> {
   routes![
     authorize,
+    check_authorization,
+    login,
     admin::create_developer,
+    admin::get_developer_tree,
+    admin::set_game_enabled,
+    admin::set_game_quota,
+    admin::set_game_max_tables,
+    admin::retrim_highscore_tables,
+    admin::recount_highscore_tables,
+    admin::import_highscore_table_scores,
+    admin::impersonate_developer,
+    admin::revoke_developer_tokens,
+    admin::set_default_security_level,
+    admin::get_verification_metrics,
+    admin::verify_signature,
+    admin::get_audit_log,
+    admin::get_failed_webhooks,
+    admin::replay_failed_webhook,
+    admin::get_highscore_table_rank_changes,
     get_developer,
     get_current_developer,
+    get_current_developer_top_scores,
+    get_current_developer_recent_submissions,
     create_game,
+    create_or_fetch_game,
     get_game,
+    create_game_secret_key,
+    rotate_game_secret_key,
+    deactivate_game_secret_key,
     create_highscore_table,
     get_highscore_table,
     get_highscore_table_scores,
+    get_highscore_table_entry,
+    get_highscore_table_trim_preview,
+    delete_highscore_table,
+    delete_highscore_table_player,
+    get_highscore_table_player_history,
   ]
 }
 
@@ -87,7 +611,12 @@ pub fn api_routes() -> Vec<Route> {
 ///
 /// Takes an API key in the X-Api-Key header and returns a JWT token
 /// if successful. The JWT token is valid for one hour after creation
-/// and can be used for any of the user-facing API endpoints.
+/// (or `JWT_EXPIRATION_MINUTES`, if set) and can be used for any of
+/// the user-facing API endpoints.
+///
+/// If `cookie` is true, the token is also set as an HttpOnly cookie,
+/// which is accepted in place of the `Authorization` header. This is
+/// intended for browser dashboards.
 ///
 /// NOTE: A JWT token is **not** used for game-facing endpoints, only
 /// for the user-facing API.
@@ -96,19 +625,99 @@ pub fn api_routes() -> Vec<Route> {
   path="/api/authorize",
   tag="authorization",
   security(("X-Api-Key" = [])),
+  params(
+    ("cookie" = Option<bool>, Query, description = "Also set the token as an HttpOnly cookie"),
+  ),
   responses(
     (status = 200, description = "A JWT token", body = ApiSuccessResponseBody<AuthResponse>),
     (status = 400, description = "Invalid API key")
   ),
 )]
-#[post("/authorize")]
-async fn authorize(api_key: XApiKey<'_>, mut db: Connection<db::Db>) -> Result<ApiSuccessResponse<AuthResponse>, ApiError> {
-  let jwt_token = create_jwt_for_api_key(api_key.0, &mut db).await.map_err(|err| {
+#[post("/authorize?<cookie>")]
+async fn authorize(
+  api_key: XApiKey<'_>,
+  cookie: Option<bool>,
+  cookies: &CookieJar<'_>,
+  config: &State<AppConfig>,
+  mut db: Connection<db::Db>,
+) -> Result<ApiSuccessResponse<AuthResponse>, ApiError> {
+  let jwt_token = create_jwt_for_api_key(api_key.0, config.jwt_expiration, &mut db).await.map_err(|err| {
+    match err {
+      AuthError::InvalidApiKey => ApiError::bad_request().with_message("Invalid API key"),
+      err => ApiError::internal_server_error(err.to_string()),
+    }
+  })?;
+  if cookie.unwrap_or(false) {
+    cookies.add(jwt_cookie(jwt_token.clone(), config.jwt_expiration));
+  }
+  Ok(ApiSuccessResponse::new(AuthResponse { token: jwt_token }))
+}
+
+/// Checks an API key without issuing a JWT token.
+///
+/// Takes an API key in the X-Api-Key header and returns the UUID of
+/// the developer it belongs to. This is intended for health checks
+/// which need to confirm that a key is still valid.
+#[utoipa::path(
+  get,
+  path="/api/authorize/check",
+  tag="authorization",
+  security(("X-Api-Key" = [])),
+  responses(
+    (status = 200, description = "The API key is valid", body = ApiSuccessResponseBody<ApiKeyCheckResponse>),
+    (status = 400, description = "Invalid API key")
+  ),
+)]
+#[get("/authorize/check")]
+async fn check_authorization(
+  api_key: XApiKey<'_>,
+  mut db: Connection<db::Db>,
+) -> Result<ApiSuccessResponse<ApiKeyCheckResponse>, ApiError> {
+  let developer_uuid = check_api_key(api_key.0, &mut db).await.map_err(|err| {
     match err {
       AuthError::InvalidApiKey => ApiError::bad_request().with_message("Invalid API key"),
       err => ApiError::internal_server_error(err.to_string()),
     }
   })?;
+  Ok(ApiSuccessResponse::new(ApiKeyCheckResponse { developer_uuid }))
+}
+
+/// Authorizes a developer using an email address and password.
+///
+/// Returns a JWT token exactly as `/api/authorize` does. Only
+/// developers who have been given a password can log in this way;
+/// API key authorization remains available to everyone.
+#[utoipa::path(
+  post,
+  path="/api/login",
+  tag="authorization",
+  security(),
+  params(
+    ("cookie" = Option<bool>, Query, description = "Also set the token as an HttpOnly cookie"),
+  ),
+  responses(
+    (status = 200, description = "A JWT token", body = ApiSuccessResponseBody<AuthResponse>),
+    (status = 400, description = "Invalid email or password")
+  ),
+)]
+#[post("/login?<cookie>", data = "<params>")]
+async fn login(
+  params: Json<LoginParams>,
+  cookie: Option<bool>,
+  cookies: &CookieJar<'_>,
+  config: &State<AppConfig>,
+  mut db: Connection<db::Db>,
+) -> Result<ApiSuccessResponse<AuthResponse>, ApiError> {
+  let Json(params) = params;
+  let jwt_token = create_jwt_for_password(&params.email, &params.password, config.jwt_expiration, &mut db).await.map_err(|err| {
+    match err {
+      AuthError::InvalidCredentials => ApiError::bad_request().with_message("Invalid email or password"),
+      err => ApiError::internal_server_error(err.to_string()),
+    }
+  })?;
+  if cookie.unwrap_or(false) {
+    cookies.add(jwt_cookie(jwt_token.clone(), config.jwt_expiration));
+  }
   Ok(ApiSuccessResponse::new(AuthResponse { token: jwt_token }))
 }
 
@@ -129,7 +738,8 @@ async fn authorize(api_key: XApiKey<'_>, mut db: Connection<db::Db>) -> Result<A
   )
 )]
 #[get("/developer/<uuid>")]
-async fn get_developer(requesting_user: DeveloperUser, uuid: ParamFromStr<Uuid>, mut db: Connection<db::Db>) -> Result<ApiSuccessResponse<DeveloperResponse>, ApiError> {
+async fn get_developer(requesting_user: DeveloperUser, uuid: Result<UuidParam, InvalidUuid>, mut db: Connection<db::Db>) -> Result<ApiSuccessResponse<DeveloperResponse>, ApiError> {
+  let uuid = uuid?;
   let matching_user = schema::developers::table
     .filter(schema::developers::developer_uuid.eq(&*uuid))
     .get_result::<models::Developer>(&mut db)
@@ -157,123 +767,580 @@ async fn get_current_developer(requesting_user: DeveloperUser, mut db: Connectio
   Ok(ApiSuccessResponse::new(DeveloperResponse::from(matching_user).without_api_key()))
 }
 
+/// Gets the top few scores on each of the current user's highscore
+/// tables.
+///
+/// At most 100 tables are included, oldest first. The response's
+/// `truncated` field reports whether any tables were left out.
+#[utoipa::path(
+  get,
+  path="/api/developer/me/top-scores",
+  tag="developer",
+  params(
+    ("per_table" = Option<u32>, Query, description = "Number of scores to return per table, at most 25. Defaults to 3"),
+  ),
+  responses(
+    (status = 200, description = "Top scores on each table", body = ApiSuccessResponseBody<TopScoresResponse>),
+    (status = 400, description = "per_table is too large"),
+  )
+)]
+#[get("/developer/me/top-scores?<per_table>")]
+async fn get_current_developer_top_scores(
+  requesting_user: DeveloperUser,
+  per_table: Option<u32>,
+  mut db: Connection<db::Db>,
+) -> Result<ApiSuccessResponse<TopScoresResponse>, ApiError> {
+  let per_table = per_table.unwrap_or(DEFAULT_TOP_SCORES_PER_TABLE);
+  if per_table > MAX_TOP_SCORES_PER_TABLE {
+    return Err(ApiError::bad_request().with_message(format!("per_table must be at most {}", MAX_TOP_SCORES_PER_TABLE)));
+  }
+  let mut highscore_tables = schema::highscore_tables::table
+    .inner_join(schema::games::table.inner_join(schema::developers::table))
+    .filter(schema::developers::developer_uuid.eq(requesting_user.user_uuid()))
+    .filter(schema::highscore_tables::deleted_at.is_null())
+    .order_by(schema::highscore_tables::id.asc())
+    .limit(MAX_TABLES_PER_TOP_SCORES_REQUEST + 1)
+    .select((models::HighscoreTable::as_select(), schema::games::game_uuid))
+    .load::<(models::HighscoreTable, Uuid)>(&mut db)
+    .await?;
+  let truncated = highscore_tables.len() as i64 > MAX_TABLES_PER_TOP_SCORES_REQUEST;
+  highscore_tables.truncate(MAX_TABLES_PER_TOP_SCORES_REQUEST as usize);
+
+  let table_ids = highscore_tables.iter().map(|(table, _)| table.id).collect::<Vec<_>>();
+  let mut top_entries = top_entries_for_tables(&table_ids, per_table, &mut db).await?.into_iter().peekable();
+  let tables = highscore_tables.into_iter().map(|(table, game_uuid)| {
+    let mut scores = Vec::new();
+    // Entries arrive grouped by table, in the same order as the
+    // tables themselves.
+    while let Some(entry) = top_entries.next_if(|entry| entry.highscore_table_id == table.id) {
+      scores.push(ScoresEntryResponse {
+        rank: entry.rank,
+        entry: ScoresResponseEntry {
+          player_name: entry.player_name,
+          player_score: ScoreValue::Float(entry.player_score),
+          player_score_metadata: entry.player_score_metadata,
+          creation_timestamp: entry.creation_timestamp,
+        }.rounded(table.score_precision).integral(table.score_is_integer),
+        cursor: None,
+      });
+    }
+    TableTopScores { game_uuid, table_uuid: table.table_uuid, name: table.name, scores }
+  }).collect();
+  Ok(ApiSuccessResponse::new(TopScoresResponse { tables, truncated }))
+}
+
+/// Gets the most recent score submissions to any of the current
+/// user's highscore tables, newest first.
+///
+/// Only submissions still on their table are included; scores since
+/// removed by `unique_entries` or `maximum_scores_retained` are not.
+#[utoipa::path(
+  get,
+  path="/api/developer/me/recent-submissions",
+  tag="developer",
+  params(
+    ("limit" = Option<u32>, Query, description = "Number of submissions to return, at most 100. Defaults to 20"),
+  ),
+  responses(
+    (status = 200, description = "Recent submissions", body = ApiSuccessResponseBody<RecentSubmissionsResponse>),
+    (status = 400, description = "limit is too large"),
+  )
+)]
+#[get("/developer/me/recent-submissions?<limit>")]
+async fn get_current_developer_recent_submissions(
+  requesting_user: DeveloperUser,
+  limit: Option<u32>,
+  mut db: Connection<db::Db>,
+) -> Result<ApiSuccessResponse<RecentSubmissionsResponse>, ApiError> {
+  let limit = limit.unwrap_or(DEFAULT_RECENT_SUBMISSIONS);
+  if limit > MAX_RECENT_SUBMISSIONS {
+    return Err(ApiError::bad_request().with_message(format!("limit must be at most {}", MAX_RECENT_SUBMISSIONS)));
+  }
+  let entries = schema::highscore_table_entries::table
+    .inner_join(schema::highscore_tables::table.inner_join(schema::games::table.inner_join(schema::developers::table)))
+    .filter(schema::developers::developer_uuid.eq(requesting_user.user_uuid()))
+    .filter(schema::highscore_tables::deleted_at.is_null())
+    .order_by((schema::highscore_table_entries::creation_timestamp.desc(), schema::highscore_table_entries::id.desc()))
+    .limit(i64::from(limit))
+    .select((
+      models::HighscoreTableEntry::as_select(),
+      schema::highscore_tables::table_uuid,
+      schema::highscore_tables::score_precision,
+      schema::highscore_tables::score_is_integer,
+      schema::games::game_uuid,
+    ))
+    .load::<(models::HighscoreTableEntry, Uuid, Option<i32>, bool, Uuid)>(&mut db)
+    .await?;
+  let submissions = entries.into_iter()
+    .map(|(entry, table_uuid, score_precision, score_is_integer, game_uuid)| RecentSubmission {
+      game_uuid,
+      table_uuid,
+      entry: ScoresResponseEntry::from(entry).rounded(score_precision).integral(score_is_integer),
+    })
+    .collect();
+  Ok(ApiSuccessResponse::new(RecentSubmissionsResponse { submissions }))
+}
+
 /// Creates a new video game.
 ///
 /// The game's returned secret key cannot be accessed after this
-/// endpoint returns.
+/// endpoint returns. Non-admin developers may have at most the
+/// server's maximum number of games.
 #[utoipa::path(
   post,
   path="/api/game",
   tag="game",
   responses(
-    (status = 200, description = "Game created successfully", body = ApiSuccessResponseBody<GameResponse>),
+    (status = 201, description = "Game created successfully", body = ApiSuccessResponseBody<GameResponse>),
     (status = 403, description = "Not allowed to create a game with these parameters"),
+    (status = 409, description = "The developer already has their maximum number of games"),
+    (status = 422, description = "One or more fields are invalid"),
   ),
 )]
 #[post("/game", data = "<params>")]
-async fn create_game(requesting_user: DeveloperUser, params: Json<NewGameDao>, mut db: Connection<db::Db>) -> Result<ApiSuccessResponse<GameResponse>, ApiError> {
-  let params = params.0;
+async fn create_game(
+  requesting_user: DeveloperUser,
+  params: Json<NewGameDao>,
+  config: &State<AppConfig>,
+  mut db: Connection<db::Db>,
+) -> Result<ApiSuccessResponse<GameResponse>, ApiError> {
+  let mut params = params.0;
   if !requesting_user.is_admin() && &params.developer_uuid != requesting_user.user_uuid() {
     return Err(ApiError::forbidden());
   }
-  let developer_id = schema::developers::table
-    .filter(schema::developers::developer_uuid.eq(&params.developer_uuid))
-    .select(schema::developers::id)
-    .first::<i32>(&mut db)
-    .await
-    .map_err(ApiError::from_on_create)?;
+  let developer_id = find_developer_for_new_game(&mut params, &mut db).await?;
+  params.validate()?;
+  check_game_limit(&requesting_user, developer_id, config, &mut db).await?;
 
-  let new_game = models::NewGame {
-    developer_id,
-    game_uuid: Uuid::new_v4(),
-    game_secret_key: generate_key(),
-    name: params.name,
-    security_level: params.security_level.unwrap_or_default(),
-  };
+  let developer_uuid = params.developer_uuid;
+  let new_game = new_game_from_params(developer_id, params);
   diesel::insert_into(schema::games::table)
     .values(&new_game)
     .execute(&mut db)
     .await
     .map_err(ApiError::from_on_create)?;
-
-  let game_response = GameResponse {
-    developer_uuid: params.developer_uuid,
-    game_uuid: new_game.game_uuid,
-    name: new_game.name,
-    game_secret_key: Some(new_game.game_secret_key),
-    security_level: new_game.security_level,
-  };
-  Ok(ApiSuccessResponse::new(game_response))
+  Ok(ApiSuccessResponse::created(GameResponse::from_new_game(new_game, developer_uuid)))
 }
 
-/// Gets details about the video game with the given UUID.
+/// Creates a new video game, or returns the existing one if the
+/// developer already has a game with the same `external_id`.
 ///
-/// Admins can query any game, while non-admins can only query their
-/// own games.
+/// This allows a client to safely retry game creation. The
+/// `external_id` field is required. A newly-created game is returned
+/// with status 201 and its secret key, as from `POST /api/game`. An
+/// existing game is returned with status 200 and without its secret
+/// key, and the other fields of the request are ignored.
 #[utoipa::path(
-  get,
-  path="/api/game/{uuid}",
+  put,
+  path="/api/game",
   tag="game",
-  params(
-    ("uuid" = OpenApiUuid, Path, description = "Game UUID"),
-  ),
   responses(
-    (status = 200, description = "Game details", body = ApiSuccessResponseBody<GameResponse>),
-    (status = 403, description = "Forbidden"),
-    (status = 404, description = "Game not found"),
+    (status = 200, description = "Existing game with this external ID", body = ApiSuccessResponseBody<GameResponse>),
+    (status = 201, description = "Game created successfully", body = ApiSuccessResponseBody<GameResponse>),
+    (status = 403, description = "Not allowed to create a game with these parameters"),
+    (status = 409, description = "The developer already has their maximum number of games"),
+    (status = 422, description = "One or more fields are invalid"),
   ),
 )]
-#[get("/game/<uuid>")]
-async fn get_game(requesting_user: DeveloperUser, uuid: ParamFromStr<Uuid>, mut db: Connection<db::Db>) -> Result<ApiSuccessResponse<GameResponse>, ApiError> {
-  let (game, developer_uuid) = schema::games::table
-    .filter(schema::games::game_uuid.eq(&*uuid))
-    .inner_join(schema::developers::table)
-    .select((schema::games::all_columns, schema::developers::developer_uuid))
-    .first::<(models::Game, Uuid)>(&mut db)
-    .await
-    .optional()?
-    .check_permission(&requesting_user)?;
+#[put("/game", data = "<params>")]
+async fn create_or_fetch_game(
+  requesting_user: DeveloperUser,
+  params: Json<NewGameDao>,
+  config: &State<AppConfig>,
+  mut db: Connection<db::Db>,
+) -> Result<ApiSuccessResponse<GameResponse>, ApiError> {
+  let mut params = params.0;
+  if !requesting_user.is_admin() && &params.developer_uuid != requesting_user.user_uuid() {
+    return Err(ApiError::forbidden());
+  }
+  let developer_id = find_developer_for_new_game(&mut params, &mut db).await?;
+  let mut errors = FieldErrors::new();
+  params.collect_field_errors(&mut errors);
+  if params.external_id.is_none() {
+    errors.add("external_id", "external_id is required");
+  }
+  errors.into_result()?;
+  let external_id = params.external_id.clone().expect("external_id was validated");
 
-  let game_response = GameResponse {
-    developer_uuid,
-    game_uuid: game.game_uuid,
+  let developer_uuid = params.developer_uuid;
+  if let Some(game) = find_game_by_external_id(developer_id, &external_id, &mut db).await? {
+    return Ok(ApiSuccessResponse::new(GameResponse::from_game(game, developer_uuid)));
+  }
+  check_game_limit(&requesting_user, developer_id, config, &mut db).await?;
+  let new_game = new_game_from_params(developer_id, params);
+  let insert_result = diesel::insert_into(schema::games::table)
+    .values(&new_game)
+    .execute(&mut db)
+    .await;
+  match insert_result {
+    Ok(_) => Ok(ApiSuccessResponse::created(GameResponse::from_new_game(new_game, developer_uuid))),
+    Err(err @ DieselError::DatabaseError(DatabaseErrorKind::UniqueViolation, _)) => {
+      // A concurrent request created the game between our lookup and
+      // our insert, so return the game it created.
+      match find_game_by_external_id(developer_id, &external_id, &mut db).await? {
+        Some(game) => Ok(ApiSuccessResponse::new(GameResponse::from_game(game, developer_uuid))),
+        None => Err(ApiError::from_on_create(err)),
+      }
+    }
+    Err(err) => Err(ApiError::from_on_create(err)),
+  }
+}
+
+/// Looks up the ID of the developer who will own the new game. If
+/// `params` has no security level, the developer's default is filled
+/// in, so that validation sees the level the game will actually get.
+async fn find_developer_for_new_game(params: &mut NewGameDao, db: &mut AsyncPgConnection) -> Result<i32, ApiError> {
+  let (developer_id, default_security_level) = schema::developers::table
+    .filter(schema::developers::developer_uuid.eq(&params.developer_uuid))
+    .select((schema::developers::id, schema::developers::default_security_level))
+    .first::<(i32, Option<i32>)>(db)
+    .await
+    .map_err(ApiError::from_on_create)?;
+  params.security_level = params.security_level.or(default_security_level);
+  Ok(developer_id)
+}
+
+/// Rejects the creation of another game for the developer if they
+/// already have [`AppConfig::max_games_per_developer`] games. Admins
+/// are not limited.
+async fn check_game_limit(
+  requesting_user: &DeveloperUser,
+  developer_id: i32,
+  config: &AppConfig,
+  db: &mut AsyncPgConnection,
+) -> Result<(), ApiError> {
+  if requesting_user.is_admin() {
+    // Implicitly trust admin users. Do not restrict their inputs.
+    return Ok(());
+  }
+  let game_count = schema::games::table
+    .filter(schema::games::developer_id.eq(developer_id))
+    .count()
+    .get_result::<i64>(db)
+    .await?;
+  if game_count >= config.max_games_per_developer {
+    let message = format!("This developer already has the maximum of {} games", config.max_games_per_developer);
+    return Err(ApiError::conflict(&message));
+  }
+  Ok(())
+}
+
+/// The game to insert for a `POST` or `PUT` to `/api/game`, with a
+/// fresh UUID and secret key.
+fn new_game_from_params(developer_id: i32, params: NewGameDao) -> models::NewGame {
+  models::NewGame {
+    developer_id,
+    game_uuid: Uuid::now_v7(),
+    game_secret_key: generate_key_of_length(params.secret_key_length.unwrap_or(DEFAULT_KEY_LENGTH)),
+    name: params.name,
+    security_level: params.security_level.unwrap_or_default(),
+    min_submit_interval_ms: params.min_submit_interval_ms,
+    enabled: true,
+    description: params.description,
+    platform: params.platform,
+    daily_submission_quota: None,
+    external_id: params.external_id,
+    default_algorithm: params.default_algorithm.map(|algo| algo.name().to_owned()),
+  }
+}
+
+async fn find_game_by_external_id(
+  developer_id: i32,
+  external_id: &str,
+  db: &mut AsyncPgConnection,
+) -> diesel::QueryResult<Option<models::Game>> {
+  schema::games::table
+    .filter(schema::games::developer_id.eq(developer_id))
+    .filter(schema::games::external_id.eq(external_id))
+    .select(models::Game::as_select())
+    .first::<models::Game>(db)
+    .await
+    .optional()
+}
+
+/// Gets details about the video game with the given UUID.
+///
+/// Admins can query any game, while non-admins can only query their
+/// own games.
+#[utoipa::path(
+  get,
+  path="/api/game/{uuid}",
+  tag="game",
+  params(
+    ("uuid" = OpenApiUuid, Path, description = "Game UUID"),
+  ),
+  responses(
+    (status = 200, description = "Game details", body = ApiSuccessResponseBody<GameResponse>),
+    (status = 403, description = "Forbidden"),
+    (status = 404, description = "Game not found"),
+  ),
+)]
+#[get("/game/<uuid>")]
+async fn get_game(requesting_user: DeveloperUser, uuid: Result<UuidParam, InvalidUuid>, mut db: Connection<db::Db>) -> Result<ApiSuccessResponse<GameResponse>, ApiError> {
+  let uuid = uuid?;
+  let (game, developer_uuid) = schema::games::table
+    .filter(schema::games::game_uuid.eq(&*uuid))
+    .inner_join(schema::developers::table)
+    .select((schema::games::all_columns, schema::developers::developer_uuid))
+    .first::<(models::Game, Uuid)>(&mut db)
+    .await
+    .optional()?
+    .check_permission(&requesting_user)?;
+
+  let game_response = GameResponse {
+    developer_uuid,
+    game_uuid: game.game_uuid,
     name: game.name,
     game_secret_key: None,
     security_level: game.security_level,
+    min_submit_interval_ms: game.min_submit_interval_ms,
+    enabled: game.enabled,
+    description: game.description,
+    platform: game.platform,
+    daily_submission_quota: game.daily_submission_quota,
+    external_id: game.external_id,
+    default_algorithm: game.default_algorithm,
+    max_tables: game.max_tables,
   };
   Ok(ApiSuccessResponse::new(game_response))
 }
 
+/// Creates an additional secret key for the game.
+///
+/// Game requests which name this key in their `key_id` field are
+/// verified against it rather than the game's original secret key.
+/// Several keys may be active at once, so that a new key can be
+/// shipped while clients using an old one keep working. The returned
+/// secret key cannot be accessed after this endpoint returns.
+///
+/// Requesting user must either own the game or be an admin.
+#[utoipa::path(
+  post,
+  path="/api/game/{uuid}/keys",
+  tag="game",
+  params(
+    ("uuid" = OpenApiUuid, Path, description = "Game UUID"),
+  ),
+  responses(
+    (status = 201, description = "Secret key created successfully", body = ApiSuccessResponseBody<GameSecretKeyResponse>),
+    (status = 403, description = "Forbidden"),
+    (status = 404, description = "Game not found"),
+  ),
+)]
+#[post("/game/<uuid>/keys")]
+async fn create_game_secret_key(
+  requesting_user: DeveloperUser,
+  uuid: Result<UuidParam, InvalidUuid>,
+  mut db: Connection<db::Db>,
+) -> Result<ApiSuccessResponse<GameSecretKeyResponse>, ApiError> {
+  let uuid = uuid?;
+  let (game_id, _developer_uuid) = schema::games::table
+    .filter(schema::games::game_uuid.eq(&*uuid))
+    .inner_join(schema::developers::table)
+    .select((schema::games::id, schema::developers::developer_uuid))
+    .first::<(i32, Uuid)>(&mut db)
+    .await
+    .optional()?
+    .check_permission(&requesting_user)?;
+  let new_key = models::NewGameSecretKey {
+    game_id,
+    key_id: Uuid::now_v7(),
+    secret_key: generate_key(),
+  };
+  diesel::insert_into(schema::game_secret_keys::table)
+    .values(&new_key)
+    .execute(&mut db)
+    .await
+    .map_err(ApiError::from_on_create)?;
+  Ok(ApiSuccessResponse::created(GameSecretKeyResponse {
+    key_id: new_key.key_id,
+    secret_key: Some(new_key.secret_key),
+    active: true,
+  }))
+}
+
+/// Replaces the game's original secret key with a new one.
+///
+/// Requests without a `key_id` which are signed with the old key are
+/// still accepted until the server's rotation window (72 hours by
+/// default) has passed, so that clients can be updated without a hard
+/// cutover. Rotating again before then ends the old key's window
+/// early. The returned secret key cannot be accessed after this
+/// endpoint returns.
+///
+/// Requesting user must either own the game or be an admin.
+#[utoipa::path(
+  post,
+  path="/api/game/{uuid}/rotate-secret",
+  tag="game",
+  params(
+    ("uuid" = OpenApiUuid, Path, description = "Game UUID"),
+  ),
+  responses(
+    (status = 200, description = "Secret key rotated successfully", body = ApiSuccessResponseBody<RotateGameSecretResponse>),
+    (status = 403, description = "Forbidden"),
+    (status = 404, description = "Game not found"),
+  ),
+)]
+#[post("/game/<uuid>/rotate-secret")]
+async fn rotate_game_secret_key(
+  requesting_user: DeveloperUser,
+  uuid: Result<UuidParam, InvalidUuid>,
+  config: &State<AppConfig>,
+  mut db: Connection<db::Db>,
+) -> Result<ApiSuccessResponse<RotateGameSecretResponse>, ApiError> {
+  let uuid = uuid?;
+  let ((game_id, old_secret_key), _developer_uuid) = schema::games::table
+    .filter(schema::games::game_uuid.eq(&*uuid))
+    .inner_join(schema::developers::table)
+    .select(((schema::games::id, schema::games::game_secret_key), schema::developers::developer_uuid))
+    .first::<((i32, String), Uuid)>(&mut db)
+    .await
+    .optional()?
+    .check_permission(&requesting_user)?;
+  // Keep the game's chosen key length.
+  let key_length = URL_SAFE_NO_PAD.decode(&old_secret_key).map_or(DEFAULT_KEY_LENGTH, |bytes| bytes.len());
+  let new_secret_key = generate_key_of_length(key_length);
+  let previous_secret_valid_until = Utc::now() + config.secret_rotation_window;
+  diesel::update(schema::games::table)
+    .filter(schema::games::id.eq(game_id))
+    .set((
+      schema::games::game_secret_key.eq(&new_secret_key),
+      schema::games::previous_game_secret_key.eq(&old_secret_key),
+      schema::games::previous_secret_key_expires_at.eq(previous_secret_valid_until.naive_utc()),
+    ))
+    .execute(&mut db)
+    .await?;
+  Ok(ApiSuccessResponse::new(RotateGameSecretResponse {
+    game_secret_key: new_secret_key,
+    previous_secret_valid_until,
+  }))
+}
+
+/// Deactivates one of the game's additional secret keys. Requests
+/// signed with a deactivated key are rejected.
+///
+/// Requesting user must either own the game or be an admin.
+#[utoipa::path(
+  delete,
+  path="/api/game/{uuid}/keys/{key_id}",
+  tag="game",
+  params(
+    ("uuid" = OpenApiUuid, Path, description = "Game UUID"),
+    ("key_id" = OpenApiUuid, Path, description = "Secret key ID"),
+  ),
+  responses(
+    (status = 200, description = "Secret key deactivated", body = ApiSuccessResponseBody<GameSecretKeyResponse>),
+    (status = 403, description = "Forbidden"),
+    (status = 404, description = "Game or key not found"),
+  ),
+)]
+#[delete("/game/<uuid>/keys/<key_id>")]
+async fn deactivate_game_secret_key(
+  requesting_user: DeveloperUser,
+  uuid: Result<UuidParam, InvalidUuid>,
+  key_id: Result<UuidParam, InvalidUuid>,
+  mut db: Connection<db::Db>,
+) -> Result<ApiSuccessResponse<GameSecretKeyResponse>, ApiError> {
+  let uuid = uuid?;
+  let key_id = key_id?;
+  let (game_id, _developer_uuid) = schema::games::table
+    .filter(schema::games::game_uuid.eq(&*uuid))
+    .inner_join(schema::developers::table)
+    .select((schema::games::id, schema::developers::developer_uuid))
+    .first::<(i32, Uuid)>(&mut db)
+    .await
+    .optional()?
+    .check_permission(&requesting_user)?;
+  let key = diesel::update(schema::game_secret_keys::table)
+    .filter(schema::game_secret_keys::game_id.eq(game_id))
+    .filter(schema::game_secret_keys::key_id.eq(&*key_id))
+    .set(schema::game_secret_keys::active.eq(false))
+    .returning(models::GameSecretKey::as_returning())
+    .get_result::<models::GameSecretKey>(&mut db)
+    .await?;
+  Ok(ApiSuccessResponse::new(GameSecretKeyResponse {
+    key_id: key.key_id,
+    secret_key: None,
+    active: key.active,
+  }))
+}
+
 /// Creates a new highscore table.
 ///
 /// Requesting user must either own the game or be an admin.
+///
+/// By default, a `maximum_scores_retained` outside of the permitted
+/// range is silently clamped. If `strict` is true, such a value is
+/// instead rejected with a 422.
+///
+/// Non-admins may not give a game more than its maximum number of
+/// tables, which is the server's default unless an admin has set one
+/// for the game.
 #[utoipa::path(
   post,
   path="/api/highscore-table",
   tag="highscore-table",
+  params(
+    ("strict" = Option<bool>, Query, description = "Reject, rather than clamp, an out-of-range maximum_scores_retained"),
+  ),
   responses(
-    (status = 200, description = "Highscore table created successfully", body = ApiSuccessResponseBody<HighscoreTableResponse>),
+    (status = 201, description = "Highscore table created successfully", body = ApiSuccessResponseBody<HighscoreTableResponse>),
     (status = 403, description = "Forbidden"),
+    (status = 409, description = "The game already has its maximum number of tables"),
+    (status = 422, description = "One or more fields are invalid"),
   ),
 )]
-#[post("/highscore-table", data = "<params>")]
-async fn create_highscore_table(requesting_user: DeveloperUser, params: Json<NewHighscoreTableDao>, mut db: Connection<db::Db>) -> Result<ApiSuccessResponse<HighscoreTableResponse>, ApiError> {
+#[post("/highscore-table?<strict>", data = "<params>")]
+async fn create_highscore_table(
+  requesting_user: DeveloperUser,
+  params: Json<NewHighscoreTableDao>,
+  strict: Option<bool>,
+  config: &State<AppConfig>,
+  mut db: Connection<db::Db>,
+) -> Result<ApiSuccessResponse<HighscoreTableResponse>, ApiError> {
   let params = params.0;
-  let (game_id, _) = schema::games::table
+  params.validate()?;
+  let maximum_scores_retained = if strict.unwrap_or(false) {
+    check_max_scores(params.maximum_scores_retained, &requesting_user)?
+  } else {
+    normalize_max_scores(params.maximum_scores_retained, &requesting_user)
+  };
+  let ((game_id, max_tables), _) = schema::games::table
     .filter(schema::games::game_uuid.eq(&params.game_uuid))
     .inner_join(schema::developers::table)
-    .select((schema::games::id, schema::developers::developer_uuid))
-    .first::<(i32, Uuid)>(&mut db)
+    .select(((schema::games::id, schema::games::max_tables), schema::developers::developer_uuid))
+    .first::<((i32, Option<i32>), Uuid)>(&mut db)
     .await
     .optional()?
     .check_permission(&requesting_user)?;
+  if !requesting_user.is_admin() {
+    let max_tables = max_tables.map_or(config.max_tables_per_game, i64::from);
+    let table_count = schema::highscore_tables::table
+      .filter(schema::highscore_tables::game_id.eq(game_id))
+      .filter(schema::highscore_tables::deleted_at.is_null())
+      .count()
+      .get_result::<i64>(&mut db)
+      .await?;
+    if table_count >= max_tables {
+      return Err(ApiError::conflict(&format!("This game already has the maximum of {} highscore tables", max_tables)));
+    }
+  }
 
   let new_highscore_table = models::NewHighscoreTable {
     game_id,
     name: params.name,
-    table_uuid: Uuid::new_v4(),
-    maximum_scores_retained: normalize_max_scores(params.maximum_scores_retained, &requesting_user),
+    table_uuid: Uuid::now_v7(),
+    maximum_scores_retained,
     unique_entries: params.unique_entries,
+    anti_cheat_sigma: params.anti_cheat_sigma,
+    score_precision: params.score_precision,
+    default_player_name: params.default_player_name,
+    store_only_improvements: params.store_only_improvements,
+    submissions_open_at: params.submissions_open_at.map(|t| t.naive_utc()),
+    submissions_close_at: params.submissions_close_at.map(|t| t.naive_utc()),
+    score_is_integer: params.score_is_integer,
+    public_reads: params.public_reads,
   };
   diesel::insert_into(schema::highscore_tables::table)
     .values(&new_highscore_table)
@@ -286,8 +1353,17 @@ async fn create_highscore_table(requesting_user: DeveloperUser, params: Json<New
     table_uuid: new_highscore_table.table_uuid,
     name: new_highscore_table.name,
     maximum_scores_retained: new_highscore_table.maximum_scores_retained,
+    anti_cheat_sigma: new_highscore_table.anti_cheat_sigma,
+    score_precision: new_highscore_table.score_precision,
+    default_player_name: new_highscore_table.default_player_name,
+    store_only_improvements: new_highscore_table.store_only_improvements,
+    submissions_open_at: params.submissions_open_at,
+    submissions_close_at: params.submissions_close_at,
+    score_is_integer: new_highscore_table.score_is_integer,
+    deleted_at: None,
+    public_reads: new_highscore_table.public_reads,
   };
-  Ok(ApiSuccessResponse::new(response))
+  Ok(ApiSuccessResponse::created(response))
 }
 
 /// Non-admin users are not permitted to make highscore tables with no
@@ -308,24 +1384,47 @@ fn normalize_max_scores(maximum_scores_retained: Option<i32>, requesting_user: &
   Some(n)
 }
 
+/// As [`normalize_max_scores`], but rejects an out-of-range value
+/// with an HTTP 422 rather than clamping it. Omitting the limit counts
+/// as requesting an unlimited table.
+fn check_max_scores(maximum_scores_retained: Option<i32>, requesting_user: &DeveloperUser) -> Result<Option<i32>, ApiError> {
+  let normalized = normalize_max_scores(maximum_scores_retained, requesting_user);
+  if normalized != maximum_scores_retained {
+    let mut errors = FieldErrors::new();
+    errors.add("maximum_scores_retained", format!("maximum_scores_retained must be between 0 and {}", MAX_HIGHSCORES_RETAINED_FOR_NON_ADMIN));
+    errors.into_result()?;
+  }
+  Ok(normalized)
+}
+
 /// Queries the details of a highscore table.
 ///
-/// Requesting user must be an admin or the owner of the game.
+/// A soft-deleted table is reported as not found unless
+/// `include_deleted` is true.
+///
+/// Requesting user must be an admin or the owner of the game. Tables
+/// which the requesting user does not own are reported as not found.
 #[utoipa::path(
   get,
   path="/api/highscore-table/{uuid}",
   tag="highscore-table",
   params(
     ("uuid" = OpenApiUuid, Path, description = "Highscore table UUID"),
+    ("include_deleted" = Option<bool>, Query, description = "Return the table even if it has been soft-deleted"),
   ),
   responses(
     (status = 200, description = "Highscore table details", body = ApiSuccessResponseBody<HighscoreTableResponse>),
-    (status = 403, description = "Forbidden"),
     (status = 404, description = "Highscore table not found"),
   ),
 )]
-#[get("/highscore-table/<uuid>")]
-async fn get_highscore_table(requesting_user: DeveloperUser, uuid: ParamFromStr<Uuid>, mut db: Connection<db::Db>) -> Result<ApiSuccessResponse<HighscoreTableResponse>, ApiError> {
+#[get("/highscore-table/<uuid>?<include_deleted>")]
+async fn get_highscore_table(
+  requesting_user: DeveloperUser,
+  uuid: Result<UuidParam, InvalidUuid>,
+  include_deleted: Option<bool>,
+  mut db: Connection<db::Db>,
+) -> Result<ApiSuccessResponse<HighscoreTableResponse>, ApiError> {
+  let uuid = uuid?;
   let ((highscore_table, game_uuid), _developer_uuid) = schema::highscore_tables::table
     .filter(schema::highscore_tables::table_uuid.eq(&*uuid))
     .inner_join(schema::games::table.inner_join(schema::developers::table))
@@ -333,63 +1432,823 @@ async fn get_highscore_table(requesting_user: DeveloperUser, uuid: ParamFromStr<
     .first::<((models::HighscoreTable, Uuid), Uuid)>(&mut db)
     .await
     .optional()?
-    .check_permission(&requesting_user)?;
+    .check_permission_hidden(&requesting_user)?;
+  check_not_deleted(&highscore_table, include_deleted)?;
   let response = HighscoreTableResponse {
     game_uuid,
     table_uuid: highscore_table.table_uuid,
     name: highscore_table.name,
     maximum_scores_retained: highscore_table.maximum_scores_retained,
+    anti_cheat_sigma: highscore_table.anti_cheat_sigma,
+    score_precision: highscore_table.score_precision,
+    default_player_name: highscore_table.default_player_name,
+    store_only_improvements: highscore_table.store_only_improvements,
+    submissions_open_at: highscore_table.submissions_open_at.map(|t| t.and_utc()),
+    submissions_close_at: highscore_table.submissions_close_at.map(|t| t.and_utc()),
+    score_is_integer: highscore_table.score_is_integer,
+    deleted_at: highscore_table.deleted_at.map(|t| t.and_utc()),
+    public_reads: highscore_table.public_reads,
   };
   Ok(ApiSuccessResponse::new(response))
 }
 
 /// Returns a list of all highscores on the given table.
 ///
-/// Returned table is sorted from highest to lowest score.
+/// Returned table is sorted from highest to lowest score, unless
+/// `order_by` or `direction` say otherwise. Results may be paginated
+/// with `limit` and `offset`, and restricted to scores submitted
+/// between `from_time` (inclusive) and `to_time` (exclusive). The
+/// total number of matching scores is reported in the `X-Total-Count`
+/// header, and links to adjacent pages in the `Link` header.
+///
+/// If the request's `Accept` header includes `application/x-ndjson`,
+/// the scores are instead streamed as newline-delimited JSON, one
+/// score object per line.
+///
+/// A soft-deleted table is reported as not found unless
+/// `include_deleted` is true.
 ///
-/// Requesting user must be an admin or the owner of the game.
+/// Requesting user must be an admin or the owner of the game. Tables
+/// which the requesting user does not own are reported as not found.
 #[utoipa::path(
   get,
   path="/api/highscore-table/{uuid}/scores",
   tag="highscore-table",
   params(
     ("uuid" = OpenApiUuid, Path, description = "Highscore table UUID"),
+    ("limit" = Option<u32>, Query, description = "Maximum number of scores to return, at most the server's maximum page size"),
+    ("offset" = Option<u32>, Query, description = "Number of scores to skip"),
+    ("raw" = Option<bool>, Query, description = "Return scores at full precision, ignoring the table's score_precision"),
+    ("order_by" = Option<String>, Query, description = "Order by `score` (default) or submission `time`"),
+    ("direction" = Option<String>, Query, description = "`asc` or `desc`. Defaults to highest scores first, or newest scores first when ordering by time"),
+    ("from_time" = Option<String>, Query, description = "Only return scores submitted at or after this time"),
+    ("to_time" = Option<String>, Query, description = "Only return scores submitted before this time"),
+    ("include_metadata" = Option<bool>, Query, description = "If false, return `player_score_metadata` as null. Defaults to true"),
+    ("include_deleted" = Option<bool>, Query, description = "Return the scores even if the table has been soft-deleted"),
   ),
   responses(
-    (status = 200, description = "Highscore table details", body = ApiSuccessResponseBody<ScoresResponse>),
-    (status = 403, description = "Forbidden"),
+    (status = 200, description = "Highscore table details", body = ApiSuccessResponseBody<ScoresResponse>, headers(
+      ("X-Total-Count" = i64, description = "Total number of matching scores on the table"),
+      ("Link" = String, description = "RFC 5988 links to the first, previous, next, and last pages"),
+    )),
     (status = 404, description = "Highscore table not found"),
+    (status = 422, description = "`from_time` is after `to_time`"),
   ),
 )]
-#[get("/highscore-table/<uuid>/scores")]
+#[get("/highscore-table/<uuid>/scores?<params..>")]
 async fn get_highscore_table_scores(
   requesting_user: DeveloperUser,
-  uuid: ParamFromStr<Uuid>,
+  uuid: Result<UuidParam, InvalidUuid>,
+  params: ScoresParams,
+  pagination: Pagination,
+  accepts_ndjson: AcceptsNdjson,
   mut db: Connection<db::Db>,
-) -> Result<ApiSuccessResponse<ScoresResponse>, ApiError> {
-  let (highscore_table_id, _developer_uuid) = schema::highscore_tables::table
-    .filter(schema::highscore_tables::table_uuid.eq(&*uuid))
-    .inner_join(schema::games::table.inner_join(schema::developers::table))
-    .select((schema::highscore_tables::id, schema::developers::developer_uuid))
-    .first::<(i32, Uuid)>(&mut db)
-    .await
-    .optional()?
-    .check_permission(&requesting_user)?;
-  let scores = get_scores_for_table(highscore_table_id, None, &mut db).await?;
-  Ok(ApiSuccessResponse::new(scores))
+) -> Result<Paginated<Either<ApiSuccessResponse<ScoresResponse>, NdjsonStream<impl Stream<Item = String> + Send + 'static>>>, ApiError> {
+  let uuid = uuid?;
+  params.validate()?;
+  let (highscore_table, _developer_uuid) = find_highscore_table_with_owner(&uuid, &mut db)
+    .await?
+    .check_permission_hidden(&requesting_user)?;
+  check_not_deleted(&highscore_table, params.include_deleted)?;
+  let highscore_table_id = highscore_table.id;
+  let score_precision = if params.raw.unwrap_or(false) { None } else { highscore_table.score_precision };
+  let options = ScoresQuery {
+    limit: Some(pagination.limit),
+    offset: Some(pagination.offset),
+    score_precision,
+    score_is_integer: highscore_table.score_is_integer,
+    order_by: params.order_by.unwrap_or_default(),
+    direction: params.direction,
+    from_time: params.from_time.map(|QueryTimestamp(time)| time),
+    to_time: params.to_time.map(|QueryTimestamp(time)| time),
+    omit_metadata: !params.include_metadata.unwrap_or(true),
+    player_name: None,
+    since: None,
+  };
+  let total_count = if options.from_time.is_none() && options.to_time.is_none() {
+    highscore_table.entry_count
+  } else {
+    count_scores_for_table(highscore_table_id, &options, &mut db).await?
+  };
+  let body = if accepts_ndjson.0 {
+    Either::Right(stream_scores_for_table(highscore_table_id, &options, db))
+  } else {
+    Either::Left(ApiSuccessResponse::new(get_scores_for_table(highscore_table_id, &options, &mut db).await?))
+  };
+  Ok(Paginated::from_pagination(body, pagination, total_count))
 }
 
-pub async fn get_scores_for_table(highscore_table_id: i32, limit: Option<u32>, db: &mut AsyncPgConnection) -> diesel::QueryResult<ScoresResponse> {
-  let mut query = schema::highscore_table_entries::table
+/// Returns a single entry on the given table, together with its
+/// current rank.
+///
+/// A soft-deleted table is reported as not found unless
+/// `include_deleted` is true.
+///
+/// Requesting user must be an admin or the owner of the game. Tables
+/// which the requesting user does not own are reported as not found.
+#[utoipa::path(
+  get,
+  path="/api/highscore-table/{uuid}/scores/{entry_id}",
+  tag="highscore-table",
+  params(
+    ("uuid" = OpenApiUuid, Path, description = "Highscore table UUID"),
+    ("entry_id" = i32, Path, description = "Highscore table entry ID"),
+    ("raw" = Option<bool>, Query, description = "Return the score at full precision, ignoring the table's score_precision"),
+    ("ranking" = Option<String>, Query, description = "`standard` (default) or `dense` ranking of tied scores"),
+    ("include_deleted" = Option<bool>, Query, description = "Read the table even if it has been soft-deleted"),
+  ),
+  responses(
+    (status = 200, description = "Highscore table entry", body = ApiSuccessResponseBody<ScoresEntryResponse>),
+    (status = 404, description = "Highscore table or entry not found"),
+  ),
+)]
+#[get("/highscore-table/<uuid>/scores/<entry_id>?<raw>&<ranking>&<include_deleted>")]
+async fn get_highscore_table_entry(
+  requesting_user: DeveloperUser,
+  uuid: Result<UuidParam, InvalidUuid>,
+  entry_id: i32,
+  raw: Option<bool>,
+  ranking: Option<Ranking>,
+  include_deleted: Option<bool>,
+  mut db: Connection<db::Db>,
+) -> Result<ApiSuccessResponse<ScoresEntryResponse>, ApiError> {
+  let uuid = uuid?;
+  let (highscore_table, _developer_uuid) = find_highscore_table_with_owner(&uuid, &mut db)
+    .await?
+    .check_permission_hidden(&requesting_user)?;
+  check_not_deleted(&highscore_table, include_deleted)?;
+  let highscore_table_id = highscore_table.id;
+  // Note: Filter on table ID as well, so that an entry from another
+  // table is reported as not found.
+  let entry = schema::highscore_table_entries::table
+    .filter(schema::highscore_table_entries::id.eq(entry_id))
     .filter(schema::highscore_table_entries::highscore_table_id.eq(highscore_table_id))
-    .order((schema::highscore_table_entries::player_score.desc(), schema::highscore_table_entries::creation_timestamp.asc()))
-    .into_boxed();
-  if let Some(limit) = limit {
-    query = query.limit(limit as i64);
+    .first::<models::HighscoreTableEntry>(&mut db)
+    .await?;
+  let rank = rank_of_score(highscore_table_id, entry.player_score, ranking.unwrap_or_default(), &mut db).await?;
+  let score_precision = if raw.unwrap_or(false) { None } else { highscore_table.score_precision };
+  let entry = ScoresResponseEntry::from(entry)
+    .rounded(score_precision)
+    .integral(highscore_table.score_is_integer);
+  Ok(ApiSuccessResponse::new(ScoresEntryResponse { entry, rank, cursor: None }))
+}
+
+/// Reports how many scores would be removed from the table if its
+/// `maximum_scores_retained` were lowered to `max`. Nothing is
+/// deleted.
+///
+/// A soft-deleted table is reported as not found unless
+/// `include_deleted` is true.
+///
+/// Requesting user must be an admin or the owner of the game. Tables
+/// which the requesting user does not own are reported as not found.
+#[utoipa::path(
+  get,
+  path="/api/highscore-table/{uuid}/trim-preview",
+  tag="highscore-table",
+  params(
+    ("uuid" = OpenApiUuid, Path, description = "Highscore table UUID"),
+    ("max" = u32, Query, description = "Proposed maximum number of scores retained"),
+    ("include_deleted" = Option<bool>, Query, description = "Read the table even if it has been soft-deleted"),
+  ),
+  responses(
+    (status = 200, description = "Number of scores which would be removed", body = ApiSuccessResponseBody<TrimPreviewResponse>),
+    (status = 404, description = "Highscore table not found"),
+  ),
+)]
+#[get("/highscore-table/<uuid>/trim-preview?<max>&<include_deleted>")]
+async fn get_highscore_table_trim_preview(
+  requesting_user: DeveloperUser,
+  uuid: Result<UuidParam, InvalidUuid>,
+  max: u32,
+  include_deleted: Option<bool>,
+  mut db: Connection<db::Db>,
+) -> Result<ApiSuccessResponse<TrimPreviewResponse>, ApiError> {
+  let uuid = uuid?;
+  let (highscore_table, _developer_uuid) = find_highscore_table_with_owner(&uuid, &mut db)
+    .await?
+    .check_permission_hidden(&requesting_user)?;
+  check_not_deleted(&highscore_table, include_deleted)?;
+  let max = i32::try_from(max).map_err(|_| ApiError::bad_request().with_message("max is too large"))?;
+  let rows_removed = count_extra_highscore_rows(highscore_table.id, max, &mut db).await?;
+  Ok(ApiSuccessResponse::new(TrimPreviewResponse { rows_removed }))
+}
+
+/// Deletes every score submitted under the given player name on the
+/// given table, such as in response to a data deletion request.
+///
+/// The deletion is recorded in the audit log, together with the
+/// optional `reason`, of at most 1000 characters.
+///
+/// A soft-deleted table is reported as not found unless
+/// `include_deleted` is true.
+///
+/// Requesting user must be an admin or the owner of the game. Tables
+/// which the requesting user does not own are reported as not found.
+#[utoipa::path(
+  delete,
+  path="/api/highscore-table/{uuid}/players/{name}",
+  tag="highscore-table",
+  params(
+    ("uuid" = OpenApiUuid, Path, description = "Highscore table UUID"),
+    ("name" = String, Path, description = "Player name whose scores should be deleted"),
+    ("reason" = Option<String>, Query, description = "Why the scores are being deleted, recorded in the audit log"),
+    ("include_deleted" = Option<bool>, Query, description = "Delete the scores even if the table has been soft-deleted"),
+  ),
+  responses(
+    (status = 200, description = "Number of scores deleted", body = ApiSuccessResponseBody<DeletePlayerResponse>),
+    (status = 404, description = "Highscore table not found"),
+    (status = 422, description = "`reason` is too long"),
+  ),
+)]
+#[delete("/highscore-table/<uuid>/players/<name>?<reason>&<include_deleted>")]
+async fn delete_highscore_table_player(
+  requesting_user: DeveloperUser,
+  uuid: Result<UuidParam, InvalidUuid>,
+  name: &str,
+  reason: Option<String>,
+  include_deleted: Option<bool>,
+  mut db: Connection<db::Db>,
+) -> Result<ApiSuccessResponse<DeletePlayerResponse>, ApiError> {
+  let uuid = uuid?;
+  if let Some(reason) = &reason {
+    let mut errors = FieldErrors::new();
+    errors.check_length("reason", reason, MAX_DESCRIPTION_LENGTH);
+    errors.into_result()?;
+  }
+  let (highscore_table, _developer_uuid) = find_highscore_table_with_owner(&uuid, &mut db)
+    .await?
+    .check_permission_hidden(&requesting_user)?;
+  check_not_deleted(&highscore_table, include_deleted)?;
+  let highscore_table_id = highscore_table.id;
+  let rows_removed = db.transaction::<usize, diesel::result::Error, _>(|db| async move {
+    let rows_removed = diesel::delete(schema::highscore_table_entries::table)
+      .filter(schema::highscore_table_entries::highscore_table_id.eq(highscore_table_id))
+      .filter(schema::highscore_table_entries::player_name.eq(name))
+      .execute(db)
+      .await?;
+    adjust_entry_count(highscore_table_id, -(rows_removed as i64), db).await?;
+    let mut details = format!("player_name = {:?}, rows_removed = {}", name, rows_removed);
+    if let Some(reason) = &reason {
+      details.push_str(&format!(", reason = {:?}", reason));
+    }
+    record_audit_event(*requesting_user.user_uuid(), AuditAction::DeletePlayerScores, Some(highscore_table.table_uuid), Some(details), db).await?;
+    Ok(rows_removed)
+  }.scope_boxed()).await?;
+  Ok(ApiSuccessResponse::new(DeletePlayerResponse { rows_removed }))
+}
+
+/// Returns a player's best score and rank on the given table as of
+/// each of their submissions, oldest first, for plotting their
+/// progress.
+///
+/// The rank at each point counts only scores submitted up to that
+/// time, as in the admin rank changes endpoint. Scores since removed
+/// from the table (by `unique_entries`, `maximum_scores_retained`, or
+/// deletion) are not counted, so the history is an approximation for
+/// tables which discard scores.
+///
+/// A soft-deleted table is reported as not found unless
+/// `include_deleted` is true.
+///
+/// Requesting user must be an admin or the owner of the game. Tables
+/// which the requesting user does not own are reported as not found.
+#[utoipa::path(
+  get,
+  path="/api/highscore-table/{uuid}/players/{name}/history",
+  tag="highscore-table",
+  params(
+    ("uuid" = OpenApiUuid, Path, description = "Highscore table UUID"),
+    ("name" = String, Path, description = "Player name whose history should be returned"),
+    ("include_deleted" = Option<bool>, Query, description = "Read the table even if it has been soft-deleted"),
+  ),
+  responses(
+    (status = 200, description = "The player's rank history", body = ApiSuccessResponseBody<PlayerRankHistoryResponse>),
+    (status = 404, description = "Highscore table not found"),
+  ),
+)]
+#[get("/highscore-table/<uuid>/players/<name>/history?<include_deleted>")]
+async fn get_highscore_table_player_history(
+  requesting_user: DeveloperUser,
+  uuid: Result<UuidParam, InvalidUuid>,
+  name: &str,
+  include_deleted: Option<bool>,
+  mut db: Connection<db::Db>,
+) -> Result<ApiSuccessResponse<PlayerRankHistoryResponse>, ApiError> {
+  let uuid = uuid?;
+  let (highscore_table, _developer_uuid) = find_highscore_table_with_owner(&uuid, &mut db)
+    .await?
+    .check_permission_hidden(&requesting_user)?;
+  check_not_deleted(&highscore_table, include_deleted)?;
+  // A player's rank is one more than the number of other players who
+  // had a strictly higher score by the time of their submission.
+  let history = diesel::sql_query(
+    "SELECT creation_timestamp, player_score, best_score,
+        1 + (
+          SELECT COUNT(DISTINCT others.player_name)
+          FROM highscore_table_entries AS others
+          WHERE others.highscore_table_id = $1 AND others.player_name <> $2
+            AND others.creation_timestamp <= submissions.creation_timestamp
+            AND others.player_score > submissions.best_score
+        ) AS rank
+      FROM (
+        SELECT id, creation_timestamp, player_score,
+          MAX(player_score) OVER (ORDER BY creation_timestamp ASC, id ASC) AS best_score
+        FROM highscore_table_entries
+        WHERE highscore_table_id = $1 AND player_name = $2
+      ) AS submissions
+      ORDER BY creation_timestamp ASC, id ASC",
+  )
+    .bind::<diesel::sql_types::Int4, _>(highscore_table.id)
+    .bind::<diesel::sql_types::Varchar, _>(name)
+    .load::<PlayerRankHistoryPoint>(&mut db)
+    .await?;
+  Ok(ApiSuccessResponse::new(PlayerRankHistoryResponse { player_name: name.to_owned(), history }))
+}
+
+/// Deletes a highscore table.
+///
+/// By default the table is soft-deleted: it keeps its scores, but is
+/// left out of listings and no longer accepts submissions or serves
+/// scores to games. Its owner may still read it with
+/// `include_deleted`. If `hard` is true, the table and all of its
+/// scores are instead removed permanently. Only admins may hard-delete
+/// a table, and a soft-deleted table may be hard-deleted later.
+///
+/// The deletion is recorded in the audit log.
+///
+/// Requesting user must be an admin or the owner of the game. Tables
+/// which the requesting user does not own are reported as not found.
+#[utoipa::path(
+  delete,
+  path="/api/highscore-table/{uuid}",
+  tag="highscore-table",
+  params(
+    ("uuid" = OpenApiUuid, Path, description = "Highscore table UUID"),
+    ("hard" = Option<bool>, Query, description = "Remove the table and its scores permanently. Admin only"),
+  ),
+  responses(
+    (status = 200, description = "Highscore table deleted", body = ApiSuccessResponseBody<DeleteHighscoreTableResponse>),
+    (status = 403, description = "Only admins may hard-delete a table"),
+    (status = 404, description = "Highscore table not found, or already soft-deleted"),
+  ),
+)]
+#[delete("/highscore-table/<uuid>?<hard>")]
+async fn delete_highscore_table(
+  requesting_user: DeveloperUser,
+  uuid: Result<UuidParam, InvalidUuid>,
+  hard: Option<bool>,
+  mut db: Connection<db::Db>,
+) -> Result<ApiSuccessResponse<DeleteHighscoreTableResponse>, ApiError> {
+  let uuid = uuid?;
+  let hard = hard.unwrap_or(false);
+  let (highscore_table, _developer_uuid) = find_highscore_table_with_owner(&uuid, &mut db)
+    .await?
+    .check_permission_hidden(&requesting_user)?;
+  if hard && !requesting_user.is_admin() {
+    return Err(ApiError::forbidden().with_message("Only admins may permanently delete a highscore table"));
+  }
+  if !hard {
+    check_not_deleted(&highscore_table, None)?;
+  }
+  let actor_uuid = *requesting_user.user_uuid();
+  let response = db.transaction::<DeleteHighscoreTableResponse, diesel::result::Error, _>(|db| async move {
+    let response = if hard {
+      let rows_removed = diesel::delete(schema::highscore_table_entries::table)
+        .filter(schema::highscore_table_entries::highscore_table_id.eq(highscore_table.id))
+        .execute(db)
+        .await?;
+      diesel::delete(schema::highscore_tables::table)
+        .filter(schema::highscore_tables::id.eq(highscore_table.id))
+        .execute(db)
+        .await?;
+      DeleteHighscoreTableResponse { hard, rows_removed }
+    } else {
+      diesel::update(schema::highscore_tables::table)
+        .filter(schema::highscore_tables::id.eq(highscore_table.id))
+        .set(schema::highscore_tables::deleted_at.eq(Utc::now().naive_utc()))
+        .execute(db)
+        .await?;
+      DeleteHighscoreTableResponse { hard, rows_removed: 0 }
+    };
+    let details = format!("name = {:?}, hard = {}, rows_removed = {}", highscore_table.name, hard, response.rows_removed);
+    record_audit_event(actor_uuid, AuditAction::DeleteHighscoreTable, Some(highscore_table.table_uuid), Some(details), db).await?;
+    Ok(response)
+  }.scope_boxed()).await?;
+  Ok(ApiSuccessResponse::new(response))
+}
+
+/// Reports a soft-deleted table as not found, unless the caller asked
+/// to include deleted tables.
+fn check_not_deleted(highscore_table: &models::HighscoreTable, include_deleted: Option<bool>) -> Result<(), ApiError> {
+  if highscore_table.deleted_at.is_some() && !include_deleted.unwrap_or(false) {
+    return Err(ApiError::not_found().with_message("This highscore table has been deleted"));
   }
-  let entries = query
-    .load::<models::HighscoreTableEntry>(db)
+  Ok(())
+}
+
+/// The rank that the given score has (or would have) on the table,
+/// starting at 1. Tied scores share a rank, so this is one more than
+/// the number of strictly higher scores, or with [`Ranking::Dense`],
+/// the number of strictly higher distinct scores.
+pub async fn rank_of_score(
+  highscore_table_id: i32,
+  player_score: f64,
+  ranking: Ranking,
+  db: &mut AsyncPgConnection,
+) -> diesel::QueryResult<i64> {
+  let higher_scores = schema::highscore_table_entries::table
+    .filter(schema::highscore_table_entries::highscore_table_id.eq(highscore_table_id))
+    .filter(schema::highscore_table_entries::player_score.gt(player_score));
+  let higher_scores = match ranking {
+    Ranking::Standard => higher_scores.count().get_result::<i64>(db).await?,
+    Ranking::Dense => {
+      higher_scores
+        .select(diesel::dsl::count_distinct(schema::highscore_table_entries::player_score))
+        .get_result::<i64>(db)
+        .await?
+    }
+  };
+  Ok(higher_scores + 1)
+}
+
+pub async fn get_scores_for_table(highscore_table_id: i32, options: &ScoresQuery, db: &mut AsyncPgConnection) -> diesel::QueryResult<ScoresResponse> {
+  let entries = scores_for_table_query(highscore_table_id, options)
+    .load::<RankedTableEntry>(db)
     .await?;
-  let entries = entries.into_iter().map(ScoresResponseEntry::from).collect();
+  let entries = entries
+    .into_iter()
+    .map(|entry| options.response_entry(entry))
+    .collect();
   Ok(ScoresResponse { scores: entries })
 }
+
+/// As [`get_scores_for_table`], but streams the scores as NDJSON
+/// lines as the rows are read from the database. Since the response
+/// has already begun by the time the rows are read, a database error
+/// partway through is logged and ends the stream early.
+pub fn stream_scores_for_table(
+  highscore_table_id: i32,
+  options: &ScoresQuery,
+  mut db: Connection<db::Db>,
+) -> NdjsonStream<impl Stream<Item = String> + Send + 'static> {
+  let query = scores_for_table_query(highscore_table_id, options);
+  let options = options.clone();
+  NdjsonStream(stream! {
+    let mut entries = match query.load_stream::<RankedTableEntry>(&mut db).await {
+      Ok(entries) => entries,
+      Err(err) => {
+        error!("Failed to stream scores for table {}: {}", highscore_table_id, err);
+        return;
+      }
+    };
+    while let Some(entry) = entries.next().await {
+      let line = entry
+        .map_err(|err| err.to_string())
+        .and_then(|entry| to_ndjson_line(&options.response_entry(entry)).map_err(|err| err.to_string()));
+      match line {
+        Ok(line) => yield line,
+        Err(err) => {
+          error!("Failed to stream scores for table {}: {}", highscore_table_id, err);
+          return;
+        }
+      }
+    }
+  })
+}
+
+/// The scores on the table requested by `options`, each with its
+/// standard competition rank on the whole table. Ranks are computed
+/// before the time window and pagination are applied, so that they
+/// are consistent from page to page.
+fn scores_for_table_query(
+  highscore_table_id: i32,
+  options: &ScoresQuery,
+) -> BoxedSqlQuery<'static, Pg, SqlQuery> {
+  // Diesel's query builder has no support for window functions.
+  // Postgres treats a null LIMIT or OFFSET as absent.
+  diesel::sql_query(format!(
+    "SELECT *
+      FROM (
+        SELECT *, RANK() OVER (ORDER BY player_score DESC) AS rank
+        FROM highscore_table_entries
+        WHERE highscore_table_id = $1
+      ) AS ranked_entries
+      WHERE ($2 IS NULL OR creation_timestamp >= $2)
+        AND ($3 IS NULL OR creation_timestamp < $3)
+        AND ($6 IS NULL OR player_name = $6)
+        AND ($7 IS NULL OR (creation_timestamp, id) > ($7, $8))
+      ORDER BY {}
+      LIMIT $4 OFFSET $5",
+    options.order_by_sql(),
+  ))
+    .into_boxed()
+    .bind::<diesel::sql_types::Int4, _>(highscore_table_id)
+    .bind::<diesel::sql_types::Nullable<diesel::sql_types::Timestamptz>, _>(options.from_time)
+    .bind::<diesel::sql_types::Nullable<diesel::sql_types::Timestamptz>, _>(options.to_time)
+    .bind::<diesel::sql_types::Nullable<diesel::sql_types::Int8>, _>(options.limit.map(i64::from))
+    .bind::<diesel::sql_types::Nullable<diesel::sql_types::Int8>, _>(options.offset.map(i64::from))
+    .bind::<diesel::sql_types::Nullable<diesel::sql_types::Varchar>, _>(options.player_name.clone())
+    .bind::<diesel::sql_types::Nullable<diesel::sql_types::Timestamptz>, _>(options.since.map(|since| since.creation_timestamp))
+    .bind::<diesel::sql_types::Nullable<diesel::sql_types::Int4>, _>(options.since.map(|since| since.id))
+}
+
+/// All scores on the table within the time window given by
+/// `options`, and by the given player if any, unordered and
+/// unpaginated.
+fn scores_in_time_window(
+  highscore_table_id: i32,
+  options: &ScoresQuery,
+) -> schema::highscore_table_entries::BoxedQuery<'static, Pg> {
+  use schema::highscore_table_entries::{creation_timestamp, id};
+
+  let mut query = schema::highscore_table_entries::table
+    .filter(schema::highscore_table_entries::highscore_table_id.eq(highscore_table_id))
+    .into_boxed();
+  if let Some(from_time) = options.from_time {
+    query = query.filter(creation_timestamp.ge(from_time));
+  }
+  if let Some(to_time) = options.to_time {
+    query = query.filter(creation_timestamp.lt(to_time));
+  }
+  if let Some(since) = options.since {
+    query = query.filter(
+      creation_timestamp.gt(since.creation_timestamp)
+        .or(creation_timestamp.eq(since.creation_timestamp).and(id.gt(since.id))),
+    );
+  }
+  if let Some(player_name) = &options.player_name {
+    query = query.filter(schema::highscore_table_entries::player_name.eq(player_name.clone()));
+  }
+  query
+}
+
+/// Counts the total number of scores on the table matching
+/// `options`, irrespective of pagination.
+pub async fn count_scores_for_table(highscore_table_id: i32, options: &ScoresQuery, db: &mut AsyncPgConnection) -> diesel::QueryResult<i64> {
+  scores_in_time_window(highscore_table_id, options)
+    .count()
+    .get_result(db)
+    .await
+}
+
+/// A score loaded by [`scores_for_table_query`], with its rank.
+#[derive(QueryableByName)]
+struct RankedTableEntry {
+  #[diesel(embed)]
+  entry: models::HighscoreTableEntry,
+  #[diesel(sql_type = diesel::sql_types::Int8)]
+  rank: i64,
+}
+
+#[derive(Debug, Clone, QueryableByName)]
+struct RankedEntry {
+  #[diesel(sql_type = diesel::sql_types::Int4)]
+  highscore_table_id: i32,
+  #[diesel(sql_type = diesel::sql_types::Varchar)]
+  player_name: String,
+  #[diesel(sql_type = diesel::sql_types::Float8)]
+  player_score: f64,
+  #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Text>)]
+  player_score_metadata: Option<String>,
+  #[diesel(sql_type = diesel::sql_types::Timestamptz)]
+  creation_timestamp: NaiveDateTime,
+  #[diesel(sql_type = diesel::sql_types::Int8)]
+  rank: i64,
+}
+
+/// Loads the top `per_table` entries of each of the given tables in a
+/// single query, ordered by table ID and then from highest score to
+/// lowest. Ranks are standard competition ranks within each table.
+async fn top_entries_for_tables(
+  highscore_table_ids: &[i32],
+  per_table: u32,
+  db: &mut AsyncPgConnection,
+) -> diesel::QueryResult<Vec<RankedEntry>> {
+  // Diesel's query builder has no support for window functions.
+  diesel::sql_query(
+    "SELECT highscore_table_id, player_name, player_score, player_score_metadata, creation_timestamp, rank
+      FROM (
+        SELECT *,
+          RANK() OVER (PARTITION BY highscore_table_id ORDER BY player_score DESC) AS rank,
+          ROW_NUMBER() OVER (PARTITION BY highscore_table_id ORDER BY player_score DESC, creation_timestamp ASC) AS row_number
+        FROM highscore_table_entries
+        WHERE highscore_table_id = ANY($1)
+      ) AS ranked_entries
+      WHERE row_number <= $2
+      ORDER BY highscore_table_id ASC, row_number ASC",
+  )
+    .bind::<diesel::sql_types::Array<diesel::sql_types::Int4>, _>(highscore_table_ids)
+    .bind::<diesel::sql_types::Int8, _>(i64::from(per_table))
+    .load(db)
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use rocket::local::blocking::Client;
+
+  fn at(date: &str) -> NaiveDateTime {
+    NaiveDateTime::parse_from_str(date, "%Y-%m-%d %H:%M:%S").unwrap()
+  }
+
+  fn entry() -> ScoresResponseEntry {
+    ScoresResponseEntry {
+      player_name: String::from("Alice"),
+      player_score: ScoreValue::Float(10.0),
+      player_score_metadata: None,
+      creation_timestamp: at("2025-02-01 05:33:10"),
+    }
+  }
+
+  #[test]
+  fn test_timestamp_formats() {
+    assert_eq!(TimestampFormat::Space.format(&at("2025-02-01 05:33:10")), "2025-02-01 05:33:10");
+    assert_eq!(TimestampFormat::Iso8601.format(&at("2025-02-01 05:33:10")), "2025-02-01T05:33:10Z");
+  }
+
+  #[test]
+  fn test_timestamp_format_from_str() {
+    assert_eq!("space".parse::<TimestampFormat>().ok(), Some(TimestampFormat::Space));
+    assert_eq!("ISO8601".parse::<TimestampFormat>().ok(), Some(TimestampFormat::Iso8601));
+    assert!("rfc3339".parse::<TimestampFormat>().is_err());
+  }
+
+  fn score(player_score: f64) -> ScoresResponseEntry {
+    ScoresResponseEntry { player_score: ScoreValue::Float(player_score), ..entry() }
+  }
+
+  #[test]
+  fn test_rounded_scores() {
+    assert_eq!(score(12.3456).rounded(Some(2)).player_score, ScoreValue::Float(12.35));
+    assert_eq!(score(12.3456).rounded(Some(0)).player_score, ScoreValue::Float(12.0));
+    assert_eq!(score(-12.3456).rounded(Some(1)).player_score, ScoreValue::Float(-12.3));
+    assert_eq!(score(12.3456).rounded(None).player_score, ScoreValue::Float(12.3456));
+  }
+
+  #[test]
+  fn test_integral_scores() {
+    assert_eq!(score(12.5).integral(true).player_score, ScoreValue::Integer(13));
+    assert_eq!(score(-2.5).integral(true).player_score, ScoreValue::Integer(-3));
+    assert_eq!(score(12.5).integral(false).player_score, ScoreValue::Float(12.5));
+    assert_eq!(score(12.5).integral(true).rounded(Some(1)).player_score, ScoreValue::Integer(13));
+    assert_eq!(serde_json::to_value(score(7.0).integral(true)).unwrap()["player_score"], serde_json::json!(7));
+  }
+
+  #[test]
+  fn test_timestamp_format_scope() {
+    assert_eq!(TimestampFormat::current(), TimestampFormat::Space);
+    let json = TimestampFormat::Iso8601.scope(|| {
+      assert_eq!(TimestampFormat::Space.scope(TimestampFormat::current), TimestampFormat::Space);
+      serde_json::to_value(entry()).unwrap()
+    });
+    assert_eq!(json["creation_timestamp"], "2025-02-01T05:33:10Z");
+    assert_eq!(TimestampFormat::current(), TimestampFormat::Space);
+    assert_eq!(serde_json::to_value(entry()).unwrap()["creation_timestamp"], "2025-02-01 05:33:10");
+  }
+
+  #[rocket::get("/entry")]
+  fn entry_route() -> ApiSuccessResponse<ScoresResponseEntry> {
+    ApiSuccessResponse::new(entry())
+  }
+
+  #[test]
+  fn test_response_uses_configured_timestamp_format() {
+    let config = AppConfig::from_vars(|name| (name == TIMESTAMP_FORMAT_ENV_VAR).then(|| String::from("iso8601")));
+    let rocket = rocket::build().manage(config).mount("/", routes![entry_route]);
+    let client = Client::untracked(rocket).unwrap();
+    let body = client.get("/entry").dispatch().into_json::<serde_json::Value>().unwrap();
+    assert_eq!(body["creation_timestamp"], "2025-02-01T05:33:10Z");
+  }
+
+  fn stored_entry(id: i32, creation_timestamp: NaiveDateTime) -> models::HighscoreTableEntry {
+    models::HighscoreTableEntry {
+      id,
+      highscore_table_id: 1,
+      player_name: String::from("Alice"),
+      player_score: 10.0,
+      player_score_metadata: None,
+      creation_timestamp,
+    }
+  }
+
+  fn polling_query(since: ScoresCursor, limit: u32) -> ScoresQuery {
+    ScoresQuery {
+      limit: Some(limit),
+      order_by: ScoresOrderBy::Time,
+      direction: Some(SortDirection::Asc),
+      since: Some(since),
+      ..ScoresQuery::default()
+    }
+  }
+
+  #[test]
+  fn test_scores_cursor_round_trip() {
+    let cursor = ScoresCursor { creation_timestamp: at("2025-02-01 05:33:10") + chrono::TimeDelta::microseconds(123_456), id: 42 };
+    assert_eq!(ScoresCursor::decode(&cursor.encode()), Some(cursor));
+    assert_eq!(ScoresCursor::decode(""), None);
+    assert_eq!(ScoresCursor::decode("not a cursor"), None);
+    assert_eq!(ScoresCursor::decode(&URL_SAFE_NO_PAD.encode("12:x")), None);
+    assert_eq!(ScoresCursor::decode("2025-02-01 05:33:10"), None);
+    assert_eq!(ScoresCursor::decode("2025-02-01T05:33:10Z"), None);
+  }
+
+  #[test]
+  fn test_scores_cursor_order() {
+    let second = at("2025-02-01 05:33:10");
+    let earlier = ScoresCursor { creation_timestamp: second + chrono::TimeDelta::microseconds(1), id: 9 };
+    let later = ScoresCursor { creation_timestamp: second + chrono::TimeDelta::microseconds(2), id: 3 };
+    let tied = ScoresCursor { creation_timestamp: later.creation_timestamp, id: 4 };
+    assert!(earlier < later);
+    assert!(later < tied);
+    // A plain timestamp is after every score submitted at that
+    // instant, but before any score submitted later.
+    assert!(ScoresCursor::after_time(later.creation_timestamp) > tied);
+    assert!(ScoresCursor::after_time(second) < earlier);
+  }
+
+  #[rocket::get("/since?<since>")]
+  fn since_route(since: ScoresCursor) -> String {
+    format!("{} {}", since.creation_timestamp, since.id)
+  }
+
+  #[test]
+  fn test_scores_cursor_query_parameter() {
+    let client = Client::untracked(rocket::build().mount("/", routes![since_route])).unwrap();
+    let cursor = ScoresCursor { creation_timestamp: at("2025-02-01 05:33:10") + chrono::TimeDelta::microseconds(5), id: 7 };
+    let response = client.get(format!("/since?since={}", cursor.encode())).dispatch();
+    assert_eq!(response.into_string().unwrap(), "2025-02-01 05:33:10.000005 7");
+    let response = client.get("/since?since=2025-02-01%2005:33:10").dispatch();
+    assert_eq!(response.into_string().unwrap(), format!("2025-02-01 05:33:10 {}", i32::MAX));
+    let response = client.get("/since?since=yesterday").dispatch();
+    assert_eq!(response.status(), rocket::http::Status::UnprocessableEntity);
+  }
+
+  #[test]
+  fn test_polling_entries_carry_their_cursor() {
+    let stored = stored_entry(7, at("2025-02-01 05:33:10") + chrono::TimeDelta::microseconds(250));
+    let cursor = ScoresCursor::of_entry(&stored);
+    let options = polling_query(ScoresCursor::after_time(at("2025-02-01 05:33:09")), 10);
+    let response = options.response_entry(RankedTableEntry { entry: stored.clone(), rank: 1 });
+    assert_eq!(response.cursor.as_deref().and_then(ScoresCursor::decode), Some(cursor));
+    let response = ScoresQuery::default().response_entry(RankedTableEntry { entry: stored, rank: 1 });
+    assert_eq!(response.cursor, None);
+    assert!(serde_json::to_value(response).unwrap().get("cursor").is_none());
+  }
+
+  #[test]
+  fn test_since_filters_after_cursor_and_caps_at_limit() {
+    let since = ScoresCursor { creation_timestamp: at("2025-02-01 05:33:10") + chrono::TimeDelta::microseconds(250), id: 7 };
+    let query = diesel::debug_query::<Pg, _>(&scores_for_table_query(1, &polling_query(since, 25))).to_string();
+    assert!(query.contains("(creation_timestamp, id) > ($7, $8)"), "{}", query);
+    assert!(query.contains("ORDER BY creation_timestamp ASC, id ASC"), "{}", query);
+    assert!(query.contains("LIMIT $4"), "{}", query);
+    assert!(query.contains("Some(25)"), "{}", query);
+    assert!(query.contains("Some(2025-02-01T05:33:10.000250)"), "{}", query);
+    assert!(query.contains("Some(7)"), "{}", query);
+
+    let count = diesel::debug_query::<Pg, _>(&scores_in_time_window(1, &polling_query(since, 25))).to_string();
+    assert!(count.contains(r#"("highscore_table_entries"."creation_timestamp" > $2) OR (("highscore_table_entries"."creation_timestamp" = $3) AND ("highscore_table_entries"."id" > $4))"#), "{}", count);
+  }
+
+  /// The game as it is later read back from the database.
+  fn stored_game(new_game: &models::NewGame) -> models::Game {
+    models::Game {
+      id: 1,
+      developer_id: new_game.developer_id,
+      game_uuid: new_game.game_uuid,
+      game_secret_key: new_game.game_secret_key.clone(),
+      name: new_game.name.clone(),
+      security_level: new_game.security_level,
+      min_submit_interval_ms: new_game.min_submit_interval_ms,
+      enabled: new_game.enabled,
+      description: new_game.description.clone(),
+      platform: new_game.platform.clone(),
+      daily_submission_quota: new_game.daily_submission_quota,
+      external_id: new_game.external_id.clone(),
+      default_algorithm: new_game.default_algorithm.clone(),
+      previous_game_secret_key: None,
+      previous_secret_key_expires_at: None,
+      max_tables: None,
+    }
+  }
+
+  #[test]
+  fn test_game_metadata_round_trips() {
+    let params = serde_json::from_value::<NewGameDao>(serde_json::json!({
+      "developer_uuid": "0194c0b0-0000-7000-8000-000000000001",
+      "name": "Game",
+      "description": "A game about bananas",
+      "platform": "Windows, Linux",
+    })).unwrap();
+    let new_game = new_game_from_params(1, params);
+    let developer_uuid = Uuid::now_v7();
+    let fetched = GameResponse::from_game(stored_game(&new_game), developer_uuid);
+    let created = GameResponse::from_new_game(new_game, developer_uuid);
+    for response in [created, fetched] {
+      assert_eq!(response.description.as_deref(), Some("A game about bananas"));
+      assert_eq!(response.platform.as_deref(), Some("Windows, Linux"));
+    }
+  }
+}
@@ -0,0 +1,168 @@
+
+//! Record of administrative and moderation actions, for later
+//! review.
+
+use crate::db::{schema, models};
+use crate::util::UuidParam;
+use super::api::QueryTimestamp;
+use super::openapi::OpenApiUuid;
+
+use rocket::{FromForm, FromFormField};
+use serde::Serialize;
+use uuid::Uuid;
+use diesel::prelude::*;
+use diesel::pg::Pg;
+use diesel_async::{RunQueryDsl, AsyncPgConnection};
+use utoipa::ToSchema;
+
+/// Default number of entries returned by the audit log endpoint.
+pub const DEFAULT_AUDIT_LOG_LIMIT: u32 = 100;
+
+/// Maximum number of entries returned by the audit log endpoint in
+/// one page.
+pub const MAX_AUDIT_LOG_LIMIT: u32 = 1000;
+
+/// An administrative action recorded in the audit log.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, FromFormField)]
+pub enum AuditAction {
+  #[field(value = "create_developer")]
+  CreateDeveloper,
+  #[field(value = "set_game_enabled")]
+  SetGameEnabled,
+  #[field(value = "set_game_quota")]
+  SetGameQuota,
+  #[field(value = "retrim")]
+  Retrim,
+  #[field(value = "recount")]
+  Recount,
+  #[field(value = "import_scores")]
+  ImportScores,
+  #[field(value = "impersonate_developer")]
+  ImpersonateDeveloper,
+  #[field(value = "revoke_tokens")]
+  RevokeTokens,
+  #[field(value = "set_default_security_level")]
+  SetDefaultSecurityLevel,
+  #[field(value = "delete_player_scores")]
+  DeletePlayerScores,
+  #[field(value = "set_game_max_tables")]
+  SetGameMaxTables,
+  #[field(value = "delete_highscore_table")]
+  DeleteHighscoreTable,
+  #[field(value = "replay_webhook")]
+  ReplayWebhook,
+}
+
+/// Query parameters accepted by the audit log endpoint.
+#[derive(Debug, Clone, Default, FromForm)]
+pub struct AuditLogParams {
+  pub actor: Option<UuidParam>,
+  pub action: Option<AuditAction>,
+  pub since: Option<QueryTimestamp>,
+  pub until: Option<QueryTimestamp>,
+  pub limit: Option<u32>,
+  pub offset: Option<u32>,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct AuditLogEntryResponse {
+  pub id: i32,
+  /// The user who performed the action. This is an administrator,
+  /// except for deletions made by a game's owner.
+  #[schema(value_type = OpenApiUuid)]
+  pub actor_uuid: Uuid,
+  /// What was done, such as `set_game_quota`.
+  pub action: String,
+  /// The developer, game, or highscore table acted upon, if any.
+  #[schema(value_type = Option<OpenApiUuid>)]
+  pub target_uuid: Option<Uuid>,
+  /// Free-form description of the action's arguments or outcome.
+  pub details: Option<String>,
+  /// When the action was performed, in UTC.
+  #[schema(value_type = String, example = "2025-02-01T05:33:10Z")]
+  pub timestamp: chrono::DateTime<chrono::Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct AuditLogResponse {
+  /// Matching entries, newest first.
+  pub entries: Vec<AuditLogEntryResponse>,
+}
+
+impl AuditAction {
+  pub fn as_str(self) -> &'static str {
+    match self {
+      AuditAction::CreateDeveloper => "create_developer",
+      AuditAction::SetGameEnabled => "set_game_enabled",
+      AuditAction::SetGameQuota => "set_game_quota",
+      AuditAction::Retrim => "retrim",
+      AuditAction::Recount => "recount",
+      AuditAction::ImportScores => "import_scores",
+      AuditAction::ImpersonateDeveloper => "impersonate_developer",
+      AuditAction::RevokeTokens => "revoke_tokens",
+      AuditAction::SetDefaultSecurityLevel => "set_default_security_level",
+      AuditAction::DeletePlayerScores => "delete_player_scores",
+      AuditAction::SetGameMaxTables => "set_game_max_tables",
+      AuditAction::DeleteHighscoreTable => "delete_highscore_table",
+      AuditAction::ReplayWebhook => "replay_webhook",
+    }
+  }
+}
+
+impl AuditLogParams {
+  pub fn limit(&self) -> u32 {
+    self.limit.unwrap_or(DEFAULT_AUDIT_LOG_LIMIT)
+  }
+
+  /// The audit log, filtered by these parameters but not paginated.
+  pub fn filtered_query(&self) -> schema::audit_log::BoxedQuery<'static, Pg> {
+    let mut query = schema::audit_log::table.into_boxed();
+    if let Some(actor) = &self.actor {
+      query = query.filter(schema::audit_log::actor_uuid.eq(actor.0));
+    }
+    if let Some(action) = self.action {
+      query = query.filter(schema::audit_log::action.eq(action.as_str()));
+    }
+    if let Some(QueryTimestamp(since)) = self.since {
+      query = query.filter(schema::audit_log::timestamp.ge(since));
+    }
+    if let Some(QueryTimestamp(until)) = self.until {
+      query = query.filter(schema::audit_log::timestamp.le(until));
+    }
+    query
+  }
+}
+
+impl From<models::AuditLogEntry> for AuditLogEntryResponse {
+  fn from(entry: models::AuditLogEntry) -> Self {
+    Self {
+      id: entry.id,
+      actor_uuid: entry.actor_uuid,
+      action: entry.action,
+      target_uuid: entry.target_uuid,
+      details: entry.details,
+      timestamp: entry.timestamp.and_utc(),
+    }
+  }
+}
+
+/// Appends an entry to the audit log.
+pub async fn record_audit_event(
+  actor_uuid: Uuid,
+  action: AuditAction,
+  target_uuid: Option<Uuid>,
+  details: Option<String>,
+  db: &mut AsyncPgConnection,
+) -> QueryResult<()> {
+  let entry = models::NewAuditLogEntry {
+    actor_uuid,
+    action: action.as_str().to_owned(),
+    target_uuid,
+    details,
+  };
+  diesel::insert_into(schema::audit_log::table)
+    .values(&entry)
+    .execute(db)
+    .await?;
+  Ok(())
+}
@@ -4,6 +4,7 @@ use uuid::Uuid;
 use bitflags::bitflags;
 use thiserror::Error;
 use jsonwebtoken::{encode, decode, EncodingKey, DecodingKey, Validation, Header};
+use chrono::{DateTime, TimeDelta, Utc};
 
 use std::env;
 
@@ -16,6 +17,18 @@ pub struct JwtClaim {
   pub user_flags: UserFlags,
   /// Expiration time, in seconds since the Unix epoch.
   pub exp: usize,
+  /// Time at which the token was issued, in seconds since the Unix
+  /// epoch. Tokens issued before this claim existed read as 0.
+  #[serde(default)]
+  pub iat: usize,
+  /// Time before which the token must not be accepted, in seconds
+  /// since the Unix epoch.
+  #[serde(default)]
+  pub nbf: usize,
+  /// If this token was issued for an administrator acting as another
+  /// developer, the administrator's UUID.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub impersonated_by: Option<Uuid>,
 }
 
 #[derive(Debug, Clone, Error)]
@@ -28,36 +41,82 @@ pub enum JwtError {
 }
 
 pub const SECRET_KEY_ENV_VAR: &str = "JWT_SECRET_KEY";
-pub const JWT_EXPIRATION_TIME: chrono::Duration = chrono::Duration::hours(1);
+pub const IMPERSONATION_EXPIRATION_TIME: chrono::Duration = chrono::Duration::minutes(15);
 
 bitflags! {
   #[derive(Debug, Clone, Default, Copy, PartialEq, Eq, Serialize, Deserialize)]
   pub struct UserFlags: u32 {
     const ADMIN = 0b00000001;
+    const SUPER_ADMIN = 0b00000010;
   }
 }
 
-pub fn create_token(user_uuid: &Uuid, user_flags: UserFlags) -> Result<String, JwtError> {
+/// Creates a token which expires `expiration` from now.
+pub fn create_token(user_uuid: &Uuid, user_flags: UserFlags, expiration: TimeDelta) -> Result<String, JwtError> {
+  create_token_valid_from(user_uuid, user_flags, chrono::Utc::now(), expiration)
+}
+
+/// Creates a token which cannot be used until `not_before`. The token
+/// expires `expiration` after it becomes valid.
+pub fn create_token_valid_from(
+  user_uuid: &Uuid,
+  user_flags: UserFlags,
+  not_before: DateTime<Utc>,
+  expiration: TimeDelta,
+) -> Result<String, JwtError> {
   let claim = JwtClaim {
     sub: user_uuid.to_owned(),
     user_flags,
-    exp: (chrono::Utc::now() + JWT_EXPIRATION_TIME).timestamp() as usize,
+    exp: (not_before + expiration).timestamp() as usize,
+    iat: chrono::Utc::now().timestamp() as usize,
+    nbf: not_before.timestamp() as usize,
+    impersonated_by: None,
+  };
+  encode_claim(&claim)
+}
+
+/// Creates a short-lived token allowing the administrator
+/// `admin_uuid` to act as the developer `user_uuid`. The token
+/// carries none of the administrator's flags, so it grants exactly
+/// the target developer's permissions.
+pub fn create_impersonation_token(user_uuid: &Uuid, admin_uuid: &Uuid) -> Result<String, JwtError> {
+  let now = chrono::Utc::now();
+  let claim = JwtClaim {
+    sub: user_uuid.to_owned(),
+    user_flags: UserFlags::empty(),
+    exp: (now + IMPERSONATION_EXPIRATION_TIME).timestamp() as usize,
+    iat: now.timestamp() as usize,
+    nbf: now.timestamp() as usize,
+    impersonated_by: Some(admin_uuid.to_owned()),
   };
+  encode_claim(&claim)
+}
+
+fn encode_claim(claim: &JwtClaim) -> Result<String, JwtError> {
   let encoding_key = EncodingKey::from_base64_secret(&get_secret_key()?)?;
   let token = encode(
     &Header::default(),
-    &claim,
+    claim,
     &encoding_key,
   )?;
   Ok(token)
 }
 
-pub fn verify_token(token_str: &str) -> Result<JwtClaim, JwtError> {
-  let decoding_key = DecodingKey::from_base64_secret(&get_secret_key()?)?;
+/// Verifies a token, tolerating `leeway_seconds` of clock skew in its
+/// time-based claims.
+pub fn verify_token(token_str: &str, leeway_seconds: u64) -> Result<JwtClaim, JwtError> {
+  decode_claim(token_str, &get_secret_key()?, leeway_seconds)
+}
+
+fn decode_claim(token_str: &str, secret_key: &str, leeway_seconds: u64) -> Result<JwtClaim, JwtError> {
+  let decoding_key = DecodingKey::from_base64_secret(secret_key)?;
+  let mut validation = Validation::default();
+  validation.leeway = leeway_seconds;
+  validation.validate_nbf = true;
   let claims = decode::<JwtClaim>(
     token_str,
     &decoding_key,
-    &Validation::default(),
+    &validation,
   )?;
   Ok(claims.claims)
 }
@@ -66,3 +125,73 @@ fn get_secret_key() -> Result<String, JwtError> {
   env::var(SECRET_KEY_ENV_VAR)
     .map_err(|_| JwtError::MissingJwtSecretKeyEnvVar)
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  /// Base64 of a test-only secret key.
+  const SECRET_KEY: &str = "dGVzdC1vbmx5LXNlY3JldC1rZXk=";
+
+  fn encode_test_claim(claim: &JwtClaim) -> String {
+    encode(&Header::default(), claim, &EncodingKey::from_base64_secret(SECRET_KEY).unwrap()).unwrap()
+  }
+
+  fn token_expired_seconds_ago(seconds: i64) -> String {
+    let now = chrono::Utc::now();
+    let claim = JwtClaim {
+      sub: Uuid::now_v7(),
+      user_flags: UserFlags::empty(),
+      exp: (now - TimeDelta::seconds(seconds)).timestamp() as usize,
+      iat: (now - TimeDelta::hours(1)).timestamp() as usize,
+      nbf: (now - TimeDelta::hours(1)).timestamp() as usize,
+      impersonated_by: None,
+    };
+    encode_test_claim(&claim)
+  }
+
+  fn token_valid_in_seconds(seconds: i64) -> String {
+    let now = chrono::Utc::now();
+    let claim = JwtClaim {
+      sub: Uuid::now_v7(),
+      user_flags: UserFlags::empty(),
+      exp: (now + TimeDelta::hours(1)).timestamp() as usize,
+      iat: now.timestamp() as usize,
+      nbf: (now + TimeDelta::seconds(seconds)).timestamp() as usize,
+      impersonated_by: None,
+    };
+    encode_test_claim(&claim)
+  }
+
+  #[test]
+  fn test_leeway_accepts_recently_expired_token() {
+    let token = token_expired_seconds_ago(10);
+    assert!(decode_claim(&token, SECRET_KEY, 30).is_ok());
+  }
+
+  #[test]
+  fn test_expired_token_rejected_without_leeway() {
+    let token = token_expired_seconds_ago(10);
+    assert!(decode_claim(&token, SECRET_KEY, 0).is_err());
+  }
+
+  #[test]
+  fn test_token_rejected_with_wrong_key() {
+    let token = token_expired_seconds_ago(-60);
+    assert!(decode_claim(&token, SECRET_KEY, 30).is_ok());
+    assert!(decode_claim(&token, "b3RoZXIta2V5", 30).is_err());
+  }
+
+  #[test]
+  fn test_token_rejected_before_nbf() {
+    let token = token_valid_in_seconds(600);
+    assert!(decode_claim(&token, SECRET_KEY, 30).is_err());
+  }
+
+  #[test]
+  fn test_leeway_accepts_token_nearly_valid() {
+    let token = token_valid_in_seconds(10);
+    assert!(decode_claim(&token, SECRET_KEY, 30).is_ok());
+    assert!(decode_claim(&token, SECRET_KEY, 0).is_err());
+  }
+}
@@ -3,20 +3,27 @@
 
 mod header;
 mod jwt;
+mod password;
 
 pub use header::{XApiKey, X_API_KEY_HEADER};
-pub use jwt::{create_token, verify_token, JwtClaim, JwtError, UserFlags};
+pub use password::{hash_password, verify_password, find_matching_password, PasswordHashError};
+pub use jwt::{create_token, create_token_valid_from, create_impersonation_token, verify_token, JwtClaim, JwtError, UserFlags};
+pub use jwt::{IMPERSONATION_EXPIRATION_TIME, SECRET_KEY_ENV_VAR};
 
+use crate::config::{AppConfig, DEFAULT_JWT_LEEWAY_SECONDS};
 use crate::db::schema::developers;
 use crate::util::header::Authorization;
-use super::error::ApiError;
+use super::db::Db;
+use super::error::{stash_error, ApiError};
 
-use rocket::http::Status;
+use rocket::http::{Cookie, SameSite, Status};
 use rocket::request::{self, Request, FromRequest};
 use thiserror::Error;
 use diesel::prelude::*;
 use diesel_async::{AsyncPgConnection, RunQueryDsl};
+use rocket_db_pools::Connection;
 use uuid::Uuid;
+use chrono::{NaiveDateTime, TimeDelta};
 
 use std::str::FromStr;
 use std::convert::AsRef;
@@ -30,6 +37,8 @@ pub enum AuthError {
   DieselError(#[from] diesel::result::Error),
   #[error("Invalid API key")]
   InvalidApiKey,
+  #[error("Invalid email or password")]
+  InvalidCredentials,
 }
 
 /// Rocket request guard that requires an `Authorization: Bearer xxx`
@@ -49,6 +58,17 @@ pub struct AdminUser {
   claim: JwtClaim,
 }
 
+/// Rocket request guard that requires an `Authorization: Bearer xxx`
+/// header for a super admin user, who may use endpoints too dangerous
+/// for ordinary admins.
+///
+/// The requests accepted by this guard are strictly a subset of those
+/// accepted by [`AdminUser`].
+#[derive(Debug, Clone)]
+pub struct SuperAdminUser {
+  claim: JwtClaim,
+}
+
 /// Subset of the `Developer` model containing the columns needed to
 /// generate a JWT token.
 #[derive(Queryable, Selectable, Clone)]
@@ -57,32 +77,94 @@ pub struct AdminUser {
 struct DeveloperPerms {
   pub developer_uuid: Uuid,
   pub is_admin: bool,
+  pub is_super_admin: bool,
+  pub flags: i32,
 }
 
 pub const MISSING_AUTH_HEADER: &str = "Missing Authorization header";
+pub const REVOKED_TOKEN: &str = "Token has been revoked";
+/// Name of the cookie which may carry a JWT token, as an alternative
+/// to the `Authorization` header for browser clients.
+pub const JWT_COOKIE_NAME: &str = "topbanana_jwt";
 pub const INVALID_AUTH_HEADER: &str = "Invalid Authorization header";
 
-pub async fn create_jwt_for_api_key(api_key: &str, db: &mut AsyncPgConnection) -> Result<String, AuthError> {
-  let perms = developers::table.filter(developers::api_key.eq(api_key))
+pub async fn create_jwt_for_api_key(api_key: &str, expiration: TimeDelta, db: &mut AsyncPgConnection) -> Result<String, AuthError> {
+  let perms = find_perms_for_api_key(api_key, db).await?;
+  let user_flags = perms.user_flags();
+  let token = create_token(&perms.developer_uuid, user_flags, expiration)?;
+  Ok(token)
+}
+
+/// Checks that the API key belongs to a developer, returning that
+/// developer's UUID, without creating a token.
+pub async fn check_api_key(api_key: &str, db: &mut AsyncPgConnection) -> Result<Uuid, AuthError> {
+  let perms = find_perms_for_api_key(api_key, db).await?;
+  Ok(perms.developer_uuid)
+}
+
+async fn find_perms_for_api_key(api_key: &str, db: &mut AsyncPgConnection) -> Result<DeveloperPerms, AuthError> {
+  developers::table.filter(developers::api_key.eq(api_key))
     .select(DeveloperPerms::as_select())
     .first(db)
     .await
-    .optional()?;
-  let Some(perms) = perms else {
-    return Err(AuthError::InvalidApiKey);
+    .optional()?
+    .ok_or(AuthError::InvalidApiKey)
+}
+
+/// Creates a JWT token for the developer with the given email and
+/// password. Developers without a password cannot log in this way.
+pub async fn create_jwt_for_password(
+  email: &str,
+  password: &str,
+  expiration: TimeDelta,
+  db: &mut AsyncPgConnection,
+) -> Result<String, AuthError> {
+  // Email is not unique on its own, so check each candidate.
+  let candidates = developers::table
+    .filter(developers::email.eq(email))
+    .filter(developers::password_hash.is_not_null())
+    .select((DeveloperPerms::as_select(), developers::password_hash.assume_not_null()))
+    .load::<(DeveloperPerms, String)>(db)
+    .await?;
+  let (candidates, password_hashes): (Vec<_>, Vec<_>) = candidates.into_iter().unzip();
+  let Some(index) = find_matching_password(password, password_hashes).await else {
+    return Err(AuthError::InvalidCredentials);
   };
-  let user_flags = perms.user_flags();
-  let token = create_token(&perms.developer_uuid, user_flags)?;
+  let perms = &candidates[index];
+  let token = create_token(&perms.developer_uuid, perms.user_flags(), expiration)?;
   Ok(token)
 }
 
+/// Builds the HttpOnly cookie carrying a JWT token. The cookie
+/// expires along with the token, `expiration` from now.
+pub fn jwt_cookie(token: String, expiration: TimeDelta) -> Cookie<'static> {
+  Cookie::build((JWT_COOKIE_NAME, token))
+    .path("/api")
+    .http_only(true)
+    .secure(true)
+    .same_site(SameSite::Strict)
+    .max_age(rocket::time::Duration::seconds(expiration.num_seconds()))
+    .build()
+}
+
 impl DeveloperUser {
   pub fn user_uuid(&self) -> &Uuid {
     &self.claim.sub
   }
 
+  /// Whether the user's claim carries all of the given flags.
+  pub fn has_flag(&self, flag: UserFlags) -> bool {
+    self.claim.user_flags.contains(flag)
+  }
+
   pub fn is_admin(&self) -> bool {
-    self.claim.user_flags.contains(UserFlags::ADMIN)
+    self.has_flag(UserFlags::ADMIN)
+  }
+
+  /// The administrator acting as this user, if the request was made
+  /// with an impersonation token.
+  pub fn impersonated_by(&self) -> Option<&Uuid> {
+    self.claim.impersonated_by.as_ref()
   }
 }
 
@@ -90,34 +172,114 @@ impl AdminUser {
   pub fn user_uuid(&self) -> &Uuid {
     &self.claim.sub
   }
+
+  /// Whether the user's claim carries all of the given flags.
+  pub fn has_flag(&self, flag: UserFlags) -> bool {
+    self.claim.user_flags.contains(flag)
+  }
+
+  /// Always true for a successfully-constructed `AdminUser`, but
+  /// provided for parity with [`DeveloperUser::is_admin`].
+  pub fn is_admin(&self) -> bool {
+    self.has_flag(UserFlags::ADMIN)
+  }
+
+  pub fn is_super_admin(&self) -> bool {
+    self.has_flag(UserFlags::ADMIN | UserFlags::SUPER_ADMIN)
+  }
+}
+
+impl SuperAdminUser {
+  pub fn user_uuid(&self) -> &Uuid {
+    &self.claim.sub
+  }
+
+  /// Whether the user's claim carries all of the given flags.
+  pub fn has_flag(&self, flag: UserFlags) -> bool {
+    self.claim.user_flags.contains(flag)
+  }
 }
 
 impl DeveloperPerms {
+  /// The persisted flags, together with those implied by the older
+  /// `is_admin` and `is_super_admin` columns. Unknown bits are
+  /// dropped. A super admin flag on a developer who is not an admin
+  /// grants nothing, since [`SuperAdminUser`] requires both.
   fn user_flags(&self) -> UserFlags {
+    let mut flags = UserFlags::from_bits_truncate(self.flags as u32);
     if self.is_admin {
-      UserFlags::ADMIN
-    } else {
-      UserFlags::empty()
+      flags |= UserFlags::ADMIN;
+    }
+    if self.is_super_admin {
+      flags |= UserFlags::SUPER_ADMIN;
     }
+    flags
   }
 }
 
+/// Whether the claim was issued before its developer's
+/// `tokens_valid_after` cutoff, as [`issued_before_cutoff`].
+async fn is_revoked(claim: &JwtClaim, db: &mut AsyncPgConnection) -> QueryResult<bool> {
+  let tokens_valid_after = developers::table
+    .filter(developers::developer_uuid.eq(claim.sub))
+    .select(developers::tokens_valid_after)
+    .first::<Option<NaiveDateTime>>(db)
+    .await
+    .optional()?
+    .flatten();
+  Ok(issued_before_cutoff(claim, tokens_valid_after))
+}
+
+/// Whether the claim was issued before `tokens_valid_after`, if there
+/// is such a cutoff. The claim's `iat` has only one-second precision,
+/// so a token issued in the same second as the cutoff is still
+/// accepted.
+fn issued_before_cutoff(claim: &JwtClaim, tokens_valid_after: Option<NaiveDateTime>) -> bool {
+  tokens_valid_after.is_some_and(|cutoff| (claim.iat as i64) < cutoff.and_utc().timestamp())
+}
+
 #[rocket::async_trait]
 impl<'r> FromRequest<'r> for DeveloperUser {
   type Error = ApiError;
 
   async fn from_request(req: &'r Request<'_>) -> request::Outcome<Self, ApiError> {
-    let Some(auth_header) = req.headers().get_one("Authorization")
-      .and_then(|value| Authorization::from_str(value).ok()) else {
-        return request::Outcome::Error((Status::Unauthorized, ApiError::unauthorized().with_message(MISSING_AUTH_HEADER)));
-      };
-    if auth_header.scheme != "Bearer" {
-      return request::Outcome::Error((Status::Unauthorized, ApiError::unauthorized().with_message(INVALID_AUTH_HEADER)));
+    fn fail(req: &Request<'_>, err: ApiError) -> request::Outcome<DeveloperUser, ApiError> {
+      stash_error(req, &err);
+      request::Outcome::Error((err.status(), err))
     }
-    let token = auth_header.params;
-    let Ok(claim) = verify_token(&token) else {
-      return request::Outcome::Error((Status::Unauthorized, ApiError::unauthorized().with_message(INVALID_AUTH_HEADER)));
+
+    // The Authorization header takes precedence. The cookie is only
+    // consulted if the header is absent.
+    let token = match req.headers().get_one("Authorization") {
+      Some(value) => {
+        let Ok(auth_header) = Authorization::from_str(value) else {
+          return fail(req, ApiError::unauthorized().with_message(INVALID_AUTH_HEADER));
+        };
+        if auth_header.scheme != "Bearer" {
+          return fail(req, ApiError::unauthorized().with_message(INVALID_AUTH_HEADER));
+        }
+        auth_header.params
+      }
+      None => {
+        let Some(cookie) = req.cookies().get(JWT_COOKIE_NAME) else {
+          return fail(req, ApiError::unauthorized().with_message(MISSING_AUTH_HEADER));
+        };
+        cookie.value().to_owned()
+      }
+    };
+    let leeway_seconds = req.rocket().state::<AppConfig>().map_or(DEFAULT_JWT_LEEWAY_SECONDS, |config| config.jwt_leeway_seconds);
+    let Ok(claim) = verify_token(&token, leeway_seconds) else {
+      return fail(req, ApiError::unauthorized().with_message(INVALID_AUTH_HEADER));
+    };
+    let mut db = match req.guard::<Connection<Db>>().await {
+      request::Outcome::Success(db) => db,
+      _ => return fail(req, ApiError::from_status(Status::ServiceUnavailable)),
     };
+    match is_revoked(&claim, &mut db).await {
+      Ok(false) => {}
+      Ok(true) => return fail(req, ApiError::unauthorized().with_message(REVOKED_TOKEN)),
+      Err(err) => return fail(req, ApiError::from(err)),
+    }
     request::Outcome::Success(DeveloperUser { claim })
   }
 }
@@ -132,13 +294,30 @@ impl<'r> FromRequest<'r> for AdminUser {
       request::Outcome::Error(f) => return request::Outcome::Error(f),
       request::Outcome::Forward(f) => return request::Outcome::Forward(f),
     };
-    if !developer.claim.user_flags.contains(UserFlags::ADMIN) {
+    if !developer.is_admin() {
       return request::Outcome::Error((Status::Forbidden, ApiError::forbidden()));
     }
     request::Outcome::Success(AdminUser { claim: developer.claim })
   }
 }
 
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for SuperAdminUser {
+  type Error = ApiError;
+
+  async fn from_request(req: &'r Request<'_>) -> request::Outcome<Self, ApiError> {
+    let admin = match AdminUser::from_request(req).await {
+      request::Outcome::Success(admin) => admin,
+      request::Outcome::Error(f) => return request::Outcome::Error(f),
+      request::Outcome::Forward(f) => return request::Outcome::Forward(f),
+    };
+    if !admin.is_super_admin() {
+      return request::Outcome::Error((Status::Forbidden, ApiError::forbidden()));
+    }
+    request::Outcome::Success(SuperAdminUser { claim: admin.claim })
+  }
+}
+
 impl AsRef<JwtClaim> for DeveloperUser {
   fn as_ref(&self) -> &JwtClaim {
     &self.claim
@@ -150,3 +329,98 @@ impl AsRef<JwtClaim> for AdminUser {
     &self.claim
   }
 }
+
+impl AsRef<JwtClaim> for SuperAdminUser {
+  fn as_ref(&self) -> &JwtClaim {
+    &self.claim
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  use rocket::{get, routes};
+  use rocket::http::Header;
+  use rocket::local::blocking::{Client, LocalResponse};
+
+  fn claim(user_flags: UserFlags) -> JwtClaim {
+    JwtClaim { sub: Uuid::now_v7(), user_flags, exp: 0, iat: 0, nbf: 0, impersonated_by: None }
+  }
+
+  fn perms(flags: i32, is_admin: bool, is_super_admin: bool) -> DeveloperPerms {
+    DeveloperPerms { developer_uuid: Uuid::now_v7(), is_admin, is_super_admin, flags }
+  }
+
+  #[test]
+  fn test_developer_has_flag() {
+    let developer = DeveloperUser { claim: claim(UserFlags::empty()) };
+    assert!(!developer.is_admin());
+    assert!(!developer.has_flag(UserFlags::ADMIN));
+    assert!(developer.has_flag(UserFlags::empty()));
+
+    let admin = DeveloperUser { claim: claim(UserFlags::ADMIN) };
+    assert!(admin.is_admin());
+    assert!(!admin.has_flag(UserFlags::ADMIN | UserFlags::SUPER_ADMIN));
+  }
+
+  #[test]
+  fn test_admin_has_flag() {
+    let admin = AdminUser { claim: claim(UserFlags::ADMIN) };
+    assert!(admin.is_admin());
+    assert!(!admin.is_super_admin());
+    assert!(!admin.has_flag(UserFlags::SUPER_ADMIN));
+
+    // A super admin flag without the admin flag does not make a
+    // super admin.
+    let admin = AdminUser { claim: claim(UserFlags::SUPER_ADMIN) };
+    assert!(!admin.is_super_admin());
+    let admin = AdminUser { claim: claim(UserFlags::ADMIN | UserFlags::SUPER_ADMIN) };
+    assert!(admin.is_super_admin());
+  }
+
+  #[test]
+  fn test_user_flags_combine_columns() {
+    assert_eq!(perms(0, false, false).user_flags(), UserFlags::empty());
+    assert_eq!(perms(0, true, false).user_flags(), UserFlags::ADMIN);
+    assert_eq!(perms(0, true, true).user_flags(), UserFlags::ADMIN | UserFlags::SUPER_ADMIN);
+    assert_eq!(perms(UserFlags::ADMIN.bits() as i32, false, false).user_flags(), UserFlags::ADMIN);
+    assert_eq!(perms(UserFlags::SUPER_ADMIN.bits() as i32, true, false).user_flags(), UserFlags::ADMIN | UserFlags::SUPER_ADMIN);
+    // Unknown bits are dropped.
+    assert_eq!(perms(0b1000_0001, false, false).user_flags(), UserFlags::ADMIN);
+  }
+
+  #[test]
+  fn test_token_issued_before_cutoff_is_revoked() {
+    let now = chrono::Utc::now();
+    let cutoff_time = (now - TimeDelta::minutes(5)).naive_utc();
+    let cutoff = Some(cutoff_time);
+    let mut stale = claim(UserFlags::empty());
+    stale.iat = (now - TimeDelta::hours(1)).timestamp() as usize;
+    assert!(issued_before_cutoff(&stale, cutoff));
+    let mut fresh = claim(UserFlags::empty());
+    fresh.iat = now.timestamp() as usize;
+    assert!(!issued_before_cutoff(&fresh, cutoff));
+    // A token from the same second as the cutoff is still accepted.
+    let mut same_second = claim(UserFlags::empty());
+    same_second.iat = cutoff_time.and_utc().timestamp() as usize;
+    assert!(!issued_before_cutoff(&same_second, cutoff));
+    assert!(!issued_before_cutoff(&stale, None));
+  }
+
+  #[get("/developer")]
+  fn developer(_user: DeveloperUser) {}
+
+  #[test]
+  fn test_guard_errors_reach_the_catcher() {
+    let rocket = rocket::build().mount("/", routes![developer]).register("/", crate::server::error::catchers());
+    let client = Client::untracked(rocket).unwrap();
+    let reason = |response: LocalResponse<'_>| {
+      assert_eq!(response.status(), Status::Unauthorized);
+      response.into_json::<serde_json::Value>().unwrap()["reason"].as_str().unwrap().to_owned()
+    };
+    assert_eq!(reason(client.get("/developer").dispatch()), MISSING_AUTH_HEADER);
+    let basic = client.get("/developer").header(Header::new("Authorization", "Basic dXNlcjpwYXNz")).dispatch();
+    assert_eq!(reason(basic), INVALID_AUTH_HEADER);
+  }
+}
@@ -0,0 +1,109 @@
+
+//! Password hashing for developers who log in with a password rather
+//! than an API key.
+
+use argon2::Argon2;
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use rand::{TryRngCore, RngCore};
+use rand::rngs::OsRng;
+use rocket::tokio::task;
+use thiserror::Error;
+
+use std::panic;
+use std::sync::OnceLock;
+
+#[derive(Debug, Clone, Error)]
+#[error("Failed to hash password: {0}")]
+pub struct PasswordHashError(argon2::password_hash::Error);
+
+/// Hashes a password with Argon2 and a fresh random salt, producing
+/// a PHC-format string suitable for storage.
+pub fn hash_password(password: &str) -> Result<String, PasswordHashError> {
+  let mut salt_bytes = [0u8; 16];
+  OsRng.unwrap_err().fill_bytes(&mut salt_bytes);
+  let salt = SaltString::encode_b64(&salt_bytes).map_err(PasswordHashError)?;
+  let hash = Argon2::default()
+    .hash_password(password.as_bytes(), &salt)
+    .map_err(PasswordHashError)?;
+  Ok(hash.to_string())
+}
+
+/// Checks a password against a stored PHC-format hash. A malformed
+/// stored hash never matches.
+pub fn verify_password(password: &str, password_hash: &str) -> bool {
+  let Ok(password_hash) = PasswordHash::new(password_hash) else {
+    return false;
+  };
+  Argon2::default().verify_password(password.as_bytes(), &password_hash).is_ok()
+}
+
+/// Checks a password against each of the given stored hashes in turn,
+/// returning the index of the first which matches.
+///
+/// Argon2 is deliberately slow, so the checks run on the blocking
+/// thread pool rather than the async executor. If there are no hashes
+/// to check, the password is checked against a dummy hash instead, so
+/// that an unknown email takes as long to reject as a wrong password.
+pub async fn find_matching_password(password: &str, password_hashes: Vec<String>) -> Option<usize> {
+  let password = password.to_owned();
+  let result = task::spawn_blocking(move || {
+    if password_hashes.is_empty() {
+      verify_password(&password, dummy_password_hash());
+      return None;
+    }
+    password_hashes.iter().position(|password_hash| verify_password(&password, password_hash))
+  }).await;
+  result.unwrap_or_else(|err| panic::resume_unwind(err.into_panic()))
+}
+
+/// A hash of a random password, with the same parameters as real
+/// hashes, which no password will match in practice.
+fn dummy_password_hash() -> &'static str {
+  static DUMMY_PASSWORD_HASH: OnceLock<String> = OnceLock::new();
+  DUMMY_PASSWORD_HASH.get_or_init(|| {
+    let mut password_bytes = [0u8; 32];
+    OsRng.unwrap_err().fill_bytes(&mut password_bytes);
+    let password = password_bytes.iter().map(|byte| format!("{:02x}", byte)).collect::<String>();
+    hash_password(&password).expect("Failed to hash dummy password")
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_verify_correct_password() {
+    let password_hash = hash_password("hunter2").unwrap();
+    assert!(verify_password("hunter2", &password_hash));
+  }
+
+  #[test]
+  fn test_verify_incorrect_password() {
+    let password_hash = hash_password("hunter2").unwrap();
+    assert!(!verify_password("hunter3", &password_hash));
+    assert!(!verify_password("", &password_hash));
+  }
+
+  #[test]
+  fn test_verify_malformed_hash() {
+    assert!(!verify_password("hunter2", "hunter2"));
+  }
+
+  #[test]
+  fn test_dummy_hash_is_well_formed() {
+    assert!(PasswordHash::new(dummy_password_hash()).is_ok());
+  }
+
+  #[rocket::async_test]
+  async fn test_find_matching_password() {
+    let password_hashes = vec![hash_password("hunter2").unwrap(), hash_password("hunter3").unwrap()];
+    assert_eq!(find_matching_password("hunter3", password_hashes.clone()).await, Some(1));
+    assert_eq!(find_matching_password("hunter4", password_hashes).await, None);
+  }
+
+  #[rocket::async_test]
+  async fn test_find_matching_password_without_candidates() {
+    assert_eq!(find_matching_password("hunter2", Vec::new()).await, None);
+  }
+}
@@ -0,0 +1,73 @@
+
+//! The server's clock, for clients to synchronize with.
+//!
+//! Game requests whose `request_timestamp` is too far from the
+//! server's clock are rejected, so a client with a badly set clock can
+//! compare against `/time` and correct its timestamps before signing.
+
+use super::cors::WithWildcardCors;
+use super::error::{ApiSuccessResponse, ApiSuccessResponseBody};
+
+use rocket::{Route, get, routes};
+use serde::Serialize;
+use utoipa::ToSchema;
+use chrono::{SecondsFormat, Utc};
+
+pub fn clock_routes() -> Vec<Route> {
+  routes![get_time]
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct TimeResponse {
+  /// The current time, in seconds since the Unix epoch. This is the
+  /// format of a game request's `request_timestamp`.
+  #[schema(example = "1738387990")]
+  pub unix_seconds: i64,
+  /// The current time in UTC, in RFC 3339 format.
+  #[schema(example = "2025-02-01T05:33:10Z")]
+  pub rfc3339: String,
+}
+
+/// Reports the server's current time.
+///
+/// This endpoint requires no authentication.
+#[utoipa::path(
+  get,
+  path="/time",
+  tag="server",
+  security(),
+  responses(
+    (status = 200, description = "The server's current time", body = ApiSuccessResponseBody<TimeResponse>),
+  ),
+)]
+#[get("/time")]
+pub fn get_time() -> WithWildcardCors<ApiSuccessResponse<TimeResponse>> {
+  let now = Utc::now();
+  WithWildcardCors(ApiSuccessResponse::new(TimeResponse {
+    unix_seconds: now.timestamp(),
+    rfc3339: now.to_rfc3339_opts(SecondsFormat::Secs, true),
+  }))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  use chrono::DateTime;
+  use rocket::http::Status;
+  use rocket::local::blocking::Client;
+  use serde_json::Value;
+
+  #[test]
+  fn test_time_reports_current_time() {
+    let client = Client::untracked(rocket::build().mount("/", clock_routes())).unwrap();
+    let response = client.get("/time").dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    let body = response.into_json::<Value>().unwrap();
+    let unix_seconds = body["unix_seconds"].as_i64().unwrap();
+    assert!((Utc::now().timestamp() - unix_seconds).abs() <= 5, "{}", unix_seconds);
+    let rfc3339 = DateTime::parse_from_rfc3339(body["rfc3339"].as_str().unwrap()).unwrap();
+    assert_eq!(rfc3339.timestamp(), unix_seconds);
+    assert!(body["rfc3339"].as_str().unwrap().ends_with('Z'));
+  }
+}
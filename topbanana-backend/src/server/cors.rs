@@ -1,20 +1,167 @@
 
 //! Custom responders for modifying CORS headers.
 
+use crate::config::AppConfig;
+
 use rocket::http::{Header, Status};
 use rocket::response::{Responder, Response};
 use rocket::Request;
+use log::warn;
+
+/// Environment variable selecting the [`CorsMode`], one of
+/// `wildcard` (the default), `strict`, or `disabled`.
+pub const CORS_MODE_ENV_VAR: &str = "CORS_MODE";
 
-/// Wrapper for adding wildcard CORS headers.
+/// Environment variable listing the origins allowed in
+/// [`CorsMode::Strict`], separated by commas.
+pub const CORS_ALLOWED_ORIGINS_ENV_VAR: &str = "CORS_ALLOWED_ORIGINS";
+
+/// Wrapper for adding CORS headers to game-facing responses. Despite
+/// the name, the headers added depend on the server's [`CorsConfig`];
+/// wildcard headers are only the default.
 #[derive(Debug, Clone)]
 pub struct WithWildcardCors<T>(pub T);
 
+/// Which CORS headers game-facing responses carry.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CorsMode {
+  /// Any origin is allowed, with `Access-Control-Allow-Origin: *`.
+  #[default]
+  Wildcard,
+  /// Only the origins in [`CorsConfig::allowed_origins`] are allowed,
+  /// and each is echoed back individually.
+  Strict,
+  /// No CORS headers are sent, so browsers allow only same-origin
+  /// requests.
+  Disabled,
+}
+
+/// CORS settings, kept in Rocket's managed state as part of
+/// [`AppConfig`].
+#[derive(Debug, Clone, Default)]
+pub struct CorsConfig {
+  pub mode: CorsMode,
+  pub allowed_origins: Vec<String>,
+}
+
+impl CorsConfig {
+  /// Reads the configuration through `vars`, as
+  /// [`AppConfig::from_vars`] does. An invalid mode is logged and the
+  /// default is used instead.
+  pub fn from_vars(vars: &impl Fn(&str) -> Option<String>) -> Self {
+    let mode = match vars(CORS_MODE_ENV_VAR) {
+      None => CorsMode::default(),
+      Some(value) => match value.trim().to_ascii_lowercase().as_str() {
+        "wildcard" => CorsMode::Wildcard,
+        "strict" => CorsMode::Strict,
+        "disabled" => CorsMode::Disabled,
+        _ => {
+          warn!("Invalid {} value {:?}, using default", CORS_MODE_ENV_VAR, value);
+          CorsMode::default()
+        }
+      },
+    };
+    let allowed_origins: Vec<String> = vars(CORS_ALLOWED_ORIGINS_ENV_VAR)
+      .map(|value| {
+        value.split(',')
+          .map(|origin| origin.trim().trim_end_matches('/').to_owned())
+          .filter(|origin| !origin.is_empty())
+          .collect()
+      })
+      .unwrap_or_default();
+    if mode == CorsMode::Strict && allowed_origins.is_empty() {
+      warn!("{} is strict but {} is empty; all cross-origin requests will be refused", CORS_MODE_ENV_VAR, CORS_ALLOWED_ORIGINS_ENV_VAR);
+    }
+    CorsConfig { mode, allowed_origins }
+  }
+
+  /// The `Access-Control-Allow-Origin` value for the request, or
+  /// `None` if no CORS headers should be sent.
+  pub fn allowed_origin(&self, req: &Request<'_>) -> Option<String> {
+    match self.mode {
+      CorsMode::Wildcard => Some(String::from("*")),
+      CorsMode::Strict => {
+        let origin = req.headers().get_one("Origin")?;
+        self.allowed_origins.iter()
+          .any(|allowed| allowed == origin)
+          .then(|| origin.to_owned())
+      }
+      CorsMode::Disabled => None,
+    }
+  }
+}
+
 impl<'r, T: Responder<'r, 'static>> Responder<'r, 'static> for WithWildcardCors<T> {
   fn respond_to(self, req: &'r Request<'_>) -> Result<Response<'static>, Status> {
     let mut response = self.0.respond_to(req)?;
-    response.set_header(Header::new("Access-Control-Allow-Origin", "*"));
+    let default_config = CorsConfig::default();
+    let config = req.rocket().state::<AppConfig>().map_or(&default_config, |config| &config.cors);
+    if config.mode == CorsMode::Strict {
+      response.set_header(Header::new("Vary", "Origin"));
+    }
+    let Some(allowed_origin) = config.allowed_origin(req) else {
+      return Ok(response);
+    };
+    response.set_header(Header::new("Access-Control-Allow-Origin", allowed_origin));
     response.set_header(Header::new("Access-Control-Allow-Methods", "GET, POST, OPTIONS"));
-    response.set_header(Header::new("Access-Control-Allow-Headers", "Content-Type"));
+    response.set_header(Header::new("Access-Control-Allow-Headers", "Content-Type, Content-Encoding"));
     Ok(response)
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  use rocket::{get, routes};
+  use rocket::http::Header;
+  use rocket::local::blocking::Client;
+
+  const ALLOWED: &str = "https://game.example.com";
+
+  #[get("/scores")]
+  fn scores() -> WithWildcardCors<&'static str> {
+    WithWildcardCors("[]")
+  }
+
+  fn client(mode: &str) -> Client {
+    let mode = mode.to_owned();
+    let config = AppConfig::from_vars(|name| match name {
+      CORS_MODE_ENV_VAR => Some(mode.clone()),
+      CORS_ALLOWED_ORIGINS_ENV_VAR => Some(format!("{}/, https://other.example.com", ALLOWED)),
+      _ => None,
+    });
+    Client::untracked(rocket::build().manage(config).mount("/", routes![scores])).unwrap()
+  }
+
+  /// The `Access-Control-Allow-Origin` and `Vary` headers of a
+  /// response to a request from `origin`.
+  fn cors_headers(client: &Client, origin: &str) -> (Option<String>, Option<String>) {
+    let response = client.get("/scores").header(Header::new("Origin", origin.to_owned())).dispatch();
+    let headers = response.headers();
+    (
+      headers.get_one("Access-Control-Allow-Origin").map(str::to_owned),
+      headers.get_one("Vary").map(str::to_owned),
+    )
+  }
+
+  #[test]
+  fn test_wildcard_mode() {
+    let client = client("wildcard");
+    assert_eq!(cors_headers(&client, "https://anywhere.example.com"), (Some(String::from("*")), None));
+  }
+
+  #[test]
+  fn test_strict_mode() {
+    let client = client("strict");
+    assert_eq!(cors_headers(&client, ALLOWED), (Some(ALLOWED.to_owned()), Some(String::from("Origin"))));
+    assert_eq!(cors_headers(&client, "https://evil.example.com"), (None, Some(String::from("Origin"))));
+  }
+
+  #[test]
+  fn test_disabled_mode() {
+    let client = client("disabled");
+    let response = client.get("/scores").header(Header::new("Origin", ALLOWED)).dispatch();
+    assert!(response.headers().iter().all(|header| !header.name().as_str().starts_with("Access-Control-")));
+  }
+}
@@ -1,11 +1,15 @@
 
-use crate::db::models;
+use crate::db::{schema, models};
 use super::auth::DeveloperUser;
 use super::error::ApiError;
 use super::openapi::OpenApiUuid;
+use super::requests::RequestAlgorithm;
 
 use serde::{Deserialize, Serialize};
+use chrono::{DateTime, Utc};
 use uuid::Uuid;
+use diesel::prelude::*;
+use diesel_async::{RunQueryDsl, AsyncPgConnection};
 use utoipa::ToSchema;
 
 /// Trait for objects which have a developer that owns them.
@@ -37,6 +41,20 @@ pub trait DeveloperOwned {
     }
     Err(ApiError::forbidden())
   }
+
+  /// As [`DeveloperOwned::check_permission`], but a non-admin user
+  /// who does not own the object receives a 404 rather than a 403.
+  /// That is, "not yours" is treated the same as "not found", so that
+  /// callers cannot use the endpoint to enumerate existing objects.
+  fn check_permission_hidden(object: Option<Self>, requesting_user: &DeveloperUser) -> Result<Self, ApiError>
+  where Self: Sized {
+    if requesting_user.is_admin() {
+      return object.ok_or(ApiError::not_found());
+    }
+    object
+      .filter(|object| requesting_user.user_uuid() == object.get_developer_uuid())
+      .ok_or(ApiError::not_found())
+  }
 }
 
 /// Extension trait for `Option<T>` where `T` implements [`DeveloperOwned`].
@@ -44,6 +62,8 @@ pub trait DeveloperOwnedExt: Sized {
   type Target: DeveloperOwned;
 
   fn check_permission(self, requesting_user: &DeveloperUser) -> Result<Self::Target, ApiError>;
+
+  fn check_permission_hidden(self, requesting_user: &DeveloperUser) -> Result<Self::Target, ApiError>;
 }
 
 impl DeveloperOwned for models::Developer {
@@ -70,8 +90,32 @@ impl<T: DeveloperOwned + Sized> DeveloperOwnedExt for Option<T> {
   fn check_permission(self, requesting_user: &DeveloperUser) -> Result<T, ApiError> {
     T::check_permission(self, requesting_user)
   }
+
+  fn check_permission_hidden(self, requesting_user: &DeveloperUser) -> Result<T, ApiError> {
+    T::check_permission_hidden(self, requesting_user)
+  }
+}
+
+/// Looks up the highscore table with the given UUID, together with
+/// the UUID of the developer who owns its game. The result is ready
+/// to be passed to [`DeveloperOwnedExt::check_permission`] or
+/// [`DeveloperOwnedExt::check_permission_hidden`].
+pub async fn find_highscore_table_with_owner(
+  table_uuid: &Uuid,
+  db: &mut AsyncPgConnection,
+) -> diesel::QueryResult<Option<(models::HighscoreTable, Uuid)>> {
+  schema::highscore_tables::table
+    .filter(schema::highscore_tables::table_uuid.eq(table_uuid))
+    .inner_join(schema::games::table.inner_join(schema::developers::table))
+    .select((models::HighscoreTable::as_select(), schema::developers::developer_uuid))
+    .first::<(models::HighscoreTable, Uuid)>(db)
+    .await
+    .optional()
 }
 
+/// Developer details, in the same shape for both developer creation
+/// and developer lookup. The only difference is that `api_key` is
+/// populated on creation.
 #[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct DeveloperResponse {
   /// The developer's unique identifier.
@@ -82,6 +126,13 @@ pub struct DeveloperResponse {
   pub url: Option<String>,
   #[schema(examples("false"))]
   pub is_admin: bool,
+  /// Super admins may additionally use the most dangerous admin
+  /// endpoints.
+  #[schema(examples("false"))]
+  pub is_super_admin: bool,
+  /// Security level given to the developer's new games which do not
+  /// specify one. If this field is `null`, such games get level 0.
+  pub default_security_level: Option<i32>,
   /// The API key is only supplied upon initial user creation and
   /// cannot be recovered after the fact.
   #[serde(skip_serializing_if = "Option::is_none")]
@@ -98,6 +149,8 @@ pub struct NewGameDao {
   /// The user-facing name of the new game to create.
   pub name: String,
   #[serde(default)]
+  /// If omitted, the developer's `default_security_level` is used.
+  ///
   /// The default value of 10 for security level enables modern
   /// security features and will reject requests which use outdated
   /// security protocols.
@@ -108,6 +161,36 @@ pub struct NewGameDao {
   /// security level zero.
   #[schema(example = "10")]
   pub security_level: Option<i32>,
+  /// Minimum number of milliseconds that must elapse between two
+  /// score submissions for this game. Submissions arriving faster
+  /// than this are rejected with HTTP 429. Omit for no limit.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub min_submit_interval_ms: Option<i32>,
+  /// A free-form description of the game, for display purposes only.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub description: Option<String>,
+  /// The platform(s) the game runs on, for display purposes only.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  #[schema(example = "Windows, Linux")]
+  pub platform: Option<String>,
+  /// Number of random bytes in the game's secret key, between 32 and
+  /// 64. Shorter keys may suit constrained environments. Defaults to
+  /// 64.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  #[schema(example = "64")]
+  pub secret_key_length: Option<usize>,
+  /// An identifier of the client's choosing, unique among the
+  /// developer's games. Required by `PUT /api/game`, which uses it to
+  /// find a game created by an earlier attempt.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  #[schema(example = "my-game")]
+  pub external_id: Option<String>,
+  /// The algorithm used for game requests which omit `algo`. It must
+  /// be strong enough for the game's security level. If omitted,
+  /// every request must name its algorithm.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  #[schema(value_type = Option<String>, example = "sha256")]
+  pub default_algorithm: Option<RequestAlgorithm>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
@@ -125,6 +208,27 @@ pub struct GameResponse {
   /// The game's security level, indicating which hashing algorithms
   /// are permitted.
   pub security_level: i32,
+  /// Minimum number of milliseconds between two score submissions for
+  /// this game. If this field is `null`, then there is no limit.
+  pub min_submit_interval_ms: Option<i32>,
+  /// Whether the game currently accepts new score submissions.
+  /// Disabled games can still be read from.
+  pub enabled: bool,
+  /// A free-form description of the game, if one was given.
+  pub description: Option<String>,
+  /// The platform(s) the game runs on, if given.
+  pub platform: Option<String>,
+  /// Maximum number of scores this game may submit per UTC day. If
+  /// this field is `null`, then there is no limit.
+  pub daily_submission_quota: Option<i32>,
+  /// The client-supplied identifier of this game, if one was given.
+  pub external_id: Option<String>,
+  /// The algorithm used for game requests which omit `algo`. If this
+  /// field is `null`, then every request must name its algorithm.
+  pub default_algorithm: Option<String>,
+  /// Maximum number of highscore tables this game may have. If this
+  /// field is `null`, then the server's default applies.
+  pub max_tables: Option<i32>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
@@ -144,6 +248,46 @@ pub struct NewHighscoreTableDao {
   #[serde(default)]
   #[schema(example = "false")]
   pub unique_entries: bool,
+  /// If set, submissions more than this many standard deviations
+  /// above the table's current mean score are rejected as
+  /// implausible. Omit to accept any score.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub anti_cheat_sigma: Option<f64>,
+  /// Number of decimal places to which scores are rounded when
+  /// displayed. Stored scores are never rounded. Omit to display
+  /// scores at full precision.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub score_precision: Option<i32>,
+  /// Name recorded for scores submitted with an empty or blank
+  /// `player_name`. Omit to reject such scores.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub default_player_name: Option<String>,
+  /// If true, a submission is stored only if it beats the player's
+  /// current best score on the table. Otherwise, it is discarded and
+  /// the existing best is reported instead. Default is false.
+  #[serde(default)]
+  #[schema(example = "false")]
+  pub store_only_improvements: bool,
+  /// If set, scores submitted before this time, in RFC 3339 format,
+  /// are rejected.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  #[schema(value_type = Option<String>, example = "2025-02-01T00:00:00Z")]
+  pub submissions_open_at: Option<DateTime<Utc>>,
+  /// If set, scores submitted at or after this time, in RFC 3339
+  /// format, are rejected.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  #[schema(value_type = Option<String>, example = "2025-02-08T00:00:00Z")]
+  pub submissions_close_at: Option<DateTime<Utc>>,
+  /// If true, only whole-number scores are accepted, and scores are
+  /// returned as JSON integers. Default is false.
+  #[serde(default)]
+  #[schema(example = "false")]
+  pub score_is_integer: bool,
+  /// If true, games may read the table's scores without signing the
+  /// request. Submissions must always be signed. Default is false.
+  #[serde(default)]
+  #[schema(example = "false")]
+  pub public_reads: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
@@ -157,6 +301,38 @@ pub struct HighscoreTableResponse {
   /// The maximum number of scores retained by this highscore table.
   /// If this field is `null`, then there is no limit.
   pub maximum_scores_retained: Option<i32>,
+  /// Number of standard deviations above the mean beyond which
+  /// submissions are rejected. If this field is `null`, then no
+  /// statistical check is performed.
+  pub anti_cheat_sigma: Option<f64>,
+  /// Number of decimal places to which scores are rounded when
+  /// displayed. If this field is `null`, then scores are displayed at
+  /// full precision.
+  pub score_precision: Option<i32>,
+  /// Name recorded for scores submitted with a blank `player_name`.
+  /// If this field is `null`, then such scores are rejected.
+  pub default_player_name: Option<String>,
+  /// Whether submissions which do not beat the player's best score
+  /// are discarded rather than stored.
+  pub store_only_improvements: bool,
+  /// Time at which the table begins accepting scores. If this field
+  /// is `null`, then the table has always accepted scores.
+  #[schema(value_type = Option<String>)]
+  pub submissions_open_at: Option<DateTime<Utc>>,
+  /// Time at which the table stops accepting scores. If this field is
+  /// `null`, then the table accepts scores indefinitely.
+  #[schema(value_type = Option<String>)]
+  pub submissions_close_at: Option<DateTime<Utc>>,
+  /// Whether the table accepts only whole-number scores and returns
+  /// them as JSON integers.
+  pub score_is_integer: bool,
+  /// Time at which the table was soft-deleted. If this field is
+  /// `null`, then the table has not been deleted.
+  #[schema(value_type = Option<String>)]
+  pub deleted_at: Option<DateTime<Utc>>,
+  /// Whether games may read the table's scores without signing the
+  /// request.
+  pub public_reads: bool,
 }
 
 impl DeveloperResponse {
@@ -168,6 +344,46 @@ impl DeveloperResponse {
 }
 
 impl GameResponse {
+  /// The response for an existing game, which never includes the
+  /// secret key.
+  pub fn from_game(game: models::Game, developer_uuid: Uuid) -> Self {
+    Self {
+      developer_uuid,
+      game_uuid: game.game_uuid,
+      name: game.name,
+      game_secret_key: None,
+      security_level: game.security_level,
+      min_submit_interval_ms: game.min_submit_interval_ms,
+      enabled: game.enabled,
+      description: game.description,
+      platform: game.platform,
+      daily_submission_quota: game.daily_submission_quota,
+      external_id: game.external_id,
+      default_algorithm: game.default_algorithm,
+      max_tables: game.max_tables,
+    }
+  }
+
+  /// The response for a game which was just created, including its
+  /// secret key.
+  pub fn from_new_game(new_game: models::NewGame, developer_uuid: Uuid) -> Self {
+    Self {
+      developer_uuid,
+      game_uuid: new_game.game_uuid,
+      name: new_game.name,
+      game_secret_key: Some(new_game.game_secret_key),
+      security_level: new_game.security_level,
+      min_submit_interval_ms: new_game.min_submit_interval_ms,
+      enabled: new_game.enabled,
+      description: new_game.description,
+      platform: new_game.platform,
+      daily_submission_quota: new_game.daily_submission_quota,
+      external_id: new_game.external_id,
+      default_algorithm: new_game.default_algorithm,
+      max_tables: None,
+    }
+  }
+
   /// Removes the secret key from the response.
   pub fn without_secret_key(mut self) -> Self {
     self.game_secret_key = None;
@@ -175,6 +391,26 @@ impl GameResponse {
   }
 }
 
+impl HighscoreTableResponse {
+  pub fn from_table(highscore_table: models::HighscoreTable, game_uuid: Uuid) -> Self {
+    Self {
+      game_uuid,
+      table_uuid: highscore_table.table_uuid,
+      name: highscore_table.name,
+      maximum_scores_retained: highscore_table.maximum_scores_retained,
+      anti_cheat_sigma: highscore_table.anti_cheat_sigma,
+      score_precision: highscore_table.score_precision,
+      default_player_name: highscore_table.default_player_name,
+      store_only_improvements: highscore_table.store_only_improvements,
+      submissions_open_at: highscore_table.submissions_open_at.map(|t| t.and_utc()),
+      submissions_close_at: highscore_table.submissions_close_at.map(|t| t.and_utc()),
+      score_is_integer: highscore_table.score_is_integer,
+      deleted_at: highscore_table.deleted_at.map(|t| t.and_utc()),
+      public_reads: highscore_table.public_reads,
+    }
+  }
+}
+
 impl From<models::Developer> for DeveloperResponse {
   fn from(d: models::Developer) -> Self {
     Self {
@@ -183,6 +419,8 @@ impl From<models::Developer> for DeveloperResponse {
       email: d.email,
       url: d.url,
       is_admin: d.is_admin,
+      is_super_admin: d.is_super_admin,
+      default_security_level: d.default_security_level,
       api_key: d.api_key,
     }
   }
@@ -196,7 +434,59 @@ impl From<models::NewDeveloper> for DeveloperResponse {
       email: d.email,
       url: d.url,
       is_admin: d.is_admin,
+      is_super_admin: d.is_super_admin,
+      default_security_level: d.default_security_level,
       api_key: d.api_key,
     }
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  use serde_json::Value;
+
+  fn new_developer() -> models::NewDeveloper {
+    models::NewDeveloper {
+      developer_uuid: Uuid::now_v7(),
+      name: String::from("Alice"),
+      email: String::from("alice@example.com"),
+      url: Some(String::from("https://example.com")),
+      is_admin: true,
+      api_key: Some(String::from("api-key")),
+      password_hash: None,
+      is_super_admin: false,
+      default_security_level: Some(10),
+    }
+  }
+
+  /// The developer as it is later read back from the database.
+  fn stored_developer(new_developer: &models::NewDeveloper) -> models::Developer {
+    models::Developer {
+      id: 1,
+      developer_uuid: new_developer.developer_uuid,
+      name: new_developer.name.clone(),
+      email: new_developer.email.clone(),
+      url: new_developer.url.clone(),
+      is_admin: new_developer.is_admin,
+      api_key: new_developer.api_key.clone(),
+      password_hash: Some(String::from("hash")),
+      is_super_admin: new_developer.is_super_admin,
+      flags: 0,
+      tokens_valid_after: None,
+      default_security_level: new_developer.default_security_level,
+    }
+  }
+
+  #[test]
+  fn test_developer_creation_and_fetch_return_same_fields() {
+    let new_developer = new_developer();
+    let fetched = serde_json::to_value(DeveloperResponse::from(stored_developer(&new_developer)).without_api_key()).unwrap();
+    let mut created = serde_json::to_value(DeveloperResponse::from(new_developer)).unwrap();
+    assert_eq!(created.as_object_mut().unwrap().remove("api_key"), Some(Value::from("api-key")));
+    assert_eq!(created, fetched);
+    assert_eq!(fetched["is_admin"], true);
+    assert!(fetched.get("api_key").is_none());
+  }
+}
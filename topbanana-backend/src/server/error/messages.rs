@@ -6,3 +6,16 @@ pub const UNKNOWN_DB_ERROR: &str = "An unexpected database error occurred";
 pub const BAD_REQUEST: &str = "Bad Request";
 pub const UNAUTHORIZED: &str = "Unauthorized";
 pub const FORBIDDEN: &str = "Forbidden";
+pub const UPGRADE_REQUIRED: &str = "Upgrade Required";
+pub const TOO_MANY_REQUESTS: &str = "Too Many Requests";
+pub const UNPROCESSABLE_ENTITY: &str = "Unprocessable Entity";
+pub const INTERNAL_SERVER_ERROR: &str = "Internal Server Error";
+
+// Machine-readable codes, for errors which clients may need to tell
+// apart from others sharing their status.
+
+pub const GAME_DISABLED_CODE: &str = "game_disabled";
+pub const SUBMISSIONS_NOT_OPEN_CODE: &str = "submissions_not_open";
+pub const SUBMISSIONS_CLOSED_CODE: &str = "submissions_closed";
+pub const DAILY_QUOTA_EXCEEDED_CODE: &str = "daily_quota_exceeded";
+pub const SUBMITTED_TOO_SOON_CODE: &str = "submitted_too_soon";
@@ -1,8 +1,11 @@
 
 pub mod messages;
 
+use crate::server::api::TimestampFormat;
+use crate::util::InvalidUuid;
+
 use rocket::{Request, Catcher, catch, catchers};
-use rocket::http::Status;
+use rocket::http::{Header, Status};
 use rocket::response::{self, Responder};
 use rocket::serde::json::Json;
 use serde::{Serialize, Deserialize};
@@ -10,6 +13,7 @@ use thiserror::Error;
 use diesel::result::{DatabaseErrorKind, Error as DieselError};
 use utoipa::ToSchema;
 
+use std::collections::BTreeMap;
 use std::fmt::Display;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
@@ -19,8 +23,12 @@ pub enum ApiStatus {
   Error,
 }
 
-#[derive(Debug, Clone, Responder)]
+/// Rocket responder which responds with a successful JSON-like
+/// object. The status is 200 unless constructed with
+/// [`ApiSuccessResponse::created`].
+#[derive(Debug, Clone)]
 pub struct ApiSuccessResponse<T> {
+  status: Status,
   json: Json<ApiSuccessResponseBody<T>>,
 }
 
@@ -38,12 +46,22 @@ pub struct ApiSuccessResponseBody<T> {
 pub struct ApiError {
   status: Status,
   message: String,
+  fields: Option<BTreeMap<String, String>>,
+  code: Option<String>,
+  headers: Vec<Header<'static>>,
 }
 
 #[derive(Debug, Clone, Serialize)]
 struct ErrorPayload {
   status: ApiStatus,
   reason: String,
+  /// Per-field validation failures, keyed by field name.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  fields: Option<BTreeMap<String, String>>,
+  /// A stable, machine-readable identifier for the error, for
+  /// clients which need to distinguish errors sharing a status.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  code: Option<String>,
 }
 
 impl<T: Serialize> ApiSuccessResponse<T> {
@@ -53,45 +71,72 @@ impl<T: Serialize> ApiSuccessResponse<T> {
       body
     };
     ApiSuccessResponse {
+      status: Status::Ok,
       json: Json(body),
     }
   }
+
+  /// A response with status 201, for endpoints which create a
+  /// resource.
+  pub fn created(body: T) -> ApiSuccessResponse<T> {
+    ApiSuccessResponse {
+      status: Status::Created,
+      ..ApiSuccessResponse::new(body)
+    }
+  }
 }
 
 impl ApiError {
-  pub fn bad_request() -> ApiError {
+  fn new(status: Status, message: impl Into<String>) -> ApiError {
     ApiError {
-      status: Status::BadRequest,
-      message: messages::BAD_REQUEST.to_string(),
+      status,
+      message: message.into(),
+      fields: None,
+      code: None,
+      headers: Vec::new(),
     }
   }
 
+  /// An error with the given status and that status's standard
+  /// reason phrase as the message.
+  pub fn from_status(status: Status) -> ApiError {
+    ApiError::new(status, status.reason_lossy())
+  }
+
+  pub fn bad_request() -> ApiError {
+    ApiError::new(Status::BadRequest, messages::BAD_REQUEST)
+  }
+
   pub fn unauthorized() -> ApiError {
-    ApiError {
-      status: Status::Unauthorized,
-      message: messages::UNAUTHORIZED.to_string(),
-    }
+    ApiError::new(Status::Unauthorized, messages::UNAUTHORIZED)
   }
 
   pub fn forbidden() -> ApiError {
-    ApiError {
-      status: Status::Forbidden,
-      message: messages::FORBIDDEN.to_string(),
-    }
+    ApiError::new(Status::Forbidden, messages::FORBIDDEN)
   }
 
   pub fn not_found() -> ApiError {
-    ApiError {
-      status: Status::NotFound,
-      message: messages::NOT_FOUND.to_string(),
-    }
+    ApiError::new(Status::NotFound, messages::NOT_FOUND)
+  }
+
+  /// A 426 Upgrade Required, indicating that the client must switch
+  /// to a stronger protocol before the request can succeed.
+  pub fn upgrade_required() -> ApiError {
+    ApiError::new(Status::UpgradeRequired, messages::UPGRADE_REQUIRED)
+  }
+
+  pub fn too_many_requests() -> ApiError {
+    ApiError::new(Status::TooManyRequests, messages::TOO_MANY_REQUESTS)
+  }
+
+  /// A 422 Unprocessable Entity, indicating that the request was
+  /// well-formed but failed validation.
+  pub fn unprocessable_entity() -> ApiError {
+    ApiError::new(Status::UnprocessableEntity, messages::UNPROCESSABLE_ENTITY)
   }
 
   pub fn conflict(message: &str) -> ApiError {
-    ApiError {
-      status: Status::Conflict,
-      message: message.to_string(),
-    }
+    ApiError::new(Status::Conflict, message)
   }
 
   /// A 500 Internal Server Error.
@@ -101,10 +146,7 @@ impl ApiError {
   /// [`Error`](std::error::Error) since `anyhow` doesn't implement
   /// that.
   pub fn internal_server_error(message: impl Display) -> ApiError {
-    ApiError {
-      status: Status::InternalServerError,
-      message: message.to_string(),
-    }
+    ApiError::new(Status::InternalServerError, message.to_string())
   }
 
   pub fn status(&self) -> Status {
@@ -115,11 +157,42 @@ impl ApiError {
     &self.message
   }
 
+  pub fn fields(&self) -> Option<&BTreeMap<String, String>> {
+    self.fields.as_ref()
+  }
+
+  pub fn code(&self) -> Option<&str> {
+    self.code.as_deref()
+  }
+
+  pub fn headers(&self) -> &[Header<'static>] {
+    &self.headers
+  }
+
   pub fn with_message(mut self, message: impl Into<String>) -> Self {
     self.message = message.into();
     self
   }
 
+  /// Attaches a map of per-field validation failures to the error.
+  pub fn with_fields(mut self, fields: BTreeMap<String, String>) -> Self {
+    self.fields = Some(fields);
+    self
+  }
+
+  /// Adds a header to the error response.
+  pub fn with_header(mut self, header: impl Into<Header<'static>>) -> Self {
+    self.headers.push(header.into());
+    self
+  }
+
+  /// Attaches a stable, machine-readable error code, such as
+  /// `"banned_player"`, to the error.
+  pub fn with_code(mut self, code: impl Into<String>) -> Self {
+    self.code = Some(code.into());
+    self
+  }
+
   /// As `ApiError::from` but traets [`DieselError::NotFound`] as an
   /// HTTP 400 rather than HTTP 404. This is suitable to use on
   /// creation requests, where the primary task is not the lookup and
@@ -134,18 +207,36 @@ impl ApiError {
 }
 
 impl ErrorPayload {
-  pub fn new(message: String) -> ErrorPayload {
+  pub fn new(message: String, fields: Option<BTreeMap<String, String>>, code: Option<String>) -> ErrorPayload {
     ErrorPayload {
       status: ApiStatus::Error,
       reason: message,
+      fields,
+      code,
     }
   }
 }
 
+impl<'r, T: Serialize> Responder<'r, 'static> for ApiSuccessResponse<T> {
+  fn respond_to(self, req: &'r Request<'_>) -> response::Result<'static> {
+    TimestampFormat::configured(req).scope(|| (self.status, self.json).respond_to(req))
+  }
+}
+
 impl<'r> Responder<'r, 'static> for ApiError {
   fn respond_to(self, req: &'r Request<'_>) -> response::Result<'static> {
-    let payload = ErrorPayload::new(self.message);
-    (self.status, Json(payload)).respond_to(req)
+    let payload = ErrorPayload::new(self.message, self.fields, self.code);
+    let mut response = (self.status, Json(payload)).respond_to(req)?;
+    for header in self.headers {
+      response.set_header(header);
+    }
+    Ok(response)
+  }
+}
+
+impl From<InvalidUuid> for ApiError {
+  fn from(err: InvalidUuid) -> Self {
+    ApiError::bad_request().with_message(err.to_string())
   }
 }
 
@@ -183,25 +274,124 @@ impl<T, E: Display + 'static> ApiErrorExt for Result<T, E> {
   }
 }
 
+/// Request-local storage for an [`ApiError`] produced by a request
+/// guard. Rocket does not pass guard errors to catchers, so guards
+/// which want their error reported verbatim store it here.
+struct StashedError(Option<ApiError>);
+
+/// Stores `err` as the error for the current request, so that the
+/// catcher for its status reports it instead of a generic message.
+/// Only the first stashed error for a request is retained.
+pub fn stash_error(req: &Request<'_>, err: &ApiError) {
+  req.local_cache(|| StashedError(Some(err.clone())));
+}
+
+/// Retrieves the stashed error for the current request, if there is
+/// one with the given status.
+fn stashed_error(req: &Request<'_>, status: Status) -> Option<ApiError> {
+  req.local_cache(|| StashedError(None)).0.as_ref()
+    .filter(|err| err.status() == status)
+    .cloned()
+}
+
 pub fn catchers() -> Vec<Catcher> {
   catchers![
     bad_request_catcher,
     unauthorized_catcher,
     forbidden_catcher,
+    not_found_catcher,
+    internal_server_error_catcher,
+    default_catcher,
   ]
 }
 
 #[catch(400)]
-pub fn bad_request_catcher(_: &Request) -> ApiError {
-  ApiError::bad_request()
+pub fn bad_request_catcher(req: &Request) -> ApiError {
+  stashed_error(req, Status::BadRequest).unwrap_or_else(ApiError::bad_request)
 }
 
 #[catch(401)]
-pub fn unauthorized_catcher(_: &Request) -> ApiError {
-  ApiError::unauthorized()
+pub fn unauthorized_catcher(req: &Request) -> ApiError {
+  stashed_error(req, Status::Unauthorized).unwrap_or_else(ApiError::unauthorized)
 }
 
 #[catch(403)]
-pub fn forbidden_catcher(_: &Request) -> ApiError {
-  ApiError::forbidden()
+pub fn forbidden_catcher(req: &Request) -> ApiError {
+  stashed_error(req, Status::Forbidden).unwrap_or_else(ApiError::forbidden)
+}
+
+#[catch(404)]
+pub fn not_found_catcher(req: &Request) -> ApiError {
+  stashed_error(req, Status::NotFound).unwrap_or_else(ApiError::not_found)
+}
+
+#[catch(500)]
+pub fn internal_server_error_catcher(req: &Request) -> ApiError {
+  stashed_error(req, Status::InternalServerError)
+    .unwrap_or_else(|| ApiError::internal_server_error(messages::INTERNAL_SERVER_ERROR))
+}
+
+/// Fallback for statuses without a dedicated catcher. Reports the
+/// stashed request error if there is one.
+#[catch(default)]
+pub fn default_catcher(status: Status, req: &Request) -> ApiError {
+  stashed_error(req, status).unwrap_or_else(|| ApiError::from_status(status))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  use rocket::{get, routes};
+  use rocket::local::blocking::Client;
+  use serde_json::Value;
+
+  #[get("/banned")]
+  fn banned() -> ApiError {
+    ApiError::forbidden().with_message("Player is banned").with_code("banned_player")
+  }
+
+  #[get("/forbidden")]
+  fn forbidden() -> ApiError {
+    ApiError::forbidden()
+  }
+
+  fn client() -> Client {
+    let rocket = rocket::build()
+      .mount("/", routes![banned, forbidden])
+      .register("/api", catchers())
+      .register("/", catchers());
+    Client::untracked(rocket).unwrap()
+  }
+
+  fn get_json(client: &Client, path: &str) -> (Status, Value) {
+    let response = client.get(path).dispatch();
+    let status = response.status();
+    (status, response.into_json().unwrap())
+  }
+
+  #[test]
+  fn test_error_code_in_body() {
+    let client = client();
+    let (status, body) = get_json(&client, "/banned");
+    assert_eq!(status, Status::Forbidden);
+    assert_eq!(body["status"], "error");
+    assert_eq!(body["reason"], "Player is banned");
+    assert_eq!(body["code"], "banned_player");
+
+    let (status, body) = get_json(&client, "/forbidden");
+    assert_eq!(status, Status::Forbidden);
+    assert!(body.get("code").is_none());
+  }
+
+  #[test]
+  fn test_unknown_paths_get_json_errors() {
+    let client = client();
+    for path in ["/no-such-path", "/api/no-such-path"] {
+      let (status, body) = get_json(&client, path);
+      assert_eq!(status, Status::NotFound, "{}", path);
+      assert_eq!(body["status"], "error", "{}", path);
+      assert_eq!(body["reason"], messages::NOT_FOUND, "{}", path);
+    }
+  }
 }
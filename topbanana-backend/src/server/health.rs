@@ -0,0 +1,124 @@
+
+//! Health checks for load balancers and orchestrators.
+//!
+//! `/health` is a liveness check which only confirms that the server
+//! is running. `/health/ready` is a readiness check which also
+//! requires the database to be reachable and fully migrated.
+
+use super::db::Db;
+use super::error::{ApiError, ApiSuccessResponse, ApiSuccessResponseBody};
+
+use rocket::{Route, get, routes};
+use rocket::http::Status;
+use rocket_db_pools::Connection;
+use diesel::QueryableByName;
+use diesel_async::RunQueryDsl;
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use std::collections::BTreeSet;
+
+mod migrations {
+  include!(concat!(env!("OUT_DIR"), "/migrations.rs"));
+}
+
+pub use migrations::MIGRATION_VERSIONS;
+
+pub fn health_routes() -> Vec<Route> {
+  routes![get_health, get_readiness]
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct HealthResponse {
+  #[schema(example = "ok")]
+  pub status: &'static str,
+}
+
+#[derive(Debug, Clone, QueryableByName)]
+struct AppliedMigration {
+  #[diesel(sql_type = diesel::sql_types::Varchar)]
+  version: String,
+}
+
+/// Reports that the server is running.
+///
+/// This endpoint requires no authentication and does not touch the
+/// database.
+#[utoipa::path(
+  get,
+  path="/health",
+  tag="server",
+  security(),
+  responses(
+    (status = 200, description = "The server is running", body = ApiSuccessResponseBody<HealthResponse>),
+  ),
+)]
+#[get("/health")]
+pub fn get_health() -> ApiSuccessResponse<HealthResponse> {
+  ApiSuccessResponse::new(HealthResponse { status: "ok" })
+}
+
+/// Reports whether the server is ready to serve requests.
+///
+/// The server is ready once the database is reachable and every
+/// migration the server was built with has been applied. Until then,
+/// this endpoint responds with HTTP 503. This endpoint requires no
+/// authentication.
+#[utoipa::path(
+  get,
+  path="/health/ready",
+  tag="server",
+  security(),
+  responses(
+    (status = 200, description = "The server is ready", body = ApiSuccessResponseBody<HealthResponse>),
+    (status = 503, description = "The database is unreachable or has pending migrations"),
+  ),
+)]
+#[get("/health/ready")]
+pub async fn get_readiness(mut db: Connection<Db>) -> Result<ApiSuccessResponse<HealthResponse>, ApiError> {
+  let applied = diesel::sql_query("SELECT version FROM __diesel_schema_migrations")
+    .load::<AppliedMigration>(&mut db)
+    .await
+    .map_err(|_| ApiError::from_status(Status::ServiceUnavailable).with_message("Could not read applied migrations"))?;
+  let applied = applied.into_iter().map(|migration| migration.version).collect::<BTreeSet<_>>();
+  let pending = pending_migrations(MIGRATION_VERSIONS, &applied);
+  if !pending.is_empty() {
+    let message = format!("{} pending migration(s): {}", pending.len(), pending.join(", "));
+    return Err(ApiError::from_status(Status::ServiceUnavailable).with_message(message));
+  }
+  Ok(ApiSuccessResponse::new(HealthResponse { status: "ok" }))
+}
+
+/// The versions in `embedded` which have not been `applied`, in
+/// order.
+pub fn pending_migrations<'a>(embedded: &[&'a str], applied: &BTreeSet<String>) -> Vec<&'a str> {
+  embedded.iter()
+    .copied()
+    .filter(|version| !applied.contains(*version))
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_pending_migrations() {
+    let embedded = ["20250101000000", "20250201000000", "20250301000000"];
+    let applied = ["20250101000000", "20250301000000", "20240101000000"].map(String::from).into_iter().collect::<BTreeSet<_>>();
+    assert_eq!(pending_migrations(&embedded, &applied), ["20250201000000"]);
+    assert_eq!(pending_migrations(&embedded, &BTreeSet::new()), embedded);
+    assert!(pending_migrations(&[], &applied).is_empty());
+  }
+
+  #[test]
+  fn test_embedded_versions_match_diesel() {
+    // Diesel records a migration named `2025-01-01-000000_name` as
+    // version `20250101000000`.
+    assert!(!MIGRATION_VERSIONS.is_empty());
+    assert!(MIGRATION_VERSIONS.is_sorted());
+    for version in MIGRATION_VERSIONS {
+      assert!(version.len() == 14 && version.bytes().all(|byte| byte.is_ascii_digit()), "{}", version);
+    }
+  }
+}
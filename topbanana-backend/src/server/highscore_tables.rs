@@ -1,33 +1,102 @@
 
 use crate::db::{schema, models};
-use crate::server::requests::{GameRequestPayload, GameRequestBody};
-use crate::util::DataFromStr;
+use crate::util::UuidParam;
+use crate::server::requests::{GameRequestBody, ReplayableGameRequest, VerifiedGameRequest};
 use super::db;
-use super::error::{ApiSuccessResponse, ApiError};
-use super::api::{get_scores_for_table, ScoresResponse};
+use super::error::{messages, ApiSuccessResponse, ApiError};
+use super::api::{count_scores_for_table, get_scores_for_table, rank_of_score, stream_scores_for_table};
+use super::api::{Ranking, ScoresCursor, ScoresEntryResponse, ScoresOrderBy, ScoresQuery, ScoresResponse, ScoresResponseEntry, SortDirection};
 use super::cors::WithWildcardCors;
+use super::ndjson::{AcceptsNdjson, NdjsonStream};
+use super::pagination::{Paginated, Pagination};
+use super::throttle::{SubmissionThrottle, WithSubmissionLimits, quota_day, quota_remaining, whole_seconds};
+use super::transport::ClientIp;
+use super::webhooks::{NewHighscoreEvent, WebhookSender};
 
-use rocket::{Route, get, post, options, routes};
+use rocket::{Either, Route, State, get, post, options, routes};
+use rocket::http::Header;
+use rocket::futures::stream::Stream;
 use rocket_db_pools::Connection;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 use diesel::prelude::*;
+use diesel::pg::Pg;
+use diesel::sql_types::Bool;
 use diesel_async::{RunQueryDsl, AsyncConnection, AsyncPgConnection};
 use scoped_futures::ScopedFutureExt;
+use log::warn;
+
+use std::collections::HashMap;
+use chrono::{NaiveDate, NaiveDateTime, SecondsFormat, TimeDelta};
 
 pub fn highscore_table_routes() -> Vec<Route> {
   routes![
     get_highscore_table_scores,
-    get_highscore_table_scores_with_limit,
+    get_multi_highscore_table_scores,
+    get_multi_highscore_table_scores_with_limit,
+    get_player_highscore_table_scores,
+    get_public_highscore_table_scores,
     post_new_highscore_table_score,
+    preview_highscore_table_score,
     preflight_new_highscore_table_score,
+    preflight_preview_highscore_table_score,
     preflight_highscore_table_scores,
+    preflight_multi_highscore_table_scores,
+    preflight_player_highscore_table_scores,
+    preflight_public_highscore_table_scores,
   ]
 }
 
+/// Maximum number of tables which may be queried in a single
+/// multi-table request.
+pub const MAX_TABLES_PER_MULTI_REQUEST: usize = 16;
+
+/// Minimum number of existing scores a table must have before its
+/// anti-cheat bound is enforced. Below this, the mean and standard
+/// deviation are too noisy to judge new submissions by.
+pub const MIN_ANTI_CHEAT_SAMPLE_SIZE: i64 = 10;
+
+diesel::sql_function! {
+  #[aggregate]
+  fn stddev_pop(x: diesel::sql_types::Double) -> diesel::sql_types::Nullable<diesel::sql_types::Double>;
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 struct GetHighscoreTableParams {
   pub table_uuid: Uuid,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub include_metadata: Option<bool>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct GetPlayerHighscoreTableParams {
+  pub table_uuid: Uuid,
+  pub player_name: String,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub include_metadata: Option<bool>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct GetMultiHighscoreTableParams {
+  pub table_uuids: Vec<Uuid>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub include_metadata: Option<bool>,
+}
+
+/// Scores for a single table, with enough of the table's settings to
+/// tell an empty table apart from a missing one.
+#[derive(Debug, Clone, Serialize)]
+struct TableScoresResponse {
+  pub name: String,
+  pub maximum_scores_retained: Option<i32>,
+  #[serde(flatten)]
+  pub scores: ScoresResponse,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct MultiScoresResponse {
+  /// Scores for each requested table, keyed by table UUID.
+  pub tables: HashMap<Uuid, ScoresResponse>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -42,109 +111,749 @@ struct PostHighscoreTableParams {
 #[derive(Debug, Clone, Serialize)]
 struct PostHighscoreTableResponse {
   pub message: &'static str,
+  /// The entry exactly as stored, with its rank on the table. This is
+  /// `null` if the new score was not retained, either because the
+  /// player has a higher score on a unique-entry table or because the
+  /// score did not make the table's retention limit. On a table with
+  /// `store_only_improvements`, a score which does not beat the
+  /// player's best is not stored, and this is the existing best.
+  pub entry: Option<ScoresEntryResponse>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct PreviewHighscoreTableParams {
+  pub table_uuid: Uuid,
+  pub player_name: String,
+  pub player_score: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct PreviewHighscoreTableResponse {
+  /// The rank the score would have if submitted now.
+  pub rank: i64,
+  /// Whether the score would be kept if submitted now, given the
+  /// table's `unique_entries` and `maximum_scores_retained` settings.
+  pub retained: bool,
 }
 
-#[get("/scores", data = "<params>")]
+/// Returns the scores on a table. If `since` is given, only scores
+/// strictly after it are returned, oldest first, each with a `cursor`
+/// which a client polling for new scores passes as the next `since`
+/// to resume exactly after the newest score it has seen. A plain
+/// timestamp is also accepted as `since`.
+#[get("/scores?<limit>&<since>", data = "<params>")]
 async fn get_highscore_table_scores(
-  params: DataFromStr<GameRequestPayload>,
-  db: Connection<db::Db>,
-) -> Result<WithWildcardCors<ApiSuccessResponse<ScoresResponse>>, ApiError> {
-  get_highscore_table_scores_impl(params, None, db).await
+  params: VerifiedGameRequest<GetHighscoreTableParams>,
+  limit: Option<u32>,
+  since: Option<ScoresCursor>,
+  accepts_ndjson: AcceptsNdjson,
+) -> Result<WithWildcardCors<Either<ApiSuccessResponse<TableScoresResponse>, NdjsonStream<impl Stream<Item = String> + Send + 'static>>>, ApiError> {
+  let (params, db) = params.into_parts();
+  get_highscore_table_scores_impl(params, limit, since, accepts_ndjson, db).await
+}
+
+#[get("/scores/multi", data = "<params>")]
+async fn get_multi_highscore_table_scores(
+  params: VerifiedGameRequest<GetMultiHighscoreTableParams>,
+) -> Result<WithWildcardCors<ApiSuccessResponse<MultiScoresResponse>>, ApiError> {
+  let (params, db) = params.into_parts();
+  get_multi_highscore_table_scores_impl(params, None, db).await
 }
 
-#[get("/scores?<limit>", data = "<params>")]
-async fn get_highscore_table_scores_with_limit(
-  params: DataFromStr<GameRequestPayload>,
+#[get("/scores/multi?<limit>", data = "<params>")]
+async fn get_multi_highscore_table_scores_with_limit(
+  params: VerifiedGameRequest<GetMultiHighscoreTableParams>,
   limit: u32,
-  db: Connection<db::Db>,
-) -> Result<WithWildcardCors<ApiSuccessResponse<ScoresResponse>>, ApiError> {
-  get_highscore_table_scores_impl(params, Some(limit), db).await
+) -> Result<WithWildcardCors<ApiSuccessResponse<MultiScoresResponse>>, ApiError> {
+  let (params, db) = params.into_parts();
+  get_multi_highscore_table_scores_impl(params, Some(limit), db).await
+}
+
+/// Returns every score the given player has on the table, highest
+/// first, with each score's rank among all of the table's scores.
+/// Results may be paginated with `limit` and `offset`.
+#[get("/scores/player", data = "<params>")]
+async fn get_player_highscore_table_scores(
+  params: VerifiedGameRequest<GetPlayerHighscoreTableParams>,
+  pagination: Pagination,
+) -> Result<WithWildcardCors<Paginated<ApiSuccessResponse<TableScoresResponse>>>, ApiError> {
+  let (params, mut db) = params.into_parts();
+  // Note: Filter on game UUID as well, as in
+  // get_highscore_table_scores_impl.
+  let highscore_table = schema::highscore_tables::table
+    .inner_join(schema::games::table)
+    .filter(schema::highscore_tables::table_uuid.eq(params.body.table_uuid))
+    .filter(schema::highscore_tables::deleted_at.is_null())
+    .filter(schema::games::game_uuid.eq(params.game_uuid))
+    .select(models::HighscoreTable::as_select())
+    .first::<models::HighscoreTable>(&mut db)
+    .await
+    .optional()?
+    .ok_or_else(|| ApiError::not_found().with_message("No such highscore table for this game"))?;
+  let options = ScoresQuery {
+    limit: Some(pagination.limit),
+    offset: Some(pagination.offset),
+    score_precision: highscore_table.score_precision,
+    score_is_integer: highscore_table.score_is_integer,
+    omit_metadata: !params.body.include_metadata.unwrap_or(true),
+    player_name: Some(params.body.player_name),
+    ..ScoresQuery::default()
+  };
+  let total_count = count_scores_for_table(highscore_table.id, &options, &mut db).await?;
+  // A player with no scores gets an empty list, as an empty table
+  // does.
+  let scores = get_scores_for_table(highscore_table.id, &options, &mut db).await?;
+  let response = TableScoresResponse {
+    name: highscore_table.name,
+    maximum_scores_retained: highscore_table.maximum_scores_retained,
+    scores,
+  };
+  Ok(WithWildcardCors(Paginated::from_pagination(ApiSuccessResponse::new(response), pagination, total_count)))
 }
 
 #[post("/scores/new", data = "<params>")]
 async fn post_new_highscore_table_score(
-  params: DataFromStr<GameRequestPayload>,
-  mut db: Connection<db::Db>,
-) -> Result<WithWildcardCors<ApiSuccessResponse<PostHighscoreTableResponse>>, ApiError> {
-  let params = GameRequestBody::<PostHighscoreTableParams>::full_verify(&params, &mut db).await?;
+  params: VerifiedGameRequest<PostHighscoreTableParams>,
+  throttle: &State<SubmissionThrottle>,
+  webhooks: &State<WebhookSender>,
+  client_ip: Option<ClientIp>,
+) -> Result<WithWildcardCors<WithSubmissionLimits<ApiSuccessResponse<PostHighscoreTableResponse>>>, ApiError> {
+  let (params, mut db) = params.into_parts();
   // Note: Filter on game UUID as well. If the user gives a mismatched
   // game UUID and table UUID, we have to reject the request for
   // security reasons.
-  let (highscore_table_id, maximum_scores_retained, unique_entries) = schema::highscore_tables::table
+  let (highscore_table, game) = schema::highscore_tables::table
     .inner_join(schema::games::table)
     .filter(schema::highscore_tables::table_uuid.eq(params.body.table_uuid))
+    .filter(schema::highscore_tables::deleted_at.is_null())
     .filter(schema::games::game_uuid.eq(params.game_uuid))
-    .select((schema::highscore_tables::id, schema::highscore_tables::maximum_scores_retained, schema::highscore_tables::unique_entries))
-    .first::<(i32, Option<i32>, bool)>(&mut db)
+    .select((models::HighscoreTable::as_select(), models::Game::as_select()))
+    .first::<(models::HighscoreTable, models::Game)>(&mut db)
     .await?;
+  let now = chrono::Utc::now().naive_utc();
+  check_accepting_submissions(&game, &highscore_table, now)?;
+  // Blank names are replaced by the table's default, if it has one.
+  let player_name = if params.body.player_name.trim().is_empty() {
+    highscore_table.default_player_name.clone()
+      .ok_or_else(|| ApiError::unprocessable_entity().with_message("player_name must not be empty"))?
+  } else {
+    params.body.player_name.clone()
+  };
+  check_score_is_integral(&highscore_table, params.body.player_score)?;
+  let highscore_table_id = highscore_table.id;
+  let score_is_integer = highscore_table.score_is_integer;
+  let maximum_scores_retained = highscore_table.maximum_scores_retained;
+  let unique_entries = highscore_table.unique_entries;
+  let min_interval = game.min_submit_interval_ms.map(|min_submit_interval_ms| TimeDelta::milliseconds(min_submit_interval_ms.into()));
+  let mut unstored_quota_remaining = None;
+  // This early check only spares the work below. The quota is
+  // enforced when the submission is counted, inside the transaction.
+  if let Some(daily_submission_quota) = game.daily_submission_quota {
+    let submissions_today = submissions_on_day(game.id, quota_day(now), &mut db).await?;
+    if quota_remaining(daily_submission_quota, submissions_today + 1).is_none() {
+      warn!("Rejected score submission for game {} over its daily quota of {}", params.game_uuid, daily_submission_quota);
+      return Err(ApiError::too_many_requests().with_message("Game has reached its daily submission quota").with_code(messages::DAILY_QUOTA_EXCEEDED_CODE));
+    }
+    unstored_quota_remaining = Some(daily_submission_quota - submissions_today);
+  }
+  check_score_plausible(&highscore_table, &params.body.player_name, params.body.player_score, &mut db).await?;
+  // The throttle comes after every check which can reject the
+  // submission, so that rejected submissions do not take up the
+  // game's slot. A submission which is not stored after all hands its
+  // slot back below.
+  let throttle_slot = match min_interval {
+    None => None,
+    Some(min_interval) => match throttle.try_submit_at_time(params.game_uuid, min_interval, now) {
+      Ok(slot) => Some(slot),
+      Err(wait) => {
+        warn!(
+          "Rejected score submission for game {} from {:?} within minimum submission interval",
+          params.game_uuid, client_ip.map(|ClientIp(ip)| ip),
+        );
+        return Err(
+          ApiError::too_many_requests()
+            .with_message("Score submitted too soon after the previous submission")
+            .with_code(messages::SUBMITTED_TOO_SOON_CODE)
+            .with_header(Header::new("Retry-After", whole_seconds(wait).to_string())),
+        );
+      }
+    },
+  };
+  let release_throttle_slot = || throttle_slot.into_iter().for_each(|slot| throttle.release(slot));
+  // Reported after the submission, so that it reflects whether the
+  // submission took up the game's slot.
+  let rate_limit_reset = || min_interval.map(|min_interval| throttle.wait_at_time(params.game_uuid, min_interval, now));
   let new_entry = models::NewHighscoreTableEntry {
     highscore_table_id,
-    player_name: params.body.player_name,
+    player_name,
     player_score: params.body.player_score,
     player_score_metadata: params.body.player_score_metadata,
+    creation_timestamp: None,
   };
 
-  db.transaction::<(), diesel::result::Error, _>(|db| async move {
-    diesel::insert_into(schema::highscore_table_entries::table)
+  let game_id = game.id;
+  let game_uuid = params.game_uuid;
+  let daily_submission_quota = game.daily_submission_quota;
+  let store_only_improvements = highscore_table.store_only_improvements;
+  let outcome = db.transaction::<_, ApiError, _>(|db| async move {
+    if store_only_improvements {
+      // The player may have no entries yet, so there is no row of
+      // theirs to lock. Locking the table's row instead serializes
+      // concurrent submissions to the table until the transaction
+      // ends, so that two of them cannot both beat the same previous
+      // best. The lock is taken before the quota counter's, and an
+      // unimproved score returns before the counter is touched.
+      schema::highscore_tables::table
+        .filter(schema::highscore_tables::id.eq(highscore_table_id))
+        .select(schema::highscore_tables::id)
+        .for_update()
+        .first::<i32>(db)
+        .await?;
+      let previous_best = player_best_entry(highscore_table_id, &new_entry.player_name, db).await?;
+      if let Some(previous_best) = unbeaten_best(previous_best, new_entry.player_score) {
+        return Ok(SubmissionOutcome::NotImproved(previous_best));
+      }
+    }
+    // Counting the submission locks the game's counter row until the
+    // transaction ends, so concurrent submissions are counted one at a
+    // time and cannot all slip under the quota.
+    let remaining_today = match daily_submission_quota {
+      None => None,
+      Some(daily_submission_quota) => {
+        let submissions_today = count_submission(game_id, quota_day(now), db).await?;
+        let Some(remaining) = quota_remaining(daily_submission_quota, submissions_today) else {
+          warn!("Rejected score submission for game {} over its daily quota of {}", game_uuid, daily_submission_quota);
+          return Err(ApiError::too_many_requests().with_message("Game has reached its daily submission quota").with_code(messages::DAILY_QUOTA_EXCEEDED_CODE));
+        };
+        Some(remaining)
+      }
+    };
+    let inserted_entry = diesel::insert_into(schema::highscore_table_entries::table)
       .values(&new_entry)
-      .execute(db)
+      .returning(models::HighscoreTableEntry::as_returning())
+      .get_result(db)
       .await?;
+    adjust_entry_count(highscore_table_id, 1, db).await?;
     if unique_entries {
-      // Remove all but the highest score by this user.
-      let top_entry_id = schema::highscore_table_entries::table
-        .filter(schema::highscore_table_entries::highscore_table_id.eq(highscore_table_id))
-        .filter(schema::highscore_table_entries::player_name.eq(&new_entry.player_name))
-        .order_by(schema::highscore_table_entries::player_score.desc())
-        .select(schema::highscore_table_entries::id)
-        .first::<i32>(db)
-        .await?;
-      diesel::delete(schema::highscore_table_entries::table)
-        .filter(schema::highscore_table_entries::highscore_table_id.eq(highscore_table_id))
-        .filter(schema::highscore_table_entries::player_name.eq(&new_entry.player_name))
-        .filter(schema::highscore_table_entries::id.ne(top_entry_id))
-        .execute(db)
-        .await?;
+      remove_duplicate_player_rows(highscore_table_id, &new_entry.player_name, db).await?;
     }
     remove_extra_highscore_rows(highscore_table_id, maximum_scores_retained, db).await?;
-    Ok(())
-  }.scope_boxed()).await?;
+    // The new entry may itself have been removed by either of the
+    // above.
+    let retained = diesel::select(diesel::dsl::exists(
+      schema::highscore_table_entries::table.filter(schema::highscore_table_entries::id.eq(inserted_entry.id)),
+    ))
+      .get_result::<bool>(db)
+      .await?;
+    Ok(SubmissionOutcome::Stored { entry: retained.then_some(inserted_entry), quota_remaining: remaining_today })
+  }.scope_boxed()).await.inspect_err(|_| release_throttle_slot())?;
+
+  let (stored_entry, quota_remaining) = match outcome {
+    SubmissionOutcome::Stored { entry, quota_remaining } => (entry, quota_remaining),
+    SubmissionOutcome::NotImproved(previous_best) => {
+      release_throttle_slot();
+      let rank = rank_of_score(highscore_table_id, previous_best.player_score, Ranking::Standard, &mut db).await?;
+      let resp = PostHighscoreTableResponse {
+        message: "Score did not beat the player's best and was not stored",
+        entry: Some(ScoresEntryResponse { entry: ScoresResponseEntry::from(previous_best).integral(score_is_integer), rank, cursor: None }),
+      };
+      return Ok(WithWildcardCors(WithSubmissionLimits {
+        inner: ApiSuccessResponse::new(resp),
+        rate_limit_reset: rate_limit_reset(),
+        // Nothing was stored, so nothing counts against the quota.
+        quota_remaining: unstored_quota_remaining,
+      }));
+    }
+  };
+  let entry = match stored_entry {
+    Some(stored_entry) => {
+      let rank = rank_of_score(highscore_table_id, stored_entry.player_score, Ranking::Standard, &mut db).await?;
+      Some(ScoresEntryResponse { entry: ScoresResponseEntry::from(stored_entry).integral(score_is_integer), rank, cursor: None })
+    }
+    None => None,
+  };
+  // Only scores which made it onto the table are announced.
+  if let Some(entry) = &entry {
+    webhooks.send_new_highscore(&NewHighscoreEvent::new(game_uuid, highscore_table.table_uuid, entry), &mut db).await;
+  }
+  let resp = PostHighscoreTableResponse { message: "New score added successfully", entry };
+  Ok(WithWildcardCors(WithSubmissionLimits {
+    inner: ApiSuccessResponse::new(resp),
+    rate_limit_reset: rate_limit_reset(),
+    quota_remaining,
+  }))
+}
+
+/// Reports where a score would land if it were submitted now, without
+/// storing it. The request is signed like any other, but its request
+/// UUID is not consumed, so the same preview may be sent again and
+/// then submitted. A score which `scores/new` would reject is rejected
+/// here in the same way, except that the game's submission interval
+/// and daily quota, which depend on when the score is submitted, are
+/// not checked.
+#[post("/scores/preview", data = "<params>")]
+async fn preview_highscore_table_score(
+  params: ReplayableGameRequest<PreviewHighscoreTableParams>,
+) -> Result<WithWildcardCors<ApiSuccessResponse<PreviewHighscoreTableResponse>>, ApiError> {
+  let (params, mut db) = params.into_parts();
+  // Note: Filter on game UUID as well, as in
+  // post_new_highscore_table_score.
+  let (highscore_table, game) = schema::highscore_tables::table
+    .inner_join(schema::games::table)
+    .filter(schema::highscore_tables::table_uuid.eq(params.body.table_uuid))
+    .filter(schema::highscore_tables::deleted_at.is_null())
+    .filter(schema::games::game_uuid.eq(params.game_uuid))
+    .select((models::HighscoreTable::as_select(), models::Game::as_select()))
+    .first::<(models::HighscoreTable, models::Game)>(&mut db)
+    .await?;
+  check_accepting_submissions(&game, &highscore_table, chrono::Utc::now().naive_utc())?;
+  let player_name = if params.body.player_name.trim().is_empty() {
+    highscore_table.default_player_name.clone()
+      .ok_or_else(|| ApiError::unprocessable_entity().with_message("player_name must not be empty"))?
+  } else {
+    params.body.player_name
+  };
+  check_score_is_integral(&highscore_table, params.body.player_score)?;
+  check_score_plausible(&highscore_table, &player_name, params.body.player_score, &mut db).await?;
+  let rank = rank_of_score(highscore_table.id, params.body.player_score, Ranking::Standard, &mut db).await?;
+  let retained = would_retain_score(&highscore_table, &player_name, params.body.player_score, &mut db).await?;
+  Ok(WithWildcardCors(ApiSuccessResponse::new(PreviewHighscoreTableResponse { rank, retained })))
+}
+
+/// Whether a score submitted now would be stored and survive the
+/// `unique_entries` and `maximum_scores_retained` trimming which
+/// follows every submission. A new score is the latest on the table,
+/// so it loses every tie.
+async fn would_retain_score(
+  highscore_table: &models::HighscoreTable,
+  player_name: &str,
+  player_score: f64,
+  db: &mut AsyncPgConnection,
+) -> diesel::QueryResult<bool> {
+  use schema::highscore_table_entries::dsl;
 
-  let resp = PostHighscoreTableResponse { message: "New score added successfully" };
-  Ok(WithWildcardCors(ApiSuccessResponse::new(resp)))
+  if highscore_table.unique_entries || highscore_table.store_only_improvements {
+    let has_better_score = diesel::select(diesel::dsl::exists(
+      dsl::highscore_table_entries
+        .filter(dsl::highscore_table_id.eq(highscore_table.id))
+        .filter(dsl::player_name.eq(player_name))
+        .filter(dsl::player_score.ge(player_score)),
+    ))
+      .get_result::<bool>(db)
+      .await?;
+    if has_better_score {
+      return Ok(false);
+    }
+  }
+  let Some(maximum_scores_retained) = highscore_table.maximum_scores_retained else {
+    return Ok(true);
+  };
+  // Any other scores by this player on a unique-entry table are lower,
+  // so they never count against the new score here.
+  let scores_ahead = dsl::highscore_table_entries
+    .filter(dsl::highscore_table_id.eq(highscore_table.id))
+    .filter(dsl::player_score.ge(player_score))
+    .count()
+    .get_result::<i64>(db)
+    .await?;
+  Ok(scores_ahead < i64::from(maximum_scores_retained))
+}
+
+/// What became of a submission which passed every check.
+enum SubmissionOutcome {
+  /// The table has `store_only_improvements` and the player's
+  /// existing best, given here, was at least as high, so nothing was
+  /// stored.
+  NotImproved(models::HighscoreTableEntry),
+  /// The score was stored. `entry` is `None` if it was then trimmed
+  /// from the table.
+  Stored { entry: Option<models::HighscoreTableEntry>, quota_remaining: Option<i32> },
+}
+
+/// The player's previous best, if a new score of `player_score` does
+/// not beat it. Ties go to the existing entry.
+fn unbeaten_best(previous_best: Option<models::HighscoreTableEntry>, player_score: f64) -> Option<models::HighscoreTableEntry> {
+  previous_best.filter(|best| best.player_score >= player_score)
+}
+
+/// The player's highest score on the table, earliest first among
+/// ties, if they have any.
+async fn player_best_entry(
+  table_id: i32,
+  player_name: &str,
+  db: &mut AsyncPgConnection,
+) -> diesel::QueryResult<Option<models::HighscoreTableEntry>> {
+  schema::highscore_table_entries::table
+    .filter(schema::highscore_table_entries::highscore_table_id.eq(table_id))
+    .filter(schema::highscore_table_entries::player_name.eq(player_name))
+    .order_by((schema::highscore_table_entries::player_score.desc(), schema::highscore_table_entries::creation_timestamp.asc()))
+    .select(models::HighscoreTableEntry::as_select())
+    .first(db)
+    .await
+    .optional()
+}
+
+/// Rejects a submission made at `now` to a table which is not taking
+/// scores at all, because its game is disabled or because it is
+/// outside the table's submission window.
+fn check_accepting_submissions(game: &models::Game, highscore_table: &models::HighscoreTable, now: NaiveDateTime) -> Result<(), ApiError> {
+  if !game.enabled {
+    return Err(ApiError::forbidden().with_message("This game is no longer accepting new scores").with_code(messages::GAME_DISABLED_CODE));
+  }
+  check_submission_window(highscore_table, now)
+}
+
+/// Rejects a fractional score on a table with `score_is_integer` set.
+fn check_score_is_integral(highscore_table: &models::HighscoreTable, player_score: f64) -> Result<(), ApiError> {
+  if highscore_table.score_is_integer && player_score.fract() != 0.0 {
+    return Err(ApiError::unprocessable_entity().with_message("player_score must be a whole number on this table"));
+  }
+  Ok(())
+}
+
+/// Rejects a score above the table's anti-cheat threshold, if it has
+/// one.
+async fn check_score_plausible(
+  highscore_table: &models::HighscoreTable,
+  player_name: &str,
+  player_score: f64,
+  db: &mut AsyncPgConnection,
+) -> Result<(), ApiError> {
+  let Some(anti_cheat_sigma) = highscore_table.anti_cheat_sigma else {
+    return Ok(());
+  };
+  if let Some(threshold) = anti_cheat_threshold(highscore_table.id, anti_cheat_sigma, db).await? {
+    if player_score > threshold {
+      warn!(
+        "Rejected implausible score {} by {:?} on table {} (threshold {})",
+        player_score, player_name, highscore_table.table_uuid, threshold,
+      );
+      return Err(ApiError::unprocessable_entity().with_message("Score is implausibly high for this table"));
+    }
+  }
+  Ok(())
+}
+
+/// Rejects a submission made at `now` if it falls outside the table's
+/// submission window.
+fn check_submission_window(highscore_table: &models::HighscoreTable, now: NaiveDateTime) -> Result<(), ApiError> {
+  if let Some(open_at) = highscore_table.submissions_open_at {
+    if now < open_at {
+      let message = format!("This table does not accept scores until {}", format_window_time(open_at));
+      return Err(ApiError::forbidden().with_message(message).with_code(messages::SUBMISSIONS_NOT_OPEN_CODE));
+    }
+  }
+  if let Some(close_at) = highscore_table.submissions_close_at {
+    if now >= close_at {
+      let message = format!("This table stopped accepting scores at {}", format_window_time(close_at));
+      return Err(ApiError::forbidden().with_message(message).with_code(messages::SUBMISSIONS_CLOSED_CODE));
+    }
+  }
+  Ok(())
+}
+
+fn format_window_time(time: NaiveDateTime) -> String {
+  time.and_utc().to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+/// The number of scores the game has submitted on the given UTC day.
+/// Every stored submission counts, including those since removed from
+/// their table by `unique_entries` or `maximum_scores_retained`.
+async fn submissions_on_day(game_id: i32, day: NaiveDate, db: &mut AsyncPgConnection) -> QueryResult<i32> {
+  let submission_count = schema::game_daily_submissions::table
+    .filter(schema::game_daily_submissions::game_id.eq(game_id))
+    .filter(schema::game_daily_submissions::day.eq(day))
+    .select(schema::game_daily_submissions::submission_count)
+    .first::<i32>(db)
+    .await
+    .optional()?;
+  Ok(submission_count.unwrap_or(0))
+}
+
+/// Counts one more submission by the game on the given UTC day,
+/// returning the day's new total. This is a single statement, which
+/// holds a lock on the game's counter row until the surrounding
+/// transaction ends.
+async fn count_submission(game_id: i32, day: NaiveDate, db: &mut AsyncPgConnection) -> QueryResult<i32> {
+  use schema::game_daily_submissions::dsl;
+
+  let new_row = models::NewGameDailySubmissions { game_id, day, submission_count: 1 };
+  diesel::insert_into(dsl::game_daily_submissions)
+    .values(&new_row)
+    .on_conflict((dsl::game_id, dsl::day))
+    .do_update()
+    .set(dsl::submission_count.eq(dsl::submission_count + 1))
+    .returning(dsl::submission_count)
+    .get_result(db)
+    .await
 }
 
 async fn get_highscore_table_scores_impl(
-  params: DataFromStr<GameRequestPayload>,
+  params: GameRequestBody<GetHighscoreTableParams>,
   limit: Option<u32>,
+  since: Option<ScoresCursor>,
+  accepts_ndjson: AcceptsNdjson,
   mut db: Connection<db::Db>,
-) -> Result<WithWildcardCors<ApiSuccessResponse<ScoresResponse>>, ApiError> {
-  let params = GameRequestBody::<GetHighscoreTableParams>::full_verify(&params, &mut db).await?;
+) -> Result<WithWildcardCors<Either<ApiSuccessResponse<TableScoresResponse>, NdjsonStream<impl Stream<Item = String> + Send + 'static>>>, ApiError> {
   // Note: Filter on game UUID as well. If the user gives a mismatched
   // game UUID and table UUID, we have to reject the request for
   // security reasons.
-  let highscore_table_id = schema::highscore_tables::table
+  let highscore_table = schema::highscore_tables::table
     .inner_join(schema::games::table)
     .filter(schema::highscore_tables::table_uuid.eq(params.body.table_uuid))
+    .filter(schema::highscore_tables::deleted_at.is_null())
+    .filter(schema::games::game_uuid.eq(params.game_uuid))
+    .select(models::HighscoreTable::as_select())
+    .first::<models::HighscoreTable>(&mut db)
+    .await
+    .optional()?
+    .ok_or_else(|| ApiError::not_found().with_message("No such highscore table for this game"))?;
+  let omit_metadata = !params.body.include_metadata.unwrap_or(true);
+  table_scores_response(highscore_table, limit, since, omit_metadata, accepts_ndjson, db).await
+}
+
+/// Returns the scores on a table with `public_reads` set, without
+/// requiring a signed request. Tables without `public_reads`, and
+/// those which do not exist, are both reported as not found.
+#[get("/scores/public?<table_uuid>&<limit>&<include_metadata>")]
+async fn get_public_highscore_table_scores(
+  table_uuid: UuidParam,
+  limit: Option<u32>,
+  include_metadata: Option<bool>,
+  accepts_ndjson: AcceptsNdjson,
+  mut db: Connection<db::Db>,
+) -> Result<WithWildcardCors<Either<ApiSuccessResponse<TableScoresResponse>, NdjsonStream<impl Stream<Item = String> + Send + 'static>>>, ApiError> {
+  let highscore_table = schema::highscore_tables::table
+    .filter(schema::highscore_tables::table_uuid.eq(&*table_uuid))
+    .filter(schema::highscore_tables::deleted_at.is_null())
+    .filter(schema::highscore_tables::public_reads.eq(true))
+    .select(models::HighscoreTable::as_select())
+    .first::<models::HighscoreTable>(&mut db)
+    .await
+    .optional()?
+    .ok_or_else(|| ApiError::not_found().with_message("No such public highscore table"))?;
+  let omit_metadata = !include_metadata.unwrap_or(true);
+  table_scores_response(highscore_table, limit, None, omit_metadata, accepts_ndjson, db).await
+}
+
+/// The scores on `highscore_table`, for the game-facing scores
+/// endpoints. Scores submitted after `since` are returned oldest
+/// first; otherwise, all scores are returned highest first.
+async fn table_scores_response(
+  highscore_table: models::HighscoreTable,
+  limit: Option<u32>,
+  since: Option<ScoresCursor>,
+  omit_metadata: bool,
+  accepts_ndjson: AcceptsNdjson,
+  mut db: Connection<db::Db>,
+) -> Result<WithWildcardCors<Either<ApiSuccessResponse<TableScoresResponse>, NdjsonStream<impl Stream<Item = String> + Send + 'static>>>, ApiError> {
+  // When polling for new scores, the oldest come first, so that a
+  // capped response can be resumed from its last cursor.
+  let (order_by, direction) = match since {
+    Some(_) => (ScoresOrderBy::Time, Some(SortDirection::Asc)),
+    None => (ScoresOrderBy::Score, None),
+  };
+  let options = ScoresQuery {
+    limit,
+    score_precision: highscore_table.score_precision,
+    score_is_integer: highscore_table.score_is_integer,
+    order_by,
+    direction,
+    omit_metadata,
+    since,
+    ..ScoresQuery::default()
+  };
+  if accepts_ndjson.0 {
+    return Ok(WithWildcardCors(Either::Right(stream_scores_for_table(highscore_table.id, &options, db))));
+  }
+  // An empty table is still a table: its scores are simply an empty
+  // list, alongside its name and retention limit.
+  let scores = get_scores_for_table(highscore_table.id, &options, &mut db).await?;
+  let response = TableScoresResponse {
+    name: highscore_table.name,
+    maximum_scores_retained: highscore_table.maximum_scores_retained,
+    scores,
+  };
+  Ok(WithWildcardCors(Either::Left(ApiSuccessResponse::new(response))))
+}
+
+async fn get_multi_highscore_table_scores_impl(
+  params: GameRequestBody<GetMultiHighscoreTableParams>,
+  limit: Option<u32>,
+  mut db: Connection<db::Db>,
+) -> Result<WithWildcardCors<ApiSuccessResponse<MultiScoresResponse>>, ApiError> {
+  let mut table_uuids = params.body.table_uuids;
+  table_uuids.sort();
+  table_uuids.dedup();
+  if table_uuids.len() > MAX_TABLES_PER_MULTI_REQUEST {
+    return Err(ApiError::bad_request().with_message(format!("At most {} tables may be requested at once", MAX_TABLES_PER_MULTI_REQUEST)));
+  }
+  // Note: Filter on game UUID as well. Every requested table must
+  // belong to the requesting game, so if any table is missing from
+  // the result, we reject the whole request.
+  let highscore_tables = schema::highscore_tables::table
+    .inner_join(schema::games::table)
+    .filter(schema::highscore_tables::table_uuid.eq_any(&table_uuids))
+    .filter(schema::highscore_tables::deleted_at.is_null())
     .filter(schema::games::game_uuid.eq(params.game_uuid))
-    .select(schema::highscore_tables::id)
-    .first::<i32>(&mut db)
+    .select((
+      schema::highscore_tables::id,
+      schema::highscore_tables::table_uuid,
+      schema::highscore_tables::score_precision,
+      schema::highscore_tables::score_is_integer,
+    ))
+    .load::<(i32, Uuid, Option<i32>, bool)>(&mut db)
     .await?;
-  let scores = get_scores_for_table(highscore_table_id, limit, &mut db).await?;
-  Ok(WithWildcardCors(ApiSuccessResponse::new(scores)))
+  if highscore_tables.len() != table_uuids.len() {
+    return Err(ApiError::not_found());
+  }
+  let omit_metadata = !params.body.include_metadata.unwrap_or(true);
+  let mut tables = HashMap::with_capacity(highscore_tables.len());
+  for (highscore_table_id, table_uuid, score_precision, score_is_integer) in highscore_tables {
+    let options = ScoresQuery { limit, score_precision, score_is_integer, omit_metadata, ..ScoresQuery::default() };
+    let scores = get_scores_for_table(highscore_table_id, &options, &mut db).await?;
+    tables.insert(table_uuid, scores);
+  }
+  Ok(WithWildcardCors(ApiSuccessResponse::new(MultiScoresResponse { tables })))
 }
 
-async fn remove_extra_highscore_rows(
+/// Computes the score above which submissions to the given table are
+/// rejected, namely `sigma` standard deviations above the mean of the
+/// existing scores. Returns `None` if the table has too few scores
+/// for the statistics to be meaningful.
+async fn anti_cheat_threshold(
   table_id: i32,
-  maximum_scores_retained: Option<i32>,
+  sigma: f64,
+  db: &mut AsyncPgConnection,
+) -> diesel::QueryResult<Option<f64>> {
+  use schema::highscore_table_entries::dsl::*;
+
+  let (count, mean, stddev) = highscore_table_entries
+    .filter(highscore_table_id.eq(table_id))
+    .select((diesel::dsl::count_star(), diesel::dsl::avg(player_score), stddev_pop(player_score)))
+    .first::<(i64, Option<f64>, Option<f64>)>(db)
+    .await?;
+  if count < MIN_ANTI_CHEAT_SAMPLE_SIZE {
+    return Ok(None);
+  }
+  Ok(mean.zip(stddev).map(|(mean, stddev)| mean + sigma * stddev))
+}
+
+/// Adds `delta` to the table's `entry_count`. The addition happens in
+/// a single `UPDATE`, so concurrent adjustments are never lost. This
+/// should be called in the same transaction as the insert or delete
+/// it accounts for.
+pub async fn adjust_entry_count(
+  table_id: i32,
+  delta: i64,
   db: &mut AsyncPgConnection,
 ) -> diesel::QueryResult<()> {
+  if delta == 0 {
+    return Ok(());
+  }
+  diesel::update(schema::highscore_tables::table)
+    .filter(schema::highscore_tables::id.eq(table_id))
+    .set(schema::highscore_tables::entry_count.eq(schema::highscore_tables::entry_count + delta))
+    .execute(db)
+    .await?;
+  Ok(())
+}
+
+/// Recomputes `entry_count` for every table from the entries
+/// themselves, returning the number of tables whose count had
+/// drifted.
+pub async fn recount_entries(db: &mut AsyncPgConnection) -> diesel::QueryResult<usize> {
+  // Diesel's query builder cannot express a correlated subquery in
+  // an UPDATE.
+  diesel::sql_query(
+    "UPDATE highscore_tables
+      SET entry_count = counts.actual_count
+      FROM (
+        SELECT highscore_tables.id, COUNT(highscore_table_entries.id) AS actual_count
+        FROM highscore_tables
+        LEFT JOIN highscore_table_entries ON highscore_table_entries.highscore_table_id = highscore_tables.id
+        GROUP BY highscore_tables.id
+      ) AS counts
+      WHERE highscore_tables.id = counts.id AND highscore_tables.entry_count <> counts.actual_count",
+  )
+    .execute(db)
+    .await
+}
+
+/// Deletes all but the highest score by the given player on the
+/// table, as required by `unique_entries`, returning the number of
+/// rows removed.
+pub async fn remove_duplicate_player_rows(
+  table_id: i32,
+  player_name: &str,
+  db: &mut AsyncPgConnection,
+) -> diesel::QueryResult<usize> {
+  let Some(top_entry_id) = schema::highscore_table_entries::table
+    .filter(schema::highscore_table_entries::highscore_table_id.eq(table_id))
+    .filter(schema::highscore_table_entries::player_name.eq(player_name))
+    .order_by(schema::highscore_table_entries::player_score.desc())
+    .select(schema::highscore_table_entries::id)
+    .first::<i32>(db)
+    .await
+    .optional()? else {
+    // Nothing to do.
+    return Ok(0);
+  };
+  let rows_removed = diesel::delete(schema::highscore_table_entries::table)
+    .filter(schema::highscore_table_entries::highscore_table_id.eq(table_id))
+    .filter(schema::highscore_table_entries::player_name.eq(player_name))
+    .filter(schema::highscore_table_entries::id.ne(top_entry_id))
+    .execute(db)
+    .await?;
+  adjust_entry_count(table_id, -(rows_removed as i64), db).await?;
+  Ok(rows_removed)
+}
+
+/// Deletes all but the top `maximum_scores_retained` scores on the
+/// table, returning the number of rows removed.
+pub async fn remove_extra_highscore_rows(
+  table_id: i32,
+  maximum_scores_retained: Option<i32>,
+  db: &mut AsyncPgConnection,
+) -> diesel::QueryResult<usize> {
   use schema::highscore_table_entries::dsl::*;
 
   let Some(maximum_scores_retained) = maximum_scores_retained else {
     // Nothing to do.
-    return Ok(())
+    return Ok(0)
   };
 
+  let rows_removed = diesel::delete(highscore_table_entries)
+    .filter(extra_highscore_rows(table_id, maximum_scores_retained))
+    .execute(db)
+    .await?;
+  adjust_entry_count(table_id, -(rows_removed as i64), db).await?;
+  Ok(rows_removed)
+}
+
+/// Counts the scores which [`remove_extra_highscore_rows`] would
+/// delete if the table retained only `maximum_scores_retained`
+/// scores, without deleting anything.
+pub async fn count_extra_highscore_rows(
+  table_id: i32,
+  maximum_scores_retained: i32,
+  db: &mut AsyncPgConnection,
+) -> diesel::QueryResult<i64> {
+  schema::highscore_table_entries::table
+    .filter(extra_highscore_rows(table_id, maximum_scores_retained))
+    .count()
+    .get_result(db)
+    .await
+}
+
+/// Filter matching the scores on the table which fall outside its top
+/// `maximum_scores_retained`.
+fn extra_highscore_rows(
+  table_id: i32,
+  maximum_scores_retained: i32,
+) -> Box<dyn BoxableExpression<schema::highscore_table_entries::table, Pg, SqlType = Bool>> {
+  use schema::highscore_table_entries::dsl::*;
+
   let retained_entries = diesel::alias!(schema::highscore_table_entries as retained_entries);
 
   let scores_to_retain = retained_entries
@@ -152,12 +861,7 @@ async fn remove_extra_highscore_rows(
     .order((retained_entries.field(player_score).desc(), retained_entries.field(creation_timestamp).asc()))
     .limit(maximum_scores_retained as i64)
     .select(retained_entries.field(id));
-  diesel::delete(highscore_table_entries)
-    .filter(highscore_table_id.eq(table_id))
-    .filter(id.ne_all(scores_to_retain))
-    .execute(db)
-    .await?;
-  Ok(())
+  Box::new(highscore_table_id.eq(table_id).and(id.ne_all(scores_to_retain)))
 }
 
 #[options("/scores/new")]
@@ -165,7 +869,139 @@ async fn preflight_new_highscore_table_score() -> WithWildcardCors<()> {
   WithWildcardCors(())
 }
 
+#[options("/scores/preview")]
+async fn preflight_preview_highscore_table_score() -> WithWildcardCors<()> {
+  WithWildcardCors(())
+}
+
 #[options("/scores")]
 async fn preflight_highscore_table_scores() -> WithWildcardCors<()> {
   WithWildcardCors(())
 }
+
+#[options("/scores/multi")]
+async fn preflight_multi_highscore_table_scores() -> WithWildcardCors<()> {
+  WithWildcardCors(())
+}
+
+#[options("/scores/player")]
+async fn preflight_player_highscore_table_scores() -> WithWildcardCors<()> {
+  WithWildcardCors(())
+}
+
+#[options("/scores/public")]
+async fn preflight_public_highscore_table_scores() -> WithWildcardCors<()> {
+  WithWildcardCors(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  use rocket::http::Status;
+
+  fn game(enabled: bool) -> models::Game {
+    models::Game {
+      id: 1,
+      developer_id: 1,
+      game_uuid: Uuid::now_v7(),
+      game_secret_key: String::from("secret"),
+      name: String::from("Game"),
+      security_level: 0,
+      min_submit_interval_ms: None,
+      enabled,
+      description: None,
+      platform: None,
+      daily_submission_quota: None,
+      external_id: None,
+      default_algorithm: None,
+      previous_game_secret_key: None,
+      previous_secret_key_expires_at: None,
+      max_tables: None,
+    }
+  }
+
+  fn table(open_at: Option<NaiveDateTime>, close_at: Option<NaiveDateTime>) -> models::HighscoreTable {
+    models::HighscoreTable {
+      id: 1,
+      game_id: 1,
+      name: String::from("Table"),
+      table_uuid: Uuid::now_v7(),
+      maximum_scores_retained: None,
+      unique_entries: false,
+      anti_cheat_sigma: None,
+      score_precision: None,
+      entry_count: 0,
+      default_player_name: None,
+      store_only_improvements: false,
+      submissions_open_at: open_at,
+      submissions_close_at: close_at,
+      score_is_integer: false,
+      deleted_at: None,
+      public_reads: false,
+    }
+  }
+
+  fn at(hour: u32) -> NaiveDateTime {
+    NaiveDate::from_ymd_opt(2026, 1, 1).unwrap().and_hms_opt(hour, 0, 0).unwrap()
+  }
+
+  fn rejection_code(result: Result<(), ApiError>) -> Option<String> {
+    result.err().map(|err| {
+      assert_eq!(err.status(), Status::Forbidden);
+      err.code().unwrap().to_owned()
+    })
+  }
+
+  #[test]
+  fn test_accepting_submissions() {
+    let open_table = table(Some(at(9)), Some(at(17)));
+    assert_eq!(rejection_code(check_accepting_submissions(&game(true), &open_table, at(12))), None);
+    assert_eq!(
+      rejection_code(check_accepting_submissions(&game(false), &open_table, at(12))).as_deref(),
+      Some(messages::GAME_DISABLED_CODE),
+    );
+    assert_eq!(
+      rejection_code(check_accepting_submissions(&game(true), &open_table, at(8))).as_deref(),
+      Some(messages::SUBMISSIONS_NOT_OPEN_CODE),
+    );
+    assert_eq!(
+      rejection_code(check_accepting_submissions(&game(true), &open_table, at(17))).as_deref(),
+      Some(messages::SUBMISSIONS_CLOSED_CODE),
+    );
+    assert_eq!(rejection_code(check_accepting_submissions(&game(true), &table(None, None), at(0))), None);
+  }
+
+  #[test]
+  fn test_score_is_integral() {
+    let mut integer_table = table(None, None);
+    integer_table.score_is_integer = true;
+    assert!(check_score_is_integral(&integer_table, 12.0).is_ok());
+    let err = check_score_is_integral(&integer_table, 12.5).unwrap_err();
+    assert_eq!(err.status(), Status::UnprocessableEntity);
+    assert!(check_score_is_integral(&table(None, None), 12.5).is_ok());
+  }
+
+  fn entry(player_score: f64) -> models::HighscoreTableEntry {
+    models::HighscoreTableEntry {
+      id: 1,
+      highscore_table_id: 1,
+      player_name: String::from("Alice"),
+      player_score,
+      player_score_metadata: None,
+      creation_timestamp: at(12),
+    }
+  }
+
+  #[test]
+  fn test_improving_submission_is_stored() {
+    assert!(unbeaten_best(None, 10.0).is_none());
+    assert!(unbeaten_best(Some(entry(10.0)), 10.5).is_none());
+  }
+
+  #[test]
+  fn test_non_improving_submission_returns_previous_best() {
+    assert_eq!(unbeaten_best(Some(entry(10.0)), 9.0).map(|best| best.player_score), Some(10.0));
+    assert_eq!(unbeaten_best(Some(entry(10.0)), 10.0).map(|best| best.player_score), Some(10.0));
+  }
+}
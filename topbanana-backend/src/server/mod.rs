@@ -1,16 +1,30 @@
 
 pub mod admin;
 pub mod api;
+pub mod audit;
 pub mod auth;
+pub mod clock;
 pub mod cors;
 pub mod data_access;
+pub mod diagnostics;
 pub mod db;
 pub mod error;
+pub mod health;
 pub mod highscore_tables;
+pub mod ndjson;
 pub mod openapi;
+pub mod pagination;
 pub mod requests;
+pub mod throttle;
+pub mod transport;
+pub mod validation;
+pub mod version;
+pub mod webhooks;
+
+use crate::config::AppConfig;
 
 use rocket::{Rocket, Build, Ignite};
+use rocket::fairing::AdHoc;
 use rocket::fs::{FileServer, relative};
 use rocket_db_pools::Database;
 use utoipa::OpenApi;
@@ -24,8 +38,49 @@ pub fn build_rocket() -> Rocket<Build> {
   rocket::build()
     .mount("/api", api::api_routes())
     .mount("/tables", highscore_tables::highscore_table_routes())
+    .mount("/", version::version_routes())
+    .mount("/", health::health_routes())
+    .mount("/", clock::clock_routes())
+    .mount("/", transport::transport_routes())
+    .mount("/", openapi::openapi_routes())
     .mount("/", FileServer::from(relative!("static")))
     .mount("/", SwaggerUi::new("/swagger-ui/<_..>").url("/api-docs/openapi.json", openapi::ApiDoc::openapi()))
     .attach(db::Db::init())
+    .attach(AdHoc::on_ignite("Historical request writer", spawn_historical_request_writer))
+    .attach(AdHoc::on_ignite("Webhook sender", spawn_webhook_sender))
+    .manage(throttle::SubmissionThrottle::new())
+    .manage(requests::VerificationMetrics::new())
+    .manage(AppConfig::from_env())
+    .attach(transport::RequireHttps)
+    .attach(requests::VerifyDebugHeader)
+    .attach(AdHoc::on_liftoff("Startup summary", |rocket| Box::pin(diagnostics::log_startup_summary(rocket))))
     .register("/api", error::catchers())
+    .register("/tables", error::catchers())
+    .register("/", error::catchers())
+}
+
+/// If batching is enabled, starts the background writer for
+/// historical requests. Must run after the database pool has been
+/// initialized.
+async fn spawn_historical_request_writer(rocket: Rocket<Build>) -> Rocket<Build> {
+  if !rocket.state::<AppConfig>().is_some_and(|config| config.batch_historical_requests) {
+    return rocket;
+  }
+  let Some(pool) = db::Db::fetch(&rocket) else {
+    return rocket;
+  };
+  let writer = requests::HistoricalRequestWriter::spawn((**pool).clone());
+  rocket.manage(writer)
+}
+
+/// Starts the background task which delivers webhooks. It runs even
+/// if no webhook is configured, so that failed deliveries can still be
+/// replayed. Must run after the database pool has been initialized.
+async fn spawn_webhook_sender(rocket: Rocket<Build>) -> Rocket<Build> {
+  let Some(pool) = db::Db::fetch(&rocket) else {
+    return rocket;
+  };
+  let url = rocket.state::<AppConfig>().and_then(|config| config.new_highscore_webhook_url.clone());
+  let sender = webhooks::WebhookSender::spawn(url, (**pool).clone());
+  rocket.manage(sender)
 }
@@ -0,0 +1,121 @@
+
+//! Newline-delimited JSON responses, for clients which process large
+//! collections incrementally rather than as a single JSON document.
+
+use super::api::TimestampFormat;
+
+use rocket::http::{ContentType, MediaType};
+use rocket::request::{self, FromRequest, Request};
+use rocket::response::{self, Responder, Response};
+use rocket::response::stream::ReaderStream;
+use rocket::futures::stream::{self, Stream, StreamExt};
+use serde::Serialize;
+
+use std::convert::Infallible;
+use std::io::Cursor;
+
+/// Request guard reporting whether the client listed
+/// `application/x-ndjson` in its `Accept` header. This guard never
+/// fails.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AcceptsNdjson(pub bool);
+
+/// Streaming responder which emits each item of the inner stream as
+/// one line of an `application/x-ndjson` body. Items should be
+/// produced by [`to_ndjson_line`].
+#[derive(Debug, Clone)]
+pub struct NdjsonStream<S>(pub S);
+
+pub fn ndjson_content_type() -> ContentType {
+  ContentType::new("application", "x-ndjson")
+}
+
+/// Serializes a value as a single NDJSON line, including the
+/// trailing newline.
+pub fn to_ndjson_line<T: Serialize>(value: &T) -> serde_json::Result<String> {
+  let mut line = serde_json::to_string(value)?;
+  line.push('\n');
+  Ok(line)
+}
+
+fn is_ndjson(media_type: &MediaType) -> bool {
+  media_type.top() == "application" && media_type.sub() == "x-ndjson"
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for AcceptsNdjson {
+  type Error = Infallible;
+
+  async fn from_request(req: &'r Request<'_>) -> request::Outcome<Self, Infallible> {
+    let accepts_ndjson = req.accept().is_some_and(|accept| accept.media_types().any(is_ndjson));
+    request::Outcome::Success(AcceptsNdjson(accepts_ndjson))
+  }
+}
+
+impl<'r, S> Responder<'r, 'static> for NdjsonStream<S>
+where S: Stream<Item = String> + Send + 'static {
+  fn respond_to(self, req: &'r Request<'_>) -> response::Result<'static> {
+    // Lines are serialized as the stream is polled, long after this
+    // returns, so each poll runs in the configured timestamp format.
+    let timestamp_format = TimestampFormat::configured(req);
+    let mut lines = Box::pin(self.0);
+    let lines = stream::poll_fn(move |cx| timestamp_format.scope(|| lines.as_mut().poll_next(cx)));
+    Response::build()
+      .header(ndjson_content_type())
+      .streamed_body(ReaderStream::from(lines.map(Cursor::new)))
+      .ok()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::config::AppConfig;
+  use crate::server::api::{ScoreValue, ScoresResponseEntry, TIMESTAMP_FORMAT_ENV_VAR};
+
+  use chrono::NaiveDateTime;
+  use rocket::{get, routes};
+  use rocket::local::blocking::Client;
+  use serde_json::Value;
+
+  fn entry(player_name: &str, player_score: f64) -> ScoresResponseEntry {
+    ScoresResponseEntry {
+      player_name: player_name.to_owned(),
+      player_score: ScoreValue::Float(player_score),
+      player_score_metadata: None,
+      creation_timestamp: NaiveDateTime::parse_from_str("2025-02-01 05:33:10", "%Y-%m-%d %H:%M:%S").unwrap(),
+    }
+  }
+
+  #[get("/entries")]
+  fn entries() -> NdjsonStream<impl Stream<Item = String> + Send + 'static> {
+    // Serialized lazily, as the response is streamed.
+    let entries = vec![entry("Alice", 3.0), entry("Bob", 2.0), entry("Carol", 1.0)];
+    NdjsonStream(stream::iter(entries).map(|entry| to_ndjson_line(&entry).unwrap()))
+  }
+
+  fn lines(rocket: rocket::Rocket<rocket::Build>) -> Vec<Value> {
+    let client = Client::untracked(rocket.mount("/", routes![entries])).unwrap();
+    let response = client.get("/entries").dispatch();
+    assert_eq!(response.content_type(), Some(ndjson_content_type()));
+    let body = response.into_string().unwrap();
+    assert!(body.ends_with('\n'));
+    body.lines().map(|line| serde_json::from_str(line).unwrap()).collect()
+  }
+
+  #[test]
+  fn test_one_json_object_per_line_in_order() {
+    let lines = lines(rocket::build());
+    let names = lines.iter().map(|line| line["player_name"].as_str().unwrap()).collect::<Vec<_>>();
+    assert_eq!(names, ["Alice", "Bob", "Carol"]);
+    assert_eq!(lines[0]["creation_timestamp"], "2025-02-01 05:33:10");
+  }
+
+  #[test]
+  fn test_lines_use_configured_timestamp_format() {
+    let config = AppConfig::from_vars(|name| (name == TIMESTAMP_FORMAT_ENV_VAR).then(|| String::from("iso8601")));
+    let lines = lines(rocket::build().manage(config));
+    assert_eq!(lines.len(), 3);
+    assert!(lines.iter().all(|line| line["creation_timestamp"] == "2025-02-01T05:33:10Z"));
+  }
+}
@@ -1,7 +1,9 @@
 
-use super::{admin, api};
+use super::{admin, api, clock, health, version};
 use crate::server::data_access;
 
+use rocket::{Route, get, routes};
+use rocket::serde::json::Json;
 use utoipa::{Modify, OpenApi, ToSchema, openapi};
 use utoipa::openapi::security::{SecurityScheme, ApiKey, ApiKeyValue, Http, HttpAuthScheme, SecurityRequirement};
 use uuid::Uuid;
@@ -9,16 +11,19 @@ use uuid::Uuid;
 #[derive(OpenApi)]
 #[openapi(
   paths(
-    api::authorize,
-    admin::create_developer, api::get_developer, api::get_current_developer,
-    api::create_game, api::get_game,
+    api::authorize, api::check_authorization, api::login, admin::impersonate_developer, admin::revoke_developer_tokens,
+    admin::create_developer, admin::get_developer_tree, admin::set_default_security_level, api::get_developer, api::get_current_developer, api::get_current_developer_top_scores, api::get_current_developer_recent_submissions,
+    api::create_game, api::create_or_fetch_game, api::get_game, api::create_game_secret_key, api::rotate_game_secret_key, api::deactivate_game_secret_key, admin::set_game_enabled, admin::set_game_quota, admin::set_game_max_tables,
     api::create_highscore_table, api::get_highscore_table, api::get_highscore_table_scores,
+    api::get_highscore_table_entry, api::get_highscore_table_trim_preview, api::delete_highscore_table, api::delete_highscore_table_player, api::get_highscore_table_player_history, admin::retrim_highscore_tables, admin::recount_highscore_tables, admin::import_highscore_table_scores,
+    version::get_version, clock::get_time, health::get_health, health::get_readiness, admin::get_verification_metrics, admin::verify_signature, admin::get_audit_log, admin::get_failed_webhooks, admin::replay_failed_webhook, admin::get_highscore_table_rank_changes,
   ),
   tags(
     (name = "authorization", description = "Authorization API for developers"),
     (name = "developer", description = "Query information about individual developers"),
     (name = "game", description = "Video game access and creation"),
     (name = "highscore-table", description = "Highscore table access and creation"),
+    (name = "server", description = "Information about the server itself"),
   ),
   modifiers(&SecurityAddon),
   components(
@@ -30,6 +35,10 @@ pub struct ApiDoc;
 
 pub struct SecurityAddon;
 
+pub fn openapi_routes() -> Vec<Route> {
+  routes![get_openapi_json]
+}
+
 /// [`Uuid`] does not implement [`ToSchema`], so we use this type as
 /// documentation for any OpenAPI responses or parameters that contain
 /// a value of type `Uuid`. Note that this type is ONLY used for
@@ -56,3 +65,29 @@ impl Modify for SecurityAddon {
     openapi.security = Some(vec![SecurityRequirement::new("Bearer".to_string(), Vec::<String>::new())]);
   }
 }
+
+/// Serves the OpenAPI document on its own, for client code
+/// generators which have no use for the Swagger UI.
+#[get("/openapi.json")]
+fn get_openapi_json() -> Json<openapi::OpenApi> {
+  Json(ApiDoc::openapi())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  use rocket::http::{ContentType, Status};
+  use rocket::local::blocking::Client;
+
+  #[test]
+  fn test_openapi_json_is_a_document() {
+    let client = Client::untracked(rocket::build().mount("/", openapi_routes())).unwrap();
+    let response = client.get("/openapi.json").dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.content_type(), Some(ContentType::JSON));
+    let document = response.into_json::<openapi::OpenApi>().unwrap();
+    assert!(document.paths.paths.contains_key("/version"));
+    assert!(document.components.unwrap().security_schemes.contains_key("Bearer"));
+  }
+}
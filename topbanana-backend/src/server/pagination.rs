@@ -0,0 +1,212 @@
+
+//! Request guards for reading pagination parameters, and responders
+//! for reporting pagination information in HTTP headers.
+
+use crate::config::{AppConfig, DEFAULT_MAX_PAGE_SIZE};
+use super::error::{ApiError, stash_error};
+
+use rocket::http::{Header, Status};
+use rocket::http::uri::fmt::{Query, UriDisplay};
+use rocket::request::{self, FromRequest};
+use rocket::response::{self, Responder};
+use rocket::Request;
+
+/// Request guard reading the `limit` and `offset` query parameters.
+///
+/// A `limit` above [`AppConfig::max_page_size`] is reduced to it, and
+/// a missing `limit` is [`AppConfig::max_page_size`], so that no
+/// request can read an unbounded page. A missing `offset` is zero. A
+/// value which is not a non-negative integer is rejected with HTTP
+/// 400.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Pagination {
+  /// Maximum number of items to return.
+  pub limit: u32,
+  /// Number of items to skip.
+  pub offset: u32,
+}
+
+/// Wrapper which adds `X-Total-Count` and RFC 5988 `Link` headers to
+/// a paginated response. The JSON body of the inner responder is
+/// unchanged.
+///
+/// Links are computed from the request URI, with the `limit` and
+/// `offset` query parameters replaced and all other query parameters
+/// preserved. If no `limit` was given, the whole collection fits on
+/// one page and only `X-Total-Count` is set.
+#[derive(Debug, Clone)]
+pub struct Paginated<T> {
+  pub inner: T,
+  pub limit: Option<u32>,
+  pub offset: u32,
+  pub total_count: i64,
+}
+
+pub const X_TOTAL_COUNT_HEADER: &str = "X-Total-Count";
+
+impl Pagination {
+  /// The pagination for the given parameters, with `limit` clamped to
+  /// `max_page_size` and defaulting to it.
+  pub fn new(limit: Option<u32>, offset: Option<u32>, max_page_size: u32) -> Self {
+    Pagination {
+      limit: limit.map_or(max_page_size, |limit| limit.min(max_page_size)),
+      offset: offset.unwrap_or(0),
+    }
+  }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Pagination {
+  type Error = ApiError;
+
+  async fn from_request(req: &'r Request<'_>) -> request::Outcome<Self, ApiError> {
+    fn fail(req: &Request<'_>, field: &str) -> request::Outcome<Pagination, ApiError> {
+      let err = ApiError::bad_request().with_message(format!("{} must be a non-negative integer", field));
+      stash_error(req, &err);
+      request::Outcome::Error((Status::BadRequest, err))
+    }
+
+    let Ok(limit) = req.query_value::<u32>("limit").transpose() else {
+      return fail(req, "limit");
+    };
+    let Ok(offset) = req.query_value::<u32>("offset").transpose() else {
+      return fail(req, "offset");
+    };
+    let max_page_size = req.rocket().state::<AppConfig>().map_or(DEFAULT_MAX_PAGE_SIZE, |config| config.max_page_size);
+    request::Outcome::Success(Pagination::new(limit, offset, max_page_size))
+  }
+}
+
+impl<T> Paginated<T> {
+  pub fn new(inner: T, limit: Option<u32>, offset: Option<u32>, total_count: i64) -> Self {
+    Paginated { inner, limit, offset: offset.unwrap_or(0), total_count }
+  }
+
+  /// As [`Paginated::new`], for a page read with the given
+  /// [`Pagination`].
+  pub fn from_pagination(inner: T, pagination: Pagination, total_count: i64) -> Self {
+    Paginated { inner, limit: Some(pagination.limit), offset: pagination.offset, total_count }
+  }
+}
+
+/// The `(rel, offset)` pairs of the pages linked to from the page at
+/// `offset`.
+fn link_offsets(limit: u32, offset: u32, total_count: i64) -> Vec<(&'static str, u64)> {
+  let limit = u64::from(limit.max(1));
+  let offset = u64::from(offset);
+  let total_count = u64::try_from(total_count).unwrap_or(0);
+  let last = total_count.saturating_sub(1) / limit * limit;
+  let mut links = vec![("first", 0)];
+  if offset > 0 {
+    links.push(("prev", offset.saturating_sub(limit)));
+  }
+  if offset + limit < total_count {
+    links.push(("next", offset + limit));
+  }
+  links.push(("last", last));
+  links
+}
+
+impl<'r, T: Responder<'r, 'static>> Responder<'r, 'static> for Paginated<T> {
+  fn respond_to(self, req: &'r Request<'_>) -> response::Result<'static> {
+    let mut response = self.inner.respond_to(req)?;
+    response.set_header(Header::new(X_TOTAL_COUNT_HEADER, self.total_count.to_string()));
+    if let Some(limit) = self.limit {
+      let path = req.uri().path();
+      let other_params = req.uri().query()
+        .map(|query| {
+          query.segments()
+            .filter(|(key, _)| *key != "limit" && *key != "offset")
+            .map(|(key, value)| format!("{}={}&", key, &value as &dyn UriDisplay<Query>))
+            .collect::<String>()
+        })
+        .unwrap_or_default();
+      let links = link_offsets(limit, self.offset, self.total_count)
+        .into_iter()
+        .map(|(rel, offset)| format!("<{}?{}limit={}&offset={}>; rel=\"{}\"", path, other_params, limit, offset, rel))
+        .collect::<Vec<_>>()
+        .join(", ");
+      response.set_header(Header::new("Link", links));
+    }
+    Ok(response)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use rocket::get;
+  use rocket::local::blocking::Client;
+
+  #[get("/paginated")]
+  fn paginated(pagination: Pagination) -> String {
+    format!("{} {}", pagination.limit, pagination.offset)
+  }
+
+  fn client(config: Option<AppConfig>) -> Client {
+    let rocket = rocket::build().mount("/", rocket::routes![paginated]);
+    let rocket = match config {
+      Some(config) => rocket.manage(config),
+      None => rocket,
+    };
+    Client::untracked(rocket).unwrap()
+  }
+
+  fn config_with_max_page_size(max_page_size: &str) -> AppConfig {
+    AppConfig::from_vars(|name| (name == crate::config::MAX_PAGE_SIZE_ENV_VAR).then(|| max_page_size.to_owned()))
+  }
+
+  #[test]
+  fn test_new_defaults_limit_to_max_page_size() {
+    assert_eq!(Pagination::new(None, None, 50), Pagination { limit: 50, offset: 0 });
+  }
+
+  #[test]
+  fn test_new_clamps_limit() {
+    assert_eq!(Pagination::new(Some(500), Some(20), 50), Pagination { limit: 50, offset: 20 });
+    assert_eq!(Pagination::new(Some(10), Some(20), 50), Pagination { limit: 10, offset: 20 });
+  }
+
+  #[test]
+  fn test_guard_defaults() {
+    let client = client(None);
+    let response = client.get("/paginated").dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.into_string().unwrap(), format!("{} 0", DEFAULT_MAX_PAGE_SIZE));
+  }
+
+  #[test]
+  fn test_guard_clamps_to_configured_max_page_size() {
+    let client = client(Some(config_with_max_page_size("25")));
+    assert_eq!(client.get("/paginated?limit=100&offset=5").dispatch().into_string().unwrap(), "25 5");
+    assert_eq!(client.get("/paginated?limit=10").dispatch().into_string().unwrap(), "10 0");
+    assert_eq!(client.get("/paginated").dispatch().into_string().unwrap(), "25 0");
+  }
+
+  #[test]
+  fn test_guard_rejects_garbage() {
+    let client = client(None);
+    for uri in ["/paginated?limit=-1", "/paginated?limit=ten", "/paginated?offset=-5", "/paginated?offset=1.5"] {
+      assert_eq!(client.get(uri).dispatch().status(), Status::BadRequest, "{}", uri);
+    }
+  }
+
+  #[test]
+  fn test_link_offsets_middle_page() {
+    assert_eq!(
+      link_offsets(10, 20, 45),
+      vec![("first", 0), ("prev", 10), ("next", 30), ("last", 40)],
+    );
+  }
+
+  #[test]
+  fn test_link_offsets_first_and_last_pages() {
+    assert_eq!(link_offsets(10, 0, 45), vec![("first", 0), ("next", 10), ("last", 40)]);
+    assert_eq!(link_offsets(10, 40, 45), vec![("first", 0), ("prev", 30), ("last", 40)]);
+  }
+
+  #[test]
+  fn test_link_offsets_empty_collection() {
+    assert_eq!(link_offsets(10, 0, 0), vec![("first", 0), ("last", 0)]);
+  }
+}
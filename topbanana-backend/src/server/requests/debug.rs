@@ -0,0 +1,175 @@
+
+//! Optional description of each game request's verification outcome,
+//! for developers integrating a new client.
+//!
+//! When enabled by [`AppConfig::verify_debug`], responses to game
+//! requests carry an [`VERIFY_DEBUG_HEADER`] header saying which
+//! algorithm and key were used and how verification ended. Neither
+//! the secret key nor the signature itself is ever included.
+
+use super::{GameRequestPayload, RequestBodyVerifyError, SigningDetails, SigningKey};
+use crate::config::AppConfig;
+
+use base64::engine::general_purpose::URL_SAFE;
+use base64::Engine;
+use rocket::{Request, Response};
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::http::Header;
+
+/// Header describing how a game request's verification went.
+pub const VERIFY_DEBUG_HEADER: &str = "X-TopBanana-Verify-Debug";
+
+/// Fairing which copies a request's verification description, if one
+/// was recorded, into the [`VERIFY_DEBUG_HEADER`] of its response.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct VerifyDebugHeader;
+
+/// The verification description for a request, kept in the request's
+/// local cache.
+#[derive(Debug, Clone, Default)]
+struct VerifyDebug(Option<String>);
+
+/// Records the outcome of verifying `payload` for the
+/// [`VerifyDebugHeader`] fairing, if debugging is enabled. `details`
+/// gives the algorithm and key verification actually used, which may
+/// differ from those the request names.
+pub fn record_verify_debug<T>(
+  req: &Request<'_>,
+  payload: &GameRequestPayload,
+  details: SigningDetails,
+  result: &Result<T, RequestBodyVerifyError>,
+) {
+  if !req.rocket().state::<AppConfig>().is_some_and(|config| config.verify_debug) {
+    return;
+  }
+  let description = describe(payload, details, result);
+  req.local_cache(|| VerifyDebug(Some(description)));
+}
+
+fn describe<T>(payload: &GameRequestPayload, details: SigningDetails, result: &Result<T, RequestBodyVerifyError>) -> String {
+  let outcome = match result {
+    Ok(_) => String::from("ok"),
+    // Database errors may describe the query, which is no business of
+    // the client's.
+    Err(RequestBodyVerifyError::DieselError(_)) => String::from("database error"),
+    Err(err) => err.to_string(),
+  };
+  let mut parts = vec![format!("result={}", outcome)];
+  if let Some(algo) = details.algo {
+    parts.push(format!("algo={}", algo.name()));
+    parts.push(format!("expected_signature_bytes={}", algo.into_hasher().output_len()));
+  }
+  match details.key {
+    Some(SigningKey::Current) => parts.push(String::from("key_id=original")),
+    Some(SigningKey::Previous) => parts.push(String::from("key_id=previous")),
+    Some(SigningKey::Id(key_id)) => parts.push(format!("key_id={}", key_id)),
+    None => {}
+  }
+  if let Ok(signature) = URL_SAFE.decode(payload.signature_base64.as_bytes()) {
+    parts.push(format!("signature_bytes={}", signature.len()));
+  }
+  parts.join("; ")
+}
+
+#[rocket::async_trait]
+impl Fairing for VerifyDebugHeader {
+  fn info(&self) -> Info {
+    Info { name: "Verification debug header", kind: Kind::Response }
+  }
+
+  async fn on_response<'r>(&self, req: &'r Request<'_>, res: &mut Response<'r>) {
+    if let VerifyDebug(Some(description)) = req.local_cache(VerifyDebug::default) {
+      res.set_header(Header::new(VERIFY_DEBUG_HEADER, description.clone()));
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::server::requests::{RequestAlgorithm, VerificationError};
+
+  use rocket::{get, routes};
+  use rocket::request::{self, FromRequest};
+  use rocket::local::blocking::Client;
+  use uuid::Uuid;
+
+  /// A payload with no `algo` or `key_id`, signed with a 32-byte
+  /// signature.
+  fn payload() -> GameRequestPayload {
+    let signature = URL_SAFE.encode([0u8; 32]);
+    GameRequestPayload::new(URL_SAFE.encode(br#"{"game_uuid":"x"}"#), signature)
+  }
+
+  /// Request guard which records a failed verification, as the game
+  /// request guards do.
+  struct RecordsVerification;
+
+  #[rocket::async_trait]
+  impl<'r> FromRequest<'r> for RecordsVerification {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> request::Outcome<Self, ()> {
+      let result = Err::<(), _>(RequestBodyVerifyError::NoSuchGame);
+      record_verify_debug(req, &payload(), SigningDetails::default(), &result);
+      request::Outcome::Success(RecordsVerification)
+    }
+  }
+
+  #[get("/verified")]
+  fn verified(_guard: RecordsVerification) -> &'static str {
+    "ok"
+  }
+
+  fn debug_header(verify_debug: bool) -> Option<String> {
+    let config = AppConfig::from_vars(|name| (name == crate::config::VERIFY_DEBUG_ENV_VAR).then(|| verify_debug.to_string()));
+    let rocket = rocket::build().manage(config).mount("/", routes![verified]).attach(VerifyDebugHeader);
+    let client = Client::untracked(rocket).unwrap();
+    let response = client.get("/verified").dispatch();
+    response.headers().get_one(VERIFY_DEBUG_HEADER).map(str::to_owned)
+  }
+
+  #[test]
+  fn test_header_sent_only_when_enabled() {
+    assert_eq!(debug_header(true).as_deref(), Some("result=No such game; signature_bytes=32"));
+    assert_eq!(debug_header(false), None);
+  }
+
+  #[test]
+  fn test_describe_reports_game_default_algorithm() {
+    let details = SigningDetails { algo: Some(RequestAlgorithm::Sha256), key: Some(SigningKey::Current) };
+    assert_eq!(
+      describe(&payload(), details, &Ok::<(), _>(())),
+      "result=ok; algo=sha256; expected_signature_bytes=32; key_id=original; signature_bytes=32",
+    );
+  }
+
+  #[test]
+  fn test_describe_reports_previous_key() {
+    let details = SigningDetails { algo: Some(RequestAlgorithm::Sha1), key: Some(SigningKey::Previous) };
+    let description = describe(&payload(), details, &Ok::<(), _>(()));
+    assert!(description.contains("algo=sha1; expected_signature_bytes=20; key_id=previous"), "{}", description);
+  }
+
+  #[test]
+  fn test_describe_reports_key_id() {
+    let key_id = Uuid::now_v7();
+    let details = SigningDetails { algo: Some(RequestAlgorithm::Sha256), key: Some(SigningKey::Id(key_id)) };
+    let result = Err::<(), _>(RequestBodyVerifyError::VerificationError(VerificationError::UnknownKey));
+    let description = describe(&payload(), details, &result);
+    assert!(description.starts_with("result=Unknown or inactive signing key;"), "{}", description);
+    assert!(description.contains(&format!("key_id={}", key_id)), "{}", description);
+  }
+
+  #[test]
+  fn test_describe_omits_what_verification_did_not_reach() {
+    let result = Err::<(), _>(RequestBodyVerifyError::NoSuchGame);
+    assert_eq!(describe(&payload(), SigningDetails::default(), &result), "result=No such game; signature_bytes=32");
+  }
+
+  #[test]
+  fn test_describe_hides_database_errors() {
+    let result = Err::<(), _>(RequestBodyVerifyError::DieselError(diesel::result::Error::NotFound));
+    assert!(describe(&payload(), SigningDetails::default(), &result).starts_with("result=database error"));
+  }
+}
@@ -0,0 +1,74 @@
+
+//! Decompression of game request bodies sent with a
+//! `Content-Encoding`.
+
+use super::GameRequestPayload;
+use crate::server::error::ApiError;
+
+use flate2::read::GzDecoder;
+use rocket::Request;
+use rocket::data::{Data, Limits, ToByteUnit};
+use rocket::http::Status;
+
+use std::io::Read;
+use std::str::from_utf8;
+
+/// Largest accepted game request body after decompression. Anything
+/// larger is rejected with a 413 rather than decompressed further, so
+/// that a small compressed body cannot expand without bound.
+pub const MAX_DECOMPRESSED_BODY_BYTES: u64 = 1024 * 1024;
+
+/// Encoding of a game request body, from its `Content-Encoding`
+/// header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContentEncoding {
+  Identity,
+  Gzip,
+}
+
+impl ContentEncoding {
+  /// The encoding of the request's body, or a 415 if the encoding is
+  /// not supported.
+  pub fn of_request(req: &Request<'_>) -> Result<ContentEncoding, ApiError> {
+    match req.headers().get_one("Content-Encoding").map(str::trim) {
+      None | Some("") => Ok(ContentEncoding::Identity),
+      Some(encoding) if encoding.eq_ignore_ascii_case("identity") => Ok(ContentEncoding::Identity),
+      Some(encoding) if encoding.eq_ignore_ascii_case("gzip") => Ok(ContentEncoding::Gzip),
+      Some(encoding) => Err(
+        ApiError::from_status(Status::UnsupportedMediaType)
+          .with_message(format!("Unsupported Content-Encoding: {}", encoding)),
+      ),
+    }
+  }
+}
+
+/// Reads and decompresses a gzipped game request body. The compressed
+/// body is subject to the Rocket string datatype limit, and the
+/// decompressed body to [`MAX_DECOMPRESSED_BODY_BYTES`].
+///
+/// The signature is checked against the decompressed payload, so a
+/// compressed request is signed exactly as an uncompressed one.
+pub async fn read_gzip_payload(req: &Request<'_>, data: Data<'_>) -> Result<GameRequestPayload, ApiError> {
+  let limit = req.limits().get("string").unwrap_or(Limits::STRING);
+  let compressed = data.open(limit).into_bytes().await
+    .map_err(|err| ApiError::bad_request().with_message(err.to_string()))?;
+  if !compressed.is_complete() {
+    return Err(payload_too_large(limit.as_u64()));
+  }
+  let mut decompressed = Vec::new();
+  GzDecoder::new(compressed.as_slice())
+    .take(MAX_DECOMPRESSED_BODY_BYTES + 1)
+    .read_to_end(&mut decompressed)
+    .map_err(|err| ApiError::bad_request().with_message(format!("Malformed gzip body: {}", err)))?;
+  if decompressed.len() as u64 > MAX_DECOMPRESSED_BODY_BYTES {
+    return Err(payload_too_large(MAX_DECOMPRESSED_BODY_BYTES));
+  }
+  let text = from_utf8(&decompressed)
+    .map_err(|err| ApiError::bad_request().with_message(err.to_string()))?;
+  text.parse().map_err(ApiError::from)
+}
+
+fn payload_too_large(limit: u64) -> ApiError {
+  ApiError::from_status(Status::PayloadTooLarge)
+    .with_message(format!("Request body exceeds {}", limit.bytes()))
+}
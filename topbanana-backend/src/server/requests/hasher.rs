@@ -9,6 +9,10 @@ pub trait RequestSigningHasher {
   fn security_level(&self) -> SecurityLevel;
 
   fn apply_hash(&self, buf: &str) -> Box<[u8]>;
+
+  /// Length in bytes of the digests produced by
+  /// [`apply_hash`](RequestSigningHasher::apply_hash).
+  fn output_len(&self) -> usize;
 }
 
 #[derive(Debug, Clone, Error)]
@@ -50,6 +54,10 @@ impl RequestSigningHasher for Sha256Hasher {
     hasher.update(buf.as_bytes());
     hasher.finalize().into_iter().collect()
   }
+
+  fn output_len(&self) -> usize {
+    <Sha256 as Digest>::output_size()
+  }
 }
 
 impl RequestSigningHasher for Sha1Hasher {
@@ -62,6 +70,10 @@ impl RequestSigningHasher for Sha1Hasher {
     hasher.update(buf.as_bytes());
     hasher.finalize().into_iter().collect()
   }
+
+  fn output_len(&self) -> usize {
+    <Sha1 as Digest>::output_size()
+  }
 }
 
 impl From<SecurityLevel> for i32 {
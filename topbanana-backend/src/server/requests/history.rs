@@ -0,0 +1,317 @@
+
+//! Recording of request UUIDs, for replay protection.
+//!
+//! By default, each request UUID is inserted into
+//! `historical_requests` as part of verifying the request. If
+//! batching is enabled, the insert is instead handed to a background
+//! task which writes UUIDs in batches, while a set of not-yet-written
+//! UUIDs is kept in memory so that the replay check remains
+//! synchronous.
+//!
+//! That set belongs to one server process. A replay is rejected
+//! immediately by the process which saw the original request, but a
+//! replay sent to another process sharing the database is only
+//! rejected once the original's batch has been written. Batching
+//! therefore only gives per-process replay protection until then; run
+//! a single process, or leave batching off, if that gap matters.
+//!
+//! A batch which fails to write is retried with exponential backoff.
+//! The queue of UUIDs waiting to be written is bounded, and a UUID
+//! which finds it full is written directly, as if batching were off.
+
+use crate::db::{schema, models};
+
+use diesel::prelude::*;
+use diesel_async::{RunQueryDsl, AsyncPgConnection};
+use diesel_async::pooled_connection::deadpool::Pool;
+use log::{error, warn};
+use rocket::tokio;
+use rocket::tokio::sync::mpsc::{self, error::TrySendError};
+use uuid::Uuid;
+
+use std::collections::HashSet;
+use std::future::Future;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::Duration;
+
+/// Environment variable which, if set to `true` or `1`, enables
+/// batched writes of historical requests. See
+/// [`AppConfig::batch_historical_requests`](crate::config::AppConfig::batch_historical_requests).
+pub const BATCH_ENV_VAR: &str = "BATCH_HISTORICAL_REQUESTS";
+
+/// Maximum number of request UUIDs written in one insert.
+pub const MAX_BATCH_SIZE: usize = 256;
+
+/// Maximum number of request UUIDs waiting to be written. Beyond
+/// this, UUIDs are written directly.
+pub const MAX_QUEUED_REQUESTS: usize = 16 * MAX_BATCH_SIZE;
+
+/// Number of times a batch is attempted before it is given up on.
+pub const BATCH_WRITE_ATTEMPTS: u32 = 5;
+
+/// Delay before the first retry of a failed batch. Each later retry
+/// waits twice as long as the one before.
+pub const INITIAL_RETRY_DELAY: Duration = Duration::from_millis(200);
+
+/// Background writer for historical requests, kept in Rocket's
+/// managed state when batching is enabled.
+#[derive(Debug)]
+pub struct HistoricalRequestWriter {
+  /// UUIDs which have been accepted but not yet written to the
+  /// database.
+  in_flight: Arc<Mutex<HashSet<Uuid>>>,
+  sender: mpsc::Sender<Uuid>,
+}
+
+/// Records the request UUID, returning false if it has been seen
+/// before. This is a single statement, so that two concurrent
+/// requests with the same UUID cannot both pass: if nothing was
+/// inserted, the UUID has already been seen.
+pub async fn record_request(request_uuid: Uuid, db: &mut AsyncPgConnection) -> diesel::QueryResult<bool> {
+  let new_row = models::NewHistoricalRequest { request_uuid };
+  let inserted_rows = diesel::insert_into(schema::historical_requests::table)
+    .values(&new_row)
+    .on_conflict(schema::historical_requests::request_uuid)
+    .do_nothing()
+    .execute(db)
+    .await?;
+  Ok(inserted_rows > 0)
+}
+
+/// The recorded request UUIDs, as seen by
+/// [`HistoricalRequestWriter::record_request`]. This is the database,
+/// except in tests.
+trait RequestLog {
+  /// Whether the UUID has already been recorded.
+  async fn contains(&mut self, request_uuid: Uuid) -> diesel::QueryResult<bool>;
+
+  /// Records the UUID, as [`record_request`] does.
+  async fn record(&mut self, request_uuid: Uuid) -> diesel::QueryResult<bool>;
+}
+
+impl RequestLog for AsyncPgConnection {
+  async fn contains(&mut self, request_uuid: Uuid) -> diesel::QueryResult<bool> {
+    diesel::select(diesel::dsl::exists(
+      schema::historical_requests::table
+        .filter(schema::historical_requests::request_uuid.eq(request_uuid)),
+    ))
+      .get_result::<bool>(self)
+      .await
+  }
+
+  async fn record(&mut self, request_uuid: Uuid) -> diesel::QueryResult<bool> {
+    record_request(request_uuid, self).await
+  }
+}
+
+impl HistoricalRequestWriter {
+  /// Spawns the background task, which writes to the database using
+  /// connections from `pool`.
+  pub fn spawn(pool: Pool<AsyncPgConnection>) -> Self {
+    let (sender, receiver) = mpsc::channel(MAX_QUEUED_REQUESTS);
+    let writer = HistoricalRequestWriter::new(sender);
+    tokio::spawn(run_writer(pool, Arc::clone(&writer.in_flight), receiver));
+    writer
+  }
+
+  fn new(sender: mpsc::Sender<Uuid>) -> Self {
+    HistoricalRequestWriter { in_flight: Arc::new(Mutex::new(HashSet::new())), sender }
+  }
+
+  /// As [`record_request`], but the insert itself happens later in
+  /// the background. A UUID is rejected if it is either already in
+  /// the database or still waiting to be written.
+  pub async fn record_request(&self, request_uuid: Uuid, db: &mut AsyncPgConnection) -> diesel::QueryResult<bool> {
+    self.record_request_in(request_uuid, db).await
+  }
+
+  async fn record_request_in(&self, request_uuid: Uuid, log: &mut impl RequestLog) -> diesel::QueryResult<bool> {
+    if !self.in_flight().insert(request_uuid) {
+      return Ok(false);
+    }
+    let already_written = log.contains(request_uuid).await
+      .inspect_err(|_| { self.in_flight().remove(&request_uuid); })?;
+    if already_written {
+      self.in_flight().remove(&request_uuid);
+      return Ok(false);
+    }
+    match self.sender.try_send(request_uuid) {
+      Ok(()) => Ok(true),
+      Err(err) => {
+        // Write the UUID ourselves, rather than waiting for room in
+        // the queue or for a writer which will never come back.
+        match err {
+          TrySendError::Full(_) => warn!("Historical request queue is full; recording request {} directly", request_uuid),
+          TrySendError::Closed(_) => error!("Historical request writer has stopped; recording request {} directly", request_uuid),
+        }
+        let recorded = log.record(request_uuid).await;
+        self.in_flight().remove(&request_uuid);
+        recorded
+      }
+    }
+  }
+
+  fn in_flight(&self) -> MutexGuard<'_, HashSet<Uuid>> {
+    self.in_flight.lock().unwrap_or_else(PoisonError::into_inner)
+  }
+}
+
+async fn run_writer(
+  pool: Pool<AsyncPgConnection>,
+  in_flight: Arc<Mutex<HashSet<Uuid>>>,
+  mut receiver: mpsc::Receiver<Uuid>,
+) {
+  let mut batch = Vec::with_capacity(MAX_BATCH_SIZE);
+  while receiver.recv_many(&mut batch, MAX_BATCH_SIZE).await > 0 {
+    let result = retry_with_backoff(BATCH_WRITE_ATTEMPTS, INITIAL_RETRY_DELAY, || write_batch(&pool, &batch)).await;
+    match result {
+      Ok(()) => {
+        let mut in_flight = in_flight.lock().unwrap_or_else(PoisonError::into_inner);
+        for request_uuid in &batch {
+          in_flight.remove(request_uuid);
+        }
+      }
+      Err(err) => {
+        // Leave the UUIDs in memory, so that they are still rejected
+        // as replays for the lifetime of this process.
+        error!("Failed to record {} historical requests after {} attempts: {}", batch.len(), BATCH_WRITE_ATTEMPTS, err);
+      }
+    }
+    batch.clear();
+  }
+}
+
+/// Runs `attempt` until it succeeds, up to `attempts` times, doubling
+/// the delay between attempts each time. Returns the last error if
+/// every attempt fails.
+async fn retry_with_backoff<F, Fut>(attempts: u32, initial_delay: Duration, mut attempt: F) -> anyhow::Result<()>
+where F: FnMut() -> Fut,
+      Fut: Future<Output = anyhow::Result<()>> {
+  let mut delay = initial_delay;
+  let mut attempts_made = 1;
+  loop {
+    match attempt().await {
+      Ok(()) => return Ok(()),
+      Err(err) if attempts_made >= attempts => return Err(err),
+      Err(err) => {
+        warn!("Attempt {} of {} failed, retrying in {:?}: {}", attempts_made, attempts, delay, err);
+        tokio::time::sleep(delay).await;
+        delay *= 2;
+        attempts_made += 1;
+      }
+    }
+  }
+}
+
+async fn write_batch(pool: &Pool<AsyncPgConnection>, batch: &[Uuid]) -> anyhow::Result<()> {
+  let mut db = pool.get().await?;
+  let new_rows = batch.iter()
+    .map(|&request_uuid| models::NewHistoricalRequest { request_uuid })
+    .collect::<Vec<_>>();
+  diesel::insert_into(schema::historical_requests::table)
+    .values(&new_rows)
+    .on_conflict(schema::historical_requests::request_uuid)
+    .do_nothing()
+    .execute(&mut db)
+    .await?;
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  use std::cell::Cell;
+
+  /// An in-memory stand-in for `historical_requests`.
+  #[derive(Default)]
+  struct MemoryLog(HashSet<Uuid>);
+
+  impl RequestLog for MemoryLog {
+    async fn contains(&mut self, request_uuid: Uuid) -> diesel::QueryResult<bool> {
+      Ok(self.0.contains(&request_uuid))
+    }
+
+    async fn record(&mut self, request_uuid: Uuid) -> diesel::QueryResult<bool> {
+      Ok(self.0.insert(request_uuid))
+    }
+  }
+
+  /// An attempt which fails the first `failures` times it is run,
+  /// counting its runs in `runs`.
+  fn flaky(failures: u32, runs: &Cell<u32>) -> impl FnMut() -> std::future::Ready<anyhow::Result<()>> + '_ {
+    move || {
+      runs.set(runs.get() + 1);
+      std::future::ready(if runs.get() > failures { Ok(()) } else { Err(anyhow::anyhow!("write failed")) })
+    }
+  }
+
+  #[rocket::async_test]
+  async fn test_retry_succeeds_after_failures() {
+    let runs = Cell::new(0);
+    let result = retry_with_backoff(5, Duration::from_millis(1), flaky(2, &runs)).await;
+    assert!(result.is_ok());
+    assert_eq!(runs.get(), 3);
+  }
+
+  #[rocket::async_test]
+  async fn test_retry_gives_up_after_attempts() {
+    let runs = Cell::new(0);
+    let result = retry_with_backoff(3, Duration::from_millis(1), flaky(u32::MAX, &runs)).await;
+    assert!(result.is_err());
+    assert_eq!(runs.get(), 3);
+  }
+
+  #[rocket::async_test]
+  async fn test_retry_backs_off_exponentially() {
+    let runs = Cell::new(0);
+    let start = tokio::time::Instant::now();
+    let result = retry_with_backoff(3, Duration::from_millis(20), flaky(2, &runs)).await;
+    assert!(result.is_ok());
+    // Waits 20ms, then 40ms.
+    assert!(start.elapsed() >= Duration::from_millis(60));
+  }
+
+  #[rocket::async_test]
+  async fn test_queued_request_is_rejected_as_replay() {
+    let (sender, mut receiver) = mpsc::channel(MAX_QUEUED_REQUESTS);
+    let writer = HistoricalRequestWriter::new(sender);
+    let mut log = MemoryLog::default();
+    let request_uuid = Uuid::now_v7();
+    assert!(writer.record_request_in(request_uuid, &mut log).await.unwrap());
+    assert!(!writer.record_request_in(request_uuid, &mut log).await.unwrap());
+    // Queued for the background task, not yet written.
+    assert_eq!(receiver.try_recv().ok(), Some(request_uuid));
+    assert!(log.0.is_empty());
+  }
+
+  #[rocket::async_test]
+  async fn test_written_request_is_rejected_as_replay() {
+    let (sender, _receiver) = mpsc::channel(MAX_QUEUED_REQUESTS);
+    let writer = HistoricalRequestWriter::new(sender);
+    let request_uuid = Uuid::now_v7();
+    let mut log = MemoryLog(HashSet::from([request_uuid]));
+    assert!(!writer.record_request_in(request_uuid, &mut log).await.unwrap());
+    // Not left in flight, since it was never queued.
+    assert!(writer.in_flight().is_empty());
+  }
+
+  #[rocket::async_test]
+  async fn test_full_queue_records_directly() {
+    let (sender, mut receiver) = mpsc::channel(1);
+    let writer = HistoricalRequestWriter::new(sender);
+    let mut log = MemoryLog::default();
+    let (queued, direct) = (Uuid::now_v7(), Uuid::now_v7());
+    assert!(writer.record_request_in(queued, &mut log).await.unwrap());
+    assert!(writer.record_request_in(direct, &mut log).await.unwrap());
+    assert_eq!(log.0, HashSet::from([direct]));
+    assert!(!writer.record_request_in(direct, &mut log).await.unwrap());
+    assert!(!writer.record_request_in(queued, &mut log).await.unwrap());
+
+    // Likewise once the background task has stopped.
+    receiver.close();
+    let late = Uuid::now_v7();
+    assert!(writer.record_request_in(late, &mut log).await.unwrap());
+    assert!(log.0.contains(&late));
+  }
+}
@@ -0,0 +1,151 @@
+
+//! Counters of game request verification outcomes, for monitoring
+//! abuse and misconfigured clients.
+
+use super::RequestBodyVerifyError;
+
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Running counts of verification outcomes since the server started,
+/// kept in Rocket's managed state.
+#[derive(Debug, Default)]
+pub struct VerificationMetrics {
+  succeeded: AtomicU64,
+  malformed: AtomicU64,
+  no_such_game: AtomicU64,
+  bad_signature: AtomicU64,
+  stale_timestamp: AtomicU64,
+  replayed: AtomicU64,
+  security_level_not_attained: AtomicU64,
+  database_error: AtomicU64,
+}
+
+/// A point-in-time copy of [`VerificationMetrics`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+pub struct VerificationMetricsSnapshot {
+  /// Requests which passed verification.
+  pub succeeded: u64,
+  /// Requests whose payload could not be decoded.
+  pub malformed: u64,
+  /// Requests naming a game which does not exist.
+  pub no_such_game: u64,
+  /// Requests with an incorrect signature.
+  pub bad_signature: u64,
+  /// Requests whose timestamp was too far from the current time.
+  pub stale_timestamp: u64,
+  /// Requests reusing an already-seen request UUID.
+  pub replayed: u64,
+  /// Requests signed with an algorithm too weak for the game.
+  pub security_level_not_attained: u64,
+  /// Requests which could not be verified due to a database error.
+  pub database_error: u64,
+}
+
+impl VerificationMetrics {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Counts the outcome of verifying a single request.
+  pub fn record<T>(&self, result: &Result<T, RequestBodyVerifyError>) {
+    let counter = match result {
+      Ok(_) => &self.succeeded,
+      Err(RequestBodyVerifyError::DeserializeError(_) | RequestBodyVerifyError::NoAlgorithm) => &self.malformed,
+      Err(RequestBodyVerifyError::DieselError(_)) => &self.database_error,
+      Err(RequestBodyVerifyError::NoSuchGame) => &self.no_such_game,
+      Err(RequestBodyVerifyError::VerificationError(_)) => &self.bad_signature,
+      Err(RequestBodyVerifyError::BadRequestTimestamp) => &self.stale_timestamp,
+      Err(RequestBodyVerifyError::RequestAlreadySeen) => &self.replayed,
+      Err(RequestBodyVerifyError::SecurityLevelNotAttained { .. }) => &self.security_level_not_attained,
+    };
+    counter.fetch_add(1, Ordering::Relaxed);
+  }
+
+  /// Counts a request whose body was not even a well-formed payload.
+  pub fn record_malformed(&self) {
+    self.malformed.fetch_add(1, Ordering::Relaxed);
+  }
+
+  pub fn snapshot(&self) -> VerificationMetricsSnapshot {
+    VerificationMetricsSnapshot {
+      succeeded: self.succeeded.load(Ordering::Relaxed),
+      malformed: self.malformed.load(Ordering::Relaxed),
+      no_such_game: self.no_such_game.load(Ordering::Relaxed),
+      bad_signature: self.bad_signature.load(Ordering::Relaxed),
+      stale_timestamp: self.stale_timestamp.load(Ordering::Relaxed),
+      replayed: self.replayed.load(Ordering::Relaxed),
+      security_level_not_attained: self.security_level_not_attained.load(Ordering::Relaxed),
+      database_error: self.database_error.load(Ordering::Relaxed),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use super::super::{DeserializeError, RequestAlgorithm, VerificationError};
+  use super::super::tests::verify_errors;
+
+  type Counter = fn(&VerificationMetricsSnapshot) -> u64;
+
+  /// Every verification failure, with the counter it must increment.
+  fn failures() -> Vec<(RequestBodyVerifyError, Counter)> {
+    #[allow(invalid_from_utf8)]
+    let utf8_error = std::str::from_utf8(b"\xff").unwrap_err();
+    vec![
+      (DeserializeError::JsonError(serde_json::from_str::<()>("{").unwrap_err()).into(), |s| s.malformed),
+      (DeserializeError::JsonError(serde_json::from_str::<()>("{}").unwrap_err()).into(), |s| s.malformed),
+      (DeserializeError::EmptyPayload.into(), |s| s.malformed),
+      (DeserializeError::Base64Error(base64::DecodeError::InvalidLength(1)).into(), |s| s.malformed),
+      (DeserializeError::Utf8Error(utf8_error).into(), |s| s.malformed),
+      (diesel::result::Error::NotFound.into(), |s| s.database_error),
+      (diesel::result::Error::BrokenTransactionManager.into(), |s| s.database_error),
+      (RequestBodyVerifyError::NoSuchGame, |s| s.no_such_game),
+      (RequestBodyVerifyError::NoAlgorithm, |s| s.malformed),
+      (VerificationError::InvalidSignature.into(), |s| s.bad_signature),
+      (VerificationError::EmptySignature.into(), |s| s.bad_signature),
+      (VerificationError::SignatureNotBase64(base64::DecodeError::InvalidLength(1)).into(), |s| s.bad_signature),
+      (VerificationError::WrongSignatureLength { expected: 32, actual: 20 }.into(), |s| s.bad_signature),
+      (VerificationError::UnknownKey.into(), |s| s.bad_signature),
+      (RequestBodyVerifyError::BadRequestTimestamp, |s| s.stale_timestamp),
+      (RequestBodyVerifyError::RequestAlreadySeen, |s| s.replayed),
+      (RequestBodyVerifyError::SecurityLevelNotAttained { minimum_algorithm: Some(RequestAlgorithm::Sha256) }, |s| s.security_level_not_attained),
+      (RequestBodyVerifyError::SecurityLevelNotAttained { minimum_algorithm: None }, |s| s.security_level_not_attained),
+    ]
+  }
+
+  /// The sum of every counter.
+  fn total(snapshot: &VerificationMetricsSnapshot) -> u64 {
+    snapshot.succeeded + snapshot.malformed + snapshot.no_such_game + snapshot.bad_signature
+      + snapshot.stale_timestamp + snapshot.replayed + snapshot.security_level_not_attained + snapshot.database_error
+  }
+
+  #[test]
+  fn test_each_failure_increments_its_own_counter() {
+    let failures = failures();
+    assert_eq!(failures.len(), verify_errors().len(), "every failure in verify_errors should be listed");
+    for (err, counter) in failures {
+      let description = format!("{:?}", err);
+      let metrics = VerificationMetrics::new();
+      metrics.record::<()>(&Err(err));
+      let snapshot = metrics.snapshot();
+      assert_eq!(counter(&snapshot), 1, "{}", description);
+      assert_eq!(total(&snapshot), 1, "{}", description);
+    }
+  }
+
+  #[test]
+  fn test_success_and_malformed_counters() {
+    let metrics = VerificationMetrics::new();
+    metrics.record(&Ok(()));
+    metrics.record(&Ok(()));
+    metrics.record_malformed();
+    let snapshot = metrics.snapshot();
+    assert_eq!(snapshot.succeeded, 2);
+    assert_eq!(snapshot.malformed, 1);
+    assert_eq!(total(&snapshot), 3);
+  }
+}
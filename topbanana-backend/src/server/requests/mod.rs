@@ -2,26 +2,41 @@
 //! Helpers for verifying request UUID and digital signature
 //! information.
 
+mod debug;
+mod encoding;
 mod hasher;
+mod history;
+mod metrics;
 
+pub use debug::{VerifyDebugHeader, VERIFY_DEBUG_HEADER};
+pub use encoding::{ContentEncoding, MAX_DECOMPRESSED_BODY_BYTES};
 pub use hasher::{RequestSigningHasher, SecurityLevel, Sha256Hasher, Sha1Hasher};
+pub use history::{HistoricalRequestWriter, BATCH_ENV_VAR};
+pub use metrics::{VerificationMetrics, VerificationMetricsSnapshot};
 
-use crate::db::{schema, models};
-use crate::server::error::ApiError;
+use crate::db::schema;
+use crate::server::db::Db;
+use crate::server::error::{ApiError, stash_error};
 
 use base64::engine::general_purpose::URL_SAFE;
 use base64::Engine;
 use thiserror::Error;
-use serde::{Deserialize, Serialize};
-use serde::de::DeserializeOwned;
+use serde::{Deserialize, Deserializer, Serialize};
+use serde::de::{self, DeserializeOwned};
 use uuid::Uuid;
-use chrono::{NaiveDateTime, TimeDelta};
+use chrono::{DateTime, NaiveDateTime, TimeDelta};
 use chrono::naive::serde::ts_seconds;
 use diesel::prelude::*;
 use diesel_async::{RunQueryDsl, AsyncPgConnection};
 use log::{debug, warn};
+use rocket::Request;
+use rocket::data::{self, Data, FromData};
+use rocket::http::{Header, Status};
+use rocket::outcome::Outcome;
+use rocket_db_pools::Connection;
 
 use std::str::{from_utf8, Utf8Error, FromStr};
+use std::ops::Deref;
 
 /// A payload for a request made from a relevant video game client.
 ///
@@ -43,13 +58,53 @@ pub struct GameRequestPayload {
 pub struct GameRequestBody<T> {
   pub game_uuid: Uuid,
   pub request_uuid: Uuid,
-  #[serde(with = "ts_seconds")]
+  #[serde(serialize_with = "ts_seconds::serialize", deserialize_with = "deserialize_request_timestamp")]
   pub request_timestamp: NaiveDateTime,
-  pub algo: RequestAlgorithm,
+  /// The algorithm the request is signed with. If absent, the game's
+  /// default algorithm is used. Verification fills this in, so it is
+  /// always present on a verified body.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub algo: Option<RequestAlgorithm>,
+  /// The game secret key the request is signed with. If absent, the
+  /// request is signed with the game's original secret key.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub key_id: Option<Uuid>,
   #[serde(flatten)]
   pub body: T,
 }
 
+/// Rocket data guard which parses a [`GameRequestPayload`] and fully
+/// verifies it (see [`GameRequestBody::full_verify`]) before the
+/// handler runs. Handlers taking this guard receive only requests
+/// whose signature, timestamp, and request UUID have been checked.
+///
+/// The guard verifies the request with a connection from the pool
+/// and then hands that same connection to the handler (see
+/// [`VerifiedGameRequest::into_parts`]), so handlers taking this
+/// guard should not also take a [`Connection`]; doing so would hold
+/// two of the pool's connections for the whole request.
+///
+/// On failure, the guard's [`ApiError`] is reported by the error
+/// catchers.
+pub struct VerifiedGameRequest<T> {
+  body: GameRequestBody<T>,
+  db: Connection<Db>,
+}
+
+/// Rocket data guard which verifies a [`GameRequestPayload`] as
+/// [`VerifiedGameRequest`] does, except that the request UUID is not
+/// checked or recorded (see
+/// [`GameRequestBody::verify_without_replay_check_at_time`]). This is
+/// only for endpoints which change nothing, such as previews.
+pub struct ReplayableGameRequest<T> {
+  body: GameRequestBody<T>,
+  db: Connection<Db>,
+}
+
+/// Header naming the weakest signing algorithm a game accepts, sent
+/// when a request is rejected for using a weaker one.
+pub const MINIMUM_ALGORITHM_HEADER: &str = "X-Minimum-Algorithm";
+
 /// Chosen algorithm for a game request.
 #[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 #[serde(rename_all="lowercase")]
@@ -58,16 +113,54 @@ pub enum RequestAlgorithm {
   Sha256,
 }
 
+/// The algorithm and key which verification of a game request settled
+/// on, as far as it got. Unlike the `algo` and `key_id` fields of the
+/// request itself, these reflect the game's default algorithm and the
+/// previous secret key accepted during a rotation window.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SigningDetails {
+  pub algo: Option<RequestAlgorithm>,
+  pub key: Option<SigningKey>,
+}
+
+/// A game secret key which a request was checked against.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SigningKey {
+  /// The game's current secret key.
+  Current,
+  /// The game's previous secret key, during a rotation window.
+  Previous,
+  /// One of the game's additional signing keys.
+  Id(Uuid),
+}
+
+/// A game request body without the `.` separating its payload from
+/// its signature.
 #[derive(Debug, Clone, Error)]
-#[error("Invalid GameRequestPayload")]
+#[error("Malformed request: expected a body of the form `payload.signature`, where both parts are base64url-encoded and separated by a `.`")]
 pub struct GameRequestPayloadFromStrError {
   _priv: (),
 }
 
 #[derive(Debug, Clone, Error)]
-#[error("Invalid request signature")]
-pub struct VerificationError {
-  _priv: (),
+#[non_exhaustive]
+pub enum VerificationError {
+  #[error("Invalid request signature")]
+  InvalidSignature,
+  /// The signature part of the request body is empty.
+  #[error("Request signature is empty")]
+  EmptySignature,
+  /// The signature part of the request body is not base64url.
+  #[error("Request signature is not valid base64url: {0}")]
+  SignatureNotBase64(base64::DecodeError),
+  /// The signature cannot have come from the algorithm in use, since
+  /// it is the wrong length.
+  #[error("Request signature should be {expected} bytes, got {actual}")]
+  WrongSignatureLength { expected: usize, actual: usize },
+  /// The request names a key which does not exist for the game or
+  /// has been deactivated.
+  #[error("Unknown or inactive signing key")]
+  UnknownKey,
 }
 
 #[derive(Debug, Error)]
@@ -75,7 +168,10 @@ pub struct VerificationError {
 pub enum DeserializeError {
   #[error("{0}")]
   JsonError(#[from] serde_json::Error),
-  #[error("{0}")]
+  /// The payload part of the request body is empty.
+  #[error("Request payload is empty")]
+  EmptyPayload,
+  #[error("Request payload is not valid base64url: {0}")]
   Base64Error(#[from] base64::DecodeError),
   #[error("{0}")]
   Utf8Error(#[from] Utf8Error),
@@ -90,6 +186,9 @@ pub enum RequestBodyVerifyError {
   DieselError(#[from] diesel::result::Error),
   #[error("No such game")]
   NoSuchGame,
+  /// The request names no algorithm and the game has no default.
+  #[error("Request has no algo and the game has no default algorithm")]
+  NoAlgorithm,
   #[error("{0}")]
   VerificationError(#[from] VerificationError),
   #[error("Request timestamp is not current")]
@@ -97,7 +196,10 @@ pub enum RequestBodyVerifyError {
   #[error("Request has already been seen")]
   RequestAlreadySeen,
   #[error("Security level not attained")]
-  SecurityLevelNotAttained,
+  SecurityLevelNotAttained {
+    /// The weakest algorithm which would have been accepted.
+    minimum_algorithm: Option<RequestAlgorithm>,
+  },
 }
 
 impl GameRequestPayload {
@@ -108,18 +210,35 @@ impl GameRequestPayload {
     }
   }
 
+  /// Checks the signature against the payload exactly as it was
+  /// transmitted. The signature covers the original base64 text, never
+  /// a re-serialization of the decoded JSON, so it does not depend on
+  /// key order or whitespace in the client's JSON. Callers must not
+  /// rebuild the payload from a deserialized body before verifying.
   pub fn verify<H>(&self, secret_key: &str, hasher: &H) -> Result<(), VerificationError>
   where H: RequestSigningHasher + ?Sized {
     let full_payload = format!("{}.{}", self.payload_base64, secret_key);
     let expected_signature = hasher.apply_hash(&full_payload);
-    let given_signature = URL_SAFE.decode(self.signature_base64.as_bytes()).map_err(|_| VerificationError { _priv: () })?;
+    if self.signature_base64.is_empty() {
+      return Err(VerificationError::EmptySignature);
+    }
+    let given_signature = URL_SAFE.decode(self.signature_base64.as_bytes()).map_err(VerificationError::SignatureNotBase64)?;
+    if given_signature.len() != hasher.output_len() {
+      return Err(VerificationError::WrongSignatureLength {
+        expected: hasher.output_len(),
+        actual: given_signature.len(),
+      });
+    }
     if expected_signature.as_ref() != given_signature.as_slice() {
-      return Err(VerificationError { _priv: () });
+      return Err(VerificationError::InvalidSignature);
     }
     Ok(())
   }
 
   pub fn deserialize<T: DeserializeOwned>(&self) -> Result<T, DeserializeError> {
+    if self.payload_base64.is_empty() {
+      return Err(DeserializeError::EmptyPayload);
+    }
     let payload = URL_SAFE.decode(&self.payload_base64)?;
     let payload = serde_json::from_str(from_utf8(&payload)?)?;
     Ok(payload)
@@ -130,66 +249,324 @@ impl<T> GameRequestBody<T> {
   /// Amount of time allowed between the system clock and a request's timestamp.
   pub const TIME_SKEW: TimeDelta = TimeDelta::days(2);
 
-  pub async fn full_verify_at_time(payload: &GameRequestPayload, db: &mut AsyncPgConnection, now: NaiveDateTime) -> Result<Self, RequestBodyVerifyError>
+  /// A description of the request for logs. Only the envelope fields
+  /// are included; the body and the signature are not.
+  pub fn redacted_summary(&self) -> String {
+    format!(
+      "game_uuid={}, request_uuid={}, request_timestamp={}, algo={}",
+      self.game_uuid, self.request_uuid, self.request_timestamp, self.algo.map_or("default", RequestAlgorithm::name),
+    )
+  }
+
+  /// Verifies the payload as of time `now`. If `writer` is given, the
+  /// request UUID is recorded through it rather than directly. The
+  /// algorithm and key used are recorded in `details`, whether or not
+  /// verification succeeds.
+  pub async fn full_verify_at_time(
+    payload: &GameRequestPayload,
+    db: &mut AsyncPgConnection,
+    writer: Option<&HistoricalRequestWriter>,
+    now: NaiveDateTime,
+    details: &mut SigningDetails,
+  ) -> Result<Self, RequestBodyVerifyError>
+  where T: DeserializeOwned {
+    let body = Self::verify_without_replay_check_at_time(payload, db, now, details).await?;
+
+    // Verify that the request UUID has not been seen before, and
+    // record it for later.
+    let recorded = match writer {
+      Some(writer) => writer.record_request(body.request_uuid, db).await?,
+      None => history::record_request(body.request_uuid, db).await?,
+    };
+    if !recorded {
+      warn!("Got repeated request ({})", body.redacted_summary());
+      return Err(RequestBodyVerifyError::RequestAlreadySeen);
+    }
+
+    Ok(body)
+  }
+
+  /// Verifies the payload as of time `now`, as
+  /// [`GameRequestBody::full_verify_at_time`] does, except that the
+  /// request UUID is neither checked nor recorded. Such a request may
+  /// be replayed, so this is only suitable for requests which change
+  /// nothing.
+  pub async fn verify_without_replay_check_at_time(
+    payload: &GameRequestPayload,
+    db: &mut AsyncPgConnection,
+    now: NaiveDateTime,
+    details: &mut SigningDetails,
+  ) -> Result<Self, RequestBodyVerifyError>
   where T: DeserializeOwned {
     debug!("Verifying payload {:?}", payload);
     let body = payload.deserialize::<Self>()?;
-    let hasher = body.algo.into_hasher();
-    let (secret_key, security_level) = schema::games::table
+    let summary = body.redacted_summary();
+    // The timestamp is checked before anything is looked up. Request
+    // UUIDs are only kept for as long as a replay could pass this
+    // check, so an older request must never reach the UUID lookup.
+    let result = match body.check_timestamp(now) {
+      Ok(()) => Self::verify_deserialized_at_time(body, payload, db, now, details).await,
+      Err(err) => Err(err),
+    };
+    result.inspect_err(|err| {
+      warn!("Failed to verify request ({}): {}", summary, err);
+    })
+  }
+
+  /// Rejects the request if its timestamp is more than
+  /// [`GameRequestBody::TIME_SKEW`] away from `now`.
+  fn check_timestamp(&self, now: NaiveDateTime) -> Result<(), RequestBodyVerifyError> {
+    let time_diff = now - self.request_timestamp;
+    if time_diff.abs() > Self::TIME_SKEW {
+      warn!("Got outdated request timestamp for game {} ({:?})", self.game_uuid, self.request_timestamp);
+      return Err(RequestBodyVerifyError::BadRequestTimestamp);
+    }
+    Ok(())
+  }
+
+  /// The checks of [`GameRequestBody::verify_without_replay_check_at_time`]
+  /// which follow deserialization.
+  async fn verify_deserialized_at_time(
+    mut body: Self,
+    payload: &GameRequestPayload,
+    db: &mut AsyncPgConnection,
+    now: NaiveDateTime,
+    details: &mut SigningDetails,
+  ) -> Result<Self, RequestBodyVerifyError> {
+    let (game_id, game_secret_key, security_level, default_algorithm, previous_secret_key) = schema::games::table
       .filter(schema::games::game_uuid.eq(body.game_uuid))
-      .select((schema::games::game_secret_key, schema::games::security_level))
-      .first::<(String, i32)>(db)
+      .select((
+        schema::games::id,
+        schema::games::game_secret_key,
+        schema::games::security_level,
+        schema::games::default_algorithm,
+        (schema::games::previous_game_secret_key, schema::games::previous_secret_key_expires_at),
+      ))
+      .first::<(i32, String, i32, Option<String>, (Option<String>, Option<NaiveDateTime>))>(db)
       .await
       .optional()?
       .ok_or(RequestBodyVerifyError::NoSuchGame)?;
 
     debug!("Found game with uuid {}, security level is {}", body.game_uuid, security_level);
 
+    // An explicit algorithm overrides the game's default.
+    let algo = body.algo
+      .or_else(|| default_algorithm.as_deref().and_then(RequestAlgorithm::from_name))
+      .ok_or(RequestBodyVerifyError::NoAlgorithm)?;
+    body.algo = Some(algo);
+    details.algo = Some(algo);
+    let hasher = algo.into_hasher();
+
     // Verify that the appropriate security level is being used.
     if i32::from(hasher.security_level()) < security_level {
       warn!("Got a request using security level {} but expected at least {}", i32::from(hasher.security_level()), security_level);
-      return Err(RequestBodyVerifyError::SecurityLevelNotAttained);
+      let minimum_algorithm = RequestAlgorithm::minimum_for_security_level(security_level);
+      return Err(RequestBodyVerifyError::SecurityLevelNotAttained { minimum_algorithm });
     }
 
     // Verify the signing key.
-    payload.verify(&secret_key, &*hasher).inspect_err(|_| {
+    details.key = Some(body.key_id.map_or(SigningKey::Current, SigningKey::Id));
+    let secret_key = match body.key_id {
+      None => game_secret_key,
+      Some(key_id) => {
+        schema::game_secret_keys::table
+          .filter(schema::game_secret_keys::game_id.eq(game_id))
+          .filter(schema::game_secret_keys::key_id.eq(key_id))
+          .filter(schema::game_secret_keys::active.eq(true))
+          .select(schema::game_secret_keys::secret_key)
+          .first::<String>(db)
+          .await
+          .optional()?
+          .ok_or_else(|| {
+            warn!("Got unknown or inactive key {} for game {}", key_id, body.game_uuid);
+            VerificationError::UnknownKey
+          })?
+      }
+    };
+    let mut result = payload.verify(&secret_key, &*hasher);
+    if body.key_id.is_none() && matches!(result, Err(VerificationError::InvalidSignature)) {
+      // During a rotation window, the game's previous secret key is
+      // accepted as well as its current one.
+      if let (Some(previous_key), Some(expires_at)) = previous_secret_key {
+        if now < expires_at {
+          result = payload.verify(&previous_key, &*hasher);
+          if result.is_ok() {
+            debug!("Request for game {} is signed with its previous secret key", body.game_uuid);
+            details.key = Some(SigningKey::Previous);
+          }
+        }
+      }
+    }
+    result.inspect_err(|_| {
       warn!("Got bad signing key for game {}", body.game_uuid);
     })?;
 
-    // Verify the date.
-    let time_diff = now - body.request_timestamp;
-    if time_diff.abs() > Self::TIME_SKEW {
-      warn!("Got outdated request timestamp for game {} ({:?})", body.game_uuid, body.request_timestamp);
-      return Err(RequestBodyVerifyError::BadRequestTimestamp);
-    }
-
-    // Verify that the request UUID has not been seen before.
-    let subquery = schema::historical_requests::table
-      .filter(schema::historical_requests::request_uuid.eq(&body.request_uuid));
-    if diesel::select(diesel::dsl::exists(subquery)).get_result::<bool>(db).await? {
-      warn!("Got repeated request with uuid {}", body.request_uuid);
-      return Err(RequestBodyVerifyError::RequestAlreadySeen);
-    }
-
-    // Everything is good; insert the request UUID into the historical
-    // requests table for later.
-    let new_row = models::NewHistoricalRequest { request_uuid: body.request_uuid };
-    diesel::insert_into(schema::historical_requests::table)
-      .values(&new_row)
-      .execute(db)
-      .await?;
-
     Ok(body)
   }
 
-  pub async fn full_verify(payload: &GameRequestPayload, db: &mut AsyncPgConnection) -> Result<Self, RequestBodyVerifyError>
+  pub async fn full_verify(
+    payload: &GameRequestPayload,
+    db: &mut AsyncPgConnection,
+    writer: Option<&HistoricalRequestWriter>,
+  ) -> Result<Self, RequestBodyVerifyError>
   where T: DeserializeOwned {
     let now = chrono::Utc::now().naive_utc();
-    Self::full_verify_at_time(payload, db, now).await
+    Self::full_verify_at_time(payload, db, writer, now, &mut SigningDetails::default()).await
   }
 }
 
+impl<T> VerifiedGameRequest<T> {
+  pub fn into_inner(self) -> GameRequestBody<T> {
+    self.body
+  }
+
+  /// The verified body, and the database connection it was verified
+  /// with.
+  pub fn into_parts(self) -> (GameRequestBody<T>, Connection<Db>) {
+    (self.body, self.db)
+  }
+}
+
+impl<T> Deref for VerifiedGameRequest<T> {
+  type Target = GameRequestBody<T>;
+
+  fn deref(&self) -> &Self::Target {
+    &self.body
+  }
+}
+
+impl<T> ReplayableGameRequest<T> {
+  pub fn into_inner(self) -> GameRequestBody<T> {
+    self.body
+  }
+
+  /// The verified body, and the database connection it was verified
+  /// with.
+  pub fn into_parts(self) -> (GameRequestBody<T>, Connection<Db>) {
+    (self.body, self.db)
+  }
+}
+
+#[rocket::async_trait]
+impl<'r, T> FromData<'r> for VerifiedGameRequest<T>
+where T: DeserializeOwned + Send {
+  type Error = ApiError;
+
+  async fn from_data(req: &'r Request<'_>, data: Data<'r>) -> data::Outcome<'r, Self> {
+    verify_from_data(req, data, true).await.map(|(body, db)| VerifiedGameRequest { body, db })
+  }
+}
+
+#[rocket::async_trait]
+impl<'r, T> FromData<'r> for ReplayableGameRequest<T>
+where T: DeserializeOwned + Send {
+  type Error = ApiError;
+
+  async fn from_data(req: &'r Request<'_>, data: Data<'r>) -> data::Outcome<'r, Self> {
+    verify_from_data(req, data, false).await.map(|(body, db)| ReplayableGameRequest { body, db })
+  }
+}
+
+/// Reads and verifies a game request body for one of the data guards
+/// above, checking and recording its request UUID only if
+/// `check_replay` is true. The body is returned with the connection
+/// used to verify it.
+async fn verify_from_data<'r, T>(
+  req: &'r Request<'_>,
+  data: Data<'r>,
+  check_replay: bool,
+) -> data::Outcome<'r, (GameRequestBody<T>, Connection<Db>), ApiError>
+where T: DeserializeOwned + Send {
+  fn fail(req: &Request<'_>, err: ApiError) -> (Status, ApiError) {
+    stash_error(req, &err);
+    (err.status(), err)
+  }
+
+  let metrics = req.rocket().state::<VerificationMetrics>();
+  let payload = match ContentEncoding::of_request(req) {
+    Ok(ContentEncoding::Identity) => match <&str>::from_data(req, data).await {
+      Outcome::Success(body) => body.parse::<GameRequestPayload>().map_err(ApiError::from),
+      Outcome::Error((status, err)) => Err(ApiError::from_status(status).with_message(err.to_string())),
+      Outcome::Forward(f) => return Outcome::Forward(f),
+    },
+    Ok(ContentEncoding::Gzip) => encoding::read_gzip_payload(req, data).await,
+    Err(err) => Err(err),
+  };
+  let payload = match payload {
+    Ok(payload) => payload,
+    Err(err) => {
+      if let Some(metrics) = metrics {
+        metrics.record_malformed();
+      }
+      return Outcome::Error(fail(req, err));
+    }
+  };
+  let mut db = match req.guard::<Connection<Db>>().await {
+    Outcome::Success(db) => db,
+    _ => return Outcome::Error(fail(req, ApiError::internal_server_error("Could not connect to database"))),
+  };
+  let now = chrono::Utc::now().naive_utc();
+  let mut details = SigningDetails::default();
+  let result = if check_replay {
+    let writer = req.rocket().state::<HistoricalRequestWriter>();
+    GameRequestBody::<T>::full_verify_at_time(&payload, &mut db, writer, now, &mut details).await
+  } else {
+    GameRequestBody::<T>::verify_without_replay_check_at_time(&payload, &mut db, now, &mut details).await
+  };
+  if let Some(metrics) = metrics {
+    metrics.record(&result);
+  }
+  debug::record_verify_debug(req, &payload, details, &result);
+  match result {
+    Ok(body) => Outcome::Success((body, db)),
+    Err(err) => Outcome::Error(fail(req, err.into())),
+  }
+}
+
+/// Largest accepted `request_timestamp`, the last second of the year
+/// 9999. Anything later is almost certainly a timestamp in
+/// milliseconds rather than seconds.
+pub const MAX_REQUEST_TIMESTAMP_SECONDS: i64 = 253_402_300_799;
+
+/// Deserializes `request_timestamp` as integer Unix seconds. Unlike
+/// [`ts_seconds`], the error message names the field and the
+/// offending value, since the most common mistake is to send
+/// milliseconds.
+fn deserialize_request_timestamp<'de, D>(deserializer: D) -> Result<NaiveDateTime, D::Error>
+where D: Deserializer<'de> {
+  let value = serde_json::Value::deserialize(deserializer)?;
+  value.as_i64()
+    .filter(|seconds| seconds.abs() <= MAX_REQUEST_TIMESTAMP_SECONDS)
+    .and_then(|seconds| DateTime::from_timestamp(seconds, 0))
+    .map(|timestamp| timestamp.naive_utc())
+    .ok_or_else(|| de::Error::custom(format!("request_timestamp must be Unix seconds, got {}", value)))
+}
+
 impl RequestAlgorithm {
+  /// All supported algorithms, from weakest to strongest.
+  pub const ALL: [RequestAlgorithm; 2] = [RequestAlgorithm::Sha1, RequestAlgorithm::Sha256];
+
+  /// The name of the algorithm, as it appears in the `algo` field.
+  pub fn name(self) -> &'static str {
+    match self {
+      RequestAlgorithm::Sha1 => "sha1",
+      RequestAlgorithm::Sha256 => "sha256",
+    }
+  }
+
+  /// The algorithm with the given name, as it appears in the `algo`
+  /// field.
+  pub fn from_name(name: &str) -> Option<RequestAlgorithm> {
+    RequestAlgorithm::ALL.into_iter().find(|algo| algo.name() == name)
+  }
+
+  /// The weakest algorithm acceptable for a game with the given
+  /// security level, if any algorithm is.
+  pub fn minimum_for_security_level(security_level: i32) -> Option<RequestAlgorithm> {
+    RequestAlgorithm::ALL.into_iter()
+      .find(|algo| i32::from(algo.into_hasher().security_level()) >= security_level)
+  }
+
   pub fn into_hasher(self) -> Box<dyn RequestSigningHasher + Send + Sync + 'static> {
     match self {
       RequestAlgorithm::Sha1 => Box::new(Sha1Hasher),
@@ -209,16 +586,343 @@ impl FromStr for GameRequestPayload {
   }
 }
 
+impl From<GameRequestPayloadFromStrError> for ApiError {
+  fn from(e: GameRequestPayloadFromStrError) -> Self {
+    ApiError::bad_request().with_message(e.to_string())
+  }
+}
+
+/// Every variant is matched explicitly, so that a new variant must be
+/// given a status deliberately. The mapping is:
+///
+/// * A payload which cannot be decoded, or which is not JSON, is a
+///   400. A payload which is JSON of the wrong shape is a 422, as with
+///   Rocket's own `Json` guard.
+/// * A signature of the wrong length, or a missing `algo` with no game
+///   default, is a 400.
+/// * An unknown game is a 404.
+/// * A signature which is missing, not base64url, or does not match is
+///   a 403. So is an unknown signing key. Only the first two say why,
+///   since the rest would tell an attacker which guesses are closer.
+/// * A stale timestamp or a replayed request UUID is a 403 saying
+///   which. These are only reached once the signature has been
+///   checked, so the client already holds the secret key.
+/// * An algorithm below the game's security level is a 426.
+/// * A database error is reported as any other database error.
 impl From<RequestBodyVerifyError> for ApiError {
   fn from(e: RequestBodyVerifyError) -> Self {
     match e {
-      RequestBodyVerifyError::DeserializeError(_) => ApiError::bad_request(),
+      RequestBodyVerifyError::DeserializeError(DeserializeError::JsonError(e)) if e.is_data() => {
+        ApiError::unprocessable_entity().with_message(format!("Malformed request: {}", e))
+      }
+      RequestBodyVerifyError::DeserializeError(
+        e @ (DeserializeError::JsonError(_) | DeserializeError::EmptyPayload | DeserializeError::Base64Error(_) | DeserializeError::Utf8Error(_)),
+      ) => {
+        ApiError::bad_request().with_message(format!("Malformed request: {}", e))
+      }
       RequestBodyVerifyError::DieselError(e) => e.into(),
-      RequestBodyVerifyError::VerificationError(_) => ApiError::forbidden(),
-      RequestBodyVerifyError::BadRequestTimestamp => ApiError::forbidden(),
-      RequestBodyVerifyError::RequestAlreadySeen => ApiError::forbidden(),
+      RequestBodyVerifyError::VerificationError(e @ VerificationError::WrongSignatureLength { .. }) => {
+        ApiError::bad_request().with_message(format!("Malformed request: {}", e))
+      }
+      RequestBodyVerifyError::VerificationError(e @ (VerificationError::EmptySignature | VerificationError::SignatureNotBase64(_))) => {
+        ApiError::forbidden().with_message(e.to_string())
+      }
+      RequestBodyVerifyError::VerificationError(VerificationError::InvalidSignature | VerificationError::UnknownKey) => ApiError::forbidden(),
+      e @ (RequestBodyVerifyError::BadRequestTimestamp | RequestBodyVerifyError::RequestAlreadySeen) => {
+        ApiError::forbidden().with_message(e.to_string())
+      }
       RequestBodyVerifyError::NoSuchGame => ApiError::not_found().with_message("No such game"),
-      RequestBodyVerifyError::SecurityLevelNotAttained => ApiError::forbidden().with_message("Invalid low-security algorithm"),
+      RequestBodyVerifyError::NoAlgorithm => ApiError::bad_request().with_message("Malformed request: algo is required, since the game has no default algorithm"),
+      RequestBodyVerifyError::SecurityLevelNotAttained { minimum_algorithm } => {
+        let err = ApiError::upgrade_required().with_message("Invalid low-security algorithm");
+        match minimum_algorithm {
+          Some(algo) => err.with_header(Header::new(MINIMUM_ALGORITHM_HEADER, algo.name())),
+          None => err,
+        }
+      }
     }
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::server::error::{self, messages};
+
+  use rocket::{State, post, routes};
+  use rocket::http::ContentType;
+  use rocket::local::blocking::Client;
+  use serde_json::Value;
+
+  use std::sync::atomic::{AtomicBool, Ordering};
+
+  #[derive(Default)]
+  struct HandlerRan(AtomicBool);
+
+  #[post("/verified", data = "<params>")]
+  fn verified(params: VerifiedGameRequest<Value>, ran: &State<HandlerRan>) {
+    let _ = params.into_inner();
+    ran.0.store(true, Ordering::SeqCst);
+  }
+
+  fn client() -> Client {
+    let rocket = rocket::build()
+      .manage(HandlerRan::default())
+      .mount("/", routes![verified])
+      .register("/", error::catchers());
+    Client::untracked(rocket).unwrap()
+  }
+
+  fn handler_ran(client: &Client) -> bool {
+    // Called through the type, since diesel's RunQueryDsl also has a
+    // `load` method.
+    AtomicBool::load(&client.rocket().state::<HandlerRan>().unwrap().0, Ordering::SeqCst)
+  }
+
+  #[test]
+  fn test_invalid_payload_is_rejected_before_handler_runs() {
+    let client = client();
+    let response = client.post("/verified").header(ContentType::Text).body("no separator here").dispatch();
+    assert_eq!(response.status(), Status::BadRequest);
+    assert!(response.into_string().unwrap().contains("payload.signature"));
+    assert!(!handler_ran(&client));
+  }
+
+  #[test]
+  fn test_missing_dot_explains_expected_format() {
+    assert!(GameRequestPayload::from_str("e30AAAA").is_err());
+    let payload = GameRequestPayload::from_str("e30.AAAA").unwrap();
+    assert_eq!((payload.payload_base64.as_str(), payload.signature_base64.as_str()), ("e30", "AAAA"));
+
+    let client = client();
+    let response = client.post("/verified").header(ContentType::Text).body("e30AAAA").dispatch();
+    assert_eq!(response.status(), Status::BadRequest);
+    let body: Value = response.into_json().unwrap();
+    assert_eq!(
+      body["reason"],
+      "Malformed request: expected a body of the form `payload.signature`, where both parts are base64url-encoded and separated by a `.`",
+    );
+  }
+
+  #[test]
+  fn test_unsupported_encoding_is_rejected_before_handler_runs() {
+    let client = client();
+    let response = client.post("/verified")
+      .header(Header::new("Content-Encoding", "br"))
+      .body("e30.AAAA")
+      .dispatch();
+    assert_eq!(response.status(), Status::UnsupportedMediaType);
+    assert!(!handler_ran(&client));
+  }
+
+  /// Every variant of [`RequestBodyVerifyError`], with the status and
+  /// message it must be reported with. Messages from serde and base64
+  /// are only checked up to the part this module writes.
+  pub(super) fn verify_errors() -> Vec<(RequestBodyVerifyError, Status, &'static str)> {
+    let data_error = serde_json::from_str::<GameRequestBody<()>>("{}").unwrap_err();
+    let syntax_error = serde_json::from_str::<GameRequestBody<()>>("{").unwrap_err();
+    let base64_error = URL_SAFE.decode("!!!").unwrap_err();
+    #[allow(invalid_from_utf8)]
+    let utf8_error = from_utf8(b"\xff").unwrap_err();
+    vec![
+      (DeserializeError::JsonError(data_error).into(), Status::UnprocessableEntity, "Malformed request: missing field"),
+      (DeserializeError::JsonError(syntax_error).into(), Status::BadRequest, "Malformed request: EOF"),
+      (DeserializeError::EmptyPayload.into(), Status::BadRequest, "Malformed request: Request payload is empty"),
+      (DeserializeError::Base64Error(base64_error).into(), Status::BadRequest, "Malformed request: Request payload is not valid base64url"),
+      (DeserializeError::Utf8Error(utf8_error).into(), Status::BadRequest, "Malformed request: invalid utf-8"),
+      (diesel::result::Error::NotFound.into(), Status::NotFound, messages::NOT_FOUND),
+      (diesel::result::Error::BrokenTransactionManager.into(), Status::InternalServerError, messages::UNKNOWN_DB_ERROR),
+      (RequestBodyVerifyError::NoSuchGame, Status::NotFound, "No such game"),
+      (RequestBodyVerifyError::NoAlgorithm, Status::BadRequest, "Malformed request: algo is required, since the game has no default algorithm"),
+      (VerificationError::InvalidSignature.into(), Status::Forbidden, messages::FORBIDDEN),
+      (VerificationError::EmptySignature.into(), Status::Forbidden, "Request signature is empty"),
+      (VerificationError::SignatureNotBase64(base64::DecodeError::InvalidLength(1)).into(), Status::Forbidden, "Request signature is not valid base64url"),
+      (
+        VerificationError::WrongSignatureLength { expected: 32, actual: 20 }.into(),
+        Status::BadRequest,
+        "Malformed request: Request signature should be 32 bytes, got 20",
+      ),
+      (VerificationError::UnknownKey.into(), Status::Forbidden, messages::FORBIDDEN),
+      (RequestBodyVerifyError::BadRequestTimestamp, Status::Forbidden, "Request timestamp is not current"),
+      (RequestBodyVerifyError::RequestAlreadySeen, Status::Forbidden, "Request has already been seen"),
+      (
+        RequestBodyVerifyError::SecurityLevelNotAttained { minimum_algorithm: Some(RequestAlgorithm::Sha256) },
+        Status::UpgradeRequired,
+        "Invalid low-security algorithm",
+      ),
+      (
+        RequestBodyVerifyError::SecurityLevelNotAttained { minimum_algorithm: None },
+        Status::UpgradeRequired,
+        "Invalid low-security algorithm",
+      ),
+    ]
+  }
+
+  /// Fails to compile when a variant is added, as a reminder to add
+  /// it to [`verify_errors`].
+  fn _every_variant_is_listed(err: RequestBodyVerifyError) {
+    match err {
+      RequestBodyVerifyError::DeserializeError(
+        DeserializeError::JsonError(_) | DeserializeError::EmptyPayload | DeserializeError::Base64Error(_) | DeserializeError::Utf8Error(_),
+      ) => {}
+      RequestBodyVerifyError::DieselError(_) => {}
+      RequestBodyVerifyError::NoSuchGame | RequestBodyVerifyError::NoAlgorithm => {}
+      RequestBodyVerifyError::VerificationError(
+        VerificationError::InvalidSignature
+          | VerificationError::EmptySignature
+          | VerificationError::SignatureNotBase64(_)
+          | VerificationError::WrongSignatureLength { .. }
+          | VerificationError::UnknownKey,
+      ) => {}
+      RequestBodyVerifyError::BadRequestTimestamp | RequestBodyVerifyError::RequestAlreadySeen => {}
+      RequestBodyVerifyError::SecurityLevelNotAttained { .. } => {}
+    }
+  }
+
+  #[test]
+  fn test_verify_error_statuses_and_messages() {
+    for (err, status, message) in verify_errors() {
+      let description = format!("{:?}", err);
+      let err = ApiError::from(err);
+      assert_eq!(err.status(), status, "{}", description);
+      assert!(err.message().starts_with(message), "{}: {:?}", description, err.message());
+    }
+  }
+
+  #[test]
+  fn test_security_level_error_names_minimum_algorithm() {
+    let minimum_algorithm_header = |err: RequestBodyVerifyError| {
+      ApiError::from(err).headers().iter()
+        .find(|header| header.name() == MINIMUM_ALGORITHM_HEADER)
+        .map(|header| header.value().to_owned())
+    };
+    assert_eq!(
+      minimum_algorithm_header(RequestBodyVerifyError::SecurityLevelNotAttained { minimum_algorithm: Some(RequestAlgorithm::Sha256) }),
+      Some(String::from("sha256")),
+    );
+    assert_eq!(minimum_algorithm_header(RequestBodyVerifyError::SecurityLevelNotAttained { minimum_algorithm: None }), None);
+  }
+
+  #[derive(Debug, Serialize, Deserialize)]
+  struct ScoreBody {
+    player_name: String,
+    player_score: f64,
+  }
+
+  const SECRET_KEY: &str = "test-secret-key";
+
+  /// A signed payload of exactly the given JSON text.
+  fn signed(json: &str) -> GameRequestPayload {
+    let payload_base64 = URL_SAFE.encode(json);
+    let signature = Sha256Hasher.apply_hash(&format!("{}.{}", payload_base64, SECRET_KEY));
+    GameRequestPayload::new(payload_base64, URL_SAFE.encode(signature))
+  }
+
+  #[test]
+  fn test_verify_uses_transmitted_payload_bytes() {
+    // Keys in an order no serializer of GameRequestBody would produce,
+    // with extra whitespace.
+    let json = r#"{ "player_score": 12.5, "request_timestamp": 1738387990,
+      "player_name": "Alice", "request_uuid": "0194c0b0-0000-7000-8000-000000000001",
+      "game_uuid": "0194c0b0-0000-7000-8000-000000000002" }"#;
+    let payload = signed(json);
+    assert!(payload.verify(SECRET_KEY, &Sha256Hasher).is_ok());
+
+    // Re-serializing the decoded body yields different bytes, which
+    // the signature does not cover.
+    let body = payload.deserialize::<GameRequestBody<ScoreBody>>().unwrap();
+    assert_eq!(body.body.player_name, "Alice");
+    let reserialized = URL_SAFE.encode(serde_json::to_string(&body).unwrap());
+    assert_ne!(reserialized, payload.payload_base64);
+    let rebuilt = GameRequestPayload::new(reserialized, payload.signature_base64.clone());
+    assert!(matches!(rebuilt.verify(SECRET_KEY, &Sha256Hasher), Err(VerificationError::InvalidSignature)));
+  }
+
+  #[test]
+  fn test_verify_rejects_tampered_payload() {
+    let payload = signed(r#"{"player_score":12.5}"#);
+    let tampered = GameRequestPayload::new(URL_SAFE.encode(r#"{"player_score":99.5}"#), payload.signature_base64.clone());
+    assert!(matches!(tampered.verify(SECRET_KEY, &Sha256Hasher), Err(VerificationError::InvalidSignature)));
+    assert!(matches!(payload.verify("other-key", &Sha256Hasher), Err(VerificationError::InvalidSignature)));
+  }
+
+  fn body_with_timestamp(request_timestamp: &str) -> Result<GameRequestBody<Value>, serde_json::Error> {
+    serde_json::from_str(&format!(
+      r#"{{"game_uuid":"0194c0b0-0000-7000-8000-000000000002","request_uuid":"0194c0b0-0000-7000-8000-000000000001","request_timestamp":{}}}"#,
+      request_timestamp,
+    ))
+  }
+
+  #[test]
+  fn test_request_timestamp_is_unix_seconds() {
+    let body = body_with_timestamp("1738387990").unwrap();
+    assert_eq!(body.request_timestamp, DateTime::from_timestamp(1_738_387_990, 0).unwrap().naive_utc());
+    assert!(body_with_timestamp(&MAX_REQUEST_TIMESTAMP_SECONDS.to_string()).is_ok());
+    let err = body_with_timestamp("1738387990000").unwrap_err();
+    assert!(err.to_string().contains("request_timestamp must be Unix seconds, got 1738387990000"), "{}", err);
+    assert!(body_with_timestamp(r#""1738387990""#).is_err());
+    assert!(body_with_timestamp("1738387990.5").is_err());
+  }
+
+  #[test]
+  fn test_verify_checks_signature_length() {
+    let payload = signed(r#"{"player_score":12.5}"#);
+    let with_signature = |signature: String| GameRequestPayload::new(payload.payload_base64.clone(), signature);
+    assert!(matches!(
+      with_signature(URL_SAFE.encode([0u8; 20])).verify(SECRET_KEY, &Sha256Hasher),
+      Err(VerificationError::WrongSignatureLength { expected: 32, actual: 20 }),
+    ));
+    // A signature of the right length is checked as usual.
+    assert!(matches!(
+      with_signature(URL_SAFE.encode([0u8; 32])).verify(SECRET_KEY, &Sha256Hasher),
+      Err(VerificationError::InvalidSignature),
+    ));
+    assert!(matches!(
+      payload.verify(SECRET_KEY, &Sha1Hasher),
+      Err(VerificationError::WrongSignatureLength { expected: 20, actual: 32 }),
+    ));
+  }
+
+  #[test]
+  fn test_verify_checks_signature_format() {
+    let payload = signed(r#"{"player_score":12.5}"#);
+    let with_signature = |signature: &str| GameRequestPayload::new(payload.payload_base64.clone(), signature.to_owned());
+    assert!(matches!(with_signature("").verify(SECRET_KEY, &Sha256Hasher), Err(VerificationError::EmptySignature)));
+    assert!(matches!(with_signature("not base64!").verify(SECRET_KEY, &Sha256Hasher), Err(VerificationError::SignatureNotBase64(_))));
+  }
+
+  #[test]
+  fn test_deserialize_checks_payload_encoding() {
+    assert!(matches!(GameRequestPayload::new(String::new(), String::new()).deserialize::<Value>(), Err(DeserializeError::EmptyPayload)));
+    assert!(matches!(GameRequestPayload::new(String::from("!!!"), String::new()).deserialize::<Value>(), Err(DeserializeError::Base64Error(_))));
+    let not_utf8 = URL_SAFE.encode(b"\xff");
+    assert!(matches!(GameRequestPayload::new(not_utf8, String::new()).deserialize::<Value>(), Err(DeserializeError::Utf8Error(_))));
+  }
+
+  #[test]
+  fn test_stale_timestamp_is_rejected_before_lookup() {
+    // check_timestamp needs no connection, so a request this old is
+    // rejected before its game or its request UUID is looked up.
+    let body = body_with_timestamp("1738387990").unwrap();
+    let skew = GameRequestBody::<Value>::TIME_SKEW;
+    assert!(body.check_timestamp(body.request_timestamp + skew).is_ok());
+    assert!(body.check_timestamp(body.request_timestamp - skew).is_ok());
+    for now in [body.request_timestamp + skew + TimeDelta::seconds(1), body.request_timestamp - skew - TimeDelta::seconds(1)] {
+      assert!(matches!(body.check_timestamp(now), Err(RequestBodyVerifyError::BadRequestTimestamp)));
+    }
+  }
+
+  #[test]
+  fn test_redacted_summary_omits_body() {
+    let mut body = body_with_timestamp("1738387990").unwrap();
+    body.body = serde_json::json!({ "player_name": "Alice" });
+    assert_eq!(
+      body.redacted_summary(),
+      "game_uuid=0194c0b0-0000-7000-8000-000000000002, request_uuid=0194c0b0-0000-7000-8000-000000000001, \
+       request_timestamp=2025-02-01 05:33:10, algo=default",
+    );
+    body.algo = Some(RequestAlgorithm::Sha1);
+    assert!(body.redacted_summary().ends_with("algo=sha1"));
+    assert!(!body.redacted_summary().contains("Alice"));
+  }
+}
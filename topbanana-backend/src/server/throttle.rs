@@ -0,0 +1,303 @@
+
+//! Per-game throttling of score submissions.
+
+use rocket::Request;
+use rocket::http::{Header, Status};
+use rocket::response::{Responder, Response};
+use uuid::Uuid;
+use chrono::{NaiveDate, NaiveDateTime, TimeDelta};
+
+use std::collections::HashMap;
+use std::sync::{Mutex, PoisonError};
+
+/// Header reporting how many seconds remain until the game's minimum
+/// submission interval allows its next submission.
+pub const RATE_LIMIT_RESET_HEADER: &str = "X-RateLimit-Reset";
+
+/// Header reporting how many more submissions the game may make
+/// before its daily submission quota is reached.
+pub const QUOTA_REMAINING_HEADER: &str = "X-Quota-Remaining";
+
+/// Wrapper which reports a game's remaining submission allowance in
+/// headers. Each header is omitted if the corresponding limit is not
+/// configured for the game.
+#[derive(Debug, Clone)]
+pub struct WithSubmissionLimits<T> {
+  pub inner: T,
+  pub rate_limit_reset: Option<TimeDelta>,
+  pub quota_remaining: Option<i32>,
+}
+
+/// In-memory record of the most recent score submission for each
+/// game, used to enforce a game's minimum submission interval.
+///
+/// This is independent of any API-key rate limiting and applies only
+/// to the signed game-facing endpoints.
+#[derive(Debug, Default)]
+pub struct SubmissionThrottle {
+  last_submissions: Mutex<HashMap<Uuid, NaiveDateTime>>,
+}
+
+impl SubmissionThrottle {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Records a submission for the given game at time `now`, provided
+  /// that at least `min_interval` has elapsed since the previous
+  /// recorded submission for that game. If the submission arrived too
+  /// soon, records nothing and returns how long the game must wait
+  /// instead.
+  pub fn try_submit_at_time(&self, game_uuid: Uuid, min_interval: TimeDelta, now: NaiveDateTime) -> Result<SubmissionSlot, TimeDelta> {
+    let mut last_submissions = self.last_submissions.lock().unwrap_or_else(PoisonError::into_inner);
+    let previous_submission = last_submissions.get(&game_uuid).copied();
+    let wait = time_until_next_submission(previous_submission, min_interval, now);
+    if wait > TimeDelta::zero() {
+      return Err(wait);
+    }
+    last_submissions.insert(game_uuid, now);
+    Ok(SubmissionSlot { game_uuid, submitted_at: now, previous_submission })
+  }
+
+  /// How long after `now` the game must wait before its next
+  /// submission is allowed. Zero if it may submit immediately.
+  pub fn wait_at_time(&self, game_uuid: Uuid, min_interval: TimeDelta, now: NaiveDateTime) -> TimeDelta {
+    let last_submissions = self.last_submissions.lock().unwrap_or_else(PoisonError::into_inner);
+    time_until_next_submission(last_submissions.get(&game_uuid).copied(), min_interval, now)
+  }
+
+  /// Un-records a submission which was not stored after all, so that
+  /// it does not hold up the game's next submission. Does nothing if
+  /// a later submission has since been recorded for the game.
+  pub fn release(&self, slot: SubmissionSlot) {
+    let mut last_submissions = self.last_submissions.lock().unwrap_or_else(PoisonError::into_inner);
+    if last_submissions.get(&slot.game_uuid) != Some(&slot.submitted_at) {
+      return;
+    }
+    match slot.previous_submission {
+      Some(previous_submission) => last_submissions.insert(slot.game_uuid, previous_submission),
+      None => last_submissions.remove(&slot.game_uuid),
+    };
+  }
+}
+
+/// A submission recorded by [`SubmissionThrottle::try_submit_at_time`],
+/// which can be handed back to [`SubmissionThrottle::release`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SubmissionSlot {
+  game_uuid: Uuid,
+  submitted_at: NaiveDateTime,
+  previous_submission: Option<NaiveDateTime>,
+}
+
+fn time_until_next_submission(previous_submission: Option<NaiveDateTime>, min_interval: TimeDelta, now: NaiveDateTime) -> TimeDelta {
+  previous_submission
+    .map(|previous_submission| previous_submission + min_interval - now)
+    .filter(|wait| *wait > TimeDelta::zero())
+    .unwrap_or_else(TimeDelta::zero)
+}
+
+/// A wait as a whole number of seconds, rounded up, as used by the
+/// `Retry-After` and [`RATE_LIMIT_RESET_HEADER`] headers.
+pub fn whole_seconds(wait: TimeDelta) -> i64 {
+  let seconds = wait.num_seconds();
+  if wait > TimeDelta::seconds(seconds) { seconds + 1 } else { seconds }
+}
+
+/// The UTC day against which a submission at `now` counts for daily
+/// submission quotas. Quotas reset when this changes, at UTC midnight.
+pub fn quota_day(now: NaiveDateTime) -> NaiveDate {
+  now.date()
+}
+
+/// How many more submissions a game may make today, given its daily
+/// quota and its number of submissions today. Returns `None` if the
+/// submissions are already over the quota.
+pub fn quota_remaining(daily_submission_quota: i32, submissions_today: i32) -> Option<i32> {
+  (submissions_today <= daily_submission_quota).then(|| daily_submission_quota - submissions_today)
+}
+
+impl<'r, T: Responder<'r, 'static>> Responder<'r, 'static> for WithSubmissionLimits<T> {
+  fn respond_to(self, req: &'r Request<'_>) -> Result<Response<'static>, Status> {
+    let mut response = self.inner.respond_to(req)?;
+    if let Some(rate_limit_reset) = self.rate_limit_reset {
+      response.set_header(Header::new(RATE_LIMIT_RESET_HEADER, whole_seconds(rate_limit_reset).to_string()));
+    }
+    if let Some(quota_remaining) = self.quota_remaining {
+      response.set_header(Header::new(QUOTA_REMAINING_HEADER, quota_remaining.to_string()));
+    }
+    Ok(response)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use rocket::local::blocking::Client;
+
+  fn at(date: &str) -> NaiveDateTime {
+    NaiveDateTime::parse_from_str(date, "%Y-%m-%d %H:%M:%S%.f").unwrap()
+  }
+
+  fn interval() -> TimeDelta {
+    TimeDelta::milliseconds(1000)
+  }
+
+  #[test]
+  fn test_throttle_rejects_submission_within_interval() {
+    let throttle = SubmissionThrottle::new();
+    let game_uuid = Uuid::now_v7();
+    assert!(throttle.try_submit_at_time(game_uuid, interval(), at("2025-02-01 12:00:00.000")).is_ok());
+    assert!(throttle.try_submit_at_time(game_uuid, interval(), at("2025-02-01 12:00:00.999")).is_err());
+  }
+
+  #[test]
+  fn test_throttle_accepts_submission_outside_interval() {
+    let throttle = SubmissionThrottle::new();
+    let game_uuid = Uuid::now_v7();
+    assert!(throttle.try_submit_at_time(game_uuid, interval(), at("2025-02-01 12:00:00.000")).is_ok());
+    assert!(throttle.try_submit_at_time(game_uuid, interval(), at("2025-02-01 12:00:01.000")).is_ok());
+  }
+
+  #[test]
+  fn test_throttle_is_per_game() {
+    let throttle = SubmissionThrottle::new();
+    let now = at("2025-02-01 12:00:00.000");
+    assert!(throttle.try_submit_at_time(Uuid::now_v7(), interval(), now).is_ok());
+    assert!(throttle.try_submit_at_time(Uuid::now_v7(), interval(), now).is_ok());
+  }
+
+  #[test]
+  fn test_rejected_submission_does_not_restart_interval() {
+    let throttle = SubmissionThrottle::new();
+    let game_uuid = Uuid::now_v7();
+    assert!(throttle.try_submit_at_time(game_uuid, interval(), at("2025-02-01 12:00:00.000")).is_ok());
+    assert!(throttle.try_submit_at_time(game_uuid, interval(), at("2025-02-01 12:00:00.500")).is_err());
+    assert!(throttle.try_submit_at_time(game_uuid, interval(), at("2025-02-01 12:00:01.000")).is_ok());
+  }
+
+  #[test]
+  fn test_released_slot_restores_previous_submission() {
+    let throttle = SubmissionThrottle::new();
+    let game_uuid = Uuid::now_v7();
+    assert!(throttle.try_submit_at_time(game_uuid, interval(), at("2025-02-01 12:00:00.000")).is_ok());
+    let slot = throttle.try_submit_at_time(game_uuid, interval(), at("2025-02-01 12:00:05.000")).unwrap();
+    throttle.release(slot);
+    // The interval runs from the first submission again, not the
+    // released one.
+    assert!(throttle.try_submit_at_time(game_uuid, interval(), at("2025-02-01 12:00:05.500")).is_ok());
+  }
+
+  #[test]
+  fn test_released_first_slot_clears_game() {
+    let throttle = SubmissionThrottle::new();
+    let game_uuid = Uuid::now_v7();
+    let slot = throttle.try_submit_at_time(game_uuid, interval(), at("2025-02-01 12:00:00.000")).unwrap();
+    throttle.release(slot);
+    assert!(throttle.try_submit_at_time(game_uuid, interval(), at("2025-02-01 12:00:00.001")).is_ok());
+  }
+
+  #[test]
+  fn test_release_keeps_later_submission() {
+    let throttle = SubmissionThrottle::new();
+    let game_uuid = Uuid::now_v7();
+    let slot = throttle.try_submit_at_time(game_uuid, interval(), at("2025-02-01 12:00:00.000")).unwrap();
+    assert!(throttle.try_submit_at_time(game_uuid, interval(), at("2025-02-01 12:00:02.000")).is_ok());
+    throttle.release(slot);
+    assert!(throttle.try_submit_at_time(game_uuid, interval(), at("2025-02-01 12:00:02.500")).is_err());
+  }
+
+  #[test]
+  fn test_rejected_submission_reports_remaining_wait() {
+    let throttle = SubmissionThrottle::new();
+    let game_uuid = Uuid::now_v7();
+    assert!(throttle.try_submit_at_time(game_uuid, interval(), at("2025-02-01 12:00:00.000")).is_ok());
+    let wait = throttle.try_submit_at_time(game_uuid, interval(), at("2025-02-01 12:00:00.250")).unwrap_err();
+    assert_eq!(wait, TimeDelta::milliseconds(750));
+  }
+
+  #[test]
+  fn test_wait_at_time() {
+    let throttle = SubmissionThrottle::new();
+    let game_uuid = Uuid::now_v7();
+    assert_eq!(throttle.wait_at_time(game_uuid, interval(), at("2025-02-01 12:00:00.000")), TimeDelta::zero());
+    assert!(throttle.try_submit_at_time(game_uuid, interval(), at("2025-02-01 12:00:00.000")).is_ok());
+    assert_eq!(throttle.wait_at_time(game_uuid, interval(), at("2025-02-01 12:00:00.000")), interval());
+    assert_eq!(throttle.wait_at_time(game_uuid, interval(), at("2025-02-01 12:00:00.600")), TimeDelta::milliseconds(400));
+    assert_eq!(throttle.wait_at_time(game_uuid, interval(), at("2025-02-01 12:00:05.000")), TimeDelta::zero());
+  }
+
+  #[test]
+  fn test_whole_seconds_rounds_up() {
+    assert_eq!(whole_seconds(TimeDelta::zero()), 0);
+    assert_eq!(whole_seconds(TimeDelta::milliseconds(1)), 1);
+    assert_eq!(whole_seconds(TimeDelta::milliseconds(1000)), 1);
+    assert_eq!(whole_seconds(TimeDelta::milliseconds(1500)), 2);
+  }
+
+  #[rocket::get("/limited?<reset_ms>&<quota>")]
+  fn limited(reset_ms: Option<i64>, quota: Option<i32>) -> WithSubmissionLimits<&'static str> {
+    WithSubmissionLimits {
+      inner: "ok",
+      rate_limit_reset: reset_ms.map(TimeDelta::milliseconds),
+      quota_remaining: quota,
+    }
+  }
+
+  fn client() -> Client {
+    Client::untracked(rocket::build().mount("/", rocket::routes![limited])).unwrap()
+  }
+
+  #[test]
+  fn test_headers_report_remaining_allowance() {
+    let client = client();
+    let response = client.get("/limited?reset_ms=1500&quota=4").dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.headers().get_one(RATE_LIMIT_RESET_HEADER), Some("2"));
+    assert_eq!(response.headers().get_one(QUOTA_REMAINING_HEADER), Some("4"));
+  }
+
+  #[test]
+  fn test_headers_omitted_without_limits() {
+    let client = client();
+    let response = client.get("/limited").dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.headers().get_one(RATE_LIMIT_RESET_HEADER), None);
+    assert_eq!(response.headers().get_one(QUOTA_REMAINING_HEADER), None);
+  }
+
+  #[test]
+  fn test_quota_remaining_counts_down_to_zero() {
+    assert_eq!(quota_remaining(3, 1), Some(2));
+    assert_eq!(quota_remaining(3, 3), Some(0));
+  }
+
+  #[test]
+  fn test_quota_remaining_rejects_submission_over_quota() {
+    assert_eq!(quota_remaining(3, 4), None);
+    assert_eq!(quota_remaining(0, 1), None);
+  }
+
+  #[test]
+  fn test_quota_day_resets_at_utc_midnight() {
+    let before_midnight = at("2025-02-01 23:59:59.999");
+    let midnight = at("2025-02-02 00:00:00.000");
+    assert_ne!(quota_day(before_midnight), quota_day(midnight));
+    assert_eq!(quota_day(midnight), quota_day(at("2025-02-02 23:59:59.999")));
+  }
+
+  #[test]
+  fn test_quota_is_available_again_on_the_next_day() {
+    // A game which filled its quota yesterday starts today's count
+    // afresh, since submissions are counted per quota_day.
+    let mut counts = std::collections::HashMap::new();
+    let yesterday = at("2025-02-01 12:00:00.000");
+    let today = at("2025-02-02 00:00:01.000");
+    for _ in 0..2 {
+      *counts.entry(quota_day(yesterday)).or_insert(0) += 1;
+    }
+    assert_eq!(quota_remaining(2, counts[&quota_day(yesterday)] + 1), None);
+    let today_count = counts.get(&quota_day(today)).copied().unwrap_or(0);
+    assert_eq!(quota_remaining(2, today_count + 1), Some(1));
+  }
+}
@@ -0,0 +1,292 @@
+
+//! Awareness of the transport a request arrived over, for deployments
+//! behind a TLS-terminating load balancer.
+//!
+//! Forwarding headers (`X-Forwarded-Proto` and `X-Forwarded-For`) are
+//! only believed when the request comes directly from a configured
+//! trusted proxy, since any client can set them.
+
+use crate::config::AppConfig;
+use super::error::ApiError;
+
+use rocket::{Data, Request, Route, get, routes};
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::http::Method;
+use rocket::http::uri::Origin;
+use rocket::request::{self, FromRequest};
+use log::warn;
+
+use std::convert::Infallible;
+use std::net::IpAddr;
+
+/// Environment variable which, if set to `true` or `1`, rejects
+/// requests which did not arrive over HTTPS.
+pub const REQUIRE_HTTPS_ENV_VAR: &str = "REQUIRE_HTTPS";
+
+/// Environment variable listing the IP addresses of trusted proxies,
+/// separated by commas.
+pub const TRUSTED_PROXIES_ENV_VAR: &str = "TRUSTED_PROXIES";
+
+pub const HTTPS_REQUIRED: &str = "HTTPS is required";
+
+/// Path to which insecure requests are rerouted when HTTPS is
+/// required, so that they are answered without reaching their
+/// intended handler.
+const INSECURE_REQUEST_PATH: &str = "/insecure-request";
+
+/// First path segment of the health checks, which are answered over
+/// any transport. Load balancers usually probe them directly over
+/// plain HTTP, without forwarding headers.
+const HEALTH_CHECK_SEGMENT: &str = "health";
+
+/// Transport settings, kept in Rocket's managed state as part of
+/// [`AppConfig`].
+#[derive(Debug, Clone, Default)]
+pub struct TransportConfig {
+  pub require_https: bool,
+  pub trusted_proxies: Vec<IpAddr>,
+}
+
+/// Fairing which reroutes requests not made over HTTPS to an error
+/// route, if [`TransportConfig::require_https`] is set. Health checks
+/// are let through regardless.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RequireHttps;
+
+/// Request guard for the address of the client which originated the
+/// request, looking through trusted proxies. This guard fails only if
+/// the connection has no remote address.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClientIp(pub IpAddr);
+
+pub fn transport_routes() -> Vec<Route> {
+  routes![reject_insecure_request]
+}
+
+impl TransportConfig {
+  /// Reads the configuration through `vars`, as
+  /// [`AppConfig::from_vars`] does. Unparseable proxy addresses are
+  /// logged and skipped.
+  pub fn from_vars(vars: &impl Fn(&str) -> Option<String>) -> Self {
+    let require_https = vars(REQUIRE_HTTPS_ENV_VAR).is_some_and(|value| value == "true" || value == "1");
+    let trusted_proxies = vars(TRUSTED_PROXIES_ENV_VAR)
+      .map(|value| {
+        value.split(',')
+          .map(str::trim)
+          .filter(|addr| !addr.is_empty())
+          .filter_map(|addr| {
+            addr.parse().inspect_err(|_| {
+              warn!("Invalid {} entry {:?}, ignoring", TRUSTED_PROXIES_ENV_VAR, addr);
+            }).ok()
+          })
+          .collect()
+      })
+      .unwrap_or_default();
+    TransportConfig { require_https, trusted_proxies }
+  }
+
+  fn is_trusted_proxy(&self, req: &Request<'_>) -> bool {
+    req.remote().is_some_and(|remote| self.trusted_proxies.contains(&remote.ip()))
+  }
+
+  /// Whether a trusted proxy reported that the request arrived over
+  /// HTTPS. The server itself does not terminate TLS, so a direct
+  /// connection never counts.
+  pub fn is_https(&self, req: &Request<'_>) -> bool {
+    if self.is_trusted_proxy(req) {
+      if let Some(proto) = req.headers().get("X-Forwarded-Proto").last() {
+        return proto.trim().eq_ignore_ascii_case("https");
+      }
+    }
+    false
+  }
+
+  /// The address of the client which originated the request. If the
+  /// request came through trusted proxies, this is the nearest
+  /// untrusted address in `X-Forwarded-For`.
+  pub fn client_ip(&self, req: &Request<'_>) -> Option<IpAddr> {
+    let remote = req.remote()?.ip();
+    if !self.is_trusted_proxy(req) {
+      return Some(remote);
+    }
+    let forwarded_for = req.headers().get("X-Forwarded-For")
+      .flat_map(|value| value.split(','))
+      .map(str::trim)
+      .collect::<Vec<_>>();
+    for addr in forwarded_for.into_iter().rev() {
+      let Ok(addr) = addr.parse::<IpAddr>() else {
+        // An unparseable entry cannot be trusted, nor can anything
+        // before it.
+        break;
+      };
+      if !self.trusted_proxies.contains(&addr) {
+        return Some(addr);
+      }
+    }
+    Some(remote)
+  }
+}
+
+#[rocket::async_trait]
+impl Fairing for RequireHttps {
+  fn info(&self) -> Info {
+    Info { name: "Require HTTPS", kind: Kind::Request }
+  }
+
+  async fn on_request(&self, req: &mut Request<'_>, _: &mut Data<'_>) {
+    let Some(config) = req.rocket().state::<AppConfig>().map(|config| &config.transport) else {
+      return;
+    };
+    if !config.require_https || config.is_https(req) || is_health_check(req) {
+      return;
+    }
+    warn!("Rejected plaintext request to {} from {:?}", req.uri(), config.client_ip(req));
+    req.set_method(Method::Get);
+    req.set_uri(Origin::parse(INSECURE_REQUEST_PATH).expect("INSECURE_REQUEST_PATH is a valid URI"));
+  }
+}
+
+fn is_health_check(req: &Request<'_>) -> bool {
+  req.uri().path().segments().next() == Some(HEALTH_CHECK_SEGMENT)
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for ClientIp {
+  type Error = Infallible;
+
+  async fn from_request(req: &'r Request<'_>) -> request::Outcome<Self, Infallible> {
+    let client_ip = match req.rocket().state::<AppConfig>().map(|config| &config.transport) {
+      Some(config) => config.client_ip(req),
+      None => req.remote().map(|remote| remote.ip()),
+    };
+    match client_ip {
+      Some(client_ip) => request::Outcome::Success(ClientIp(client_ip)),
+      None => request::Outcome::Forward(rocket::http::Status::BadRequest),
+    }
+  }
+}
+
+#[get("/insecure-request")]
+fn reject_insecure_request() -> ApiError {
+  ApiError::forbidden().with_message(HTTPS_REQUIRED)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  use rocket::http::{Header, Status};
+  use rocket::local::blocking::Client;
+
+  use std::net::SocketAddr;
+
+  const PROXY: &str = "10.0.0.1";
+
+  #[get("/ip")]
+  fn ip(client_ip: ClientIp) -> String {
+    client_ip.0.to_string()
+  }
+
+  #[get("/health")]
+  fn health() -> &'static str {
+    "ok"
+  }
+
+  fn client(require_https: bool) -> Client {
+    let config = AppConfig::from_vars(|name| match name {
+      TRUSTED_PROXIES_ENV_VAR => Some(format!("{}, 10.0.0.2, not-an-ip", PROXY)),
+      REQUIRE_HTTPS_ENV_VAR => Some(require_https.to_string()),
+      _ => None,
+    });
+    let rocket = rocket::build()
+      .manage(config)
+      .mount("/", routes![ip, health])
+      .mount("/", transport_routes())
+      .attach(RequireHttps)
+      .register("/", crate::server::error::catchers());
+    Client::untracked(rocket).unwrap()
+  }
+
+  fn remote(addr: &str) -> SocketAddr {
+    SocketAddr::new(addr.parse().unwrap(), 4000)
+  }
+
+  fn client_ip(client: &Client, remote_addr: &str, forwarded_for: Option<&str>) -> String {
+    let mut request = client.get("/ip").remote(remote(remote_addr));
+    if let Some(forwarded_for) = forwarded_for {
+      request = request.header(Header::new("X-Forwarded-For", forwarded_for.to_owned()));
+    }
+    request.dispatch().into_string().unwrap()
+  }
+
+  #[test]
+  fn test_http_request_rejected() {
+    let client = client(true);
+    let response = client.get("/ip").remote(remote("203.0.113.5")).dispatch();
+    assert_eq!(response.status(), Status::Forbidden);
+    assert!(response.into_string().unwrap().contains(HTTPS_REQUIRED));
+    // Only a trusted proxy may claim that the request was HTTPS.
+    let response = client.get("/ip")
+      .remote(remote("203.0.113.5"))
+      .header(Header::new("X-Forwarded-Proto", "https"))
+      .dispatch();
+    assert_eq!(response.status(), Status::Forbidden);
+    let response = client.get("/ip")
+      .remote(remote(PROXY))
+      .header(Header::new("X-Forwarded-Proto", "http"))
+      .dispatch();
+    assert_eq!(response.status(), Status::Forbidden);
+  }
+
+  #[test]
+  fn test_https_forwarded_request_accepted() {
+    let client = client(true);
+    let response = client.get("/ip")
+      .remote(remote(PROXY))
+      .header(Header::new("X-Forwarded-Proto", "HTTPS"))
+      .header(Header::new("X-Forwarded-For", "198.51.100.7"))
+      .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.into_string().unwrap(), "198.51.100.7");
+  }
+
+  #[test]
+  fn test_health_check_allowed_over_http() {
+    let client = client(true);
+    let response = client.get("/health").remote(remote("203.0.113.5")).dispatch();
+    assert_eq!(response.status(), Status::Ok);
+  }
+
+  #[test]
+  fn test_http_allowed_unless_required() {
+    let client = client(false);
+    let response = client.get("/ip").remote(remote("203.0.113.5")).dispatch();
+    assert_eq!(response.status(), Status::Ok);
+  }
+
+  #[test]
+  fn test_trusted_proxies_from_vars() {
+    let config = TransportConfig::from_vars(&|name| (name == TRUSTED_PROXIES_ENV_VAR).then(|| String::from(" 10.0.0.1,,::1 ,bad")));
+    assert_eq!(config.trusted_proxies, ["10.0.0.1".parse::<IpAddr>().unwrap(), "::1".parse().unwrap()]);
+    assert!(!config.require_https);
+  }
+
+  #[test]
+  fn test_client_ip_ignores_forwarding_from_untrusted_peer() {
+    let client = client(false);
+    assert_eq!(client_ip(&client, "203.0.113.5", None), "203.0.113.5");
+    assert_eq!(client_ip(&client, "203.0.113.5", Some("198.51.100.7")), "203.0.113.5");
+  }
+
+  #[test]
+  fn test_client_ip_looks_through_trusted_proxies() {
+    let client = client(false);
+    assert_eq!(client_ip(&client, PROXY, None), PROXY);
+    assert_eq!(client_ip(&client, PROXY, Some("198.51.100.7")), "198.51.100.7");
+    // Only the nearest untrusted address is believed; anything the
+    // client itself prepended is not.
+    assert_eq!(client_ip(&client, PROXY, Some("192.0.2.1, 198.51.100.7, 10.0.0.2")), "198.51.100.7");
+    assert_eq!(client_ip(&client, PROXY, Some("198.51.100.7, garbage, 10.0.0.2")), PROXY);
+    assert_eq!(client_ip(&client, PROXY, Some("10.0.0.2")), PROXY);
+  }
+}
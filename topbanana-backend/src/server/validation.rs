@@ -0,0 +1,439 @@
+
+//! Validation of user-supplied creation parameters.
+//!
+//! Validation collects every invalid field rather than stopping at
+//! the first, so that clients can fix all of their mistakes in one
+//! round trip.
+
+use super::admin::{ImportScoresParams, NewDeveloperParams, SetGameMaxTablesParams, SetGameQuotaParams};
+use super::api::ScoresParams;
+use super::audit::{AuditLogParams, MAX_AUDIT_LOG_LIMIT};
+use super::data_access::{NewGameDao, NewHighscoreTableDao};
+use super::error::ApiError;
+
+use serde::de::{self, Deserializer, SeqAccess, Visitor};
+use serde::Deserialize;
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::marker::PhantomData;
+
+/// Maximum length of user-supplied string fields. This matches the
+/// `VARCHAR(100)` columns in the database.
+pub const MAX_FIELD_LENGTH: usize = 100;
+
+/// Maximum length of free-form description fields. This matches the
+/// `VARCHAR(1000)` columns in the database.
+pub const MAX_DESCRIPTION_LENGTH: usize = 1000;
+
+/// Minimum number of random bytes in a game's secret key.
+pub const MIN_SECRET_KEY_LENGTH: usize = 32;
+
+/// Maximum number of random bytes in a game's secret key. The
+/// base64-encoded key must fit in a `VARCHAR(100)` column.
+pub const MAX_SECRET_KEY_LENGTH: usize = 64;
+
+/// Maximum number of decimal places a table may round scores to.
+/// Beyond this, an `f64` has no more precision to round away.
+pub const MAX_SCORE_PRECISION: i32 = 15;
+
+/// Accumulator for per-field validation failures.
+#[derive(Debug, Clone, Default)]
+pub struct FieldErrors {
+  errors: BTreeMap<String, String>,
+}
+
+/// Types whose fields can be validated before being used.
+pub trait Validate {
+  /// Reports every invalid field of `self` into `errors`.
+  fn collect_field_errors(&self, errors: &mut FieldErrors);
+
+  /// Validates `self`, producing an HTTP 422 listing every invalid
+  /// field on failure.
+  fn validate(&self) -> Result<(), ApiError> {
+    let mut errors = FieldErrors::new();
+    self.collect_field_errors(&mut errors);
+    errors.into_result()
+  }
+}
+
+impl FieldErrors {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Records a failure for the given field. If the field already has
+  /// a failure recorded, the earlier message is kept.
+  pub fn add(&mut self, field: &str, message: impl Into<String>) {
+    self.errors.entry(field.to_owned()).or_insert_with(|| message.into());
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.errors.is_empty()
+  }
+
+  /// Checks that a required string field is non-blank and fits in the
+  /// database.
+  pub fn check_name(&mut self, field: &str, value: &str) {
+    if value.trim().is_empty() {
+      self.add(field, format!("{} must not be empty", field));
+    } else if value.chars().count() > MAX_FIELD_LENGTH {
+      self.add(field, format!("{} must be at most {} characters", field, MAX_FIELD_LENGTH));
+    }
+  }
+
+  /// Checks that an optional string field fits in the database.
+  pub fn check_length(&mut self, field: &str, value: &str, max_length: usize) {
+    if value.chars().count() > max_length {
+      self.add(field, format!("{} must be at most {} characters", field, max_length));
+    }
+  }
+
+  pub fn into_result(self) -> Result<(), ApiError> {
+    if self.is_empty() {
+      Ok(())
+    } else {
+      Err(ApiError::unprocessable_entity().with_message("Validation failed").with_fields(self.errors))
+    }
+  }
+}
+
+impl Validate for NewDeveloperParams {
+  fn collect_field_errors(&self, errors: &mut FieldErrors) {
+    errors.check_name("name", &self.name);
+    if !is_plausible_email(&self.email) {
+      errors.add("email", "email must be a valid email address");
+    } else if self.email.chars().count() > MAX_FIELD_LENGTH {
+      errors.add("email", format!("email must be at most {} characters", MAX_FIELD_LENGTH));
+    }
+    if let Some(url) = &self.url {
+      if !(url.starts_with("http://") || url.starts_with("https://")) {
+        errors.add("url", "url must be an http or https URL");
+      } else if url.chars().count() > MAX_FIELD_LENGTH {
+        errors.add("url", format!("url must be at most {} characters", MAX_FIELD_LENGTH));
+      }
+    }
+  }
+}
+
+impl Validate for NewGameDao {
+  fn collect_field_errors(&self, errors: &mut FieldErrors) {
+    errors.check_name("name", &self.name);
+    if self.min_submit_interval_ms.is_some_and(|ms| ms < 0) {
+      errors.add("min_submit_interval_ms", "min_submit_interval_ms must be non-negative");
+    }
+    if let Some(description) = &self.description {
+      errors.check_length("description", description, MAX_DESCRIPTION_LENGTH);
+    }
+    if let Some(platform) = &self.platform {
+      errors.check_length("platform", platform, MAX_FIELD_LENGTH);
+    }
+    if let Some(external_id) = &self.external_id {
+      errors.check_name("external_id", external_id);
+    }
+    if let Some(algo) = self.default_algorithm {
+      let security_level = self.security_level.unwrap_or_default();
+      if i32::from(algo.into_hasher().security_level()) < security_level {
+        errors.add(
+          "default_algorithm",
+          format!("default_algorithm {} is too weak for security level {}", algo.name(), security_level),
+        );
+      }
+    }
+    if self.secret_key_length.is_some_and(|length| !(MIN_SECRET_KEY_LENGTH..=MAX_SECRET_KEY_LENGTH).contains(&length)) {
+      errors.add(
+        "secret_key_length",
+        format!("secret_key_length must be between {} and {}", MIN_SECRET_KEY_LENGTH, MAX_SECRET_KEY_LENGTH),
+      );
+    }
+  }
+}
+
+impl Validate for SetGameQuotaParams {
+  fn collect_field_errors(&self, errors: &mut FieldErrors) {
+    if self.daily_submission_quota.is_some_and(|quota| quota < 0) {
+      errors.add("daily_submission_quota", "daily_submission_quota must be non-negative");
+    }
+  }
+}
+
+impl Validate for SetGameMaxTablesParams {
+  fn collect_field_errors(&self, errors: &mut FieldErrors) {
+    if self.max_tables.is_some_and(|max_tables| max_tables < 0) {
+      errors.add("max_tables", "max_tables must be non-negative");
+    }
+  }
+}
+
+impl Validate for ImportScoresParams {
+  fn collect_field_errors(&self, errors: &mut FieldErrors) {
+    for (i, entry) in self.entries.iter().enumerate() {
+      errors.check_name(&format!("entries[{}].player_name", i), &entry.player_name);
+      if !entry.player_score.is_finite() {
+        errors.add(&format!("entries[{}].player_score", i), "player_score must be a finite number");
+      }
+    }
+  }
+}
+
+impl Validate for NewHighscoreTableDao {
+  fn collect_field_errors(&self, errors: &mut FieldErrors) {
+    errors.check_name("name", &self.name);
+    if self.anti_cheat_sigma.is_some_and(|sigma| !(sigma.is_finite() && sigma > 0.0)) {
+      errors.add("anti_cheat_sigma", "anti_cheat_sigma must be a positive number");
+    }
+    if self.score_precision.is_some_and(|precision| !(0..=MAX_SCORE_PRECISION).contains(&precision)) {
+      errors.add("score_precision", format!("score_precision must be between 0 and {}", MAX_SCORE_PRECISION));
+    }
+    if let Some(default_player_name) = &self.default_player_name {
+      errors.check_name("default_player_name", default_player_name);
+    }
+    if let (Some(open_at), Some(close_at)) = (self.submissions_open_at, self.submissions_close_at) {
+      if open_at >= close_at {
+        errors.add("submissions_open_at", "submissions_open_at must be before submissions_close_at");
+      }
+    }
+  }
+}
+
+impl Validate for ScoresParams {
+  fn collect_field_errors(&self, errors: &mut FieldErrors) {
+    if let (Some(from_time), Some(to_time)) = (self.from_time, self.to_time) {
+      if from_time > to_time {
+        errors.add("from_time", "from_time must not be after to_time");
+      }
+    }
+  }
+}
+
+impl Validate for AuditLogParams {
+  fn collect_field_errors(&self, errors: &mut FieldErrors) {
+    if !(1..=MAX_AUDIT_LOG_LIMIT).contains(&self.limit()) {
+      errors.add("limit", format!("limit must be between 1 and {}", MAX_AUDIT_LOG_LIMIT));
+    }
+    if let (Some(since), Some(until)) = (self.since, self.until) {
+      if since > until {
+        errors.add("since", "since must not be after until");
+      }
+    }
+  }
+}
+
+/// Deserializes an array of at most `MAX` elements, for use with
+/// `#[serde(deserialize_with)]`. Deserialization fails as soon as the
+/// limit is passed, so an oversized array is rejected without being
+/// read in full and before any database work is done.
+pub fn deserialize_bounded_vec<'de, D, T, const MAX: usize>(deserializer: D) -> Result<Vec<T>, D::Error>
+where D: Deserializer<'de>,
+      T: Deserialize<'de> {
+  deserializer.deserialize_seq(BoundedVecVisitor::<T, MAX>(PhantomData))
+}
+
+struct BoundedVecVisitor<T, const MAX: usize>(PhantomData<T>);
+
+impl<'de, T, const MAX: usize> Visitor<'de> for BoundedVecVisitor<T, MAX>
+where T: Deserialize<'de> {
+  type Value = Vec<T>;
+
+  fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "an array of at most {} elements", MAX)
+  }
+
+  fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+  where A: SeqAccess<'de> {
+    let mut elements = Vec::with_capacity(seq.size_hint().unwrap_or(0).min(MAX));
+    while let Some(element) = seq.next_element()? {
+      if elements.len() == MAX {
+        return Err(de::Error::invalid_length(MAX + 1, &self));
+      }
+      elements.push(element);
+    }
+    Ok(elements)
+  }
+}
+
+/// A deliberately loose email check: exactly one `@`, with something
+/// on either side and a dot in the domain.
+fn is_plausible_email(email: &str) -> bool {
+  let Some((local, domain)) = email.split_once('@') else {
+    return false;
+  };
+  !local.is_empty() && !domain.contains('@') && domain.contains('.') && !domain.starts_with('.') && !domain.ends_with('.')
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::server::admin::ImportedScore;
+  use crate::server::api::QueryTimestamp;
+
+  use chrono::NaiveDateTime;
+  use rocket::http::Status;
+  use serde_json::json;
+
+  fn field_errors<T: Validate>(value: &T) -> BTreeMap<String, String> {
+    match value.validate() {
+      Ok(()) => BTreeMap::new(),
+      Err(err) => {
+        assert_eq!(err.status(), Status::UnprocessableEntity);
+        err.fields().cloned().unwrap_or_default()
+      }
+    }
+  }
+
+  /// Deserializes `T` from `base` with the fields of `extra` added.
+  fn with_fields<T: for<'de> Deserialize<'de>>(base: serde_json::Value, extra: serde_json::Value) -> T {
+    let mut value = base;
+    value.as_object_mut().unwrap().extend(extra.as_object().unwrap().clone());
+    serde_json::from_value(value).unwrap()
+  }
+
+  fn developer(name: &str, email: &str, url: Option<&str>) -> NewDeveloperParams {
+    NewDeveloperParams {
+      name: name.to_owned(),
+      email: email.to_owned(),
+      url: url.map(str::to_owned),
+      default_security_level: None,
+    }
+  }
+
+  fn game(extra: serde_json::Value) -> NewGameDao {
+    with_fields(json!({ "developer_uuid": "0194c0b0-0000-7000-8000-000000000001", "name": "Game" }), extra)
+  }
+
+  fn table(extra: serde_json::Value) -> NewHighscoreTableDao {
+    with_fields(json!({ "game_uuid": "0194c0b0-0000-7000-8000-000000000001", "name": "Table" }), extra)
+  }
+
+  #[test]
+  fn test_field_errors_keep_first_message() {
+    let mut errors = FieldErrors::new();
+    assert!(errors.is_empty());
+    errors.add("name", "first");
+    errors.add("name", "second");
+    let err = errors.into_result().unwrap_err();
+    assert_eq!(err.message(), "Validation failed");
+    assert_eq!(err.fields().unwrap()["name"], "first");
+    assert!(FieldErrors::new().into_result().is_ok());
+  }
+
+  #[test]
+  fn test_check_name() {
+    let mut errors = FieldErrors::new();
+    errors.check_name("blank", "   ");
+    errors.check_name("long", &"x".repeat(MAX_FIELD_LENGTH + 1));
+    errors.check_name("fine", &"é".repeat(MAX_FIELD_LENGTH));
+    let fields = errors.into_result().unwrap_err().fields().cloned().unwrap();
+    assert_eq!(fields.keys().collect::<Vec<_>>(), ["blank", "long"]);
+    assert_eq!(fields["blank"], "blank must not be empty");
+    assert_eq!(fields["long"], "long must be at most 100 characters");
+  }
+
+  #[test]
+  fn test_new_developer_reports_every_invalid_field() {
+    assert!(developer("Alice", "alice@example.com", Some("https://example.com")).validate().is_ok());
+    let fields = field_errors(&developer("", "alice@example", Some("ftp://example.com")));
+    assert_eq!(fields["name"], "name must not be empty");
+    assert_eq!(fields["email"], "email must be a valid email address");
+    assert_eq!(fields["url"], "url must be an http or https URL");
+    let fields = field_errors(&developer(&"x".repeat(MAX_FIELD_LENGTH + 1), &format!("{}@example.com", "x".repeat(MAX_FIELD_LENGTH)), None));
+    assert_eq!(fields.keys().collect::<Vec<_>>(), ["email", "name"]);
+  }
+
+  #[test]
+  fn test_plausible_email() {
+    assert!(is_plausible_email("alice@example.com"));
+    assert!(!is_plausible_email("alice.example.com"));
+    assert!(!is_plausible_email("@example.com"));
+    assert!(!is_plausible_email("alice@@example.com"));
+    assert!(!is_plausible_email("alice@.example.com"));
+    assert!(!is_plausible_email("alice@example.com."));
+  }
+
+  #[test]
+  fn test_new_game_and_table_report_every_invalid_field() {
+    assert!(game(json!({})).validate().is_ok());
+    let fields = field_errors(&game(json!({ "name": " ", "external_id": "" })));
+    assert_eq!(fields.keys().collect::<Vec<_>>(), ["external_id", "name"]);
+
+    assert!(table(json!({ "anti_cheat_sigma": 3.0 })).validate().is_ok());
+    let fields = field_errors(&table(json!({ "name": "", "anti_cheat_sigma": 0.0, "default_player_name": " " })));
+    assert_eq!(fields.keys().collect::<Vec<_>>(), ["anti_cheat_sigma", "default_player_name", "name"]);
+  }
+
+  #[test]
+  fn test_game_metadata_length() {
+    assert!(game(json!({ "description": "x".repeat(MAX_DESCRIPTION_LENGTH), "platform": "x".repeat(MAX_FIELD_LENGTH) })).validate().is_ok());
+    let fields = field_errors(&game(json!({
+      "description": "x".repeat(MAX_DESCRIPTION_LENGTH + 1),
+      "platform": "x".repeat(MAX_FIELD_LENGTH + 1),
+    })));
+    assert_eq!(fields["description"], "description must be at most 1000 characters");
+    assert_eq!(fields["platform"], "platform must be at most 100 characters");
+  }
+
+  fn at(date: &str) -> QueryTimestamp {
+    QueryTimestamp(NaiveDateTime::parse_from_str(date, "%Y-%m-%d %H:%M:%S").unwrap())
+  }
+
+  #[test]
+  fn test_scores_time_window_validation() {
+    let scores = ScoresParams { from_time: Some(at("2025-02-02 00:00:00")), to_time: Some(at("2025-02-01 00:00:00")), ..ScoresParams::default() };
+    assert_eq!(field_errors(&scores)["from_time"], "from_time must not be after to_time");
+    let scores = ScoresParams { from_time: Some(at("2025-02-01 00:00:00")), to_time: Some(at("2025-02-01 00:00:00")), ..ScoresParams::default() };
+    assert!(scores.validate().is_ok());
+    assert!(ScoresParams { from_time: Some(at("2025-02-02 00:00:00")), ..ScoresParams::default() }.validate().is_ok());
+  }
+
+  #[test]
+  fn test_import_scores_validation() {
+    let params = serde_json::from_value::<ImportScoresParams>(json!({
+      "entries": [
+        { "player_name": "Alice", "player_score": 1.0, "creation_timestamp": "2025-02-01T05:33:10Z" },
+        { "player_name": "", "player_score": 1.0, "creation_timestamp": "2025-02-01T05:33:10Z" },
+        { "player_name": "Carol", "player_score": 2.0, "creation_timestamp": "2025-02-01T05:33:10Z" },
+      ],
+    })).unwrap();
+    assert_eq!(field_errors(&params).keys().collect::<Vec<_>>(), ["entries[1].player_name"]);
+    let params = ImportScoresParams { entries: vec![ImportedScore { player_score: f64::NAN, ..params.entries[0].clone() }] };
+    assert_eq!(field_errors(&params)["entries[0].player_score"], "player_score must be a finite number");
+  }
+
+  #[test]
+  fn test_secret_key_length_validation() {
+    for length in [MIN_SECRET_KEY_LENGTH, MAX_SECRET_KEY_LENGTH] {
+      assert!(game(json!({ "secret_key_length": length })).validate().is_ok());
+    }
+    for length in [0, MIN_SECRET_KEY_LENGTH - 1, MAX_SECRET_KEY_LENGTH + 1] {
+      let fields = field_errors(&game(json!({ "secret_key_length": length })));
+      assert_eq!(fields["secret_key_length"], "secret_key_length must be between 32 and 64");
+    }
+  }
+
+  #[test]
+  fn test_audit_log_params_validation() {
+    assert!(AuditLogParams::default().validate().is_ok());
+    let audit = AuditLogParams { limit: Some(0), since: Some(at("2025-02-02 00:00:00")), until: Some(at("2025-02-01 00:00:00")), ..AuditLogParams::default() };
+    let fields = field_errors(&audit);
+    assert_eq!(fields.keys().collect::<Vec<_>>(), ["limit", "since"]);
+    assert_eq!(fields["since"], "since must not be after until");
+    let audit = AuditLogParams { limit: Some(MAX_AUDIT_LOG_LIMIT + 1), ..AuditLogParams::default() };
+    assert_eq!(field_errors(&audit)["limit"], format!("limit must be between 1 and {}", MAX_AUDIT_LOG_LIMIT));
+    assert!(AuditLogParams { limit: Some(MAX_AUDIT_LOG_LIMIT), ..AuditLogParams::default() }.validate().is_ok());
+  }
+
+  #[derive(Debug, Deserialize)]
+  struct Bounded {
+    #[serde(deserialize_with = "deserialize_bounded_vec::<_, _, 3>")]
+    values: Vec<i32>,
+  }
+
+  #[test]
+  fn test_deserialize_bounded_vec() {
+    assert_eq!(serde_json::from_str::<Bounded>(r#"{"values":[]}"#).unwrap().values, Vec::<i32>::new());
+    assert_eq!(serde_json::from_str::<Bounded>(r#"{"values":[1,2,3]}"#).unwrap().values, [1, 2, 3]);
+    let err = serde_json::from_str::<Bounded>(r#"{"values":[1,2,3,4]}"#).unwrap_err();
+    assert!(err.to_string().contains("invalid length 4, expected an array of at most 3 elements"), "{}", err);
+    assert!(serde_json::from_str::<Bounded>(r#"{"values":{}}"#).is_err());
+  }
+}
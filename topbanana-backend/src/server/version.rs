@@ -0,0 +1,79 @@
+
+//! Server version and build information, for confirming which build
+//! is deployed.
+
+use super::error::{ApiSuccessResponse, ApiSuccessResponseBody};
+
+use rocket::{Route, get, routes};
+use serde::Serialize;
+use utoipa::ToSchema;
+use chrono::DateTime;
+
+mod build_info {
+  include!(concat!(env!("OUT_DIR"), "/build_info.rs"));
+}
+
+pub use build_info::{GIT_COMMIT, BUILD_TIMESTAMP};
+
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+pub fn version_routes() -> Vec<Route> {
+  routes![get_version]
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct VersionResponse {
+  /// The server's crate version.
+  #[schema(example = "0.1.0")]
+  pub version: &'static str,
+  /// The git commit the server was built from, or `"unknown"` if it
+  /// was built outside of a git checkout.
+  pub git_commit: &'static str,
+  /// When the server was built, in RFC 3339 format.
+  pub build_timestamp: String,
+}
+
+/// Reports the version and build of the running server.
+///
+/// This endpoint requires no authentication.
+#[utoipa::path(
+  get,
+  path="/version",
+  tag="server",
+  security(),
+  responses(
+    (status = 200, description = "Server version information", body = ApiSuccessResponseBody<VersionResponse>),
+  ),
+)]
+#[get("/version")]
+pub fn get_version() -> ApiSuccessResponse<VersionResponse> {
+  let build_timestamp = DateTime::from_timestamp(BUILD_TIMESTAMP, 0)
+    .map(|timestamp| timestamp.to_rfc3339())
+    .unwrap_or_default();
+  ApiSuccessResponse::new(VersionResponse {
+    version: VERSION,
+    git_commit: GIT_COMMIT,
+    build_timestamp,
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  use rocket::http::Status;
+  use rocket::local::blocking::Client;
+  use serde_json::Value;
+
+  #[test]
+  fn test_version_reports_package_version() {
+    let client = Client::untracked(rocket::build().mount("/", version_routes())).unwrap();
+    let response = client.get("/version").dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    let body = response.into_json::<Value>().unwrap();
+    assert_eq!(body["status"], "success");
+    assert_eq!(body["version"], env!("CARGO_PKG_VERSION"));
+    assert_eq!(body["git_commit"], GIT_COMMIT);
+    assert!(DateTime::parse_from_rfc3339(body["build_timestamp"].as_str().unwrap()).is_ok());
+  }
+}
@@ -0,0 +1,484 @@
+//! Delivery of the new-highscore webhook.
+//!
+//! If [`AppConfig::new_highscore_webhook_url`] is set, each score
+//! which is stored and kept on its table is POSTed to that URL as
+//! JSON. Deliveries are queued for a background task, so that a slow
+//! or unreachable endpoint never holds up a submission.
+//!
+//! A delivery which fails with a network error, a timeout, or a
+//! response which may succeed if repeated (HTTP 408, 429, or 5xx) is
+//! retried with exponential backoff. Any other response is not
+//! retried. A delivery which still has not succeeded is recorded in
+//! `failed_webhooks`, from which administrators may replay it. The
+//! queue of waiting deliveries is bounded, and a delivery which finds
+//! it full is recorded there straight away.
+//!
+//! [`AppConfig::new_highscore_webhook_url`]: crate::config::AppConfig::new_highscore_webhook_url
+
+use crate::db::{schema, models};
+use super::api::{ScoreValue, ScoresEntryResponse};
+use super::openapi::OpenApiUuid;
+
+use diesel_async::{RunQueryDsl, AsyncPgConnection};
+use diesel_async::pooled_connection::deadpool::Pool;
+use log::{error, warn};
+use reqwest::StatusCode;
+use reqwest::header::CONTENT_TYPE;
+use rocket::tokio;
+use rocket::tokio::sync::mpsc::{self, error::TrySendError};
+use serde::Serialize;
+use utoipa::ToSchema;
+use uuid::Uuid;
+use chrono::{DateTime, Utc};
+
+use std::fmt::{self, Display};
+use std::time::Duration;
+
+/// Environment variable naming the URL to which new highscores are
+/// POSTed. See
+/// [`AppConfig::new_highscore_webhook_url`](crate::config::AppConfig::new_highscore_webhook_url).
+pub const NEW_HIGHSCORE_WEBHOOK_URL_ENV_VAR: &str = "NEW_HIGHSCORE_WEBHOOK_URL";
+
+/// Header carrying the delivery's UUID, which stays the same across
+/// retries and replays so that the receiver can ignore duplicates.
+pub const DELIVERY_UUID_HEADER: &str = "X-TopBanana-Delivery";
+
+/// Maximum number of deliveries waiting to be sent. Beyond this,
+/// deliveries are recorded as failed without being sent.
+pub const MAX_QUEUED_WEBHOOKS: usize = 1024;
+
+/// Number of times a delivery is attempted before it is recorded as
+/// failed.
+pub const DELIVERY_ATTEMPTS: u32 = 5;
+
+/// Delay before the first retry of a failed delivery. Each later
+/// retry waits twice as long as the one before.
+pub const INITIAL_RETRY_DELAY: Duration = Duration::from_millis(500);
+
+/// How long a single attempt may take before it counts as failed.
+pub const DELIVERY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The body of a new-highscore webhook.
+#[derive(Debug, Clone, Serialize)]
+pub struct NewHighscoreEvent {
+  /// Always `new_highscore`.
+  pub event: &'static str,
+  pub game_uuid: Uuid,
+  pub table_uuid: Uuid,
+  pub player_name: String,
+  pub player_score: ScoreValue,
+  /// The score's position on the table when it was stored.
+  pub rank: i64,
+  pub creation_timestamp: DateTime<Utc>,
+}
+
+/// A webhook waiting to be sent.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WebhookDelivery {
+  pub delivery_uuid: Uuid,
+  pub url: String,
+  /// The JSON body.
+  pub payload: String,
+}
+
+/// Why an attempt to deliver a webhook failed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DeliveryError {
+  /// The attempt may succeed if repeated.
+  Transient(String),
+  /// The endpoint rejected the webhook, and will do so again.
+  Permanent(String),
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct FailedWebhookResponse {
+  #[schema(value_type = OpenApiUuid)]
+  pub delivery_uuid: Uuid,
+  /// The URL the webhook was sent to. A replay is sent here again.
+  pub url: String,
+  /// The JSON body of the webhook.
+  pub payload: String,
+  /// How many times delivery was attempted. Zero means the webhook
+  /// was never sent, because too many were already waiting.
+  pub attempts: i32,
+  /// Why the last attempt failed.
+  pub last_error: String,
+  /// When the webhook was given up on, in UTC.
+  #[schema(value_type = String, example = "2025-02-01T05:33:10Z")]
+  pub failed_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct FailedWebhooksResponse {
+  /// Failed deliveries, newest first.
+  pub webhooks: Vec<FailedWebhookResponse>,
+}
+
+/// Queue of webhooks for the background delivery task, kept in
+/// Rocket's managed state.
+#[derive(Debug)]
+pub struct WebhookSender {
+  new_highscore_url: Option<String>,
+  sender: mpsc::Sender<WebhookDelivery>,
+}
+
+/// Where deliveries which have been given up on are recorded. This is
+/// `failed_webhooks`, except in tests.
+trait DeadLetters {
+  async fn record(&mut self, failed: models::NewFailedWebhook) -> anyhow::Result<()>;
+}
+
+impl DeadLetters for AsyncPgConnection {
+  async fn record(&mut self, failed: models::NewFailedWebhook) -> anyhow::Result<()> {
+    diesel::insert_into(schema::failed_webhooks::table)
+      .values(&failed)
+      .execute(self)
+      .await?;
+    Ok(())
+  }
+}
+
+impl DeadLetters for Pool<AsyncPgConnection> {
+  async fn record(&mut self, failed: models::NewFailedWebhook) -> anyhow::Result<()> {
+    let mut db = self.get().await?;
+    db.record(failed).await
+  }
+}
+
+/// Sends a webhook once. This is an HTTP client, except in tests.
+trait WebhookTransport {
+  async fn send(&self, delivery: &WebhookDelivery) -> Result<(), DeliveryError>;
+}
+
+impl WebhookTransport for reqwest::Client {
+  async fn send(&self, delivery: &WebhookDelivery) -> Result<(), DeliveryError> {
+    let response = self.post(&delivery.url)
+      .header(CONTENT_TYPE, "application/json")
+      .header(DELIVERY_UUID_HEADER, delivery.delivery_uuid.to_string())
+      .body(delivery.payload.clone())
+      .send()
+      .await
+      .map_err(|err| DeliveryError::Transient(err.to_string()))?;
+    check_status(response.status())
+  }
+}
+
+impl NewHighscoreEvent {
+  pub fn new(game_uuid: Uuid, table_uuid: Uuid, entry: &ScoresEntryResponse) -> Self {
+    NewHighscoreEvent {
+      event: "new_highscore",
+      game_uuid,
+      table_uuid,
+      player_name: entry.entry.player_name.clone(),
+      player_score: entry.entry.player_score,
+      rank: entry.rank,
+      creation_timestamp: entry.entry.creation_timestamp.and_utc(),
+    }
+  }
+}
+
+impl WebhookDelivery {
+  fn new(url: String, payload: String) -> Self {
+    WebhookDelivery { delivery_uuid: Uuid::now_v7(), url, payload }
+  }
+
+  fn into_failed(self, attempts: u32, last_error: String) -> models::NewFailedWebhook {
+    models::NewFailedWebhook {
+      delivery_uuid: self.delivery_uuid,
+      url: self.url,
+      payload: self.payload,
+      attempts: i32::try_from(attempts).unwrap_or(i32::MAX),
+      last_error,
+    }
+  }
+}
+
+impl From<models::FailedWebhook> for WebhookDelivery {
+  fn from(failed: models::FailedWebhook) -> Self {
+    WebhookDelivery { delivery_uuid: failed.delivery_uuid, url: failed.url, payload: failed.payload }
+  }
+}
+
+impl From<models::FailedWebhook> for FailedWebhookResponse {
+  fn from(failed: models::FailedWebhook) -> Self {
+    FailedWebhookResponse {
+      delivery_uuid: failed.delivery_uuid,
+      url: failed.url,
+      payload: failed.payload,
+      attempts: failed.attempts,
+      last_error: failed.last_error,
+      failed_at: failed.failed_at.and_utc(),
+    }
+  }
+}
+
+impl Display for DeliveryError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      DeliveryError::Transient(reason) => write!(f, "{}", reason),
+      DeliveryError::Permanent(reason) => write!(f, "{} (not retried)", reason),
+    }
+  }
+}
+
+/// Whether a response with this status delivered the webhook, and if
+/// not, whether it is worth trying again.
+fn check_status(status: StatusCode) -> Result<(), DeliveryError> {
+  if status.is_success() {
+    Ok(())
+  } else if status.is_server_error() || status == StatusCode::REQUEST_TIMEOUT || status == StatusCode::TOO_MANY_REQUESTS {
+    Err(DeliveryError::Transient(format!("Endpoint responded with {}", status)))
+  } else {
+    Err(DeliveryError::Permanent(format!("Endpoint responded with {}", status)))
+  }
+}
+
+impl WebhookSender {
+  /// Spawns the background task, which records failed deliveries
+  /// using connections from `pool`. New highscores are sent to
+  /// `new_highscore_url`, if there is one; replays are sent whether or
+  /// not there is.
+  pub fn spawn(new_highscore_url: Option<String>, pool: Pool<AsyncPgConnection>) -> Self {
+    let (sender, receiver) = mpsc::channel(MAX_QUEUED_WEBHOOKS);
+    let client = reqwest::Client::builder()
+      .timeout(DELIVERY_TIMEOUT)
+      .build()
+      .expect("webhook HTTP client should build");
+    tokio::spawn(run_sender(client, pool, receiver));
+    WebhookSender::new(new_highscore_url, sender)
+  }
+
+  fn new(new_highscore_url: Option<String>, sender: mpsc::Sender<WebhookDelivery>) -> Self {
+    WebhookSender { new_highscore_url, sender }
+  }
+
+  /// Queues the new-highscore webhook for `event`, if one is
+  /// configured. Failures are logged rather than returned, since the
+  /// score has already been stored.
+  pub async fn send_new_highscore(&self, event: &NewHighscoreEvent, db: &mut AsyncPgConnection) {
+    self.send_new_highscore_in(event, db).await
+  }
+
+  async fn send_new_highscore_in(&self, event: &NewHighscoreEvent, dead_letters: &mut impl DeadLetters) {
+    let Some(url) = &self.new_highscore_url else {
+      return;
+    };
+    let payload = match serde_json::to_string(event) {
+      Ok(payload) => payload,
+      Err(err) => {
+        error!("Failed to serialize new-highscore webhook: {}", err);
+        return;
+      }
+    };
+    if let Err(err) = self.enqueue(WebhookDelivery::new(url.clone(), payload)) {
+      // Record the delivery ourselves, rather than waiting for room in
+      // the queue or for a sender which will never come back.
+      let (delivery, reason) = match err {
+        TrySendError::Full(delivery) => (delivery, "Webhook queue is full"),
+        TrySendError::Closed(delivery) => (delivery, "Webhook sender has stopped"),
+      };
+      warn!("{}; recording webhook {} as failed without sending it", reason, delivery.delivery_uuid);
+      let delivery_uuid = delivery.delivery_uuid;
+      if let Err(err) = dead_letters.record(delivery.into_failed(0, reason.to_owned())).await {
+        error!("Failed to record webhook {} as failed: {}", delivery_uuid, err);
+      }
+    }
+  }
+
+  /// Queues `delivery` to be sent as it is, such as when replaying a
+  /// failed delivery. Fails if the queue is full or the background
+  /// task has stopped.
+  pub fn enqueue(&self, delivery: WebhookDelivery) -> Result<(), TrySendError<WebhookDelivery>> {
+    self.sender.try_send(delivery)
+  }
+}
+
+async fn run_sender(
+  client: reqwest::Client,
+  mut pool: Pool<AsyncPgConnection>,
+  mut receiver: mpsc::Receiver<WebhookDelivery>,
+) {
+  while let Some(delivery) = receiver.recv().await {
+    deliver(delivery, &client, &mut pool, DELIVERY_ATTEMPTS, INITIAL_RETRY_DELAY).await;
+  }
+}
+
+/// Sends `delivery` until it succeeds, up to `attempts` times,
+/// doubling the delay between attempts each time. A permanent failure
+/// ends the attempts early. A delivery which does not succeed is
+/// recorded in `dead_letters`.
+async fn deliver(
+  delivery: WebhookDelivery,
+  transport: &impl WebhookTransport,
+  dead_letters: &mut impl DeadLetters,
+  attempts: u32,
+  initial_delay: Duration,
+) {
+  let mut delay = initial_delay;
+  let mut attempts_made = 1;
+  let err = loop {
+    match transport.send(&delivery).await {
+      Ok(()) => return,
+      Err(err @ DeliveryError::Permanent(_)) => break err,
+      Err(err) if attempts_made >= attempts => break err,
+      Err(err) => {
+        warn!("Webhook {} attempt {} of {} failed, retrying in {:?}: {}", delivery.delivery_uuid, attempts_made, attempts, delay, err);
+        tokio::time::sleep(delay).await;
+        delay *= 2;
+        attempts_made += 1;
+      }
+    }
+  };
+  error!("Giving up on webhook {} to {} after {} attempts: {}", delivery.delivery_uuid, delivery.url, attempts_made, err);
+  let delivery_uuid = delivery.delivery_uuid;
+  if let Err(record_err) = dead_letters.record(delivery.into_failed(attempts_made, err.to_string())).await {
+    error!("Failed to record webhook {} as failed: {}", delivery_uuid, record_err);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::server::api::ScoresResponseEntry;
+
+  use std::cell::Cell;
+
+  /// An in-memory stand-in for `failed_webhooks`.
+  #[derive(Default)]
+  struct MemoryDeadLetters(Vec<models::NewFailedWebhook>);
+
+  impl DeadLetters for MemoryDeadLetters {
+    async fn record(&mut self, failed: models::NewFailedWebhook) -> anyhow::Result<()> {
+      self.0.push(failed);
+      Ok(())
+    }
+  }
+
+  /// An endpoint which fails with `error` the first `failures` times
+  /// it is sent a webhook, counting its calls in `calls`.
+  struct FlakyEndpoint {
+    failures: u32,
+    error: DeliveryError,
+    calls: Cell<u32>,
+  }
+
+  impl FlakyEndpoint {
+    fn new(failures: u32, error: DeliveryError) -> Self {
+      FlakyEndpoint { failures, error, calls: Cell::new(0) }
+    }
+  }
+
+  impl WebhookTransport for FlakyEndpoint {
+    async fn send(&self, _delivery: &WebhookDelivery) -> Result<(), DeliveryError> {
+      self.calls.set(self.calls.get() + 1);
+      if self.calls.get() > self.failures { Ok(()) } else { Err(self.error.clone()) }
+    }
+  }
+
+  fn delivery() -> WebhookDelivery {
+    WebhookDelivery::new("https://example.com/hook".to_owned(), "{}".to_owned())
+  }
+
+  fn event() -> NewHighscoreEvent {
+    let entry = ScoresEntryResponse {
+      entry: ScoresResponseEntry {
+        player_name: "Alice".to_owned(),
+        player_score: ScoreValue::Integer(1200),
+        player_score_metadata: None,
+        creation_timestamp: DateTime::from_timestamp(1_738_387_990, 0).unwrap().naive_utc(),
+      },
+      rank: 3,
+      cursor: None,
+    };
+    NewHighscoreEvent::new(Uuid::nil(), Uuid::max(), &entry)
+  }
+
+  #[test]
+  fn test_check_status() {
+    assert_eq!(check_status(StatusCode::OK), Ok(()));
+    assert_eq!(check_status(StatusCode::NO_CONTENT), Ok(()));
+    for status in [StatusCode::INTERNAL_SERVER_ERROR, StatusCode::BAD_GATEWAY, StatusCode::REQUEST_TIMEOUT, StatusCode::TOO_MANY_REQUESTS] {
+      assert!(matches!(check_status(status), Err(DeliveryError::Transient(_))), "{}", status);
+    }
+    for status in [StatusCode::BAD_REQUEST, StatusCode::NOT_FOUND, StatusCode::GONE, StatusCode::MOVED_PERMANENTLY] {
+      assert!(matches!(check_status(status), Err(DeliveryError::Permanent(_))), "{}", status);
+    }
+  }
+
+  #[test]
+  fn test_event_payload() {
+    let payload = serde_json::to_value(event()).unwrap();
+    assert_eq!(payload, serde_json::json!({
+      "event": "new_highscore",
+      "game_uuid": "00000000-0000-0000-0000-000000000000",
+      "table_uuid": "ffffffff-ffff-ffff-ffff-ffffffffffff",
+      "player_name": "Alice",
+      "player_score": 1200,
+      "rank": 3,
+      "creation_timestamp": "2025-02-01T05:33:10Z",
+    }));
+  }
+
+  #[rocket::async_test]
+  async fn test_transient_failures_eventually_succeed() {
+    let endpoint = FlakyEndpoint::new(2, DeliveryError::Transient("Endpoint responded with 503".to_owned()));
+    let mut dead_letters = MemoryDeadLetters::default();
+    deliver(delivery(), &endpoint, &mut dead_letters, 5, Duration::from_millis(1)).await;
+    assert_eq!(endpoint.calls.get(), 3);
+    assert!(dead_letters.0.is_empty());
+  }
+
+  #[rocket::async_test]
+  async fn test_repeated_transient_failures_are_dead_lettered() {
+    let endpoint = FlakyEndpoint::new(u32::MAX, DeliveryError::Transient("connection refused".to_owned()));
+    let mut dead_letters = MemoryDeadLetters::default();
+    let delivery = delivery();
+    deliver(delivery.clone(), &endpoint, &mut dead_letters, 3, Duration::from_millis(1)).await;
+    assert_eq!(endpoint.calls.get(), 3);
+    assert_eq!(dead_letters.0, vec![delivery.into_failed(3, "connection refused".to_owned())]);
+  }
+
+  #[rocket::async_test]
+  async fn test_permanent_failure_is_dead_lettered_without_retrying() {
+    let endpoint = FlakyEndpoint::new(u32::MAX, DeliveryError::Permanent("Endpoint responded with 404 Not Found".to_owned()));
+    let mut dead_letters = MemoryDeadLetters::default();
+    deliver(delivery(), &endpoint, &mut dead_letters, 5, Duration::from_millis(1)).await;
+    assert_eq!(endpoint.calls.get(), 1);
+    assert_eq!(dead_letters.0.len(), 1);
+    assert_eq!(dead_letters.0[0].attempts, 1);
+    assert_eq!(dead_letters.0[0].last_error, "Endpoint responded with 404 Not Found (not retried)");
+  }
+
+  #[rocket::async_test]
+  async fn test_new_highscore_is_queued_only_when_configured() {
+    let (sender, mut receiver) = mpsc::channel(MAX_QUEUED_WEBHOOKS);
+    let mut dead_letters = MemoryDeadLetters::default();
+    WebhookSender::new(None, sender.clone()).send_new_highscore_in(&event(), &mut dead_letters).await;
+    assert!(receiver.try_recv().is_err());
+
+    let url = "https://example.com/hook".to_owned();
+    WebhookSender::new(Some(url.clone()), sender).send_new_highscore_in(&event(), &mut dead_letters).await;
+    let queued = receiver.try_recv().unwrap();
+    assert_eq!(queued.url, url);
+    assert_eq!(queued.payload, serde_json::to_string(&event()).unwrap());
+    assert!(dead_letters.0.is_empty());
+  }
+
+  #[rocket::async_test]
+  async fn test_full_queue_dead_letters_directly() {
+    let (sender, mut receiver) = mpsc::channel(1);
+    let webhooks = WebhookSender::new(Some("https://example.com/hook".to_owned()), sender);
+    let mut dead_letters = MemoryDeadLetters::default();
+    webhooks.send_new_highscore_in(&event(), &mut dead_letters).await;
+    webhooks.send_new_highscore_in(&event(), &mut dead_letters).await;
+    assert!(receiver.try_recv().is_ok());
+    assert_eq!(dead_letters.0.len(), 1);
+    assert_eq!(dead_letters.0[0].attempts, 0);
+
+    // Likewise once the background task has stopped.
+    receiver.close();
+    webhooks.send_new_highscore_in(&event(), &mut dead_letters).await;
+    assert_eq!(dead_letters.0.len(), 2);
+    assert_eq!(dead_letters.0[1].last_error, "Webhook sender has stopped");
+  }
+}
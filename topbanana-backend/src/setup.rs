@@ -1,35 +1,89 @@
 
+use crate::config::AppConfig;
 use crate::db::models::NewDeveloper;
 use crate::db::schema;
+use crate::server::auth::hash_password;
+use crate::server::diagnostics::StartupSummary;
 use crate::util::generate_key;
 
 use fern::{Dispatch, InitError, log_file};
 use humantime::format_rfc3339_seconds;
-use log::LevelFilter;
+use log::{LevelFilter, warn};
 use uuid::Uuid;
 use diesel::prelude::*;
 use diesel_async::{RunQueryDsl, AsyncConnection, AsyncPgConnection};
-use chrono::{Duration, Utc};
+use chrono::Utc;
 
 use std::env;
+use std::fmt;
+use std::str::FromStr;
 use std::time::SystemTime;
 use std::io::stdout;
 
-pub async fn generate_initial_user(force: bool) -> anyhow::Result<()> {
+/// Exit status of the process when `--generate-initial-user` did
+/// nothing because an admin user already exists.
+pub const ADMIN_ALREADY_EXISTS_EXIT_CODE: u8 = 3;
+
+/// What [`generate_initial_user`] did. Displays as the report printed
+/// when no user was created.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InitialUserOutcome {
+  /// A new admin user was created.
+  Created { developer_uuid: Uuid },
+  /// An admin user already exists, so none was created. The fields
+  /// describe the oldest existing admin.
+  AlreadyExists { developer_uuid: Uuid, email: String },
+}
+
+/// The outcome of [`generate_initial_user`] if it must not create a
+/// new admin user, given the UUID and email of the oldest existing
+/// admin, if any. Unless `force` is given, an existing admin stops a
+/// new one from being created.
+fn existing_admin_outcome(force: bool, oldest_admin: Option<(Uuid, String)>) -> Option<InitialUserOutcome> {
+  if force {
+    return None;
+  }
+  oldest_admin.map(|(developer_uuid, email)| InitialUserOutcome::AlreadyExists { developer_uuid, email })
+}
+
+impl fmt::Display for InitialUserOutcome {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      InitialUserOutcome::Created { developer_uuid } => writeln!(f, "Created admin user {}.", developer_uuid),
+      InitialUserOutcome::AlreadyExists { developer_uuid, email } => {
+        writeln!(f, "Admin user already exists, refusing to create another.")?;
+        writeln!(f, "  uuid = {}", developer_uuid)?;
+        writeln!(f, "  email = {}", email)?;
+        writeln!(f, "You may override this with --force if you know what you're doing.")
+      }
+    }
+  }
+}
+
+pub async fn generate_initial_user(force: bool, admin_password: Option<&str>) -> anyhow::Result<InitialUserOutcome> {
   let mut connection = AsyncPgConnection::establish(&env::var("DATABASE_URL")?).await?;
 
   println!("Running initial admin user setup ...");
 
-  let existing_admin_user = schema::developers::table
-    .filter(schema::developers::is_admin.eq(true));
-  if !force && diesel::select(diesel::dsl::exists(existing_admin_user)).get_result(&mut connection).await? {
-    println!("Admin user already exists, refusing to create another.");
-    println!("You may override this with --force if you know what you're doing.");
-    return Ok(());
+  let oldest_admin = if force {
+    None
+  } else {
+    schema::developers::table
+      .filter(schema::developers::is_admin.eq(true))
+      .order_by(schema::developers::id.asc())
+      .select((schema::developers::developer_uuid, schema::developers::email))
+      .first::<(Uuid, String)>(&mut connection)
+      .await
+      .optional()?
+  };
+  if let Some(outcome) = existing_admin_outcome(force, oldest_admin) {
+    print!("{}", outcome);
+    return Ok(outcome);
   }
 
-  let developer_uuid = Uuid::new_v4();
+  let developer_uuid = Uuid::now_v7();
   let api_key = generate_key();
+  let password_hash = admin_password.map(hash_password).transpose()?;
   let new_developer = NewDeveloper {
     developer_uuid,
     name: String::from("System Administrator"),
@@ -37,6 +91,9 @@ pub async fn generate_initial_user(force: bool) -> anyhow::Result<()> {
     url: None,
     is_admin: true,
     api_key: Some(api_key),
+    password_hash,
+    is_super_admin: true,
+    default_security_level: None,
   };
   diesel::insert_into(schema::developers::table)
     .values(&new_developer)
@@ -47,26 +104,59 @@ pub async fn generate_initial_user(force: bool) -> anyhow::Result<()> {
   println!("  name = {}", new_developer.name);
   println!("  email = {}", new_developer.email);
   println!("  api key = {}", new_developer.api_key.unwrap());
-  Ok(())
+  if new_developer.password_hash.is_some() {
+    println!("  password login = enabled");
+  }
+  Ok(InitialUserOutcome::Created { developer_uuid })
+}
+
+/// Prints the configuration the server would use if started now. No
+/// secrets are printed.
+pub fn print_config() {
+  println!("{}", StartupSummary::from_env());
 }
 
-pub async fn cleanup_historical_requests() -> anyhow::Result<()> {
+/// Deletes request UUIDs older than the configured retention period,
+/// returning the number deleted. Progress is printed only if `verbose`
+/// is true.
+pub async fn cleanup_historical_requests(verbose: bool) -> anyhow::Result<usize> {
+  let config = AppConfig::from_env();
   let mut connection = AsyncPgConnection::establish(&env::var("DATABASE_URL")?).await?;
 
-  println!("Cleaning up historical request records ...");
+  if verbose {
+    println!("Cleaning up historical request records ...");
+  }
 
   let rows_to_delete = schema::historical_requests::table
-    .filter(schema::historical_requests::timestamp.lt(Utc::now() - Duration::days(7)));
+    .filter(schema::historical_requests::timestamp.lt(Utc::now() - config.historical_request_retention));
   let deleted_rows_count = diesel::delete(rows_to_delete)
     .execute(&mut connection)
     .await?;
 
-  println!("Successfully deleted {} historical request record(s).", deleted_rows_count);
-  Ok(())
+  if verbose {
+    println!("Successfully deleted {} historical request record(s).", deleted_rows_count);
+  }
+  Ok(deleted_rows_count)
 }
 
+/// Environment variable setting the level of messages logged to
+/// standard output.
+pub const LOG_LEVEL_ENV_VAR: &str = "LOG_LEVEL";
+/// Environment variable setting the level of messages logged to the
+/// log file.
+pub const LOG_FILE_LEVEL_ENV_VAR: &str = "LOG_FILE_LEVEL";
+
+pub const DEFAULT_LOG_LEVEL: LevelFilter = LevelFilter::Info;
+pub const DEFAULT_LOG_FILE_LEVEL: LevelFilter = LevelFilter::Debug;
+
 /// Initialize the logger for this process.
+///
+/// Log levels are read from [`LOG_LEVEL_ENV_VAR`] and
+/// [`LOG_FILE_LEVEL_ENV_VAR`]. An invalid level is reported once the
+/// logger is running, and the default is used instead.
 pub fn setup_logger() -> Result<(), InitError> {
+  let (stdout_level, stdout_level_error) = log_level_from_env(LOG_LEVEL_ENV_VAR, DEFAULT_LOG_LEVEL);
+  let (file_level, file_level_error) = log_level_from_env(LOG_FILE_LEVEL_ENV_VAR, DEFAULT_LOG_FILE_LEVEL);
   Dispatch::new()
     .format(|out, message, record| {
       out.finish(format_args!(
@@ -77,13 +167,68 @@ pub fn setup_logger() -> Result<(), InitError> {
         message
       ))
     })
-    .level(LevelFilter::Debug)
-    .chain(log_file("log/output.log")?)
     .chain(
       Dispatch::new()
-        .level(LevelFilter::Info)
+        .level(file_level)
+        .chain(log_file("log/output.log")?)
+    )
+    .chain(
+      Dispatch::new()
+        .level(stdout_level)
         .chain(stdout())
     )
     .apply()?;
+  for error in [stdout_level_error, file_level_error].into_iter().flatten() {
+    warn!("{}", error);
+  }
   Ok(())
 }
+
+/// Parses a log level name, such as `info` or `DEBUG`.
+pub fn parse_log_level(value: &str) -> Option<LevelFilter> {
+  LevelFilter::from_str(value.trim()).ok()
+}
+
+/// Reads a log level from the environment, falling back to `default`
+/// if the variable is absent or invalid. In the latter case, a
+/// message describing the problem is also returned, since the logger
+/// is not yet available to report it.
+fn log_level_from_env(var: &str, default: LevelFilter) -> (LevelFilter, Option<String>) {
+  let Ok(value) = env::var(var) else {
+    return (default, None);
+  };
+  match parse_log_level(&value) {
+    Some(level) => (level, None),
+    None => (default, Some(format!("Invalid {} value {:?}, using default of {}", var, value, default))),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_existing_admin_stops_creation() {
+    let developer_uuid = Uuid::now_v7();
+    let oldest_admin = Some((developer_uuid, String::from("admin@example.com")));
+    let outcome = existing_admin_outcome(false, oldest_admin.clone()).unwrap();
+    assert_eq!(outcome, InitialUserOutcome::AlreadyExists { developer_uuid, email: String::from("admin@example.com") });
+    let report = outcome.to_string();
+    assert!(report.starts_with("Admin user already exists, refusing to create another.\n"));
+    assert!(report.contains(&format!("  uuid = {}\n", developer_uuid)));
+    assert!(report.contains("  email = admin@example.com\n"));
+    assert!(report.contains("--force"));
+
+    assert_eq!(existing_admin_outcome(true, oldest_admin), None);
+    assert_eq!(existing_admin_outcome(false, None), None);
+  }
+
+  #[test]
+  fn test_parse_log_level() {
+    assert_eq!(parse_log_level("info"), Some(LevelFilter::Info));
+    assert_eq!(parse_log_level(" DEBUG\n"), Some(LevelFilter::Debug));
+    assert_eq!(parse_log_level("off"), Some(LevelFilter::Off));
+    assert_eq!(parse_log_level("verbose"), None);
+    assert_eq!(parse_log_level(""), None);
+  }
+}
@@ -8,16 +8,20 @@ use base64::Engine;
 use rocket::Request;
 use rocket::http::Status;
 use rocket::request::FromParam;
+use rocket::form::{self, ValueField};
 use rocket::data::{self, Data, FromData};
+use thiserror::Error;
+use uuid::Uuid;
 
 use std::str::FromStr;
-use std::fmt::Debug;
+use std::fmt::{Debug, Display};
 use std::ops::Deref;
 use std::error::{Error as StdError};
 use std::io;
 
 /// Newtype wrapper which converts a [`FromStr`] impl into a
-/// [`FromParam`] impl.
+/// [`FromParam`] impl, or a [`form::FromFormField`] impl for query
+/// parameters.
 #[derive(Debug, Clone)]
 pub struct ParamFromStr<T>(pub T);
 
@@ -29,17 +33,48 @@ pub struct ParamFromStr<T>(pub T);
 #[derive(Debug, Clone)]
 pub struct DataFromStr<T>(pub T);
 
+/// A UUID path parameter. Surrounding whitespace is ignored, and the
+/// hyphenated, simple, braced, and URN forms are all accepted, in
+/// either case.
+///
+/// Take this as `Result<UuidParam, InvalidUuid>` and propagate the
+/// error, which the API reports as an HTTP 400. Rocket answers a
+/// parameter which fails to parse with a bare 422 otherwise.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UuidParam(pub Uuid);
+
+#[derive(Debug, Clone, Error)]
+#[error("Invalid UUID {value:?}")]
+pub struct InvalidUuid {
+  pub value: String,
+}
+
+/// Number of random bytes in keys produced by [`generate_key`].
+pub const DEFAULT_KEY_LENGTH: usize = 64;
+
 /// Generates a base64 encoding of a random sequence of bytes,
 /// appropriate for use as an API key or a secret key. Uses the
 /// operating system's default source of randomness.
 pub fn generate_key() -> String {
-  generate_key_with(&mut OsRng.unwrap_err())
+  generate_key_of_length(DEFAULT_KEY_LENGTH)
+}
+
+/// As [`generate_key`], but with `length` random bytes rather than
+/// the default.
+pub fn generate_key_of_length(length: usize) -> String {
+  generate_key_with_length(&mut OsRng.unwrap_err(), length)
 }
 
 /// Generates a base64 encoding of a random sequence of bytes,
 /// appropriate for use as an API key or a secret key.
 pub fn generate_key_with(rng: &mut impl CryptoRng) -> String {
-  let mut bytes = [0u8; 64];
+  generate_key_with_length(rng, DEFAULT_KEY_LENGTH)
+}
+
+/// As [`generate_key_with`], but with `length` random bytes rather
+/// than the default.
+pub fn generate_key_with_length(rng: &mut impl CryptoRng, length: usize) -> String {
+  let mut bytes = vec![0u8; length];
   rng.fill_bytes(&mut bytes);
   URL_SAFE_NO_PAD.encode(bytes)
 }
@@ -54,6 +89,40 @@ where T: FromStr,
   }
 }
 
+impl UuidParam {
+  pub fn parse(value: &str) -> Result<UuidParam, InvalidUuid> {
+    Uuid::try_parse(value.trim())
+      .map(UuidParam)
+      .map_err(|_| InvalidUuid { value: value.to_owned() })
+  }
+}
+
+impl<'a> FromParam<'a> for UuidParam {
+  type Error = InvalidUuid;
+
+  fn from_param(param: &'a str) -> Result<Self, InvalidUuid> {
+    UuidParam::parse(param)
+  }
+}
+
+#[rocket::async_trait]
+impl<'v> form::FromFormField<'v> for UuidParam {
+  fn from_value(field: ValueField<'v>) -> form::Result<'v, Self> {
+    UuidParam::parse(field.value).map_err(|err| form::Error::validation(err.to_string()).into())
+  }
+}
+
+#[rocket::async_trait]
+impl<'v, T> form::FromFormField<'v> for ParamFromStr<T>
+where T: FromStr + Send,
+      <T as FromStr>::Err: Display {
+  fn from_value(field: ValueField<'v>) -> form::Result<'v, Self> {
+    field.value.parse::<T>()
+      .map(ParamFromStr)
+      .map_err(|err| form::Error::validation(err.to_string()).into())
+  }
+}
+
 #[rocket::async_trait]
 impl<'r, T> FromData<'r> for DataFromStr<T>
 where T: FromStr,
@@ -78,6 +147,14 @@ impl<T> Deref for ParamFromStr<T> {
   }
 }
 
+impl Deref for UuidParam {
+  type Target = Uuid;
+
+  fn deref(&self) -> &Self::Target {
+    &self.0
+  }
+}
+
 impl<T> Deref for DataFromStr<T> {
   type Target = T;
 
@@ -85,3 +162,56 @@ impl<T> Deref for DataFromStr<T> {
     &self.0
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::server::error::ApiError;
+  use crate::server::validation::MIN_SECRET_KEY_LENGTH;
+
+  #[test]
+  fn test_generated_uuids_are_v7_and_parse() {
+    let uuid = Uuid::now_v7();
+    assert_eq!(uuid.get_version_num(), 7);
+    assert_eq!(UuidParam::parse(&uuid.to_string()).unwrap(), UuidParam(uuid));
+    // Identifiers generated before the switch to v7 still parse.
+    let v4 = "0f8fad5b-d9cb-469f-a165-70867728950e";
+    let parsed = UuidParam::parse(v4).unwrap();
+    assert_eq!(parsed.0.get_version_num(), 4);
+    assert_eq!(parsed.0.to_string(), v4);
+  }
+
+  #[test]
+  fn test_generated_key_length() {
+    for length in [MIN_SECRET_KEY_LENGTH, DEFAULT_KEY_LENGTH] {
+      let key = generate_key_with_length(&mut OsRng.unwrap_err(), length);
+      assert_eq!(URL_SAFE_NO_PAD.decode(&key).unwrap().len(), length);
+      assert_eq!(URL_SAFE_NO_PAD.decode(generate_key_of_length(length)).unwrap().len(), length);
+    }
+    assert_eq!(URL_SAFE_NO_PAD.decode(generate_key()).unwrap().len(), DEFAULT_KEY_LENGTH);
+  }
+
+  #[test]
+  fn test_uuid_param_forms() {
+    let uuid = Uuid::parse_str("0194c0b0-1234-7abc-8def-0123456789ab").unwrap();
+    for form in [
+      "0194c0b0-1234-7abc-8def-0123456789ab",
+      "0194C0B0-1234-7ABC-8DEF-0123456789AB",
+      "{0194c0b0-1234-7abc-8def-0123456789ab}",
+      "0194c0b012347abc8def0123456789ab",
+      "urn:uuid:0194c0b0-1234-7abc-8def-0123456789ab",
+      "  0194c0b0-1234-7abc-8def-0123456789ab\n",
+    ] {
+      assert_eq!(UuidParam::parse(form).unwrap(), UuidParam(uuid), "{:?}", form);
+    }
+  }
+
+  #[test]
+  fn test_invalid_uuid_param_is_bad_request() {
+    let err = UuidParam::parse("not-a-uuid").unwrap_err();
+    assert_eq!(err.value, "not-a-uuid");
+    assert_eq!(ApiError::from(err).status(), Status::BadRequest);
+    assert!(UuidParam::parse("").is_err());
+    assert!(UuidParam::parse("0194c0b0-1234-7abc-8def-0123456789").is_err());
+  }
+}